@@ -0,0 +1,770 @@
+#![allow(dead_code)]
+
+use std::fmt;
+use std::str::FromStr;
+
+#[derive(Eq, PartialEq, Copy, Clone, Debug, Hash)]
+pub struct Opcode {
+    pub code: u8
+}
+// Data Push
+pub const OP_0: Opcode = Opcode {code: 0x00};
+pub const OP_PUSH_BYTES_1: Opcode = Opcode {code: 0x01};
+pub const OP_PUSH_BYTES_2: Opcode = Opcode {code: 0x02};
+pub const OP_PUSH_BYTES_3: Opcode = Opcode {code: 0x03};
+pub const OP_PUSH_BYTES_4: Opcode = Opcode {code: 0x04};
+pub const OP_PUSH_BYTES_5: Opcode = Opcode {code: 0x05};
+pub const OP_PUSH_BYTES_6: Opcode = Opcode {code: 0x06};
+pub const OP_PUSH_BYTES_7: Opcode = Opcode {code: 0x07};
+pub const OP_PUSH_BYTES_8: Opcode = Opcode {code: 0x08};
+pub const OP_PUSH_BYTES_9: Opcode = Opcode {code: 0x09};
+pub const OP_PUSH_BYTES_10: Opcode = Opcode {code: 0x0a};
+pub const OP_PUSH_BYTES_11: Opcode = Opcode {code: 0x0b};
+pub const OP_PUSH_BYTES_12: Opcode = Opcode {code: 0x0c};
+pub const OP_PUSH_BYTES_13: Opcode = Opcode {code: 0x0d};
+pub const OP_PUSH_BYTES_14: Opcode = Opcode {code: 0x0e};
+pub const OP_PUSH_BYTES_15: Opcode = Opcode {code: 0x0f};
+pub const OP_PUSH_BYTES_16: Opcode = Opcode {code: 0x10};
+pub const OP_PUSH_BYTES_17: Opcode = Opcode {code: 0x11};
+pub const OP_PUSH_BYTES_18: Opcode = Opcode {code: 0x12};
+pub const OP_PUSH_BYTES_19: Opcode = Opcode {code: 0x13};
+pub const OP_PUSH_BYTES_20: Opcode = Opcode {code: 0x14};
+pub const OP_PUSH_BYTES_21: Opcode = Opcode {code: 0x15};
+pub const OP_PUSH_BYTES_22: Opcode = Opcode {code: 0x16};
+pub const OP_PUSH_BYTES_23: Opcode = Opcode {code: 0x17};
+pub const OP_PUSH_BYTES_24: Opcode = Opcode {code: 0x18};
+pub const OP_PUSH_BYTES_25: Opcode = Opcode {code: 0x19};
+pub const OP_PUSH_BYTES_26: Opcode = Opcode {code: 0x1a};
+pub const OP_PUSH_BYTES_27: Opcode = Opcode {code: 0x1b};
+pub const OP_PUSH_BYTES_28: Opcode = Opcode {code: 0x1c};
+pub const OP_PUSH_BYTES_29: Opcode = Opcode {code: 0x1d};
+pub const OP_PUSH_BYTES_30: Opcode = Opcode {code: 0x1e};
+pub const OP_PUSH_BYTES_31: Opcode = Opcode {code: 0x1f};
+pub const OP_PUSH_BYTES_32: Opcode = Opcode {code: 0x20};
+pub const OP_PUSH_BYTES_33: Opcode = Opcode {code: 0x21};
+pub const OP_PUSH_BYTES_34: Opcode = Opcode {code: 0x22};
+pub const OP_PUSH_BYTES_35: Opcode = Opcode {code: 0x23};
+pub const OP_PUSH_BYTES_36: Opcode = Opcode {code: 0x24};
+pub const OP_PUSH_BYTES_37: Opcode = Opcode {code: 0x25};
+pub const OP_PUSH_BYTES_38: Opcode = Opcode {code: 0x26};
+pub const OP_PUSH_BYTES_39: Opcode = Opcode {code: 0x27};
+pub const OP_PUSH_BYTES_40: Opcode = Opcode {code: 0x28};
+pub const OP_PUSH_BYTES_41: Opcode = Opcode {code: 0x29};
+pub const OP_PUSH_BYTES_42: Opcode = Opcode {code: 0x2a};
+pub const OP_PUSH_BYTES_43: Opcode = Opcode {code: 0x2b};
+pub const OP_PUSH_BYTES_44: Opcode = Opcode {code: 0x2c};
+pub const OP_PUSH_BYTES_45: Opcode = Opcode {code: 0x2d};
+pub const OP_PUSH_BYTES_46: Opcode = Opcode {code: 0x2e};
+pub const OP_PUSH_BYTES_47: Opcode = Opcode {code: 0x2f};
+pub const OP_PUSH_BYTES_48: Opcode = Opcode {code: 0x30};
+pub const OP_PUSH_BYTES_49: Opcode = Opcode {code: 0x31};
+pub const OP_PUSH_BYTES_50: Opcode = Opcode {code: 0x32};
+pub const OP_PUSH_BYTES_51: Opcode = Opcode {code: 0x33};
+pub const OP_PUSH_BYTES_52: Opcode = Opcode {code: 0x34};
+pub const OP_PUSH_BYTES_53: Opcode = Opcode {code: 0x35};
+pub const OP_PUSH_BYTES_54: Opcode = Opcode {code: 0x36};
+pub const OP_PUSH_BYTES_55: Opcode = Opcode {code: 0x37};
+pub const OP_PUSH_BYTES_56: Opcode = Opcode {code: 0x38};
+pub const OP_PUSH_BYTES_57: Opcode = Opcode {code: 0x39};
+pub const OP_PUSH_BYTES_58: Opcode = Opcode {code: 0x3a};
+pub const OP_PUSH_BYTES_59: Opcode = Opcode {code: 0x3b};
+pub const OP_PUSH_BYTES_60: Opcode = Opcode {code: 0x3c};
+pub const OP_PUSH_BYTES_61: Opcode = Opcode {code: 0x3d};
+pub const OP_PUSH_BYTES_62: Opcode = Opcode {code: 0x3e};
+pub const OP_PUSH_BYTES_63: Opcode = Opcode {code: 0x3f};
+pub const OP_PUSH_BYTES_64: Opcode = Opcode {code: 0x40};
+pub const OP_PUSH_BYTES_65: Opcode = Opcode {code: 0x41};
+pub const OP_PUSH_BYTES_66: Opcode = Opcode {code: 0x42};
+pub const OP_PUSH_BYTES_67: Opcode = Opcode {code: 0x43};
+pub const OP_PUSH_BYTES_68: Opcode = Opcode {code: 0x44};
+pub const OP_PUSH_BYTES_69: Opcode = Opcode {code: 0x45};
+pub const OP_PUSH_BYTES_70: Opcode = Opcode {code: 0x46};
+pub const OP_PUSH_BYTES_71: Opcode = Opcode {code: 0x47};
+pub const OP_PUSH_BYTES_72: Opcode = Opcode {code: 0x48};
+pub const OP_PUSH_BYTES_73: Opcode = Opcode {code: 0x49};
+pub const OP_PUSH_BYTES_74: Opcode = Opcode {code: 0x4a};
+pub const OP_PUSH_BYTES_75: Opcode = Opcode {code: 0x4b};
+pub const OP_PUSH_DATA_1: Opcode = Opcode {code: 0x4c};
+pub const OP_PUSH_DATA_2: Opcode = Opcode {code: 0x4d};
+pub const OP_PUSH_DATA_4: Opcode = Opcode {code: 0x4e};
+pub const OP_1NEGATE: Opcode = Opcode {code: 0x4f};
+pub const OP_RESERVED: Opcode = Opcode {code: 0x50};
+pub const OP_1: Opcode = Opcode {code: 0x51};
+pub const OP_2: Opcode = Opcode {code: 0x52};
+pub const OP_3: Opcode = Opcode {code: 0x53};
+pub const OP_4: Opcode = Opcode {code: 0x54};
+pub const OP_5: Opcode = Opcode {code: 0x55};
+pub const OP_6: Opcode = Opcode {code: 0x56};
+pub const OP_7: Opcode = Opcode {code: 0x57};
+pub const OP_8: Opcode = Opcode {code: 0x58};
+pub const OP_9: Opcode = Opcode {code: 0x59};
+pub const OP_10: Opcode = Opcode {code: 0x5a};
+pub const OP_11: Opcode = Opcode {code: 0x5b};
+pub const OP_12: Opcode = Opcode {code: 0x5c};
+pub const OP_13: Opcode = Opcode {code: 0x5d};
+pub const OP_14: Opcode = Opcode {code: 0x5e};
+pub const OP_15: Opcode = Opcode {code: 0x5f};
+pub const OP_16: Opcode = Opcode {code: 0x60};
+
+// Flow Control
+pub const OP_NOP: Opcode = Opcode {code: 0x61};
+pub const OP_VER: Opcode = Opcode {code: 0x62};
+pub const OP_IF: Opcode = Opcode {code: 0x63};
+pub const OP_NOTIF: Opcode = Opcode {code: 0x64};
+pub const OP_VERIF: Opcode = Opcode {code: 0x65};
+pub const OP_VERNOTIF: Opcode = Opcode {code: 0x66};
+pub const OP_ELSE: Opcode = Opcode {code: 0x67};
+pub const OP_ENDIF: Opcode = Opcode {code: 0x68};
+pub const OP_VERIFY: Opcode = Opcode {code: 0x69};
+pub const OP_RETURN: Opcode = Opcode {code: 0x6a};
+
+// Stack
+pub const OP_TOALTSTACK: Opcode = Opcode {code: 0x6b};
+pub const OP_FROMALTSTACK: Opcode = Opcode {code: 0x6c};
+pub const OP_2DROP: Opcode = Opcode {code: 0x6d};
+pub const OP_2DUP: Opcode = Opcode {code: 0x6e};
+pub const OP_3DUP: Opcode = Opcode {code: 0x6f};
+pub const OP_2OVER: Opcode = Opcode {code: 0x70};
+pub const OP_2ROT: Opcode = Opcode {code: 0x71};
+pub const OP_2SWAP: Opcode = Opcode {code: 0x72};
+pub const OP_IFDUP: Opcode = Opcode {code: 0x73};
+pub const OP_DEPTH: Opcode = Opcode {code: 0x74};
+pub const OP_DROP: Opcode = Opcode {code: 0x75};
+pub const OP_DUP: Opcode = Opcode {code: 0x76};
+pub const OP_NIP: Opcode = Opcode {code: 0x77};
+pub const OP_OVER: Opcode = Opcode {code: 0x78};
+pub const OP_PICK: Opcode = Opcode {code: 0x79};
+pub const OP_ROLL: Opcode = Opcode {code: 0x7a};
+pub const OP_ROT: Opcode = Opcode {code: 0x7b};
+pub const OP_SWAP: Opcode = Opcode {code: 0x7c};
+pub const OP_TUCK: Opcode = Opcode {code: 0x7d};
+
+// Splice
+pub const OP_CAT: Opcode = Opcode {code: 0x7e};
+pub const OP_SUBSTR: Opcode = Opcode {code: 0x7f};
+pub const OP_LEFT: Opcode = Opcode {code: 0x80};
+pub const OP_RIGHT: Opcode = Opcode {code: 0x81};
+pub const OP_SIZE: Opcode = Opcode {code: 0x82};
+
+// Bitwise Logic
+pub const OP_INVERT: Opcode = Opcode {code: 0x83};
+pub const OP_AND: Opcode = Opcode {code: 0x84};
+pub const OP_OR: Opcode = Opcode {code: 0x85};
+pub const OP_XOR: Opcode = Opcode {code: 0x86};
+pub const OP_EQUAL: Opcode = Opcode {code: 0x87};
+pub const OP_EQUALVERIFY: Opcode = Opcode {code: 0x88};
+
+pub const OP_RESERVED1: Opcode = Opcode {code: 0x89};
+pub const OP_RESERVED2: Opcode = Opcode {code: 0x8a};
+
+// Arithmetic
+pub const OP_1ADD: Opcode = Opcode {code: 0x8b};
+pub const OP_1SUB: Opcode = Opcode {code: 0x8c};
+pub const OP_2MUL: Opcode = Opcode {code: 0x8d};
+pub const OP_2DIV: Opcode = Opcode {code: 0x8e};
+pub const OP_NEGATE: Opcode = Opcode {code: 0x8f};
+pub const OP_ABS: Opcode = Opcode {code: 0x90};
+pub const OP_NOT: Opcode = Opcode {code: 0x91};
+pub const OP_0NOTEQUAL: Opcode = Opcode {code: 0x92};
+pub const OP_ADD: Opcode = Opcode {code: 0x93};
+pub const OP_SUB: Opcode = Opcode {code: 0x94};
+pub const OP_MUL: Opcode = Opcode {code: 0x95};
+pub const OP_DIV: Opcode = Opcode {code: 0x96};
+pub const OP_MOD: Opcode = Opcode {code: 0x97};
+pub const OP_LSHIFT: Opcode = Opcode {code: 0x98};
+pub const OP_RSHIFT: Opcode = Opcode {code: 0x99};
+pub const OP_BOOLAND: Opcode = Opcode {code: 0x9a};
+pub const OP_BOOLOR: Opcode = Opcode {code: 0x9b};
+pub const OP_NUMEQUAL: Opcode = Opcode {code: 0x9c};
+pub const OP_NUMEQUALVERIFY: Opcode = Opcode {code: 0x9d};
+pub const OP_NUMNOTEQUAL: Opcode = Opcode {code: 0x9e};
+pub const OP_LESSTHAN: Opcode = Opcode {code: 0x9f};
+pub const OP_GREATERTHAN: Opcode = Opcode {code: 0xa0};
+pub const OP_LESSTHANOREQUAL: Opcode = Opcode {code: 0xa1};
+pub const OP_GREATERTHANOREQUAL: Opcode = Opcode {code: 0xa2};
+pub const OP_MIN: Opcode = Opcode {code: 0xa3};
+pub const OP_MAX: Opcode = Opcode {code: 0xa4};
+pub const OP_WITHIN: Opcode = Opcode {code: 0xa5};
+
+// Crypto
+pub const OP_RIPEMD160: Opcode = Opcode {code: 0xa6};
+pub const OP_SHA1: Opcode = Opcode {code: 0xa7};
+pub const OP_SHA256: Opcode = Opcode {code: 0xa8};
+pub const OP_HASH160: Opcode = Opcode {code: 0xa9};
+pub const OP_HASH256: Opcode = Opcode {code: 0xaa};
+pub const OP_CODESEPARATOR: Opcode = Opcode {code: 0xab};
+pub const OP_CHECKSIG: Opcode = Opcode {code: 0xac};
+pub const OP_CHECKSIGVERIFY: Opcode = Opcode {code: 0xad};
+pub const OP_CHECKMULTISIG: Opcode = Opcode {code: 0xae};
+pub const OP_CHECKMULTISIGVERIFY: Opcode = Opcode {code: 0xaf};
+
+// Expansion
+pub const OP_NOP1: Opcode = Opcode {code: 0xb0};
+pub const OP_CHECKLOCKTIMEVERIFY: Opcode = Opcode {code: 0xb1};
+pub const OP_CHECKSEQUENCEVERIFY: Opcode = Opcode {code: 0xb2};
+pub const OP_NOP4: Opcode = Opcode {code: 0xb3};
+pub const OP_NOP5: Opcode = Opcode {code: 0xb4};
+pub const OP_NOP6: Opcode = Opcode {code: 0xb5};
+pub const OP_NOP7: Opcode = Opcode {code: 0xb6};
+pub const OP_NOP8: Opcode = Opcode {code: 0xb7};
+pub const OP_NOP9: Opcode = Opcode {code: 0xb8};
+pub const OP_NOP10: Opcode = Opcode {code: 0xb9};
+
+// Unassigned
+pub const OP_CHECKSIGADD: Opcode = Opcode {code: 0xba};
+pub const OP_UNASSIGNED_187: Opcode = Opcode {code: 0xbb};
+pub const OP_UNASSIGNED_188: Opcode = Opcode {code: 0xbc};
+pub const OP_UNASSIGNED_189: Opcode = Opcode {code: 0xbd};
+pub const OP_UNASSIGNED_190: Opcode = Opcode {code: 0xbe};
+pub const OP_UNASSIGNED_191: Opcode = Opcode {code: 0xbf};
+pub const OP_UNASSIGNED_192: Opcode = Opcode {code: 0xc0};
+pub const OP_UNASSIGNED_193: Opcode = Opcode {code: 0xc1};
+pub const OP_UNASSIGNED_194: Opcode = Opcode {code: 0xc2};
+pub const OP_UNASSIGNED_195: Opcode = Opcode {code: 0xc3};
+pub const OP_UNASSIGNED_196: Opcode = Opcode {code: 0xc4};
+pub const OP_UNASSIGNED_197: Opcode = Opcode {code: 0xc5};
+pub const OP_UNASSIGNED_198: Opcode = Opcode {code: 0xc6};
+pub const OP_UNASSIGNED_199: Opcode = Opcode {code: 0xc7};
+pub const OP_UNASSIGNED_200: Opcode = Opcode {code: 0xc8};
+pub const OP_UNASSIGNED_201: Opcode = Opcode {code: 0xc9};
+pub const OP_UNASSIGNED_202: Opcode = Opcode {code: 0xca};
+pub const OP_UNASSIGNED_203: Opcode = Opcode {code: 0xcb};
+pub const OP_UNASSIGNED_204: Opcode = Opcode {code: 0xcc};
+pub const OP_UNASSIGNED_205: Opcode = Opcode {code: 0xcd};
+pub const OP_UNASSIGNED_206: Opcode = Opcode {code: 0xce};
+pub const OP_UNASSIGNED_207: Opcode = Opcode {code: 0xcf};
+pub const OP_UNASSIGNED_208: Opcode = Opcode {code: 0xd0};
+pub const OP_UNASSIGNED_209: Opcode = Opcode {code: 0xd1};
+pub const OP_UNASSIGNED_210: Opcode = Opcode {code: 0xd2};
+pub const OP_UNASSIGNED_211: Opcode = Opcode {code: 0xd3};
+pub const OP_UNASSIGNED_212: Opcode = Opcode {code: 0xd4};
+pub const OP_UNASSIGNED_213: Opcode = Opcode {code: 0xd5};
+pub const OP_UNASSIGNED_214: Opcode = Opcode {code: 0xd6};
+pub const OP_UNASSIGNED_215: Opcode = Opcode {code: 0xd7};
+pub const OP_UNASSIGNED_216: Opcode = Opcode {code: 0xd8};
+pub const OP_UNASSIGNED_217: Opcode = Opcode {code: 0xd9};
+pub const OP_UNASSIGNED_218: Opcode = Opcode {code: 0xda};
+pub const OP_UNASSIGNED_219: Opcode = Opcode {code: 0xdb};
+pub const OP_UNASSIGNED_220: Opcode = Opcode {code: 0xdc};
+pub const OP_UNASSIGNED_221: Opcode = Opcode {code: 0xdd};
+pub const OP_UNASSIGNED_222: Opcode = Opcode {code: 0xde};
+pub const OP_UNASSIGNED_223: Opcode = Opcode {code: 0xdf};
+pub const OP_UNASSIGNED_224: Opcode = Opcode {code: 0xe0};
+pub const OP_UNASSIGNED_225: Opcode = Opcode {code: 0xe1};
+pub const OP_UNASSIGNED_226: Opcode = Opcode {code: 0xe2};
+pub const OP_UNASSIGNED_227: Opcode = Opcode {code: 0xe3};
+pub const OP_UNASSIGNED_228: Opcode = Opcode {code: 0xe4};
+pub const OP_UNASSIGNED_229: Opcode = Opcode {code: 0xe5};
+pub const OP_UNASSIGNED_230: Opcode = Opcode {code: 0xe6};
+pub const OP_UNASSIGNED_231: Opcode = Opcode {code: 0xe7};
+pub const OP_UNASSIGNED_232: Opcode = Opcode {code: 0xe8};
+pub const OP_UNASSIGNED_233: Opcode = Opcode {code: 0xe9};
+pub const OP_UNASSIGNED_234: Opcode = Opcode {code: 0xea};
+pub const OP_UNASSIGNED_235: Opcode = Opcode {code: 0xeb};
+pub const OP_UNASSIGNED_236: Opcode = Opcode {code: 0xec};
+pub const OP_UNASSIGNED_237: Opcode = Opcode {code: 0xed};
+pub const OP_UNASSIGNED_238: Opcode = Opcode {code: 0xee};
+pub const OP_UNASSIGNED_239: Opcode = Opcode {code: 0xef};
+pub const OP_UNASSIGNED_240: Opcode = Opcode {code: 0xf0};
+pub const OP_UNASSIGNED_241: Opcode = Opcode {code: 0xf1};
+pub const OP_UNASSIGNED_242: Opcode = Opcode {code: 0xf2};
+pub const OP_UNASSIGNED_243: Opcode = Opcode {code: 0xf3};
+pub const OP_UNASSIGNED_244: Opcode = Opcode {code: 0xf4};
+pub const OP_UNASSIGNED_245: Opcode = Opcode {code: 0xf5};
+pub const OP_UNASSIGNED_246: Opcode = Opcode {code: 0xf6};
+pub const OP_UNASSIGNED_247: Opcode = Opcode {code: 0xf7};
+pub const OP_UNASSIGNED_248: Opcode = Opcode {code: 0xf8};
+pub const OP_UNASSIGNED_249: Opcode = Opcode {code: 0xf9};
+pub const OP_UNASSIGNED_250: Opcode = Opcode {code: 0xfa};
+pub const OP_UNASSIGNED_251: Opcode = Opcode {code: 0xfb};
+pub const OP_UNASSIGNED_252: Opcode = Opcode {code: 0xfc};
+pub const OP_UNASSIGNED_253: Opcode = Opcode {code: 0xfd};
+pub const OP_UNASSIGNED_254: Opcode = Opcode {code: 0xfe};
+pub const OP_UNASSIGNED_255: Opcode = Opcode {code: 0xff};
+
+impl From<u8> for Opcode {
+    fn from(c: u8) -> Self {
+        Opcode {code: c}
+    }
+}
+
+impl fmt::Display for Opcode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.info().name)
+    }
+}
+
+// Accepts canonical names and aliases, with or without the OP_ prefix
+impl FromStr for Opcode {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let name = s.to_uppercase();
+        let name = if name.starts_with("OP_") { name } else { format!("OP_{}", name) };
+        (0..=255u8)
+            .map(Opcode::from)
+            .find(|op| op.info().name == name || op.info().aliases.contains(&name.as_str()))
+            .ok_or(format!("Unknown opcode: {}", s))
+    }
+}
+
+// Opcode groups, as listed on the Bitcoin wiki
+#[derive(Eq, PartialEq, Copy, Clone, Debug, Hash)]
+pub enum Category {
+    Constant,
+    FlowControl,
+    Stack,
+    Splice,
+    Bitwise,
+    Arithmetic,
+    Crypto,
+    Locktime,
+    Reserved,
+    Unassigned
+}
+
+impl fmt::Display for Category {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            Category::Constant => "constant",
+            Category::FlowControl => "flow control",
+            Category::Stack => "stack",
+            Category::Splice => "splice",
+            Category::Bitwise => "bitwise logic",
+            Category::Arithmetic => "arithmetic",
+            Category::Crypto => "crypto",
+            Category::Locktime => "locktime",
+            Category::Reserved => "reserved",
+            Category::Unassigned => "unassigned"
+        };
+        write!(f, "{}", name)
+    }
+}
+
+// What executing an opcode does in a given script context
+#[derive(Eq, PartialEq, Copy, Clone, Debug, Hash)]
+pub enum Behaviour {
+    Normal,
+    // Does nothing, kept for soft fork upgrades
+    UpgradableNop,
+    // Fails the script when executed
+    Invalid,
+    // Fails the script even in an unexecuted branch
+    AlwaysInvalid,
+    // Makes the whole script succeed as soon as it is parsed (tapscript OP_SUCCESSx)
+    Success
+}
+
+#[derive(Eq, PartialEq, Copy, Clone, Debug, Hash)]
+pub enum ScriptContext {
+    // Legacy and segwit v0 scripts
+    Legacy,
+    Tapscript
+}
+
+#[derive(Debug)]
+pub struct OpcodeInfo {
+    pub name: &'static str,
+    pub aliases: &'static [&'static str],
+    pub category: Category,
+    // Number of items read from and written to the main stack, None when it depends on the stack content
+    pub stack_effect: Option<(usize, usize)>,
+    // Whether it counts toward MAX_OPS_PER_SCRIPT
+    pub counts_toward_op_limit: bool,
+    pub disabled: bool,
+    pub legacy: Behaviour,
+    pub tapscript: Behaviour
+}
+
+impl OpcodeInfo {
+    const fn new(name: &'static str, category: Category, stack_effect: Option<(usize, usize)>) -> OpcodeInfo {
+        OpcodeInfo {
+            name,
+            aliases: &[],
+            category,
+            stack_effect,
+            counts_toward_op_limit: true,
+            disabled: false,
+            legacy: Behaviour::Normal,
+            tapscript: Behaviour::Normal
+        }
+    }
+
+    const fn aliases(mut self, aliases: &'static [&'static str]) -> OpcodeInfo {
+        self.aliases = aliases;
+        self
+    }
+
+    const fn not_counted(mut self) -> OpcodeInfo {
+        self.counts_toward_op_limit = false;
+        self
+    }
+
+    // Sets the legacy behaviour, tapscript behaves the same unless told otherwise
+    const fn legacy(mut self, behaviour: Behaviour) -> OpcodeInfo {
+        self.legacy = behaviour;
+        self.tapscript = behaviour;
+        self
+    }
+
+    const fn tapscript(mut self, behaviour: Behaviour) -> OpcodeInfo {
+        self.tapscript = behaviour;
+        self
+    }
+
+    const fn disabled(mut self) -> OpcodeInfo {
+        self.disabled = true;
+        self
+    }
+
+    pub fn behaviour(&self, context: ScriptContext) -> Behaviour {
+        match context {
+            ScriptContext::Legacy => self.legacy,
+            ScriptContext::Tapscript => self.tapscript
+        }
+    }
+}
+
+// Metadata of every opcode, indexed by its byte
+pub static OPCODE_TABLE: [OpcodeInfo; 256] = [
+    /* 0x00 */ OpcodeInfo::new("OP_0", Category::Constant, Some((0, 1))).aliases(&["OP_FALSE"]).not_counted(),
+    /* 0x01 */ OpcodeInfo::new("OP_PUSH_BYTES_1", Category::Constant, Some((0, 1))).aliases(&["OP_PUSHBYTES_1"]).not_counted(),
+    /* 0x02 */ OpcodeInfo::new("OP_PUSH_BYTES_2", Category::Constant, Some((0, 1))).aliases(&["OP_PUSHBYTES_2"]).not_counted(),
+    /* 0x03 */ OpcodeInfo::new("OP_PUSH_BYTES_3", Category::Constant, Some((0, 1))).aliases(&["OP_PUSHBYTES_3"]).not_counted(),
+    /* 0x04 */ OpcodeInfo::new("OP_PUSH_BYTES_4", Category::Constant, Some((0, 1))).aliases(&["OP_PUSHBYTES_4"]).not_counted(),
+    /* 0x05 */ OpcodeInfo::new("OP_PUSH_BYTES_5", Category::Constant, Some((0, 1))).aliases(&["OP_PUSHBYTES_5"]).not_counted(),
+    /* 0x06 */ OpcodeInfo::new("OP_PUSH_BYTES_6", Category::Constant, Some((0, 1))).aliases(&["OP_PUSHBYTES_6"]).not_counted(),
+    /* 0x07 */ OpcodeInfo::new("OP_PUSH_BYTES_7", Category::Constant, Some((0, 1))).aliases(&["OP_PUSHBYTES_7"]).not_counted(),
+    /* 0x08 */ OpcodeInfo::new("OP_PUSH_BYTES_8", Category::Constant, Some((0, 1))).aliases(&["OP_PUSHBYTES_8"]).not_counted(),
+    /* 0x09 */ OpcodeInfo::new("OP_PUSH_BYTES_9", Category::Constant, Some((0, 1))).aliases(&["OP_PUSHBYTES_9"]).not_counted(),
+    /* 0x0a */ OpcodeInfo::new("OP_PUSH_BYTES_10", Category::Constant, Some((0, 1))).aliases(&["OP_PUSHBYTES_10"]).not_counted(),
+    /* 0x0b */ OpcodeInfo::new("OP_PUSH_BYTES_11", Category::Constant, Some((0, 1))).aliases(&["OP_PUSHBYTES_11"]).not_counted(),
+    /* 0x0c */ OpcodeInfo::new("OP_PUSH_BYTES_12", Category::Constant, Some((0, 1))).aliases(&["OP_PUSHBYTES_12"]).not_counted(),
+    /* 0x0d */ OpcodeInfo::new("OP_PUSH_BYTES_13", Category::Constant, Some((0, 1))).aliases(&["OP_PUSHBYTES_13"]).not_counted(),
+    /* 0x0e */ OpcodeInfo::new("OP_PUSH_BYTES_14", Category::Constant, Some((0, 1))).aliases(&["OP_PUSHBYTES_14"]).not_counted(),
+    /* 0x0f */ OpcodeInfo::new("OP_PUSH_BYTES_15", Category::Constant, Some((0, 1))).aliases(&["OP_PUSHBYTES_15"]).not_counted(),
+    /* 0x10 */ OpcodeInfo::new("OP_PUSH_BYTES_16", Category::Constant, Some((0, 1))).aliases(&["OP_PUSHBYTES_16"]).not_counted(),
+    /* 0x11 */ OpcodeInfo::new("OP_PUSH_BYTES_17", Category::Constant, Some((0, 1))).aliases(&["OP_PUSHBYTES_17"]).not_counted(),
+    /* 0x12 */ OpcodeInfo::new("OP_PUSH_BYTES_18", Category::Constant, Some((0, 1))).aliases(&["OP_PUSHBYTES_18"]).not_counted(),
+    /* 0x13 */ OpcodeInfo::new("OP_PUSH_BYTES_19", Category::Constant, Some((0, 1))).aliases(&["OP_PUSHBYTES_19"]).not_counted(),
+    /* 0x14 */ OpcodeInfo::new("OP_PUSH_BYTES_20", Category::Constant, Some((0, 1))).aliases(&["OP_PUSHBYTES_20"]).not_counted(),
+    /* 0x15 */ OpcodeInfo::new("OP_PUSH_BYTES_21", Category::Constant, Some((0, 1))).aliases(&["OP_PUSHBYTES_21"]).not_counted(),
+    /* 0x16 */ OpcodeInfo::new("OP_PUSH_BYTES_22", Category::Constant, Some((0, 1))).aliases(&["OP_PUSHBYTES_22"]).not_counted(),
+    /* 0x17 */ OpcodeInfo::new("OP_PUSH_BYTES_23", Category::Constant, Some((0, 1))).aliases(&["OP_PUSHBYTES_23"]).not_counted(),
+    /* 0x18 */ OpcodeInfo::new("OP_PUSH_BYTES_24", Category::Constant, Some((0, 1))).aliases(&["OP_PUSHBYTES_24"]).not_counted(),
+    /* 0x19 */ OpcodeInfo::new("OP_PUSH_BYTES_25", Category::Constant, Some((0, 1))).aliases(&["OP_PUSHBYTES_25"]).not_counted(),
+    /* 0x1a */ OpcodeInfo::new("OP_PUSH_BYTES_26", Category::Constant, Some((0, 1))).aliases(&["OP_PUSHBYTES_26"]).not_counted(),
+    /* 0x1b */ OpcodeInfo::new("OP_PUSH_BYTES_27", Category::Constant, Some((0, 1))).aliases(&["OP_PUSHBYTES_27"]).not_counted(),
+    /* 0x1c */ OpcodeInfo::new("OP_PUSH_BYTES_28", Category::Constant, Some((0, 1))).aliases(&["OP_PUSHBYTES_28"]).not_counted(),
+    /* 0x1d */ OpcodeInfo::new("OP_PUSH_BYTES_29", Category::Constant, Some((0, 1))).aliases(&["OP_PUSHBYTES_29"]).not_counted(),
+    /* 0x1e */ OpcodeInfo::new("OP_PUSH_BYTES_30", Category::Constant, Some((0, 1))).aliases(&["OP_PUSHBYTES_30"]).not_counted(),
+    /* 0x1f */ OpcodeInfo::new("OP_PUSH_BYTES_31", Category::Constant, Some((0, 1))).aliases(&["OP_PUSHBYTES_31"]).not_counted(),
+    /* 0x20 */ OpcodeInfo::new("OP_PUSH_BYTES_32", Category::Constant, Some((0, 1))).aliases(&["OP_PUSHBYTES_32"]).not_counted(),
+    /* 0x21 */ OpcodeInfo::new("OP_PUSH_BYTES_33", Category::Constant, Some((0, 1))).aliases(&["OP_PUSHBYTES_33"]).not_counted(),
+    /* 0x22 */ OpcodeInfo::new("OP_PUSH_BYTES_34", Category::Constant, Some((0, 1))).aliases(&["OP_PUSHBYTES_34"]).not_counted(),
+    /* 0x23 */ OpcodeInfo::new("OP_PUSH_BYTES_35", Category::Constant, Some((0, 1))).aliases(&["OP_PUSHBYTES_35"]).not_counted(),
+    /* 0x24 */ OpcodeInfo::new("OP_PUSH_BYTES_36", Category::Constant, Some((0, 1))).aliases(&["OP_PUSHBYTES_36"]).not_counted(),
+    /* 0x25 */ OpcodeInfo::new("OP_PUSH_BYTES_37", Category::Constant, Some((0, 1))).aliases(&["OP_PUSHBYTES_37"]).not_counted(),
+    /* 0x26 */ OpcodeInfo::new("OP_PUSH_BYTES_38", Category::Constant, Some((0, 1))).aliases(&["OP_PUSHBYTES_38"]).not_counted(),
+    /* 0x27 */ OpcodeInfo::new("OP_PUSH_BYTES_39", Category::Constant, Some((0, 1))).aliases(&["OP_PUSHBYTES_39"]).not_counted(),
+    /* 0x28 */ OpcodeInfo::new("OP_PUSH_BYTES_40", Category::Constant, Some((0, 1))).aliases(&["OP_PUSHBYTES_40"]).not_counted(),
+    /* 0x29 */ OpcodeInfo::new("OP_PUSH_BYTES_41", Category::Constant, Some((0, 1))).aliases(&["OP_PUSHBYTES_41"]).not_counted(),
+    /* 0x2a */ OpcodeInfo::new("OP_PUSH_BYTES_42", Category::Constant, Some((0, 1))).aliases(&["OP_PUSHBYTES_42"]).not_counted(),
+    /* 0x2b */ OpcodeInfo::new("OP_PUSH_BYTES_43", Category::Constant, Some((0, 1))).aliases(&["OP_PUSHBYTES_43"]).not_counted(),
+    /* 0x2c */ OpcodeInfo::new("OP_PUSH_BYTES_44", Category::Constant, Some((0, 1))).aliases(&["OP_PUSHBYTES_44"]).not_counted(),
+    /* 0x2d */ OpcodeInfo::new("OP_PUSH_BYTES_45", Category::Constant, Some((0, 1))).aliases(&["OP_PUSHBYTES_45"]).not_counted(),
+    /* 0x2e */ OpcodeInfo::new("OP_PUSH_BYTES_46", Category::Constant, Some((0, 1))).aliases(&["OP_PUSHBYTES_46"]).not_counted(),
+    /* 0x2f */ OpcodeInfo::new("OP_PUSH_BYTES_47", Category::Constant, Some((0, 1))).aliases(&["OP_PUSHBYTES_47"]).not_counted(),
+    /* 0x30 */ OpcodeInfo::new("OP_PUSH_BYTES_48", Category::Constant, Some((0, 1))).aliases(&["OP_PUSHBYTES_48"]).not_counted(),
+    /* 0x31 */ OpcodeInfo::new("OP_PUSH_BYTES_49", Category::Constant, Some((0, 1))).aliases(&["OP_PUSHBYTES_49"]).not_counted(),
+    /* 0x32 */ OpcodeInfo::new("OP_PUSH_BYTES_50", Category::Constant, Some((0, 1))).aliases(&["OP_PUSHBYTES_50"]).not_counted(),
+    /* 0x33 */ OpcodeInfo::new("OP_PUSH_BYTES_51", Category::Constant, Some((0, 1))).aliases(&["OP_PUSHBYTES_51"]).not_counted(),
+    /* 0x34 */ OpcodeInfo::new("OP_PUSH_BYTES_52", Category::Constant, Some((0, 1))).aliases(&["OP_PUSHBYTES_52"]).not_counted(),
+    /* 0x35 */ OpcodeInfo::new("OP_PUSH_BYTES_53", Category::Constant, Some((0, 1))).aliases(&["OP_PUSHBYTES_53"]).not_counted(),
+    /* 0x36 */ OpcodeInfo::new("OP_PUSH_BYTES_54", Category::Constant, Some((0, 1))).aliases(&["OP_PUSHBYTES_54"]).not_counted(),
+    /* 0x37 */ OpcodeInfo::new("OP_PUSH_BYTES_55", Category::Constant, Some((0, 1))).aliases(&["OP_PUSHBYTES_55"]).not_counted(),
+    /* 0x38 */ OpcodeInfo::new("OP_PUSH_BYTES_56", Category::Constant, Some((0, 1))).aliases(&["OP_PUSHBYTES_56"]).not_counted(),
+    /* 0x39 */ OpcodeInfo::new("OP_PUSH_BYTES_57", Category::Constant, Some((0, 1))).aliases(&["OP_PUSHBYTES_57"]).not_counted(),
+    /* 0x3a */ OpcodeInfo::new("OP_PUSH_BYTES_58", Category::Constant, Some((0, 1))).aliases(&["OP_PUSHBYTES_58"]).not_counted(),
+    /* 0x3b */ OpcodeInfo::new("OP_PUSH_BYTES_59", Category::Constant, Some((0, 1))).aliases(&["OP_PUSHBYTES_59"]).not_counted(),
+    /* 0x3c */ OpcodeInfo::new("OP_PUSH_BYTES_60", Category::Constant, Some((0, 1))).aliases(&["OP_PUSHBYTES_60"]).not_counted(),
+    /* 0x3d */ OpcodeInfo::new("OP_PUSH_BYTES_61", Category::Constant, Some((0, 1))).aliases(&["OP_PUSHBYTES_61"]).not_counted(),
+    /* 0x3e */ OpcodeInfo::new("OP_PUSH_BYTES_62", Category::Constant, Some((0, 1))).aliases(&["OP_PUSHBYTES_62"]).not_counted(),
+    /* 0x3f */ OpcodeInfo::new("OP_PUSH_BYTES_63", Category::Constant, Some((0, 1))).aliases(&["OP_PUSHBYTES_63"]).not_counted(),
+    /* 0x40 */ OpcodeInfo::new("OP_PUSH_BYTES_64", Category::Constant, Some((0, 1))).aliases(&["OP_PUSHBYTES_64"]).not_counted(),
+    /* 0x41 */ OpcodeInfo::new("OP_PUSH_BYTES_65", Category::Constant, Some((0, 1))).aliases(&["OP_PUSHBYTES_65"]).not_counted(),
+    /* 0x42 */ OpcodeInfo::new("OP_PUSH_BYTES_66", Category::Constant, Some((0, 1))).aliases(&["OP_PUSHBYTES_66"]).not_counted(),
+    /* 0x43 */ OpcodeInfo::new("OP_PUSH_BYTES_67", Category::Constant, Some((0, 1))).aliases(&["OP_PUSHBYTES_67"]).not_counted(),
+    /* 0x44 */ OpcodeInfo::new("OP_PUSH_BYTES_68", Category::Constant, Some((0, 1))).aliases(&["OP_PUSHBYTES_68"]).not_counted(),
+    /* 0x45 */ OpcodeInfo::new("OP_PUSH_BYTES_69", Category::Constant, Some((0, 1))).aliases(&["OP_PUSHBYTES_69"]).not_counted(),
+    /* 0x46 */ OpcodeInfo::new("OP_PUSH_BYTES_70", Category::Constant, Some((0, 1))).aliases(&["OP_PUSHBYTES_70"]).not_counted(),
+    /* 0x47 */ OpcodeInfo::new("OP_PUSH_BYTES_71", Category::Constant, Some((0, 1))).aliases(&["OP_PUSHBYTES_71"]).not_counted(),
+    /* 0x48 */ OpcodeInfo::new("OP_PUSH_BYTES_72", Category::Constant, Some((0, 1))).aliases(&["OP_PUSHBYTES_72"]).not_counted(),
+    /* 0x49 */ OpcodeInfo::new("OP_PUSH_BYTES_73", Category::Constant, Some((0, 1))).aliases(&["OP_PUSHBYTES_73"]).not_counted(),
+    /* 0x4a */ OpcodeInfo::new("OP_PUSH_BYTES_74", Category::Constant, Some((0, 1))).aliases(&["OP_PUSHBYTES_74"]).not_counted(),
+    /* 0x4b */ OpcodeInfo::new("OP_PUSH_BYTES_75", Category::Constant, Some((0, 1))).aliases(&["OP_PUSHBYTES_75"]).not_counted(),
+    /* 0x4c */ OpcodeInfo::new("OP_PUSH_DATA_1", Category::Constant, Some((0, 1))).aliases(&["OP_PUSHDATA1"]).not_counted(),
+    /* 0x4d */ OpcodeInfo::new("OP_PUSH_DATA_2", Category::Constant, Some((0, 1))).aliases(&["OP_PUSHDATA2"]).not_counted(),
+    /* 0x4e */ OpcodeInfo::new("OP_PUSH_DATA_4", Category::Constant, Some((0, 1))).aliases(&["OP_PUSHDATA4"]).not_counted(),
+    /* 0x4f */ OpcodeInfo::new("OP_1NEGATE", Category::Constant, Some((0, 1))).not_counted(),
+    /* 0x50 */ OpcodeInfo::new("OP_RESERVED", Category::Reserved, Some((0, 0))).not_counted().legacy(Behaviour::Invalid).tapscript(Behaviour::Success),
+    /* 0x51 */ OpcodeInfo::new("OP_1", Category::Constant, Some((0, 1))).aliases(&["OP_TRUE"]).not_counted(),
+    /* 0x52 */ OpcodeInfo::new("OP_2", Category::Constant, Some((0, 1))).not_counted(),
+    /* 0x53 */ OpcodeInfo::new("OP_3", Category::Constant, Some((0, 1))).not_counted(),
+    /* 0x54 */ OpcodeInfo::new("OP_4", Category::Constant, Some((0, 1))).not_counted(),
+    /* 0x55 */ OpcodeInfo::new("OP_5", Category::Constant, Some((0, 1))).not_counted(),
+    /* 0x56 */ OpcodeInfo::new("OP_6", Category::Constant, Some((0, 1))).not_counted(),
+    /* 0x57 */ OpcodeInfo::new("OP_7", Category::Constant, Some((0, 1))).not_counted(),
+    /* 0x58 */ OpcodeInfo::new("OP_8", Category::Constant, Some((0, 1))).not_counted(),
+    /* 0x59 */ OpcodeInfo::new("OP_9", Category::Constant, Some((0, 1))).not_counted(),
+    /* 0x5a */ OpcodeInfo::new("OP_10", Category::Constant, Some((0, 1))).not_counted(),
+    /* 0x5b */ OpcodeInfo::new("OP_11", Category::Constant, Some((0, 1))).not_counted(),
+    /* 0x5c */ OpcodeInfo::new("OP_12", Category::Constant, Some((0, 1))).not_counted(),
+    /* 0x5d */ OpcodeInfo::new("OP_13", Category::Constant, Some((0, 1))).not_counted(),
+    /* 0x5e */ OpcodeInfo::new("OP_14", Category::Constant, Some((0, 1))).not_counted(),
+    /* 0x5f */ OpcodeInfo::new("OP_15", Category::Constant, Some((0, 1))).not_counted(),
+    /* 0x60 */ OpcodeInfo::new("OP_16", Category::Constant, Some((0, 1))).not_counted(),
+    /* 0x61 */ OpcodeInfo::new("OP_NOP", Category::FlowControl, Some((0, 0))),
+    /* 0x62 */ OpcodeInfo::new("OP_VER", Category::Reserved, Some((0, 0))).legacy(Behaviour::Invalid).tapscript(Behaviour::Success),
+    /* 0x63 */ OpcodeInfo::new("OP_IF", Category::FlowControl, Some((1, 0))),
+    /* 0x64 */ OpcodeInfo::new("OP_NOTIF", Category::FlowControl, Some((1, 0))),
+    /* 0x65 */ OpcodeInfo::new("OP_VERIF", Category::Reserved, Some((0, 0))).legacy(Behaviour::AlwaysInvalid),
+    /* 0x66 */ OpcodeInfo::new("OP_VERNOTIF", Category::Reserved, Some((0, 0))).legacy(Behaviour::AlwaysInvalid),
+    /* 0x67 */ OpcodeInfo::new("OP_ELSE", Category::FlowControl, Some((0, 0))),
+    /* 0x68 */ OpcodeInfo::new("OP_ENDIF", Category::FlowControl, Some((0, 0))),
+    /* 0x69 */ OpcodeInfo::new("OP_VERIFY", Category::FlowControl, Some((1, 0))),
+    /* 0x6a */ OpcodeInfo::new("OP_RETURN", Category::FlowControl, Some((0, 0))),
+    /* 0x6b */ OpcodeInfo::new("OP_TOALTSTACK", Category::Stack, Some((1, 0))),
+    /* 0x6c */ OpcodeInfo::new("OP_FROMALTSTACK", Category::Stack, Some((0, 1))),
+    /* 0x6d */ OpcodeInfo::new("OP_2DROP", Category::Stack, Some((2, 0))),
+    /* 0x6e */ OpcodeInfo::new("OP_2DUP", Category::Stack, Some((2, 4))),
+    /* 0x6f */ OpcodeInfo::new("OP_3DUP", Category::Stack, Some((3, 6))),
+    /* 0x70 */ OpcodeInfo::new("OP_2OVER", Category::Stack, Some((4, 6))),
+    /* 0x71 */ OpcodeInfo::new("OP_2ROT", Category::Stack, Some((6, 6))),
+    /* 0x72 */ OpcodeInfo::new("OP_2SWAP", Category::Stack, Some((4, 4))),
+    /* 0x73 */ OpcodeInfo::new("OP_IFDUP", Category::Stack, None),
+    /* 0x74 */ OpcodeInfo::new("OP_DEPTH", Category::Stack, Some((0, 1))),
+    /* 0x75 */ OpcodeInfo::new("OP_DROP", Category::Stack, Some((1, 0))),
+    /* 0x76 */ OpcodeInfo::new("OP_DUP", Category::Stack, Some((1, 2))),
+    /* 0x77 */ OpcodeInfo::new("OP_NIP", Category::Stack, Some((2, 1))),
+    /* 0x78 */ OpcodeInfo::new("OP_OVER", Category::Stack, Some((2, 3))),
+    /* 0x79 */ OpcodeInfo::new("OP_PICK", Category::Stack, None),
+    /* 0x7a */ OpcodeInfo::new("OP_ROLL", Category::Stack, None),
+    /* 0x7b */ OpcodeInfo::new("OP_ROT", Category::Stack, Some((3, 3))),
+    /* 0x7c */ OpcodeInfo::new("OP_SWAP", Category::Stack, Some((2, 2))),
+    /* 0x7d */ OpcodeInfo::new("OP_TUCK", Category::Stack, Some((2, 3))),
+    /* 0x7e */ OpcodeInfo::new("OP_CAT", Category::Splice, Some((2, 1))).disabled().legacy(Behaviour::AlwaysInvalid).tapscript(Behaviour::Success),
+    /* 0x7f */ OpcodeInfo::new("OP_SUBSTR", Category::Splice, Some((3, 1))).disabled().legacy(Behaviour::AlwaysInvalid).tapscript(Behaviour::Success),
+    /* 0x80 */ OpcodeInfo::new("OP_LEFT", Category::Splice, Some((2, 1))).disabled().legacy(Behaviour::AlwaysInvalid).tapscript(Behaviour::Success),
+    /* 0x81 */ OpcodeInfo::new("OP_RIGHT", Category::Splice, Some((2, 1))).disabled().legacy(Behaviour::AlwaysInvalid).tapscript(Behaviour::Success),
+    /* 0x82 */ OpcodeInfo::new("OP_SIZE", Category::Splice, Some((1, 2))),
+    /* 0x83 */ OpcodeInfo::new("OP_INVERT", Category::Bitwise, Some((1, 1))).disabled().legacy(Behaviour::AlwaysInvalid).tapscript(Behaviour::Success),
+    /* 0x84 */ OpcodeInfo::new("OP_AND", Category::Bitwise, Some((2, 1))).disabled().legacy(Behaviour::AlwaysInvalid).tapscript(Behaviour::Success),
+    /* 0x85 */ OpcodeInfo::new("OP_OR", Category::Bitwise, Some((2, 1))).disabled().legacy(Behaviour::AlwaysInvalid).tapscript(Behaviour::Success),
+    /* 0x86 */ OpcodeInfo::new("OP_XOR", Category::Bitwise, Some((2, 1))).disabled().legacy(Behaviour::AlwaysInvalid).tapscript(Behaviour::Success),
+    /* 0x87 */ OpcodeInfo::new("OP_EQUAL", Category::Bitwise, Some((2, 1))),
+    /* 0x88 */ OpcodeInfo::new("OP_EQUALVERIFY", Category::Bitwise, Some((2, 0))),
+    /* 0x89 */ OpcodeInfo::new("OP_RESERVED1", Category::Reserved, Some((0, 0))).legacy(Behaviour::Invalid).tapscript(Behaviour::Success),
+    /* 0x8a */ OpcodeInfo::new("OP_RESERVED2", Category::Reserved, Some((0, 0))).legacy(Behaviour::Invalid).tapscript(Behaviour::Success),
+    /* 0x8b */ OpcodeInfo::new("OP_1ADD", Category::Arithmetic, Some((1, 1))),
+    /* 0x8c */ OpcodeInfo::new("OP_1SUB", Category::Arithmetic, Some((1, 1))),
+    /* 0x8d */ OpcodeInfo::new("OP_2MUL", Category::Arithmetic, Some((1, 1))).disabled().legacy(Behaviour::AlwaysInvalid).tapscript(Behaviour::Success),
+    /* 0x8e */ OpcodeInfo::new("OP_2DIV", Category::Arithmetic, Some((1, 1))).disabled().legacy(Behaviour::AlwaysInvalid).tapscript(Behaviour::Success),
+    /* 0x8f */ OpcodeInfo::new("OP_NEGATE", Category::Arithmetic, Some((1, 1))),
+    /* 0x90 */ OpcodeInfo::new("OP_ABS", Category::Arithmetic, Some((1, 1))),
+    /* 0x91 */ OpcodeInfo::new("OP_NOT", Category::Arithmetic, Some((1, 1))),
+    /* 0x92 */ OpcodeInfo::new("OP_0NOTEQUAL", Category::Arithmetic, Some((1, 1))),
+    /* 0x93 */ OpcodeInfo::new("OP_ADD", Category::Arithmetic, Some((2, 1))),
+    /* 0x94 */ OpcodeInfo::new("OP_SUB", Category::Arithmetic, Some((2, 1))),
+    /* 0x95 */ OpcodeInfo::new("OP_MUL", Category::Arithmetic, Some((2, 1))).disabled().legacy(Behaviour::AlwaysInvalid).tapscript(Behaviour::Success),
+    /* 0x96 */ OpcodeInfo::new("OP_DIV", Category::Arithmetic, Some((2, 1))).disabled().legacy(Behaviour::AlwaysInvalid).tapscript(Behaviour::Success),
+    /* 0x97 */ OpcodeInfo::new("OP_MOD", Category::Arithmetic, Some((2, 1))).disabled().legacy(Behaviour::AlwaysInvalid).tapscript(Behaviour::Success),
+    /* 0x98 */ OpcodeInfo::new("OP_LSHIFT", Category::Arithmetic, Some((2, 1))).disabled().legacy(Behaviour::AlwaysInvalid).tapscript(Behaviour::Success),
+    /* 0x99 */ OpcodeInfo::new("OP_RSHIFT", Category::Arithmetic, Some((2, 1))).disabled().legacy(Behaviour::AlwaysInvalid).tapscript(Behaviour::Success),
+    /* 0x9a */ OpcodeInfo::new("OP_BOOLAND", Category::Arithmetic, Some((2, 1))),
+    /* 0x9b */ OpcodeInfo::new("OP_BOOLOR", Category::Arithmetic, Some((2, 1))),
+    /* 0x9c */ OpcodeInfo::new("OP_NUMEQUAL", Category::Arithmetic, Some((2, 1))),
+    /* 0x9d */ OpcodeInfo::new("OP_NUMEQUALVERIFY", Category::Arithmetic, Some((2, 0))),
+    /* 0x9e */ OpcodeInfo::new("OP_NUMNOTEQUAL", Category::Arithmetic, Some((2, 1))),
+    /* 0x9f */ OpcodeInfo::new("OP_LESSTHAN", Category::Arithmetic, Some((2, 1))),
+    /* 0xa0 */ OpcodeInfo::new("OP_GREATERTHAN", Category::Arithmetic, Some((2, 1))),
+    /* 0xa1 */ OpcodeInfo::new("OP_LESSTHANOREQUAL", Category::Arithmetic, Some((2, 1))),
+    /* 0xa2 */ OpcodeInfo::new("OP_GREATERTHANOREQUAL", Category::Arithmetic, Some((2, 1))),
+    /* 0xa3 */ OpcodeInfo::new("OP_MIN", Category::Arithmetic, Some((2, 1))),
+    /* 0xa4 */ OpcodeInfo::new("OP_MAX", Category::Arithmetic, Some((2, 1))),
+    /* 0xa5 */ OpcodeInfo::new("OP_WITHIN", Category::Arithmetic, Some((3, 1))),
+    /* 0xa6 */ OpcodeInfo::new("OP_RIPEMD160", Category::Crypto, Some((1, 1))),
+    /* 0xa7 */ OpcodeInfo::new("OP_SHA1", Category::Crypto, Some((1, 1))),
+    /* 0xa8 */ OpcodeInfo::new("OP_SHA256", Category::Crypto, Some((1, 1))),
+    /* 0xa9 */ OpcodeInfo::new("OP_HASH160", Category::Crypto, Some((1, 1))),
+    /* 0xaa */ OpcodeInfo::new("OP_HASH256", Category::Crypto, Some((1, 1))),
+    /* 0xab */ OpcodeInfo::new("OP_CODESEPARATOR", Category::Crypto, Some((0, 0))),
+    /* 0xac */ OpcodeInfo::new("OP_CHECKSIG", Category::Crypto, Some((2, 1))),
+    /* 0xad */ OpcodeInfo::new("OP_CHECKSIGVERIFY", Category::Crypto, Some((2, 0))),
+    /* 0xae */ OpcodeInfo::new("OP_CHECKMULTISIG", Category::Crypto, None).tapscript(Behaviour::Invalid),
+    /* 0xaf */ OpcodeInfo::new("OP_CHECKMULTISIGVERIFY", Category::Crypto, None).tapscript(Behaviour::Invalid),
+    /* 0xb0 */ OpcodeInfo::new("OP_NOP1", Category::Reserved, Some((0, 0))).legacy(Behaviour::UpgradableNop),
+    /* 0xb1 */ OpcodeInfo::new("OP_CHECKLOCKTIMEVERIFY", Category::Locktime, Some((1, 1))).aliases(&["OP_NOP2"]),
+    /* 0xb2 */ OpcodeInfo::new("OP_CHECKSEQUENCEVERIFY", Category::Locktime, Some((1, 1))).aliases(&["OP_NOP3"]),
+    /* 0xb3 */ OpcodeInfo::new("OP_NOP4", Category::Reserved, Some((0, 0))).legacy(Behaviour::UpgradableNop),
+    /* 0xb4 */ OpcodeInfo::new("OP_NOP5", Category::Reserved, Some((0, 0))).legacy(Behaviour::UpgradableNop),
+    /* 0xb5 */ OpcodeInfo::new("OP_NOP6", Category::Reserved, Some((0, 0))).legacy(Behaviour::UpgradableNop),
+    /* 0xb6 */ OpcodeInfo::new("OP_NOP7", Category::Reserved, Some((0, 0))).legacy(Behaviour::UpgradableNop),
+    /* 0xb7 */ OpcodeInfo::new("OP_NOP8", Category::Reserved, Some((0, 0))).legacy(Behaviour::UpgradableNop),
+    /* 0xb8 */ OpcodeInfo::new("OP_NOP9", Category::Reserved, Some((0, 0))).legacy(Behaviour::UpgradableNop),
+    /* 0xb9 */ OpcodeInfo::new("OP_NOP10", Category::Reserved, Some((0, 0))).legacy(Behaviour::UpgradableNop),
+    /* 0xba */ OpcodeInfo::new("OP_CHECKSIGADD", Category::Crypto, Some((3, 1))).legacy(Behaviour::Invalid).tapscript(Behaviour::Normal),
+    /* 0xbb */ OpcodeInfo::new("OP_UNASSIGNED_187", Category::Unassigned, Some((0, 0))).legacy(Behaviour::Invalid).tapscript(Behaviour::Success),
+    /* 0xbc */ OpcodeInfo::new("OP_UNASSIGNED_188", Category::Unassigned, Some((0, 0))).legacy(Behaviour::Invalid).tapscript(Behaviour::Success),
+    /* 0xbd */ OpcodeInfo::new("OP_UNASSIGNED_189", Category::Unassigned, Some((0, 0))).legacy(Behaviour::Invalid).tapscript(Behaviour::Success),
+    /* 0xbe */ OpcodeInfo::new("OP_UNASSIGNED_190", Category::Unassigned, Some((0, 0))).legacy(Behaviour::Invalid).tapscript(Behaviour::Success),
+    /* 0xbf */ OpcodeInfo::new("OP_UNASSIGNED_191", Category::Unassigned, Some((0, 0))).legacy(Behaviour::Invalid).tapscript(Behaviour::Success),
+    /* 0xc0 */ OpcodeInfo::new("OP_UNASSIGNED_192", Category::Unassigned, Some((0, 0))).legacy(Behaviour::Invalid).tapscript(Behaviour::Success),
+    /* 0xc1 */ OpcodeInfo::new("OP_UNASSIGNED_193", Category::Unassigned, Some((0, 0))).legacy(Behaviour::Invalid).tapscript(Behaviour::Success),
+    /* 0xc2 */ OpcodeInfo::new("OP_UNASSIGNED_194", Category::Unassigned, Some((0, 0))).legacy(Behaviour::Invalid).tapscript(Behaviour::Success),
+    /* 0xc3 */ OpcodeInfo::new("OP_UNASSIGNED_195", Category::Unassigned, Some((0, 0))).legacy(Behaviour::Invalid).tapscript(Behaviour::Success),
+    /* 0xc4 */ OpcodeInfo::new("OP_UNASSIGNED_196", Category::Unassigned, Some((0, 0))).legacy(Behaviour::Invalid).tapscript(Behaviour::Success),
+    /* 0xc5 */ OpcodeInfo::new("OP_UNASSIGNED_197", Category::Unassigned, Some((0, 0))).legacy(Behaviour::Invalid).tapscript(Behaviour::Success),
+    /* 0xc6 */ OpcodeInfo::new("OP_UNASSIGNED_198", Category::Unassigned, Some((0, 0))).legacy(Behaviour::Invalid).tapscript(Behaviour::Success),
+    /* 0xc7 */ OpcodeInfo::new("OP_UNASSIGNED_199", Category::Unassigned, Some((0, 0))).legacy(Behaviour::Invalid).tapscript(Behaviour::Success),
+    /* 0xc8 */ OpcodeInfo::new("OP_UNASSIGNED_200", Category::Unassigned, Some((0, 0))).legacy(Behaviour::Invalid).tapscript(Behaviour::Success),
+    /* 0xc9 */ OpcodeInfo::new("OP_UNASSIGNED_201", Category::Unassigned, Some((0, 0))).legacy(Behaviour::Invalid).tapscript(Behaviour::Success),
+    /* 0xca */ OpcodeInfo::new("OP_UNASSIGNED_202", Category::Unassigned, Some((0, 0))).legacy(Behaviour::Invalid).tapscript(Behaviour::Success),
+    /* 0xcb */ OpcodeInfo::new("OP_UNASSIGNED_203", Category::Unassigned, Some((0, 0))).legacy(Behaviour::Invalid).tapscript(Behaviour::Success),
+    /* 0xcc */ OpcodeInfo::new("OP_UNASSIGNED_204", Category::Unassigned, Some((0, 0))).legacy(Behaviour::Invalid).tapscript(Behaviour::Success),
+    /* 0xcd */ OpcodeInfo::new("OP_UNASSIGNED_205", Category::Unassigned, Some((0, 0))).legacy(Behaviour::Invalid).tapscript(Behaviour::Success),
+    /* 0xce */ OpcodeInfo::new("OP_UNASSIGNED_206", Category::Unassigned, Some((0, 0))).legacy(Behaviour::Invalid).tapscript(Behaviour::Success),
+    /* 0xcf */ OpcodeInfo::new("OP_UNASSIGNED_207", Category::Unassigned, Some((0, 0))).legacy(Behaviour::Invalid).tapscript(Behaviour::Success),
+    /* 0xd0 */ OpcodeInfo::new("OP_UNASSIGNED_208", Category::Unassigned, Some((0, 0))).legacy(Behaviour::Invalid).tapscript(Behaviour::Success),
+    /* 0xd1 */ OpcodeInfo::new("OP_UNASSIGNED_209", Category::Unassigned, Some((0, 0))).legacy(Behaviour::Invalid).tapscript(Behaviour::Success),
+    /* 0xd2 */ OpcodeInfo::new("OP_UNASSIGNED_210", Category::Unassigned, Some((0, 0))).legacy(Behaviour::Invalid).tapscript(Behaviour::Success),
+    /* 0xd3 */ OpcodeInfo::new("OP_UNASSIGNED_211", Category::Unassigned, Some((0, 0))).legacy(Behaviour::Invalid).tapscript(Behaviour::Success),
+    /* 0xd4 */ OpcodeInfo::new("OP_UNASSIGNED_212", Category::Unassigned, Some((0, 0))).legacy(Behaviour::Invalid).tapscript(Behaviour::Success),
+    /* 0xd5 */ OpcodeInfo::new("OP_UNASSIGNED_213", Category::Unassigned, Some((0, 0))).legacy(Behaviour::Invalid).tapscript(Behaviour::Success),
+    /* 0xd6 */ OpcodeInfo::new("OP_UNASSIGNED_214", Category::Unassigned, Some((0, 0))).legacy(Behaviour::Invalid).tapscript(Behaviour::Success),
+    /* 0xd7 */ OpcodeInfo::new("OP_UNASSIGNED_215", Category::Unassigned, Some((0, 0))).legacy(Behaviour::Invalid).tapscript(Behaviour::Success),
+    /* 0xd8 */ OpcodeInfo::new("OP_UNASSIGNED_216", Category::Unassigned, Some((0, 0))).legacy(Behaviour::Invalid).tapscript(Behaviour::Success),
+    /* 0xd9 */ OpcodeInfo::new("OP_UNASSIGNED_217", Category::Unassigned, Some((0, 0))).legacy(Behaviour::Invalid).tapscript(Behaviour::Success),
+    /* 0xda */ OpcodeInfo::new("OP_UNASSIGNED_218", Category::Unassigned, Some((0, 0))).legacy(Behaviour::Invalid).tapscript(Behaviour::Success),
+    /* 0xdb */ OpcodeInfo::new("OP_UNASSIGNED_219", Category::Unassigned, Some((0, 0))).legacy(Behaviour::Invalid).tapscript(Behaviour::Success),
+    /* 0xdc */ OpcodeInfo::new("OP_UNASSIGNED_220", Category::Unassigned, Some((0, 0))).legacy(Behaviour::Invalid).tapscript(Behaviour::Success),
+    /* 0xdd */ OpcodeInfo::new("OP_UNASSIGNED_221", Category::Unassigned, Some((0, 0))).legacy(Behaviour::Invalid).tapscript(Behaviour::Success),
+    /* 0xde */ OpcodeInfo::new("OP_UNASSIGNED_222", Category::Unassigned, Some((0, 0))).legacy(Behaviour::Invalid).tapscript(Behaviour::Success),
+    /* 0xdf */ OpcodeInfo::new("OP_UNASSIGNED_223", Category::Unassigned, Some((0, 0))).legacy(Behaviour::Invalid).tapscript(Behaviour::Success),
+    /* 0xe0 */ OpcodeInfo::new("OP_UNASSIGNED_224", Category::Unassigned, Some((0, 0))).legacy(Behaviour::Invalid).tapscript(Behaviour::Success),
+    /* 0xe1 */ OpcodeInfo::new("OP_UNASSIGNED_225", Category::Unassigned, Some((0, 0))).legacy(Behaviour::Invalid).tapscript(Behaviour::Success),
+    /* 0xe2 */ OpcodeInfo::new("OP_UNASSIGNED_226", Category::Unassigned, Some((0, 0))).legacy(Behaviour::Invalid).tapscript(Behaviour::Success),
+    /* 0xe3 */ OpcodeInfo::new("OP_UNASSIGNED_227", Category::Unassigned, Some((0, 0))).legacy(Behaviour::Invalid).tapscript(Behaviour::Success),
+    /* 0xe4 */ OpcodeInfo::new("OP_UNASSIGNED_228", Category::Unassigned, Some((0, 0))).legacy(Behaviour::Invalid).tapscript(Behaviour::Success),
+    /* 0xe5 */ OpcodeInfo::new("OP_UNASSIGNED_229", Category::Unassigned, Some((0, 0))).legacy(Behaviour::Invalid).tapscript(Behaviour::Success),
+    /* 0xe6 */ OpcodeInfo::new("OP_UNASSIGNED_230", Category::Unassigned, Some((0, 0))).legacy(Behaviour::Invalid).tapscript(Behaviour::Success),
+    /* 0xe7 */ OpcodeInfo::new("OP_UNASSIGNED_231", Category::Unassigned, Some((0, 0))).legacy(Behaviour::Invalid).tapscript(Behaviour::Success),
+    /* 0xe8 */ OpcodeInfo::new("OP_UNASSIGNED_232", Category::Unassigned, Some((0, 0))).legacy(Behaviour::Invalid).tapscript(Behaviour::Success),
+    /* 0xe9 */ OpcodeInfo::new("OP_UNASSIGNED_233", Category::Unassigned, Some((0, 0))).legacy(Behaviour::Invalid).tapscript(Behaviour::Success),
+    /* 0xea */ OpcodeInfo::new("OP_UNASSIGNED_234", Category::Unassigned, Some((0, 0))).legacy(Behaviour::Invalid).tapscript(Behaviour::Success),
+    /* 0xeb */ OpcodeInfo::new("OP_UNASSIGNED_235", Category::Unassigned, Some((0, 0))).legacy(Behaviour::Invalid).tapscript(Behaviour::Success),
+    /* 0xec */ OpcodeInfo::new("OP_UNASSIGNED_236", Category::Unassigned, Some((0, 0))).legacy(Behaviour::Invalid).tapscript(Behaviour::Success),
+    /* 0xed */ OpcodeInfo::new("OP_UNASSIGNED_237", Category::Unassigned, Some((0, 0))).legacy(Behaviour::Invalid).tapscript(Behaviour::Success),
+    /* 0xee */ OpcodeInfo::new("OP_UNASSIGNED_238", Category::Unassigned, Some((0, 0))).legacy(Behaviour::Invalid).tapscript(Behaviour::Success),
+    /* 0xef */ OpcodeInfo::new("OP_UNASSIGNED_239", Category::Unassigned, Some((0, 0))).legacy(Behaviour::Invalid).tapscript(Behaviour::Success),
+    /* 0xf0 */ OpcodeInfo::new("OP_UNASSIGNED_240", Category::Unassigned, Some((0, 0))).legacy(Behaviour::Invalid).tapscript(Behaviour::Success),
+    /* 0xf1 */ OpcodeInfo::new("OP_UNASSIGNED_241", Category::Unassigned, Some((0, 0))).legacy(Behaviour::Invalid).tapscript(Behaviour::Success),
+    /* 0xf2 */ OpcodeInfo::new("OP_UNASSIGNED_242", Category::Unassigned, Some((0, 0))).legacy(Behaviour::Invalid).tapscript(Behaviour::Success),
+    /* 0xf3 */ OpcodeInfo::new("OP_UNASSIGNED_243", Category::Unassigned, Some((0, 0))).legacy(Behaviour::Invalid).tapscript(Behaviour::Success),
+    /* 0xf4 */ OpcodeInfo::new("OP_UNASSIGNED_244", Category::Unassigned, Some((0, 0))).legacy(Behaviour::Invalid).tapscript(Behaviour::Success),
+    /* 0xf5 */ OpcodeInfo::new("OP_UNASSIGNED_245", Category::Unassigned, Some((0, 0))).legacy(Behaviour::Invalid).tapscript(Behaviour::Success),
+    /* 0xf6 */ OpcodeInfo::new("OP_UNASSIGNED_246", Category::Unassigned, Some((0, 0))).legacy(Behaviour::Invalid).tapscript(Behaviour::Success),
+    /* 0xf7 */ OpcodeInfo::new("OP_UNASSIGNED_247", Category::Unassigned, Some((0, 0))).legacy(Behaviour::Invalid).tapscript(Behaviour::Success),
+    /* 0xf8 */ OpcodeInfo::new("OP_UNASSIGNED_248", Category::Unassigned, Some((0, 0))).legacy(Behaviour::Invalid).tapscript(Behaviour::Success),
+    /* 0xf9 */ OpcodeInfo::new("OP_UNASSIGNED_249", Category::Unassigned, Some((0, 0))).legacy(Behaviour::Invalid).tapscript(Behaviour::Success),
+    /* 0xfa */ OpcodeInfo::new("OP_UNASSIGNED_250", Category::Unassigned, Some((0, 0))).legacy(Behaviour::Invalid).tapscript(Behaviour::Success),
+    /* 0xfb */ OpcodeInfo::new("OP_UNASSIGNED_251", Category::Unassigned, Some((0, 0))).legacy(Behaviour::Invalid).tapscript(Behaviour::Success),
+    /* 0xfc */ OpcodeInfo::new("OP_UNASSIGNED_252", Category::Unassigned, Some((0, 0))).legacy(Behaviour::Invalid).tapscript(Behaviour::Success),
+    /* 0xfd */ OpcodeInfo::new("OP_UNASSIGNED_253", Category::Unassigned, Some((0, 0))).legacy(Behaviour::Invalid).tapscript(Behaviour::Success),
+    /* 0xfe */ OpcodeInfo::new("OP_UNASSIGNED_254", Category::Unassigned, Some((0, 0))).legacy(Behaviour::Invalid).tapscript(Behaviour::Success),
+    /* 0xff */ OpcodeInfo::new("OP_UNASSIGNED_255", Category::Unassigned, Some((0, 0))).legacy(Behaviour::Invalid),
+];
+
+impl Opcode {
+    pub fn info(&self) -> &'static OpcodeInfo {
+        &OPCODE_TABLE[self.code as usize]
+    }
+
+    pub fn category(&self) -> Category {
+        self.info().category
+    }
+
+    pub fn stack_effect(&self) -> Option<(usize, usize)> {
+        self.info().stack_effect
+    }
+
+    pub fn counts_toward_op_limit(&self) -> bool {
+        self.info().counts_toward_op_limit
+    }
+
+    pub fn is_disabled(&self) -> bool {
+        self.info().disabled
+    }
+
+    pub fn behaviour(&self, context: ScriptContext) -> Behaviour {
+        self.info().behaviour(context)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn names_round_trip() {
+        for code in 0..=255u8 {
+            let op = Opcode::from(code);
+            assert_eq!(op.to_string().parse::<Opcode>(), Ok(op));
+        }
+        assert_eq!("OP_TRUE".parse(), Ok(OP_1));
+        assert_eq!("OP_NOP2".parse(), Ok(OP_CHECKLOCKTIMEVERIFY));
+        assert_eq!("checksig".parse(), Ok(OP_CHECKSIG));
+        assert_eq!("OP_PUSHBYTES_20".parse::<Opcode>().map(|op| op.code), Ok(20));
+        assert_eq!("OP_FOO".parse::<Opcode>(), Err(String::from("Unknown opcode: OP_FOO")));
+    }
+
+    #[test]
+    fn op_limit_counts_opcodes_above_op_16() {
+        for code in 0..=255u8 {
+            assert_eq!(Opcode::from(code).counts_toward_op_limit(), code > 0x60, "0x{:02x}", code);
+        }
+    }
+
+    #[test]
+    fn disabled_opcodes() {
+        let disabled: Vec<u8> = (0..=255u8).filter(|c| Opcode::from(*c).is_disabled()).collect();
+        assert_eq!(disabled, vec![0x7e, 0x7f, 0x80, 0x81, 0x83, 0x84, 0x85, 0x86, 0x8d, 0x8e, 0x95, 0x96, 0x97, 0x98, 0x99]);
+        assert_eq!(OP_CAT.category(), Category::Splice);
+    }
+
+    #[test]
+    fn behaviours() {
+        // OP_SUCCESSx from BIP342
+        let success: Vec<u8> = (0..=255u8).filter(|c| Opcode::from(*c).behaviour(ScriptContext::Tapscript) == Behaviour::Success).collect();
+        let mut expected = vec![80, 98, 126, 127, 128, 129, 131, 132, 133, 134, 137, 138, 141, 142, 149, 150, 151, 152, 153];
+        expected.extend(187..=254);
+        assert_eq!(success, expected);
+
+        assert_eq!(OP_VERIF.behaviour(ScriptContext::Legacy), Behaviour::AlwaysInvalid);
+        assert_eq!(OP_VERNOTIF.behaviour(ScriptContext::Tapscript), Behaviour::AlwaysInvalid);
+        assert_eq!(OP_RESERVED.behaviour(ScriptContext::Legacy), Behaviour::Invalid);
+        assert_eq!(OP_NOP1.behaviour(ScriptContext::Legacy), Behaviour::UpgradableNop);
+        assert_eq!(OP_CHECKSIGADD.behaviour(ScriptContext::Legacy), Behaviour::Invalid);
+        assert_eq!(OP_CHECKSIGADD.behaviour(ScriptContext::Tapscript), Behaviour::Normal);
+        assert_eq!(OP_CHECKMULTISIG.behaviour(ScriptContext::Tapscript), Behaviour::Invalid);
+    }
+
+    #[test]
+    fn stack_effects() {
+        assert_eq!(OP_2DUP.stack_effect(), Some((2, 4)));
+        assert_eq!(OP_CHECKSIG.stack_effect(), Some((2, 1)));
+        assert_eq!(OP_PICK.stack_effect(), None);
+        assert_eq!(OP_CHECKMULTISIG.stack_effect(), None);
+        assert_eq!(OP_WITHIN.category().to_string(), "arithmetic");
+    }
+}
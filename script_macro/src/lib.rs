@@ -0,0 +1,149 @@
+// script! and script_bytes! macros: Bitcoin Script written inline, with opcode names checked
+// against the interpreter's opcode table at compile time
+//
+//     script! { OP_DUP OP_HASH160 <0x8b6305816c87626a9ac4b972c5c376663b2f5dc8> OP_EQUALVERIFY OP_CHECKSIG }
+//     script! { <2> <key_a> <key_b> <2> OP_CHECKMULTISIG }
+//
+// Inside <...>: 0x<hex> data, decimal numbers (minimal OP_N or Script Number), string literals
+// pushed as bytes, or any Rust expression implementing btc_script_interpreter::builder::Push
+use proc_macro::{Delimiter, Group, Ident, Literal, Punct, Spacing, Span, TokenStream, TokenTree};
+
+use btc_script_opcodes::{Opcode, OP_PUSH_DATA_4};
+
+enum Item {
+    Opcode(u8),
+    Data(Vec<u8>),
+    Number(i64),
+    Expr(TokenStream)
+}
+
+type Error = (Span, String);
+
+fn literal(lit: &Literal, negative: bool) -> Result<Item, Error> {
+    let text = lit.to_string().replace('_', "");
+    let error = |message: String| (lit.span(), message);
+    if let Some(digits) = text.strip_prefix("0x") {
+        if negative {
+            return Err(error(String::from("Data pushes can't be negative")))
+        }
+        return hex::decode(digits).map(Item::Data).map_err(|_| error(format!("Invalid hex push 0x{}", digits)))
+    }
+    if let Some(string) = text.strip_prefix('"').and_then(|t| t.strip_suffix('"')) {
+        if negative || string.contains('\\') {
+            return Err(error(String::from("Only string literals without escapes can be pushed")))
+        }
+        return Ok(Item::Data(string.as_bytes().to_vec()))
+    }
+    match text.parse::<i64>() {
+        Ok(n) => Ok(Item::Number(if negative { -n } else { n })),
+        Err(_) => Err(error(format!("Expected a number, 0x<hex> data or a string, got {}", text)))
+    }
+}
+
+// Everything up to the > closing a push, nested groups are single tokens. The < and > of generics
+// are balanced, and the > of -> and => is part of the arrow: <Vec::<u8>::new()> and <|x| -> u8 { x }>
+// are single pushes, comparisons go in parentheses: <(a > b)>
+fn push(tokens: &mut impl Iterator<Item = TokenTree>, open: Span) -> Result<Item, Error> {
+    let mut inner: Vec<TokenTree> = Vec::new();
+    let mut depth = 0;
+    loop {
+        let arrow = matches!(inner.last(), Some(TokenTree::Punct(p)) if p.spacing() == Spacing::Joint && "-=".contains(p.as_char()));
+        match tokens.next() {
+            Some(TokenTree::Punct(p)) if p.as_char() == '>' && !arrow && depth == 0 => break,
+            Some(TokenTree::Punct(p)) if p.as_char() == '>' && !arrow => {
+                depth -= 1;
+                inner.push(p.into());
+            }
+            Some(TokenTree::Punct(p)) if p.as_char() == '<' => {
+                depth += 1;
+                inner.push(p.into());
+            }
+            Some(token) => inner.push(token),
+            None => return Err((open, String::from("Unclosed <"))),
+        }
+    }
+    match &inner[..] {
+        [] => Err((open, String::from("Empty push"))),
+        [TokenTree::Literal(lit)] => literal(lit, false),
+        [TokenTree::Punct(minus), TokenTree::Literal(lit)] if minus.as_char() == '-' => literal(lit, true),
+        _ => Ok(Item::Expr(inner.into_iter().collect()))
+    }
+}
+
+fn parse(input: TokenStream) -> Result<Vec<Item>, Error> {
+    let mut tokens = input.into_iter();
+    let mut items = Vec::new();
+    while let Some(token) = tokens.next() {
+        let item = match token {
+            TokenTree::Ident(ident) => {
+                let op = ident.to_string().parse::<Opcode>().map_err(|e| (ident.span(), e))?;
+                if (1..=OP_PUSH_DATA_4.code).contains(&op.code) {
+                    return Err((ident.span(), format!("{} can't be used on its own, write the data as <0x...> instead", op)))
+                }
+                Item::Opcode(op.code)
+            }
+            TokenTree::Literal(lit) => literal(&lit, false)?,
+            TokenTree::Punct(p) if p.as_char() == '-' => match tokens.next() {
+                Some(TokenTree::Literal(lit)) => literal(&lit, true)?,
+                _ => return Err((p.span(), String::from("Expected a number after -")))
+            },
+            TokenTree::Punct(p) if p.as_char() == '<' => push(&mut tokens, p.span())?,
+            other => return Err((other.span(), format!("Unexpected {} in script", other)))
+        };
+        items.push(item);
+    }
+    Ok(items)
+}
+
+fn compile_error(span: Span, message: &str) -> TokenStream {
+    let tokens: Vec<TokenTree> = vec![
+        Ident::new("compile_error", span).into(),
+        Punct::new('!', Spacing::Alone).into(),
+        Group::new(Delimiter::Parenthesis, TokenTree::from(Literal::string(message)).into()).into()
+    ];
+    tokens.into_iter().map(|mut token| { token.set_span(span); token }).collect()
+}
+
+fn code(text: &str) -> TokenStream {
+    text.parse().unwrap()
+}
+
+// { let builder = Builder::new(); let builder = builder.push_...; ... builder.<finish>() }
+fn expand(input: TokenStream, finish: &str) -> TokenStream {
+    let items = match parse(input) {
+        Ok(items) => items,
+        Err((span, message)) => return compile_error(span, &message)
+    };
+    let mut body = code("let builder = ::btc_script_interpreter::builder::Builder::new();");
+    for item in items {
+        body.extend(code("let builder ="));
+        body.extend(match item {
+            Item::Opcode(op) => code(&format!("builder.push_opcode(::btc_script_interpreter::opcodes::Opcode::from({}u8))", op)),
+            Item::Number(n) => code(&format!("builder.push_int({}i64)", n)),
+            Item::Data(data) => code(&format!("builder.push_slice(&{:?})", data)),
+            Item::Expr(expr) => {
+                let mut push = code("::btc_script_interpreter::builder::Push::push_onto");
+                let mut args = code("&");
+                args.extend([TokenTree::from(Group::new(Delimiter::Parenthesis, expr))]);
+                args.extend(code(", builder"));
+                push.extend([TokenTree::from(Group::new(Delimiter::Parenthesis, args))]);
+                push
+            }
+        });
+        body.extend(code(";"));
+    }
+    body.extend(code(&format!("builder.{}()", finish)));
+    TokenTree::from(Group::new(Delimiter::Brace, body)).into()
+}
+
+// Script as a Vec<ScriptItem>
+#[proc_macro]
+pub fn script(input: TokenStream) -> TokenStream {
+    expand(input, "into_script")
+}
+
+// Script serialized to bytes
+#[proc_macro]
+pub fn script_bytes(input: TokenStream) -> TokenStream {
+    expand(input, "into_bytes")
+}
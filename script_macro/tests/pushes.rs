@@ -0,0 +1,23 @@
+use btc_script_interpreter::builder::Builder;
+use btc_script_interpreter::opcodes::OP_EQUAL;
+use btc_script_macro::{script, script_bytes};
+
+#[test]
+fn literals() {
+    assert_eq!(hex::encode(script_bytes! { OP_DUP <0x0102> <"ab"> <17> -1 OP_EQUAL }), "7602010202616201114f87");
+    assert_eq!(script! { <0> OP_EQUAL }, Builder::new().push_int(0).push_opcode(OP_EQUAL).into_script());
+}
+
+#[test]
+fn expressions_with_angle_brackets() {
+    let data: Vec<u8> = vec![0xab, 0xcd];
+    // Generics, closures and comparisons in parentheses are part of the push
+    assert_eq!(script_bytes! { <Vec::<u8>::from([0xab, 0xcd])> OP_EQUAL }, Builder::new().push_slice(&data).push_opcode(OP_EQUAL).into_bytes());
+    assert_eq!(script_bytes! { <[1u8, 2].iter().map(|x| -> u8 { x + 1 }).sum::<u8>()> }, [0x55]);
+    assert_eq!(script_bytes! { <(data.len() > 1) as u8> <std::mem::size_of::<u32>()> }, [0x51, 0x54]);
+}
+
+#[test]
+fn compile_errors() {
+    trybuild::TestCases::new().compile_fail("tests/ui/*.rs");
+}
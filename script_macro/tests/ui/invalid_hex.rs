@@ -0,0 +1,5 @@
+use btc_script_macro::script;
+
+fn main() {
+    let _ = script! { <0x0g> OP_DROP };
+}
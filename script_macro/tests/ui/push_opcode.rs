@@ -0,0 +1,5 @@
+use btc_script_macro::script;
+
+fn main() {
+    let _ = script! { OP_PUSHDATA1 OP_1 };
+}
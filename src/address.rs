@@ -0,0 +1,425 @@
+use std::fmt;
+use std::str::FromStr;
+
+use bitcoin_hashes::{sha256d, Hash};
+
+use crate::builder::Builder;
+use crate::opcodes::*;
+use crate::solver::{solve, Template};
+
+const BASE58_ALPHABET: &[u8] = b"123456789ABCDEFGHJKLMNPQRSTUVWXYZabcdefghijkmnopqrstuvwxyz";
+const BECH32_CHARSET: &[u8] = b"qpzry9x8gf2tvdw0s3jn54khce6mua7l";
+// Checksum constants of BIP 173 (segwit v0) and BIP 350 (v1 and later)
+const BECH32_CONST: u32 = 1;
+const BECH32M_CONST: u32 = 0x2bc830a3;
+const BECH32_MAX_LENGTH: usize = 90;
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum Network {
+    Mainnet,
+    Testnet,
+    Signet,
+    Regtest
+}
+
+impl Network {
+    fn pubkey_hash_prefix(&self) -> u8 {
+        match self {
+            Network::Mainnet => 0x00,
+            _ => 0x6f
+        }
+    }
+
+    fn script_hash_prefix(&self) -> u8 {
+        match self {
+            Network::Mainnet => 0x05,
+            _ => 0xc4
+        }
+    }
+
+    // Human readable part of segwit addresses
+    fn hrp(&self) -> &'static str {
+        match self {
+            Network::Mainnet => "bc",
+            Network::Testnet | Network::Signet => "tb",
+            Network::Regtest => "bcrt"
+        }
+    }
+}
+
+impl fmt::Display for Network {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Network::Mainnet => write!(f, "mainnet"),
+            Network::Testnet => write!(f, "testnet"),
+            Network::Signet => write!(f, "signet"),
+            Network::Regtest => write!(f, "regtest")
+        }
+    }
+}
+
+impl FromStr for Network {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "main" | "mainnet" | "bitcoin" => Ok(Network::Mainnet),
+            "test" | "testnet" => Ok(Network::Testnet),
+            "signet" => Ok(Network::Signet),
+            "regtest" => Ok(Network::Regtest),
+            _ => Err(format!("Unknown network: {}", s))
+        }
+    }
+}
+
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub enum AddressError {
+    InvalidCharacter(char),
+    InvalidChecksum,
+    // Base58 version byte of no known network
+    UnknownPrefix(u8),
+    UnknownHrp(String),
+    InvalidLength,
+    MixedCase,
+    // Bech32 checksum on a v1+ program or Bech32m on a v0 program
+    WrongChecksumVariant,
+    InvalidWitnessVersion(u8),
+    InvalidProgramLength(usize)
+}
+
+impl fmt::Display for AddressError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            AddressError::InvalidCharacter(c) => write!(f, "Invalid character '{}'", c),
+            AddressError::InvalidChecksum => write!(f, "Invalid checksum"),
+            AddressError::UnknownPrefix(prefix) => write!(f, "Unknown address version byte 0x{:02x}", prefix),
+            AddressError::UnknownHrp(hrp) => write!(f, "Unknown segwit address prefix {}", hrp),
+            AddressError::InvalidLength => write!(f, "Invalid address length"),
+            AddressError::MixedCase => write!(f, "Address mixes upper and lower case"),
+            AddressError::WrongChecksumVariant => write!(f, "Segwit v0 addresses use Bech32, later versions Bech32m"),
+            AddressError::InvalidWitnessVersion(v) => write!(f, "Invalid witness version {}", v),
+            AddressError::InvalidProgramLength(len) => write!(f, "Invalid witness program length {}", len)
+        }
+    }
+}
+
+impl std::error::Error for AddressError {}
+
+// Output script and network an address encodes
+// Signet uses the testnet encodings, so signet addresses are decoded as testnet ones
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct Address {
+    pub network: Network,
+    pub script_pubkey: Vec<u8>
+}
+
+impl Address {
+    // Address of an output script, for the templates that have one
+    pub fn from_script(script_pubkey: &[u8], network: Network) -> Option<Address> {
+        match solve(script_pubkey) {
+            Template::PubKeyHash { .. } | Template::ScriptHash { .. } | Template::WitnessV0KeyHash { .. } |
+            Template::WitnessV0ScriptHash { .. } | Template::WitnessV1Taproot { .. } | Template::Anchor |
+            Template::WitnessUnknown { .. } => Some(Address { network, script_pubkey: script_pubkey.to_vec() }),
+            _ => None
+        }
+    }
+}
+
+impl fmt::Display for Address {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let script = &self.script_pubkey;
+        match solve(script) {
+            Template::PubKeyHash { hash } => write!(f, "{}", base58check_encode(self.network.pubkey_hash_prefix(), &hash)),
+            Template::ScriptHash { hash } => write!(f, "{}", base58check_encode(self.network.script_hash_prefix(), &hash)),
+            Template::NonStandard | Template::PubKey { .. } | Template::Multisig { .. } | Template::NullData { .. } => {
+                write!(f, "<no address for {}>", hex::encode(script))
+            }
+            // Witness programs: <version> <program>
+            _ => {
+                let version = if script[0] == OP_0.code { 0 } else { script[0] - OP_1.code + 1 };
+                write!(f, "{}", segwit_encode(self.network.hrp(), version, &script[2..]))
+            }
+        }
+    }
+}
+
+impl FromStr for Address {
+    type Err = AddressError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        // Segwit addresses are recognised by their prefix, anything else is Base58
+        let lower = s.to_lowercase();
+        let segwit_network = [Network::Regtest, Network::Mainnet, Network::Testnet].into_iter()
+            .find(|n| lower.starts_with(&format!("{}1", n.hrp())));
+        if let Some(network) = segwit_network {
+            let (version, program) = segwit_decode(s, network.hrp())?;
+            let script_pubkey = Builder::witness_program(version, &program).into_bytes();
+            return Ok(Address { network, script_pubkey })
+        }
+
+        let payload = base58check_decode(s)?;
+        if payload.len() != 21 {
+            return Err(AddressError::InvalidLength)
+        }
+        let (prefix, hash) = (payload[0], &payload[1..]);
+        for network in [Network::Mainnet, Network::Testnet] {
+            if prefix == network.pubkey_hash_prefix() {
+                return Ok(Address { network, script_pubkey: Builder::p2pkh(hash).into_bytes() })
+            }
+            if prefix == network.script_hash_prefix() {
+                return Ok(Address { network, script_pubkey: Builder::p2sh(hash).into_bytes() })
+            }
+        }
+        Err(AddressError::UnknownPrefix(prefix))
+    }
+}
+
+pub(crate) fn base58check_encode(prefix: u8, payload: &[u8]) -> String {
+    let mut data = vec![prefix];
+    data.extend_from_slice(payload);
+    let checksum = sha256d::Hash::hash(&data);
+    data.extend_from_slice(&checksum[..4]);
+
+    // Big number in base 256 to base 58, leading zero bytes become '1's
+    let mut digits: Vec<u8> = Vec::new();
+    for byte in &data {
+        let mut carry = *byte as u32;
+        for digit in digits.iter_mut() {
+            carry += (*digit as u32) << 8;
+            *digit = (carry % 58) as u8;
+            carry /= 58;
+        }
+        while carry > 0 {
+            digits.push((carry % 58) as u8);
+            carry /= 58;
+        }
+    }
+    let zeros = data.iter().take_while(|b| **b == 0).count();
+    let mut encoded = "1".repeat(zeros);
+    encoded.extend(digits.iter().rev().map(|d| BASE58_ALPHABET[*d as usize] as char));
+    encoded
+}
+
+pub(crate) fn base58check_decode(s: &str) -> Result<Vec<u8>, AddressError> {
+    let mut bytes: Vec<u8> = Vec::new();
+    for c in s.chars() {
+        let mut carry = BASE58_ALPHABET.iter().position(|a| *a as char == c).ok_or(AddressError::InvalidCharacter(c))? as u32;
+        for byte in bytes.iter_mut() {
+            carry += *byte as u32 * 58;
+            *byte = (carry & 0xff) as u8;
+            carry >>= 8;
+        }
+        while carry > 0 {
+            bytes.push((carry & 0xff) as u8);
+            carry >>= 8;
+        }
+    }
+    let zeros = s.chars().take_while(|c| *c == '1').count();
+    let mut data = vec![0; zeros];
+    data.extend(bytes.iter().rev());
+
+    if data.len() < 4 {
+        return Err(AddressError::InvalidLength)
+    }
+    let (payload, checksum) = data.split_at(data.len() - 4);
+    if sha256d::Hash::hash(payload)[..4] != *checksum {
+        return Err(AddressError::InvalidChecksum)
+    }
+    Ok(payload.to_vec())
+}
+
+fn bech32_polymod(values: &[u8]) -> u32 {
+    const GENERATOR: [u32; 5] = [0x3b6a57b2, 0x26508e6d, 0x1ea119fa, 0x3d4233dd, 0x2a1462b3];
+    let mut checksum = 1u32;
+    for value in values {
+        let top = checksum >> 25;
+        checksum = (checksum & 0x1ffffff) << 5 ^ *value as u32;
+        for (i, g) in GENERATOR.iter().enumerate() {
+            if (top >> i) & 1 == 1 {
+                checksum ^= g;
+            }
+        }
+    }
+    checksum
+}
+
+fn hrp_expand(hrp: &str) -> Vec<u8> {
+    let mut values: Vec<u8> = hrp.bytes().map(|b| b >> 5).collect();
+    values.push(0);
+    values.extend(hrp.bytes().map(|b| b & 31));
+    values
+}
+
+// Regroup bits, e.g. bytes into 5 bits groups
+fn convert_bits(data: &[u8], from: u32, to: u32, pad: bool) -> Option<Vec<u8>> {
+    let mut acc = 0u32;
+    let mut bits = 0;
+    let mut result = Vec::new();
+    let max = (1 << to) - 1;
+    for value in data {
+        acc = (acc << from) | *value as u32;
+        bits += from;
+        while bits >= to {
+            bits -= to;
+            result.push(((acc >> bits) & max) as u8);
+        }
+    }
+    if pad && bits > 0 {
+        result.push(((acc << (to - bits)) & max) as u8);
+    } else if !pad && (bits >= from || (acc << (to - bits)) & max != 0) {
+        return None
+    }
+    Some(result)
+}
+
+fn checksum_constant(version: u8) -> u32 {
+    if version == 0 { BECH32_CONST } else { BECH32M_CONST }
+}
+
+fn segwit_encode(hrp: &str, version: u8, program: &[u8]) -> String {
+    let mut data = vec![version];
+    data.extend(convert_bits(program, 8, 5, true).unwrap());
+    let mut values = hrp_expand(hrp);
+    values.extend(&data);
+    values.extend([0; 6]);
+    let polymod = bech32_polymod(&values) ^ checksum_constant(version);
+    data.extend((0..6).map(|i| ((polymod >> (5 * (5 - i))) & 31) as u8));
+
+    let mut encoded = format!("{}1", hrp);
+    encoded.extend(data.iter().map(|d| BECH32_CHARSET[*d as usize] as char));
+    encoded
+}
+
+fn segwit_decode(s: &str, hrp: &str) -> Result<(u8, Vec<u8>), AddressError> {
+    if s.len() > BECH32_MAX_LENGTH {
+        return Err(AddressError::InvalidLength)
+    }
+    if s.to_lowercase() != s && s.to_uppercase() != s {
+        return Err(AddressError::MixedCase)
+    }
+    let s = s.to_lowercase();
+    let separator = s.rfind('1').unwrap();
+    if &s[..separator] != hrp {
+        return Err(AddressError::UnknownHrp(s[..separator].to_string()))
+    }
+    let data = s[separator + 1..].chars()
+        .map(|c| BECH32_CHARSET.iter().position(|b| *b as char == c).map(|p| p as u8).ok_or(AddressError::InvalidCharacter(c)))
+        .collect::<Result<Vec<u8>, AddressError>>()?;
+    if data.len() < 7 {
+        return Err(AddressError::InvalidLength)
+    }
+
+    let version = data[0];
+    if version > 16 {
+        return Err(AddressError::InvalidWitnessVersion(version))
+    }
+    let mut values = hrp_expand(hrp);
+    values.extend(&data);
+    let polymod = bech32_polymod(&values);
+    if polymod != checksum_constant(version) {
+        let other_variant = if version == 0 { BECH32M_CONST } else { BECH32_CONST };
+        return Err(if polymod == other_variant {
+            AddressError::WrongChecksumVariant
+        } else {
+            AddressError::InvalidChecksum
+        })
+    }
+
+    let program = convert_bits(&data[1..data.len() - 6], 5, 8, false).ok_or(AddressError::InvalidLength)?;
+    if !(2..=40).contains(&program.len()) || (version == 0 && program.len() != 20 && program.len() != 32) {
+        return Err(AddressError::InvalidProgramLength(program.len()))
+    }
+    Ok((version, program))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn script(address: &str) -> Result<String, AddressError> {
+        address.parse::<Address>().map(|a| hex::encode(a.script_pubkey))
+    }
+
+    #[test]
+    fn base58check() {
+        let hash = hex::decode("751e76e8199196d454941c45d1b3a323f1433bd6").unwrap();
+        assert_eq!(Address { network: Network::Mainnet, script_pubkey: Builder::p2pkh(&hash).into_bytes() }.to_string(), "1BgGZ9tcN4rm9KBzDn7KprQz87SZ26SAMH");
+        assert_eq!(Address { network: Network::Testnet, script_pubkey: Builder::p2pkh(&hash).into_bytes() }.to_string(), "mrCDrCybB6J1vRfbwM5hemdJz73FwDBC8r");
+        assert_eq!(script("1BgGZ9tcN4rm9KBzDn7KprQz87SZ26SAMH"), Ok(String::from("76a914751e76e8199196d454941c45d1b3a323f1433bd688ac")));
+        // Leading zero bytes are written as '1'
+        assert_eq!(base58check_encode(0, &[0; 20]), "1111111111111111111114oLvT2");
+        assert_eq!(base58check_decode("1111111111111111111114oLvT2"), Ok(vec![0; 21]));
+
+        for network in [Network::Mainnet, Network::Testnet] {
+            let address = Address { network, script_pubkey: Builder::p2sh(&hash).into_bytes() };
+            let encoded = address.to_string();
+            assert!(encoded.starts_with(if network == Network::Mainnet { '3' } else { '2' }));
+            assert_eq!(encoded.parse(), Ok(address));
+        }
+    }
+
+    #[test]
+    fn base58check_errors() {
+        assert_eq!(script("1BgGZ9tcN4rm9KBzDn7KprQz87SZ26SAMJ"), Err(AddressError::InvalidChecksum));
+        assert_eq!(script("1BgGZ9tcN4rm9KBzDn7KprQz87SZ26SAM0"), Err(AddressError::InvalidCharacter('0')));
+        assert_eq!(script("1"), Err(AddressError::InvalidLength));
+        assert_eq!(script(&base58check_encode(0x30, &[0; 20])), Err(AddressError::UnknownPrefix(0x30)));
+        assert_eq!(script(&base58check_encode(0, &[0; 19])), Err(AddressError::InvalidLength));
+    }
+
+    #[test]
+    fn bip173_and_bip350_valid_addresses() {
+        let vectors = [
+            ("BC1QW508D6QEJXTDG4Y5R3ZARVARY0C5XW7KV8F3T4", "0014751e76e8199196d454941c45d1b3a323f1433bd6"),
+            ("tb1qrp33g0q5c5txsp9arysrx4k6zdkfs4nce4xj0gdcccefvpysxf3q0sl5k7", "00201863143c14c5166804bd19203356da136c985678cd4d27a1b8c6329604903262"),
+            ("bc1pw508d6qejxtdg4y5r3zarvary0c5xw7kw508d6qejxtdg4y5r3zarvary0c5xw7kt5nd6y", "5128751e76e8199196d454941c45d1b3a323f1433bd6751e76e8199196d454941c45d1b3a323f1433bd6"),
+            ("BC1SW50QGDZ25J", "6002751e"),
+            ("bc1zw508d6qejxtdg4y5r3zarvaryvaxxpcs", "5210751e76e8199196d454941c45d1b3a323"),
+            ("tb1qqqqqp399et2xygdj5xreqhjjvcmzhxw4aywxecjdzew6hylgvsesrxh6hy", "0020000000c4a5cad46221b2a187905e5266362b99d5e91c6ce24d165dab93e86433"),
+            ("tb1pqqqqp399et2xygdj5xreqhjjvcmzhxw4aywxecjdzew6hylgvsesf3hn0c", "5120000000c4a5cad46221b2a187905e5266362b99d5e91c6ce24d165dab93e86433"),
+            ("bc1p0xlxvlhemja6c4dqv22uapctqupfhlxm9h8z3k2e72q4k9hcz7vqzk5jj0", "512079be667ef9dcbbac55a06295ce870b07029bfcdb2dce28d959f2815b16f81798")
+        ];
+        for (address, script_pubkey) in vectors {
+            let decoded: Address = address.parse().unwrap();
+            assert_eq!(hex::encode(&decoded.script_pubkey), script_pubkey);
+            // Addresses are written in lower case
+            assert_eq!(decoded.to_string(), address.to_lowercase());
+            assert_eq!(Address::from_script(&decoded.script_pubkey, decoded.network), Some(decoded));
+        }
+        let regtest = Address { network: Network::Regtest, script_pubkey: hex::decode("0014751e76e8199196d454941c45d1b3a323f1433bd6").unwrap() };
+        assert_eq!(regtest.to_string().parse(), Ok(regtest));
+    }
+
+    #[test]
+    fn bip350_invalid_addresses() {
+        let vectors = [
+            ("bc1p0xlxvlhemja6c4dqv22uapctqupfhlxm9h8z3k2e72q4k9hcz7vqh2y7hd", AddressError::WrongChecksumVariant),
+            ("tb1z0xlxvlhemja6c4dqv22uapctqupfhlxm9h8z3k2e72q4k9hcz7vqglt7rf", AddressError::WrongChecksumVariant),
+            ("BC1S0XLXVLHEMJA6C4DQV22UAPCTQUPFHLXM9H8Z3K2E72Q4K9HCZ7VQ54WELL", AddressError::WrongChecksumVariant),
+            ("bc1qw508d6qejxtdg4y5r3zarvary0c5xw7kemeawh", AddressError::WrongChecksumVariant),
+            ("tb1q0xlxvlhemja6c4dqv22uapctqupfhlxm9h8z3k2e72q4k9hcz7vq24jc47", AddressError::WrongChecksumVariant),
+            ("bc1p38j9r5y49hruaue7wxjce0updqjuyyx0kh56v8s25huc6995vvpql3jow4", AddressError::InvalidCharacter('o')),
+            ("BC130XLXVLHEMJA6C4DQV22UAPCTQUPFHLXM9H8Z3K2E72Q4K9HCZ7VQ7ZWS8R", AddressError::InvalidWitnessVersion(17)),
+            ("bc1pw5dgrnzv", AddressError::InvalidProgramLength(1)),
+            ("bc1p0xlxvlhemja6c4dqv22uapctqupfhlxm9h8z3k2e72q4k9hcz7v8n0nx0muaewav253zgeav", AddressError::InvalidProgramLength(41)),
+            ("BC1QR508D6QEJXTDG4Y5R3ZARVARYV98GJ9P", AddressError::InvalidProgramLength(16)),
+            ("tb1p0xlxvlhemja6c4dqv22uapctqupfhlxm9h8z3k2e72q4k9hcz7vq47Zagq", AddressError::MixedCase),
+            ("bc1p0xlxvlhemja6c4dqv22uapctqupfhlxm9h8z3k2e72q4k9hcz7v07qwwzcrf", AddressError::InvalidLength),
+            ("tb1p0xlxvlhemja6c4dqv22uapctqupfhlxm9h8z3k2e72q4k9hcz7vpggkg4j", AddressError::InvalidLength),
+            ("bc1gmk9yu", AddressError::InvalidLength)
+        ];
+        for (address, error) in vectors {
+            assert_eq!(script(address), Err(error), "{}", address);
+        }
+        assert_eq!(script("bc1qw508d6qejxtdg4y5r3zarvary0c5xw7kv8f3t5"), Err(AddressError::InvalidChecksum));
+    }
+
+    #[test]
+    fn scripts_without_address() {
+        let p2pk = hex::decode("21031b84c5567b126440995d3ed5aaba0565d71e1834604819ff9c17f5e9d5dd078fac").unwrap();
+        assert_eq!(Address::from_script(&p2pk, Network::Mainnet), None);
+        assert_eq!(Address { network: Network::Mainnet, script_pubkey: vec![0x6a] }.to_string(), "<no address for 6a>");
+        assert_eq!("bitcoin".parse(), Ok(Network::Mainnet));
+        assert!("litecoin".parse::<Network>().is_err());
+    }
+}
@@ -0,0 +1,549 @@
+use crate::opcodes::*;
+use crate::script::*;
+
+// Stop exploring new paths past this number, every OP_IF on an unknown value doubles them
+pub(crate) const MAX_PATHS: usize = 4096;
+// Paths not followed yet are left out once this many items were analysed over all paths
+pub(crate) const MAX_STEPS: usize = 1 << 20;
+
+// OP_IF nesting kept the way Core does: the size and the position of the first false condition,
+// the only things deciding whether opcodes run
+#[derive(Clone, Debug, Default)]
+pub(crate) struct ConditionStack {
+    size: usize,
+    first_false: Option<usize>
+}
+
+impl ConditionStack {
+    pub(crate) fn is_empty(&self) -> bool {
+        self.size == 0
+    }
+
+    pub(crate) fn all_true(&self) -> bool {
+        self.first_false.is_none()
+    }
+
+    pub(crate) fn push(&mut self, condition: bool) {
+        if !condition && self.first_false.is_none() {
+            self.first_false = Some(self.size);
+        }
+        self.size += 1;
+    }
+
+    pub(crate) fn pop(&mut self) -> Result<(), ScriptError> {
+        self.size = self.size.checked_sub(1).ok_or(ScriptError::UnbalancedConditional)?;
+        if self.first_false == Some(self.size) {
+            self.first_false = None;
+        }
+        Ok(())
+    }
+
+    // OP_ELSE
+    pub(crate) fn toggle_top(&mut self) -> Result<(), ScriptError> {
+        let top = self.size.checked_sub(1).ok_or(ScriptError::UnbalancedConditional)?;
+        match self.first_false {
+            None => self.first_false = Some(top),
+            Some(i) if i == top => self.first_false = None,
+            Some(_) => ()
+        }
+        Ok(())
+    }
+}
+
+// OP_IF/OP_NOTIF or OP_IFDUP on an unknown value, the path can go both ways
+enum Fork {
+    Branch,
+    Dup(Value)
+}
+
+// Stack element during the analysis, only constants pushed by the script are known
+#[derive(Clone, Debug, Eq, PartialEq)]
+enum Value {
+    Known(Vec<u8>),
+    Unknown
+}
+
+impl Value {
+    fn number(&self) -> Option<Result<i64, ScriptError>> {
+        match self {
+            Value::Known(bytes) => Some(as_script_nb(bytes)),
+            Value::Unknown => None
+        }
+    }
+}
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum PathOutcome {
+    // The path runs to the end of the script
+    Completes,
+    // The path always fails at the item with this index
+    Fails { index: usize, error: ScriptError },
+    // The stack effect of the item at this index depends on the witness, analysis stops there
+    Unknown { index: usize }
+}
+
+#[derive(Debug, Clone)]
+pub struct PathAnalysis {
+    // Decisions along the path: index of the OP_IF/OP_NOTIF and whether its first branch runs,
+    // or index of the OP_IFDUP and whether it duplicates
+    pub branches: Vec<(usize, bool)>,
+    // Smallest initial stack the path needs to avoid an underflow
+    pub min_initial_items: usize,
+    // Highest number of items on the main and alt stacks, starting with min_initial_items
+    // or the given initial stack size
+    pub max_depth: usize,
+    pub outcome: PathOutcome
+}
+
+impl PathAnalysis {
+    pub fn underflows(&self) -> bool {
+        matches!(self.outcome, PathOutcome::Fails { error: ScriptError::InvalidStackOperation, .. }
+            | PathOutcome::Fails { error: ScriptError::InvalidAltstackOperation, .. })
+    }
+
+    pub fn exceeds_stack_size(&self) -> bool {
+        matches!(self.outcome, PathOutcome::Fails { error: ScriptError::StackSize, .. })
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Analysis {
+    pub paths: Vec<PathAnalysis>,
+    // Whether some paths were left out because of MAX_PATHS or MAX_STEPS
+    pub truncated: bool
+}
+
+impl Analysis {
+    // Fewest initial items needed by a path running to the end
+    pub fn min_initial_items(&self) -> Option<usize> {
+        self.paths.iter()
+            .filter(|p| p.outcome == PathOutcome::Completes)
+            .map(|p| p.min_initial_items)
+            .min()
+    }
+
+    pub fn max_depth(&self) -> usize {
+        self.paths.iter().map(|p| p.max_depth).max().unwrap_or(0)
+    }
+
+    pub fn underflows(&self) -> bool {
+        self.paths.iter().any(PathAnalysis::underflows)
+    }
+
+    pub fn exceeds_stack_size(&self) -> bool {
+        self.paths.iter().any(PathAnalysis::exceeds_stack_size)
+    }
+}
+
+// State of one path through the script
+#[derive(Clone)]
+struct Path {
+    index: usize,
+    main: Vec<Value>,
+    alt: Vec<Value>,
+    condition_stack: ConditionStack,
+    // Initial items used so far, they are added at the bottom of the main stack when needed
+    borrowed: usize,
+    // Initial stack size, None when the witness can provide any number of items
+    initial_items: Option<usize>,
+    // Highest main + alt size minus the borrowed items
+    max_relative_depth: usize,
+    branches: Vec<(usize, bool)>
+}
+
+impl Path {
+    fn depth(&self) -> usize {
+        self.initial_items.unwrap_or(self.borrowed) - self.borrowed + self.main.len() + self.alt.len()
+    }
+
+    // Make sure the main stack holds `n` items, taking the missing ones from the initial stack
+    fn ensure(&mut self, n: usize) -> Result<(), ScriptError> {
+        if self.main.len() >= n {
+            return Ok(())
+        }
+        let missing = n - self.main.len();
+        if self.initial_items.is_some_and(|k| self.borrowed + missing > k) {
+            return Err(ScriptError::InvalidStackOperation)
+        }
+        self.main.splice(0..0, vec![Value::Unknown; missing]);
+        self.borrowed += missing;
+        Ok(())
+    }
+
+    fn pop(&mut self) -> Result<Value, ScriptError> {
+        self.ensure(1)?;
+        Ok(self.main.pop().unwrap())
+    }
+
+    // Element `i` from the top, 0 being the top
+    fn top(&mut self, i: usize) -> Result<Value, ScriptError> {
+        self.ensure(i + 1)?;
+        Ok(self.main[self.main.len() - 1 - i].clone())
+    }
+
+    // Update the highest depth seen, Core checks the stack size after every opcode
+    fn check_depth(&mut self) -> Result<(), ScriptError> {
+        let relative = (self.main.len() + self.alt.len()).saturating_sub(self.borrowed);
+        self.max_relative_depth = self.max_relative_depth.max(relative);
+        if self.depth() > MAX_STACK_SIZE {
+            return Err(ScriptError::StackSize)
+        }
+        Ok(())
+    }
+
+    fn branch(&mut self, condition: bool) {
+        self.branches.push((self.index, condition));
+    }
+
+    // Follow one way of a fork: the first branch of an OP_IF or the duplication of an OP_IFDUP when `taken`
+    fn take(&mut self, fork: &Fork, taken: bool) {
+        self.branch(taken);
+        match fork {
+            Fork::Branch => self.condition_stack.push(taken),
+            Fork::Dup(v) if taken => self.main.push(v.clone()),
+            Fork::Dup(_) => ()
+        }
+    }
+
+    // Analyse one item, returns a fork when the way the path goes depends on the witness
+    fn step(&mut self, item: &ScriptItem) -> Result<Option<Fork>, PathOutcome> {
+        let index = self.index;
+        let fork = self.execute(item).map_err(|e| match e {
+            Some(error) => PathOutcome::Fails { index, error },
+            None => PathOutcome::Unknown { index }
+        })?;
+        self.check_depth().map_err(|error| PathOutcome::Fails { index, error })?;
+        Ok(fork)
+    }
+
+    // Errors are None when the result depends on unknown values
+    fn execute(&mut self, item: &ScriptItem) -> Result<Option<Fork>, Option<ScriptError>> {
+        let executing = self.condition_stack.all_true();
+        let op = match item {
+            ScriptItem::ByteArray(b) if b.len() > MAX_SCRIPT_ELEMENT_SIZE => return Err(Some(ScriptError::PushSize)),
+            ScriptItem::ByteArray(b) => {
+                if executing {
+                    self.main.push(Value::Known(b.clone()));
+                }
+                return Ok(None)
+            }
+            ScriptItem::Opcode(op) => *op
+        };
+
+        if op.is_disabled() {
+            return Err(Some(ScriptError::DisabledOpcode))
+        }
+        match op.behaviour(ScriptContext::Legacy) {
+            Behaviour::AlwaysInvalid => return Err(Some(ScriptError::BadOpcode)),
+            Behaviour::Invalid if executing => return Err(Some(ScriptError::BadOpcode)),
+            _ => ()
+        }
+
+        match op {
+            OP_IF | OP_NOTIF if executing => {
+                let condition = match self.pop()? {
+                    Value::Known(b) => as_bool(&b) != (op == OP_NOTIF),
+                    Value::Unknown => return Ok(Some(Fork::Branch))
+                };
+                self.branch(condition);
+                self.condition_stack.push(condition);
+            }
+            OP_IF | OP_NOTIF => self.condition_stack.push(false),
+            OP_ELSE => self.condition_stack.toggle_top().map_err(Some)?,
+            OP_ENDIF => self.condition_stack.pop().map_err(Some)?,
+            _ if !executing => (),
+
+            OP_0 => self.main.push(Value::Known(to_script_nb(0))),
+            OP_1NEGATE => self.main.push(Value::Known(to_script_nb(-1))),
+            Opcode { code: c } if (OP_1.code..=OP_16.code).contains(&c) => {
+                self.main.push(Value::Known(to_script_nb((c - OP_1.code + 1) as i64)))
+            }
+            OP_VERIFY => {
+                if let Value::Known(b) = self.pop()? {
+                    if !as_bool(&b) {
+                        return Err(Some(ScriptError::Verify))
+                    }
+                }
+            }
+            OP_RETURN => return Err(Some(ScriptError::OpReturn)),
+
+            OP_TOALTSTACK => {
+                let v = self.pop()?;
+                self.alt.push(v);
+            }
+            OP_FROMALTSTACK => {
+                // The alt stack always starts empty
+                let v = self.alt.pop().ok_or(Some(ScriptError::InvalidAltstackOperation))?;
+                self.main.push(v);
+            }
+            OP_DUP => {
+                let v = self.top(0)?;
+                self.main.push(v);
+            }
+            OP_2DUP | OP_3DUP | OP_OVER | OP_2OVER => {
+                let (count, from) = match op {
+                    OP_2DUP => (2, 1),
+                    OP_3DUP => (3, 2),
+                    OP_OVER => (1, 1),
+                    _ => (2, 3)
+                };
+                for _ in 0..count {
+                    let v = self.top(from)?;
+                    self.main.push(v);
+                }
+            }
+            OP_SWAP | OP_2SWAP | OP_ROT | OP_2ROT | OP_NIP | OP_TUCK => {
+                let n = match op {
+                    OP_SWAP | OP_NIP | OP_TUCK => 2,
+                    OP_ROT => 3,
+                    OP_2SWAP => 4,
+                    _ => 6
+                };
+                self.ensure(n)?;
+                let len = self.main.len();
+                match op {
+                    OP_SWAP => self.main.swap(len - 1, len - 2),
+                    OP_2SWAP => self.main[len - 4..].rotate_left(2),
+                    OP_ROT => self.main[len - 3..].rotate_left(1),
+                    OP_2ROT => self.main[len - 6..].rotate_left(2),
+                    OP_NIP => { self.main.remove(len - 2); }
+                    _ => {
+                        let v = self.main[len - 1].clone();
+                        self.main.insert(len - 2, v);
+                    }
+                }
+            }
+            OP_IFDUP => {
+                let v = self.top(0)?;
+                let condition = match &v {
+                    Value::Known(b) => as_bool(b),
+                    Value::Unknown => return Ok(Some(Fork::Dup(v)))
+                };
+                if condition {
+                    self.main.push(v);
+                }
+            }
+            OP_PICK | OP_ROLL => {
+                let n = self.pop()?.number().ok_or(None)?.map_err(Some)?;
+                // The stacks can't hold that many elements
+                if !(0..MAX_STACK_SIZE as i64).contains(&n) {
+                    return Err(Some(ScriptError::InvalidStackOperation))
+                }
+                let v = self.top(n as usize)?;
+                if op == OP_ROLL {
+                    let len = self.main.len();
+                    self.main.remove(len - 1 - n as usize);
+                }
+                self.main.push(v);
+            }
+            OP_SIZE => {
+                let size = match self.top(0)? {
+                    Value::Known(b) => Value::Known(to_script_nb(b.len() as i64)),
+                    Value::Unknown => Value::Unknown
+                };
+                self.main.push(size);
+            }
+            OP_CHECKMULTISIG | OP_CHECKMULTISIGVERIFY => {
+                let keys = self.top(0)?.number().ok_or(None)?.map_err(Some)?;
+                if !(0..=20).contains(&keys) {
+                    return Err(Some(ScriptError::PubkeyCount))
+                }
+                let sigs = self.top(keys as usize + 1)?.number().ok_or(None)?.map_err(Some)?;
+                if sigs < 0 || sigs > keys {
+                    return Err(Some(ScriptError::SigCount))
+                }
+                // Keys, signatures, both counts and the dummy element
+                let n = (keys + sigs) as usize + 3;
+                self.ensure(n)?;
+                self.main.truncate(self.main.len() - n);
+                if op == OP_CHECKMULTISIG {
+                    self.main.push(Value::Unknown);
+                }
+            }
+            _ => {
+                let (inputs, outputs) = op.stack_effect().ok_or(None)?;
+                self.ensure(inputs)?;
+                self.main.truncate(self.main.len() - inputs);
+                for _ in 0..outputs {
+                    self.main.push(Value::Unknown);
+                }
+            }
+        }
+        Ok(None)
+    }
+
+    fn finish(self, outcome: PathOutcome) -> PathAnalysis {
+        let base = self.initial_items.unwrap_or(self.borrowed);
+        PathAnalysis {
+            branches: self.branches,
+            min_initial_items: self.borrowed,
+            max_depth: base + self.max_relative_depth,
+            outcome
+        }
+    }
+}
+
+// Walk every OP_IF/OP_ELSE path of a script and compute its stack requirements.
+// `initial_items` is the size of the stack the script starts with, None when unconstrained
+// (e.g. a witness script)
+pub fn analyze(script: &Script, initial_items: Option<usize>) -> Analysis {
+    let start = Path {
+        index: 0,
+        main: Vec::new(),
+        alt: Vec::new(),
+        condition_stack: ConditionStack::default(),
+        borrowed: 0,
+        initial_items,
+        max_relative_depth: 0,
+        branches: Vec::new()
+    };
+
+    let mut paths = Vec::new();
+    let mut pending = vec![start];
+    let mut truncated = false;
+    let mut steps = 0;
+
+    while let Some(mut path) = pending.pop() {
+        if steps >= MAX_STEPS {
+            truncated = true;
+            break
+        }
+        let outcome = loop {
+            steps += 1;
+            let item = match script.get(path.index) {
+                Some(item) => item,
+                None if path.condition_stack.is_empty() => break PathOutcome::Completes,
+                None => break PathOutcome::Fails { index: path.index, error: ScriptError::UnbalancedConditional }
+            };
+            match path.step(item) {
+                Ok(Some(fork)) => {
+                    if paths.len() + pending.len() + 1 < MAX_PATHS {
+                        let mut other = path.clone();
+                        other.take(&fork, false);
+                        other.index += 1;
+                        pending.push(other);
+                    } else {
+                        truncated = true;
+                    }
+                    path.take(&fork, true);
+                    if let Err(error) = path.check_depth() {
+                        break PathOutcome::Fails { index: path.index, error }
+                    }
+                }
+                Ok(None) => (),
+                Err(outcome) => break outcome
+            }
+            path.index += 1;
+        };
+        paths.push(path.finish(outcome));
+    }
+
+    // Paths were explored last branch first
+    paths.sort_by(|a, b| a.branches.iter().map(|(i, c)| (*i, !*c)).cmp(b.branches.iter().map(|(i, c)| (*i, !*c))));
+    Analysis { paths, truncated }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::asm::parse_asm_script;
+
+    fn analysis(asm: &str, initial_items: Option<usize>) -> Analysis {
+        analyze(&parse_asm_script(asm).unwrap(), initial_items)
+    }
+
+    #[test]
+    fn p2pkh_needs_a_signature_and_a_key() {
+        let analysis = analysis("OP_DUP OP_HASH160 0x751e76e8199196d454941c45d1b3a323f1433bd6 OP_EQUALVERIFY OP_CHECKSIG", None);
+        assert_eq!(analysis.paths.len(), 1);
+        assert_eq!(analysis.paths[0].outcome, PathOutcome::Completes);
+        assert_eq!(analysis.min_initial_items(), Some(2));
+        assert_eq!(analysis.max_depth(), 4);
+        assert!(!analysis.underflows() && !analysis.truncated);
+    }
+
+    #[test]
+    fn branches_on_witness_values() {
+        let analysis = analysis("OP_IF OP_DROP OP_ELSE OP_2DROP OP_ENDIF OP_1", None);
+        let paths: Vec<(Vec<(usize, bool)>, usize)> = analysis.paths.iter().map(|p| (p.branches.clone(), p.min_initial_items)).collect();
+        assert_eq!(paths, vec![(vec![(0, true)], 2), (vec![(0, false)], 3)]);
+        assert_eq!(analysis.min_initial_items(), Some(2));
+
+        // Constants pushed by the script decide the branch
+        let analysis = self::analysis("OP_0 OP_NOTIF OP_2 OP_ELSE OP_RETURN OP_ENDIF", None);
+        assert_eq!(analysis.paths.len(), 1);
+        assert_eq!(analysis.paths[0].outcome, PathOutcome::Completes);
+    }
+
+    #[test]
+    fn failing_paths() {
+        let analysis = analysis("OP_1 OP_VERIFY OP_0 OP_VERIFY", None);
+        assert_eq!(analysis.paths[0].outcome, PathOutcome::Fails { index: 3, error: ScriptError::Verify });
+        assert_eq!(analysis.min_initial_items(), None);
+
+        let analysis = self::analysis("OP_DROP", Some(0));
+        assert_eq!(analysis.paths[0].outcome, PathOutcome::Fails { index: 0, error: ScriptError::InvalidStackOperation });
+        assert!(analysis.underflows());
+        assert!(self::analysis("OP_FROMALTSTACK", None).underflows());
+
+        let analysis = self::analysis("OP_1 OP_IF OP_2", None);
+        assert_eq!(analysis.paths[0].outcome, PathOutcome::Fails { index: 3, error: ScriptError::UnbalancedConditional });
+
+        let analysis = self::analysis(&"OP_1 ".repeat(1001), None);
+        assert!(analysis.exceeds_stack_size());
+    }
+
+    #[test]
+    fn witness_dependent_effects_stop_the_path() {
+        // Known indices borrow the missing items from the witness
+        assert_eq!(analysis("OP_2 OP_PICK OP_DROP", None).min_initial_items(), Some(3));
+        let analysis = self::analysis("OP_2 OP_PICK OP_DROP", Some(2));
+        assert_eq!(analysis.paths[0].outcome, PathOutcome::Fails { index: 1, error: ScriptError::InvalidStackOperation });
+        let analysis = self::analysis("OP_PICK OP_DROP", None);
+        assert_eq!(analysis.paths[0].outcome, PathOutcome::Unknown { index: 0 });
+        assert_eq!(self::analysis("OP_1 OP_2 OP_2 OP_ROLL", Some(1)).paths[0].outcome, PathOutcome::Completes);
+    }
+
+    #[test]
+    fn path_explosion_is_truncated() {
+        let analysis = analysis(&"OP_IF OP_ENDIF ".repeat(13), None);
+        assert!(analysis.truncated);
+        assert!(analysis.paths.len() <= MAX_PATHS);
+        assert!(!self::analysis(&"OP_IF OP_ENDIF ".repeat(11), None).truncated);
+
+        // Every path is as long as the script, the step budget leaves most of them out
+        let nested = [vec![ScriptItem::Opcode(OP_IF); 9999], vec![ScriptItem::Opcode(OP_1)], vec![ScriptItem::Opcode(OP_ENDIF); 9999]].concat();
+        let analysis = analyze(&nested, None);
+        assert!(analysis.truncated);
+        assert!(analysis.paths.len() < 100);
+        assert_eq!(analysis.paths[0].branches.len(), 9999);
+        assert_eq!(analysis.paths[0].outcome, PathOutcome::Completes);
+    }
+
+    #[test]
+    fn conditions_are_counted() {
+        let mut conditions = ConditionStack::default();
+        conditions.push(true);
+        conditions.push(false);
+        conditions.push(true);
+        assert!(!conditions.all_true());
+        assert_eq!(conditions.toggle_top(), Ok(()));
+        conditions.pop().unwrap();
+        assert_eq!(conditions.toggle_top(), Ok(()));
+        assert!(conditions.all_true());
+        conditions.pop().unwrap();
+        conditions.pop().unwrap();
+        assert!(conditions.is_empty());
+        assert_eq!(conditions.pop(), Err(ScriptError::UnbalancedConditional));
+        assert_eq!(conditions.toggle_top(), Err(ScriptError::UnbalancedConditional));
+    }
+
+    #[test]
+    fn huge_pick_and_roll_indices_fail() {
+        for op in ["OP_PICK", "OP_ROLL"] {
+            let analysis = analysis(&format!("0xffffff7f {}", op), None);
+            assert_eq!(analysis.paths[0].outcome, PathOutcome::Fails { index: 1, error: ScriptError::InvalidStackOperation });
+            assert_eq!(analysis.paths[0].min_initial_items, 0);
+        }
+    }
+}
@@ -0,0 +1,277 @@
+use std::fmt;
+
+use crate::opcodes::{Opcode, OP_0, OP_1, OP_1NEGATE, OP_PUSH_DATA_4};
+use crate::script::{encode_push, to_script_nb, Script, ScriptItem};
+
+// Parse error in an ASM source, lines and columns start at 1
+// The file is only known for sources assembled from files
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct AsmError {
+    pub file: Option<String>,
+    pub line: usize,
+    pub column: usize,
+    pub message: String
+}
+
+impl fmt::Display for AsmError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if let Some(file) = &self.file {
+            write!(f, "{}:", file)?;
+        }
+        write!(f, "{}:{}: {}", self.line, self.column, self.message)
+    }
+}
+
+impl std::error::Error for AsmError {}
+
+// Instruction read from an ASM source with its position
+pub struct AsmItem {
+    pub item: ScriptItem,
+    pub line: usize,
+    pub column: usize
+}
+
+// Link between byte offsets of an assembled script and lines of its source
+#[derive(Debug, Clone, Default)]
+pub struct SourceMap {
+    // (path, text) of the files instructions come from, the assembled file first
+    files: Vec<(String, String)>,
+    // (byte offset, line, file, line in that file) of every instruction, sorted by offset
+    // `line` is in the assembled source: for code expanded from a macro or an include, the line using it
+    entries: Vec<(usize, usize, usize, usize)>
+}
+
+// Place where an instruction is written
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub struct SourceLocation<'a> {
+    // None for ASM text that doesn't come from a file
+    pub file: Option<&'a str>,
+    pub line: usize,
+    pub text: Option<&'a str>
+}
+
+impl fmt::Display for SourceLocation<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.file {
+            Some(file) => write!(f, "{}:{}", file, self.line)?,
+            None => write!(f, "line {}", self.line)?
+        }
+        match self.text {
+            Some(text) => write!(f, ": {}", text.trim()),
+            None => Ok(())
+        }
+    }
+}
+
+impl SourceMap {
+    // Instruction written on `line` of the assembled source
+    pub fn push(&mut self, offset: usize, line: usize) {
+        self.entries.push((offset, line, 0, line));
+    }
+
+    // Instruction used on `line` of the assembled source, written on `file_line` of `file`
+    pub fn push_origin(&mut self, offset: usize, line: usize, file: usize, file_line: usize) {
+        self.entries.push((offset, line, file, file_line));
+    }
+
+    // Register a source file, returns its index
+    pub fn add_file(&mut self, path: &str, text: &str) -> usize {
+        self.files.push((path.to_string(), text.to_string()));
+        self.files.len() - 1
+    }
+
+    pub fn files(&self) -> &[(String, String)] {
+        &self.files
+    }
+
+    pub fn entries(&self) -> &[(usize, usize, usize, usize)] {
+        &self.entries
+    }
+
+    fn entry_of(&self, offset: usize) -> Option<&(usize, usize, usize, usize)> {
+        let idx = self.entries.partition_point(|(o, ..)| *o <= offset);
+        self.entries.get(idx.checked_sub(1)?)
+    }
+
+    // Line of the instruction starting at or containing `offset`
+    pub fn line_of(&self, offset: usize) -> Option<usize> {
+        self.entry_of(offset).map(|(_, line, ..)| *line)
+    }
+
+    // File, line and text where the instruction starting at or containing `offset` is written
+    pub fn location_of(&self, offset: usize) -> Option<SourceLocation<'_>> {
+        let (_, _, file, line) = *self.entry_of(offset)?;
+        let file = self.files.get(file);
+        Some(SourceLocation {
+            file: file.map(|(path, _)| path.as_str()),
+            line,
+            text: file.and_then(|(_, text)| text.lines().nth(line - 1))
+        })
+    }
+
+    // Offset of the first instruction on `line`, or on the next line holding an instruction
+    pub fn offset_of(&self, line: usize) -> Option<(usize, usize)> {
+        self.entries.iter()
+            .filter(|(_, l, ..)| *l >= line)
+            .min_by_key(|(o, l, ..)| (*l, *o))
+            .map(|(o, l, ..)| (*o, *l))
+    }
+
+    // Offsets where the code written on `file_line` of `file` starts, or on the next line of that file
+    // holding an instruction, with the actual line. A line of a macro has one offset per expansion, and
+    // in the assembled file a line using a macro or an include also holds the code it expands to.
+    pub fn offsets_in_file(&self, file: usize, file_line: usize) -> Option<(Vec<usize>, usize)> {
+        let lines = |&(_, line, f, l): &(usize, usize, usize, usize)| {
+            [(f == file).then_some(l), (file == 0).then_some(line)].into_iter().flatten()
+        };
+        let actual = self.entries.iter().flat_map(lines).filter(|l| *l >= file_line).min()?;
+        let on_line: Vec<bool> = self.entries.iter().map(|entry| lines(entry).any(|l| l == actual)).collect();
+        let offsets = self.entries.iter().enumerate()
+            .filter(|(i, _)| on_line[*i] && (*i == 0 || !on_line[i - 1]))
+            .map(|(_, (offset, ..))| *offset)
+            .collect();
+        Some((offsets, actual))
+    }
+}
+
+// Parse ASM text: opcode names (OP_DUP or DUP), decimal numbers, data pushes written as
+// 0x<hex> or <hex>, and comments starting with # up to the end of the line
+pub fn parse_asm(text: &str) -> Result<Vec<AsmItem>, AsmError> {
+    tokenize(text).into_iter().map(|(line, column, token)| {
+        let item = parse_token(token).map_err(|message| AsmError { file: None, line, column, message })?;
+        Ok(AsmItem { item, line, column })
+    }).collect()
+}
+
+// Split ASM text into (line, column, token), leaving out comments
+pub fn tokenize(text: &str) -> Vec<(usize, usize, &str)> {
+    let mut tokens = Vec::new();
+    for (i, line) in text.lines().enumerate() {
+        let code = line.split('#').next().unwrap();
+        let mut column = 0;
+        for token in code.split_whitespace() {
+            column += code[column..].find(token).unwrap();
+            tokens.push((i + 1, column + 1, token));
+            column += token.len();
+        }
+    }
+    tokens
+}
+
+pub fn parse_token(token: &str) -> Result<ScriptItem, String> {
+    let hex_data = token.strip_prefix("0x")
+        .or_else(|| token.strip_prefix('<').and_then(|t| t.strip_suffix('>')));
+    if let Some(data) = hex_data {
+        return hex::decode(data.trim_start_matches("0x"))
+            .map(ScriptItem::ByteArray)
+            .map_err(|_| format!("Invalid hex push: {}", token))
+    }
+
+    if let Ok(n) = token.parse::<i64>() {
+        return Ok(match n {
+            -1 => ScriptItem::Opcode(OP_1NEGATE),
+            0 => ScriptItem::Opcode(OP_0),
+            1..=16 => ScriptItem::Opcode(Opcode::from(OP_1.code + n as u8 - 1)),
+            _ => ScriptItem::ByteArray(to_script_nb(n))
+        })
+    }
+
+    let op = token.parse::<Opcode>()?;
+    if (1..=OP_PUSH_DATA_4.code).contains(&op.code) {
+        return Err(format!("{} can't be used on its own, write the data as 0x<hex> instead", op))
+    }
+    Ok(ScriptItem::Opcode(op))
+}
+
+// Assemble ASM text into a script, with the line of every instruction
+pub fn assemble(text: &str) -> Result<(Vec<u8>, SourceMap), AsmError> {
+    let mut bytes = Vec::new();
+    let mut source_map = SourceMap::default();
+    for asm_item in parse_asm(text)? {
+        source_map.push(bytes.len(), asm_item.line);
+        match asm_item.item {
+            ScriptItem::Opcode(op) => bytes.push(op.code),
+            ScriptItem::ByteArray(data) => bytes.extend(encode_push(&data))
+        }
+    }
+    Ok((bytes, source_map))
+}
+
+// Parse ASM text into a Script
+pub fn parse_asm_script(text: &str) -> Result<Script, AsmError> {
+    Ok(parse_asm(text)?.into_iter().map(|i| i.item).collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::opcodes::*;
+
+    const P2PKH: &str = "\
+        # Pay to the hash of the generator point
+        OP_DUP OP_HASH160 0x751e76e8199196d454941c45d1b3a323f1433bd6
+        EQUALVERIFY   # same as OP_EQUALVERIFY
+
+        OP_CHECKSIG";
+
+    #[test]
+    fn assembles_with_lines() {
+        let (bytes, source_map) = assemble(P2PKH).unwrap();
+        assert_eq!(hex::encode(&bytes), "76a914751e76e8199196d454941c45d1b3a323f1433bd688ac");
+        assert_eq!(source_map.entries(), &[(0, 2, 0, 2), (1, 2, 0, 2), (2, 2, 0, 2), (23, 3, 0, 3), (24, 5, 0, 5)]);
+
+        // Offsets inside a push belong to it
+        assert_eq!(source_map.line_of(10), Some(2));
+        assert_eq!(source_map.line_of(24), Some(5));
+        assert_eq!(source_map.offset_of(3), Some((23, 3)));
+        assert_eq!(source_map.offset_of(4), Some((24, 5)));
+        assert_eq!(source_map.offset_of(6), None);
+        assert_eq!(source_map.location_of(0).unwrap().to_string(), "line 2");
+    }
+
+    #[test]
+    fn locations_in_files() {
+        let mut source_map = SourceMap::default();
+        let main = source_map.add_file("main.asm", "OP_1\nCHECK\n");
+        let lib = source_map.add_file("lib.asm", "# check\nOP_DUP OP_DROP\n");
+        source_map.push(0, 1);
+        source_map.push_origin(1, 2, lib, 2);
+        source_map.push_origin(2, 2, lib, 2);
+        assert_eq!(main, 0);
+        assert_eq!(source_map.files()[1].0, "lib.asm");
+
+        assert_eq!(source_map.location_of(2), Some(SourceLocation { file: Some("lib.asm"), line: 2, text: Some("OP_DUP OP_DROP") }));
+        assert_eq!(source_map.location_of(0).unwrap().to_string(), "main.asm:1: OP_1");
+        assert_eq!(source_map.offsets_in_file(lib, 1), Some((vec![1], 2)));
+        assert_eq!(source_map.offsets_in_file(main, 2), Some((vec![1], 2)));
+        assert_eq!(source_map.offsets_in_file(main, 3), None);
+    }
+
+    #[test]
+    fn tokens() {
+        assert_eq!(tokenize("  OP_1 0x01\n# comment\n\tADD#inline"), vec![(1, 3, "OP_1"), (1, 8, "0x01"), (3, 2, "ADD")]);
+
+        assert_eq!(parse_token("-1"), Ok(ScriptItem::Opcode(OP_1NEGATE)));
+        assert_eq!(parse_token("16"), Ok(ScriptItem::Opcode(OP_16)));
+        assert_eq!(parse_token("17"), Ok(ScriptItem::ByteArray(vec![0x11])));
+        assert_eq!(parse_token("-1000"), Ok(ScriptItem::ByteArray(vec![0xe8, 0x83])));
+        assert_eq!(parse_token("<0xabcd>"), Ok(ScriptItem::ByteArray(vec![0xab, 0xcd])));
+        assert_eq!(parse_token("<>"), Ok(ScriptItem::ByteArray(Vec::new())));
+        assert_eq!(parse_token("0xabc"), Err(String::from("Invalid hex push: 0xabc")));
+        assert!(parse_token("OP_FOO").is_err());
+        assert_eq!(parse_token("OP_PUSHDATA1"),
+            Err(String::from("OP_PUSH_DATA_1 can't be used on its own, write the data as 0x<hex> instead")));
+    }
+
+    #[test]
+    fn errors() {
+        let error = assemble("OP_1\n  OP_2 0xzz").unwrap_err();
+        assert_eq!(error, AsmError { file: None, line: 2, column: 8, message: String::from("Invalid hex push: 0xzz") });
+        assert_eq!(error.to_string(), "2:8: Invalid hex push: 0xzz");
+        let error = AsmError { file: Some(String::from("a.asm")), ..error };
+        assert_eq!(error.to_string(), "a.asm:2:8: Invalid hex push: 0xzz");
+
+        assert_eq!(parse_asm_script("2 3 ADD 5 EQUAL").unwrap(),
+            vec![ScriptItem::Opcode(OP_2), ScriptItem::Opcode(OP_3), ScriptItem::Opcode(OP_ADD), ScriptItem::Opcode(OP_5), ScriptItem::Opcode(OP_EQUAL)]);
+    }
+}
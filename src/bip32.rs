@@ -0,0 +1,215 @@
+use std::fmt;
+use std::str::FromStr;
+
+use bitcoin_hashes::{hash160, sha512, Hash, HashEngine, Hmac, HmacEngine};
+use secp256k1::{PublicKey, Scalar, Secp256k1, SecretKey};
+
+use crate::address::{base58check_decode, base58check_encode};
+
+// Child numbers from this one up derive hardened keys
+pub const HARDENED: u32 = 1 << 31;
+
+// Version bytes of serialized extended keys
+const XPUB: [u8; 4] = [0x04, 0x88, 0xb2, 0x1e];
+const XPRV: [u8; 4] = [0x04, 0x88, 0xad, 0xe4];
+const TPUB: [u8; 4] = [0x04, 0x35, 0x87, 0xcf];
+const TPRV: [u8; 4] = [0x04, 0x35, 0x83, 0x94];
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum ExtendedKeyType {
+    Public(PublicKey),
+    Private(SecretKey)
+}
+
+// BIP 32 extended key (xpub, xprv, tpub or tprv)
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub struct ExtendedKey {
+    pub testnet: bool,
+    pub depth: u8,
+    pub parent_fingerprint: [u8; 4],
+    pub child_number: u32,
+    pub chain_code: [u8; 32],
+    pub key: ExtendedKeyType
+}
+
+impl ExtendedKey {
+    pub fn public_key(&self) -> PublicKey {
+        match self.key {
+            ExtendedKeyType::Public(key) => key,
+            ExtendedKeyType::Private(key) => PublicKey::from_secret_key(&Secp256k1::signing_only(), &key)
+        }
+    }
+
+    pub fn secret_key(&self) -> Option<SecretKey> {
+        match self.key {
+            ExtendedKeyType::Public(_) => None,
+            ExtendedKeyType::Private(key) => Some(key)
+        }
+    }
+
+    // First 4 bytes of the HASH160 of the public key, identifies the parent of derived keys
+    pub fn fingerprint(&self) -> [u8; 4] {
+        hash160::Hash::hash(&self.public_key().serialize())[..4].try_into().unwrap()
+    }
+
+    // Child key derivation, hardened children need a private key
+    pub fn derive(&self, child_number: u32) -> Result<ExtendedKey, String> {
+        let mut engine = HmacEngine::<sha512::Hash>::new(&self.chain_code);
+        match self.key {
+            ExtendedKeyType::Private(key) if child_number >= HARDENED => {
+                engine.input(&[0]);
+                engine.input(&key.secret_bytes());
+            }
+            ExtendedKeyType::Public(_) if child_number >= HARDENED => {
+                return Err(format!("Hardened derivation of child {}' needs a private key", child_number - HARDENED))
+            }
+            _ => engine.input(&self.public_key().serialize())
+        }
+        engine.input(&child_number.to_be_bytes());
+        let hmac = Hmac::<sha512::Hash>::from_engine(engine);
+        let tweak = Scalar::from_be_bytes(hmac[..32].try_into().unwrap())
+            .map_err(|_| String::from("Invalid derived key, use the next child number"))?;
+
+        let key = match self.key {
+            ExtendedKeyType::Private(key) => ExtendedKeyType::Private(key.add_tweak(&tweak)
+                .map_err(|_| String::from("Invalid derived key, use the next child number"))?),
+            ExtendedKeyType::Public(key) => ExtendedKeyType::Public(key.add_exp_tweak(&Secp256k1::verification_only(), &tweak)
+                .map_err(|_| String::from("Invalid derived key, use the next child number"))?)
+        };
+        Ok(ExtendedKey {
+            testnet: self.testnet,
+            depth: self.depth.wrapping_add(1),
+            parent_fingerprint: self.fingerprint(),
+            child_number,
+            chain_code: hmac[32..].try_into().unwrap(),
+            key
+        })
+    }
+
+    pub fn derive_path(&self, path: &[u32]) -> Result<ExtendedKey, String> {
+        path.iter().try_fold(*self, |key, child| key.derive(*child))
+    }
+}
+
+impl fmt::Display for ExtendedKey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let (version, key) = match self.key {
+            ExtendedKeyType::Public(key) => (if self.testnet { TPUB } else { XPUB }, key.serialize().to_vec()),
+            ExtendedKeyType::Private(key) => (if self.testnet { TPRV } else { XPRV }, [&[0][..], &key.secret_bytes()].concat())
+        };
+        let mut payload = version[1..].to_vec();
+        payload.push(self.depth);
+        payload.extend(self.parent_fingerprint);
+        payload.extend(self.child_number.to_be_bytes());
+        payload.extend(self.chain_code);
+        payload.extend(key);
+        write!(f, "{}", base58check_encode(version[0], &payload))
+    }
+}
+
+impl FromStr for ExtendedKey {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let data = base58check_decode(s).map_err(|e| format!("Invalid extended key {}: {}", s, e))?;
+        if data.len() != 78 {
+            return Err(format!("Invalid extended key {}: {} bytes instead of 78", s, data.len()))
+        }
+        let version: [u8; 4] = data[..4].try_into().unwrap();
+        let key = &data[45..];
+        let key = match version {
+            XPUB | TPUB => ExtendedKeyType::Public(PublicKey::from_slice(key).map_err(|_| format!("Invalid public key in {}", s))?),
+            XPRV | TPRV if key[0] == 0 => {
+                ExtendedKeyType::Private(SecretKey::from_slice(&key[1..]).map_err(|_| format!("Invalid private key in {}", s))?)
+            }
+            _ => return Err(format!("Unknown extended key version 0x{}", hex::encode(version)))
+        };
+        Ok(ExtendedKey {
+            testnet: version == TPUB || version == TPRV,
+            depth: data[4],
+            parent_fingerprint: data[5..9].try_into().unwrap(),
+            child_number: u32::from_be_bytes(data[9..13].try_into().unwrap()),
+            chain_code: data[13..45].try_into().unwrap(),
+            key
+        })
+    }
+}
+
+// "0/1h/2'" → child numbers
+pub fn parse_path(path: &str) -> Result<Vec<u32>, String> {
+    path.split('/').filter(|step| !step.is_empty()).map(|step| {
+        let (number, hardened) = match step.strip_suffix(['\'', 'h', 'H']) {
+            Some(number) => (number, HARDENED),
+            None => (step, 0)
+        };
+        match number.parse::<u32>() {
+            Ok(n) if n < HARDENED => Ok(n | hardened),
+            _ => Err(format!("Invalid derivation step {}", step))
+        }
+    }).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // BIP 32 test vector 1, seed 000102030405060708090a0b0c0d0e0f
+    const MASTER: &str = "xprv9s21ZrQH143K3QTDL4LXw2F7HEK3wJUD2nW2nRk4stbPy6cq3jPPqjiChkVvvNKmPGJxWUtg6LnF5kejMRNNU3TGtRBeJgk33yuGBxrMPHi";
+    const VECTOR_1: [(&str, &str, &str); 6] = [
+        ("m", "xpub661MyMwAqRbcFtXgS5sYJABqqG9YLmC4Q1Rdap9gSE8NqtwybGhePY2gZ29ESFjqJoCu1Rupje8YtGqsefD265TMg7usUDFdp6W1EGMcet8", MASTER),
+        ("m/0H", "xpub68Gmy5EdvgibQVfPdqkBBCHxA5htiqg55crXYuXoQRKfDBFA1WEjWgP6LHhwBZeNK1VTsfTFUHCdrfp1bgwQ9xv5ski8PX9rL2dZXvgGDnw",
+            "xprv9uHRZZhk6KAJC1avXpDAp4MDc3sQKNxDiPvvkX8Br5ngLNv1TxvUxt4cV1rGL5hj6KCesnDYUhd7oWgT11eZG7XnxHrnYeSvkzY7d2bhkJ7"),
+        ("m/0H/1", "xpub6ASuArnXKPbfEwhqN6e3mwBcDTgzisQN1wXN9BJcM47sSikHjJf3UFHKkNAWbWMiGj7Wf5uMash7SyYq527Hqck2AxYysAA7xmALppuCkwQ",
+            "xprv9wTYmMFdV23N2TdNG573QoEsfRrWKQgWeibmLntzniatZvR9BmLnvSxqu53Kw1UmYPxLgboyZQaXwTCg8MSY3H2EU4pWcQDnRnrVA1xe8fs"),
+        ("m/0H/1/2H", "xpub6D4BDPcP2GT577Vvch3R8wDkScZWzQzMMUm3PWbmWvVJrZwQY4VUNgqFJPMM3No2dFDFGTsxxpG5uJh7n7epu4trkrX7x7DogT5Uv6fcLW5",
+            "xprv9z4pot5VBttmtdRTWfWQmoH1taj2axGVzFqSb8C9xaxKymcFzXBDptWmT7FwuEzG3ryjH4ktypQSAewRiNMjANTtpgP4mLTj34bhnZX7UiM"),
+        ("m/0H/1/2H/2", "xpub6FHa3pjLCk84BayeJxFW2SP4XRrFd1JYnxeLeU8EqN3vDfZmbqBqaGJAyiLjTAwm6ZLRQUMv1ZACTj37sR62cfN7fe5JnJ7dh8zL4fiyLHV",
+            "xprvA2JDeKCSNNZky6uBCviVfJSKyQ1mDYahRjijr5idH2WwLsEd4Hsb2Tyh8RfQMuPh7f7RtyzTtdrbdqqsunu5Mm3wDvUAKRHSC34sJ7in334"),
+        ("m/0H/1/2H/2/1000000000", "xpub6H1LXWLaKsWFhvm6RVpEL9P4KfRZSW7abD2ttkWP3SSQvnyA8FSVqNTEcYFgJS2UaFcxupHiYkro49S8yGasTvXEYBVPamhGW6cFJodrTHy",
+            "xprvA41z7zogVVwxVSgdKUHDy1SKmdb533PjDz7J6N6mV6uS3ze1ai8FHa8kmHScGpWmj4WggLyQjgPie1rFSruoUihUZREPSL39UNdE3BBDu76")
+    ];
+
+    fn public(key: &ExtendedKey) -> ExtendedKey {
+        ExtendedKey { key: ExtendedKeyType::Public(key.public_key()), ..*key }
+    }
+
+    #[test]
+    fn test_vector_1() {
+        let master: ExtendedKey = MASTER.parse().unwrap();
+        for (path, xpub, xprv) in VECTOR_1 {
+            let key = master.derive_path(&parse_path(path.trim_start_matches('m')).unwrap()).unwrap();
+            assert_eq!(key.to_string(), xprv, "{}", path);
+            assert_eq!(public(&key).to_string(), xpub, "{}", path);
+            assert_eq!(xpub.parse::<ExtendedKey>(), Ok(public(&key)));
+        }
+    }
+
+    #[test]
+    fn public_derivation() {
+        let parent: ExtendedKey = VECTOR_1[1].1.parse().unwrap();
+        let child = parent.derive(1).unwrap();
+        assert_eq!(child.to_string(), VECTOR_1[2].1);
+        assert_eq!(child.parent_fingerprint, parent.fingerprint());
+        assert_eq!(child.secret_key(), None);
+        assert_eq!(parent.derive(HARDENED), Err(String::from("Hardened derivation of child 0' needs a private key")));
+    }
+
+    #[test]
+    fn serialization_errors() {
+        let mut broken = String::from(VECTOR_1[0].1);
+        broken.pop();
+        assert!(broken.parse::<ExtendedKey>().unwrap_err().starts_with("Invalid extended key"));
+        let short = base58check_encode(0x04, &[0; 40]);
+        assert_eq!(short.parse::<ExtendedKey>(), Err(format!("Invalid extended key {}: 41 bytes instead of 78", short)));
+        let unknown = base58check_encode(0x05, &[0; 77]);
+        assert_eq!(unknown.parse::<ExtendedKey>(), Err(String::from("Unknown extended key version 0x05000000")));
+    }
+
+    #[test]
+    fn paths() {
+        assert_eq!(parse_path("0/1h/2'/3H"), Ok(vec![0, 1 | HARDENED, 2 | HARDENED, 3 | HARDENED]));
+        assert_eq!(parse_path("/"), Ok(Vec::new()));
+        assert_eq!(parse_path("2147483648"), Err(String::from("Invalid derivation step 2147483648")));
+        assert_eq!(parse_path("0/x"), Err(String::from("Invalid derivation step x")));
+    }
+}
@@ -0,0 +1,276 @@
+use secp256k1::{PublicKey, XOnlyPublicKey};
+
+use crate::opcodes::*;
+use crate::script::{serialize, to_script_nb, Script, ScriptItem};
+
+// Fluent construction of scripts:
+// Builder::new().push_opcode(OP_DUP).push_opcode(OP_HASH160).push_slice(&hash).into_bytes()
+#[derive(Debug, Clone, Default, Eq, PartialEq)]
+pub struct Builder {
+    script: Script
+}
+
+impl Builder {
+    pub fn new() -> Builder {
+        Builder::default()
+    }
+
+    pub fn push_opcode(mut self, op: Opcode) -> Builder {
+        self.script.push(ScriptItem::Opcode(op));
+        self
+    }
+
+    // Number with the minimal encoding: OP_0, OP_1NEGATE and OP_1..OP_16 when possible,
+    // otherwise its Script Number encoding
+    pub fn push_int(self, value: i64) -> Builder {
+        match value {
+            0 => self.push_opcode(OP_0),
+            -1 => self.push_opcode(OP_1NEGATE),
+            1..=16 => self.push_opcode(Opcode { code: OP_1.code + value as u8 - 1 }),
+            _ => self.push_script_num(value)
+        }
+    }
+
+    // Number as pushed data even when an OP_N exists for it, as some scripts compare it by bytes
+    pub fn push_script_num(self, value: i64) -> Builder {
+        self.push_slice(&to_script_nb(value))
+    }
+
+    // Data with the smallest push opcode, use push_int for small numbers
+    pub fn push_slice(mut self, data: &[u8]) -> Builder {
+        self.script.push(match data.is_empty() {
+            true => ScriptItem::Opcode(OP_0),
+            false => ScriptItem::ByteArray(data.to_vec())
+        });
+        self
+    }
+
+    // Compressed public key
+    pub fn push_key(self, key: &PublicKey) -> Builder {
+        self.push_slice(&key.serialize())
+    }
+
+    pub fn push_script(mut self, script: &Script) -> Builder {
+        self.script.extend(script.iter().cloned());
+        self
+    }
+
+    pub fn as_script(&self) -> &Script {
+        &self.script
+    }
+
+    pub fn into_script(self) -> Script {
+        self.script
+    }
+
+    pub fn into_bytes(self) -> Vec<u8> {
+        serialize(&self.script)
+    }
+
+    // <key> OP_CHECKSIG
+    pub fn p2pk(key: &[u8]) -> Builder {
+        Builder::new().push_slice(key).push_opcode(OP_CHECKSIG)
+    }
+
+    // OP_DUP OP_HASH160 <key hash> OP_EQUALVERIFY OP_CHECKSIG
+    pub fn p2pkh(hash: &[u8]) -> Builder {
+        Builder::new().push_opcode(OP_DUP).push_opcode(OP_HASH160).push_slice(hash)
+            .push_opcode(OP_EQUALVERIFY).push_opcode(OP_CHECKSIG)
+    }
+
+    // OP_HASH160 <script hash> OP_EQUAL
+    pub fn p2sh(hash: &[u8]) -> Builder {
+        Builder::new().push_opcode(OP_HASH160).push_slice(hash).push_opcode(OP_EQUAL)
+    }
+
+    // <m> <key>... <n> OP_CHECKMULTISIG
+    pub fn multisig<K: AsRef<[u8]>>(required: usize, keys: &[K]) -> Builder {
+        let builder = keys.iter().fold(Builder::new().push_int(required as i64), |b, key| b.push_slice(key.as_ref()));
+        builder.push_int(keys.len() as i64).push_opcode(OP_CHECKMULTISIG)
+    }
+
+    // OP_RETURN <data>...
+    pub fn null_data<D: AsRef<[u8]>>(data: &[D]) -> Builder {
+        data.iter().fold(Builder::new().push_opcode(OP_RETURN), |b, d| b.push_slice(d.as_ref()))
+    }
+
+    // <version> <program>, panics unless the version is 0 to 16 and the program 2 to 40 bytes (BIP141)
+    pub fn witness_program(version: u8, program: &[u8]) -> Builder {
+        assert!(version <= 16, "Witness version {} is above 16", version);
+        assert!((2..=40).contains(&program.len()), "Witness program of {} bytes, expected 2 to 40", program.len());
+        Builder::new().push_int(version as i64).push_slice(program)
+    }
+
+    // 20-byte public key hash, panics on any other length
+    pub fn p2wpkh(hash: &[u8]) -> Builder {
+        assert_eq!(hash.len(), 20, "P2WPKH needs a 20-byte key hash");
+        Builder::witness_program(0, hash)
+    }
+
+    // 32-byte script hash, panics on any other length
+    pub fn p2wsh(hash: &[u8]) -> Builder {
+        assert_eq!(hash.len(), 32, "P2WSH needs a 32-byte script hash");
+        Builder::witness_program(0, hash)
+    }
+
+    // Output key, x-only, panics unless it is 32 bytes
+    pub fn p2tr(key: &[u8]) -> Builder {
+        assert_eq!(key.len(), 32, "P2TR needs a 32-byte x-only key");
+        Builder::witness_program(1, key)
+    }
+
+    // Pay-to-anchor, OP_1 <0x4e73>
+    pub fn anchor() -> Builder {
+        Builder::witness_program(1, &[0x4e, 0x73])
+    }
+}
+
+// Values interpolated by the script! macro: numbers with push_int, bytes and keys with push_slice
+pub trait Push {
+    fn push_onto(&self, builder: Builder) -> Builder;
+}
+
+impl<T: Push + ?Sized> Push for &T {
+    fn push_onto(&self, builder: Builder) -> Builder {
+        (**self).push_onto(builder)
+    }
+}
+
+macro_rules! push_int {
+    ($($t:ty),*) => {
+        $(impl Push for $t {
+            fn push_onto(&self, builder: Builder) -> Builder {
+                builder.push_int(*self as i64)
+            }
+        })*
+    };
+}
+
+push_int!(i8, i16, i32, i64, u8, u16, u32, usize);
+
+impl Push for [u8] {
+    fn push_onto(&self, builder: Builder) -> Builder {
+        builder.push_slice(self)
+    }
+}
+
+impl<const N: usize> Push for [u8; N] {
+    fn push_onto(&self, builder: Builder) -> Builder {
+        builder.push_slice(self)
+    }
+}
+
+impl Push for Vec<u8> {
+    fn push_onto(&self, builder: Builder) -> Builder {
+        builder.push_slice(self)
+    }
+}
+
+impl Push for PublicKey {
+    fn push_onto(&self, builder: Builder) -> Builder {
+        builder.push_key(self)
+    }
+}
+
+impl Push for XOnlyPublicKey {
+    fn push_onto(&self, builder: Builder) -> Builder {
+        builder.push_slice(&self.serialize())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const KEY_HASH: &str = "751e76e8199196d454941c45d1b3a323f1433bd6";
+    // BIP173 P2WSH example
+    const SCRIPT_HASH: &str = "1863143c14c5166804bd19203356da136c985678cd4d27a1b8c6329604903262";
+
+    fn bytes(hex: &str) -> Vec<u8> {
+        hex::decode(hex).unwrap()
+    }
+
+    #[test]
+    fn numbers() {
+        let encode = |n| hex::encode(Builder::new().push_int(n).into_bytes());
+        assert_eq!(encode(0), "00");
+        assert_eq!(encode(-1), "4f");
+        assert_eq!(encode(16), "60");
+        assert_eq!(encode(17), "0111");
+        assert_eq!(encode(128), "028000");
+        assert_eq!(encode(-128), "028080");
+        assert_eq!(hex::encode(Builder::new().push_script_num(5).into_bytes()), "0105");
+    }
+
+    #[test]
+    fn push_opcodes_follow_the_data_size() {
+        let encode = |len: usize| Builder::new().push_slice(&vec![0xab; len]).into_bytes()[..3].to_vec();
+        assert_eq!(encode(75)[0], 0x4b);
+        assert_eq!(encode(76)[..2], [0x4c, 76]);
+        assert_eq!(encode(256), [0x4d, 0x00, 0x01]);
+        assert_eq!(Builder::new().push_slice(&[]).into_bytes(), [0x00]);
+    }
+
+    #[test]
+    fn templates() {
+        let hash = bytes(KEY_HASH);
+        assert_eq!(hex::encode(Builder::p2pkh(&hash).into_bytes()), format!("76a914{}88ac", KEY_HASH));
+        assert_eq!(hex::encode(Builder::p2sh(&hash).into_bytes()), format!("a914{}87", KEY_HASH));
+        assert_eq!(hex::encode(Builder::p2wpkh(&hash).into_bytes()), format!("0014{}", KEY_HASH));
+        assert_eq!(hex::encode(Builder::p2wsh(&bytes(SCRIPT_HASH)).into_bytes()), format!("0020{}", SCRIPT_HASH));
+        assert_eq!(hex::encode(Builder::p2tr(&bytes(SCRIPT_HASH)).into_bytes()), format!("5120{}", SCRIPT_HASH));
+        assert_eq!(hex::encode(Builder::witness_program(16, &[0x75, 0x1e]).into_bytes()), "6002751e");
+        assert_eq!(hex::encode(Builder::anchor().into_bytes()), "51024e73");
+        assert_eq!(hex::encode(Builder::multisig(1, &[[0x02; 33], [0x03; 33]]).into_bytes()),
+            format!("5121{}21{}52ae", "02".repeat(33), "03".repeat(33)));
+        assert_eq!(hex::encode(Builder::null_data(&[b"hi"]).into_bytes()), "6a026869");
+    }
+
+    #[test]
+    #[should_panic(expected = "Witness version 17 is above 16")]
+    fn witness_version_above_16() {
+        Builder::witness_program(17, &bytes(KEY_HASH));
+    }
+
+    #[test]
+    #[should_panic(expected = "Witness program of 41 bytes")]
+    fn witness_program_too_long() {
+        Builder::witness_program(2, &[0; 41]);
+    }
+
+    #[test]
+    #[should_panic(expected = "P2WPKH needs a 20-byte key hash")]
+    fn p2wpkh_with_a_script_hash() {
+        Builder::p2wpkh(&bytes(SCRIPT_HASH));
+    }
+
+    #[test]
+    #[should_panic(expected = "P2WSH needs a 32-byte script hash")]
+    fn p2wsh_with_a_key_hash() {
+        Builder::p2wsh(&bytes(KEY_HASH));
+    }
+
+    #[test]
+    fn script_macro_matches_the_builder() {
+        let hash = bytes(KEY_HASH);
+        assert_eq!(crate::script_bytes! { OP_DUP OP_HASH160 <0x751e76e8199196d454941c45d1b3a323f1433bd6> OP_EQUALVERIFY OP_CHECKSIG },
+            Builder::p2pkh(&hash).into_bytes());
+        assert_eq!(crate::script! { OP_0 <hash> }, Builder::p2wpkh(&hash).into_script());
+        // Aliases are accepted
+        assert_eq!(crate::script_bytes! { OP_TRUE OP_NOP2 OP_FALSE }, [0x51, 0xb1, 0x00]);
+    }
+
+    #[test]
+    fn script_macro_pushes() {
+        assert_eq!(hex::encode(crate::script_bytes! { <0> <1> <16> <17> <-1> <-5> <1000> 2 -3 }), "00516001114f018502e803520183");
+        assert_eq!(hex::encode(crate::script_bytes! { <"hi"> <""> <0x00ff> }), "026869000200ff");
+
+        // Rust expressions go through Push
+        let key = PublicKey::from_slice(&bytes("031b84c5567b126440995d3ed5aaba0565d71e1834604819ff9c17f5e9d5dd078f")).unwrap();
+        let (n, data) = (3u8, vec![0xab, 0xcd]);
+        let script = crate::script_bytes! { <n + 1> <key> <&data> <[0xefu8; 2]> <key.x_only_public_key().0> OP_CHECKSIG };
+        let expected = Builder::new().push_int(4).push_key(&key).push_slice(&data).push_slice(&[0xef, 0xef])
+            .push_slice(&key.x_only_public_key().0.serialize()).push_opcode(OP_CHECKSIG).into_bytes();
+        assert_eq!(script, expected);
+    }
+}
@@ -0,0 +1,1210 @@
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+use std::mem;
+use std::str::FromStr;
+
+use secp256k1::PublicKey;
+use serde_json::{json, Value};
+
+use crate::builder::Builder;
+use crate::opcodes::*;
+use crate::optimize::optimize;
+use crate::script::{as_script_nb, serialize, to_script_nb, Script, ScriptItem, MAX_SCRIPT_ELEMENT_SIZE};
+
+// Contract language compiled to Script, where variables are mapped to stack positions by the compiler
+//
+//     // Bob can spend with the preimage of the hash, Alice after a day
+//     contract HashTimeLock(sig: Sig, preimage: Bytes, claim: Bool) {
+//         const alice: PubKey = 0x031b84c5567b126440995d3ed5aaba0565d71e1834604819ff9c17f5e9d5dd078f;
+//         const bob: PubKey = 0x024d4b6cd1361032ca9bd2aeb9d900aa4d45d9ead80ac9423374c451a7254d0766;
+//         const hash: Hash = 0x2bb80d537b1da3e38bd30361aa855686bde0eacd7162fef6a25fe97bf527a25b;
+//
+//         if claim {
+//             verify sha256(preimage) == hash;
+//             checkSig(bob, sig)
+//         } else {
+//             verify older(144);
+//             checkSig(alice, sig)
+//         }
+//     }
+//
+// The parameters are the witness, in order, the last one ends on top of the stack. Statements are
+// `let x = e;`, `const x: T = literal;` (top level only), `verify e;` and if/else. Blocks and if/else
+// are expressions giving the value of their last expression, the contract succeeds when its body
+// gives true. Expressions: numbers, true, false, 0x<hex>, variables, == != < <= > >= + - && || !,
+// checkSig(key, sig), sha256(x), and the timelocks `verify after(n);` and `verify older(n);`
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum Type {
+    Int,
+    Bool,
+    Bytes,
+    PubKey,
+    Sig,
+    Hash
+}
+
+impl Type {
+    // How a witness item of this type is encoded
+    pub fn encoding(&self) -> &'static str {
+        match self {
+            Type::Int => "Script Number, minimally encoded",
+            Type::Bool => "empty for false, 0x01 for true",
+            Type::Bytes => "any data up to 520 bytes",
+            Type::PubKey => "compressed public key, 33 bytes",
+            Type::Sig => "DER signature followed by the sighash type, or empty",
+            Type::Hash => "32 bytes"
+        }
+    }
+
+    pub fn check_value(&self, value: &[u8]) -> Result<(), String> {
+        let valid = match self {
+            Type::Int => as_script_nb(value).is_ok_and(|n| to_script_nb(n) == value),
+            Type::Bool => value.is_empty() || value == [1],
+            Type::Bytes => value.len() <= MAX_SCRIPT_ELEMENT_SIZE,
+            Type::PubKey => value.len() == 33 && PublicKey::from_slice(value).is_ok(),
+            Type::Sig => value.is_empty() || (value.len() <= 73 && value[0] == 0x30),
+            Type::Hash => value.len() == 32
+        };
+        match valid {
+            true => Ok(()),
+            false => Err(format!("0x{} is not a valid {} ({})", hex::encode(value), self, self.encoding()))
+        }
+    }
+
+    fn is_bytes(&self) -> bool {
+        !matches!(self, Type::Int | Type::Bool)
+    }
+}
+
+impl fmt::Display for Type {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:?}", self)
+    }
+}
+
+impl FromStr for Type {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "Int" => Ok(Type::Int),
+            "Bool" => Ok(Type::Bool),
+            "Bytes" => Ok(Type::Bytes),
+            "PubKey" => Ok(Type::PubKey),
+            "Sig" => Ok(Type::Sig),
+            "Hash" => Ok(Type::Hash),
+            _ => Err(format!("Unknown type: {} (expected Int, Bool, Bytes, PubKey, Sig or Hash)", s))
+        }
+    }
+}
+
+// Error in a contract source, lines and columns start at 1
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct CompileError {
+    pub line: usize,
+    pub column: usize,
+    pub message: String
+}
+
+impl fmt::Display for CompileError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}:{}: {}", self.line, self.column, self.message)
+    }
+}
+
+impl std::error::Error for CompileError {}
+
+// Compiled contract
+#[derive(Debug, Clone)]
+pub struct Contract {
+    pub name: String,
+    // Witness items in the order they are given, the last one ends on top of the stack
+    pub params: Vec<(String, Type)>,
+    pub script: Script
+}
+
+impl Contract {
+    // Witness layout as JSON, for the tools building the spending transactions
+    pub fn abi(&self) -> Value {
+        let witness: Vec<Value> = self.params.iter().enumerate().map(|(i, (name, ty))| json!({
+            "index": i,
+            "name": name,
+            "type": ty.to_string(),
+            "encoding": ty.encoding()
+        })).collect();
+        json!({
+            "contract": self.name,
+            "script": hex::encode(serialize(&self.script)),
+            "witness": witness
+        })
+    }
+
+    // Witness stack from the value of every parameter, checked against its type
+    pub fn witness(&self, values: &[(&str, Vec<u8>)]) -> Result<Vec<Vec<u8>>, String> {
+        if let Some((name, _)) = values.iter().find(|(name, _)| !self.params.iter().any(|(p, _)| p == name)) {
+            return Err(format!("{} has no parameter {}", self.name, name))
+        }
+        self.params.iter().map(|(name, ty)| {
+            let (_, value) = values.iter().find(|(n, _)| n == name).ok_or_else(|| format!("Missing value for {}", name))?;
+            ty.check_value(value).map_err(|e| format!("{}: {}", name, e))?;
+            Ok(value.clone())
+        }).collect()
+    }
+}
+
+// Parse, type and compile a contract
+pub fn compile(source: &str) -> Result<Contract, CompileError> {
+    let tokens = lex(source)?;
+    let mut parser = Parser { tokens, pos: 0 };
+    let (name, params, mut body) = parser.contract()?;
+    let mut resolver = Resolver { scopes: vec![(0..params.len()).map(|i| (params[i].0.clone(), i)).collect()], next: params.len() };
+    resolver.block(&mut body)?;
+
+    // A body ending with `verify e;` gives e
+    if body.tail.is_none() {
+        if let Some(Stmt { kind: StmtKind::Verify(e), .. }) = body.stmts.last() {
+            if timelock(e).is_none() {
+                body.tail = Some(e.clone());
+                body.stmts.pop();
+            }
+        }
+    }
+
+    let mut compiler = Compiler {
+        builder: Builder::new(),
+        stack: (0..params.len()).map(Some).collect(),
+        types: params.iter().map(|(_, ty)| *ty).enumerate().collect(),
+        constants: HashMap::new()
+    };
+    let result = compiler.block(&body, &HashSet::new())?;
+    match result {
+        Some(Type::Bool) => {}
+        Some(ty) => return Err(error(body.end, &format!("The contract must give a Bool, not {}", ty))),
+        // The number left by a final timelock check is never 0
+        None if ends_with_timelock(compiler.builder.as_script()) => {
+            let mut script = mem::take(&mut compiler.builder).into_script();
+            script.pop();
+            compiler.builder = Builder::new().push_script(&script);
+        }
+        None => compiler.builder = mem::take(&mut compiler.builder).push_opcode(OP_1)
+    }
+
+    Ok(Contract {
+        name,
+        params,
+        script: optimize(compiler.builder.as_script()).script
+    })
+}
+
+fn ends_with_timelock(script: &Script) -> bool {
+    matches!(script[script.len().saturating_sub(2)..],
+        [ScriptItem::Opcode(OP_CHECKLOCKTIMEVERIFY | OP_CHECKSEQUENCEVERIFY), ScriptItem::Opcode(OP_DROP)])
+}
+
+fn error(pos: (usize, usize), message: &str) -> CompileError {
+    CompileError { line: pos.0, column: pos.1, message: message.to_string() }
+}
+
+const KEYWORDS: [&str; 8] = ["contract", "let", "const", "verify", "if", "else", "true", "false"];
+
+#[derive(Debug, Clone, PartialEq)]
+enum TokenKind {
+    Ident(String),
+    Int(i64),
+    Hex(Vec<u8>),
+    Symbol(&'static str),
+    End
+}
+
+#[derive(Debug, Clone)]
+struct Token {
+    kind: TokenKind,
+    pos: (usize, usize)
+}
+
+// Longest symbols first
+const SYMBOLS: [&str; 19] = ["==", "!=", "<=", ">=", "&&", "||", "(", ")", "{", "}", ",", ":", ";", "=", "<", ">",
+    "+", "-", "!"];
+
+fn lex(source: &str) -> Result<Vec<Token>, CompileError> {
+    let mut tokens = Vec::new();
+    for (i, line) in source.lines().enumerate() {
+        let code = line.split("//").next().unwrap();
+        let mut column = 0;
+        while column < code.len() {
+            let rest = &code[column..];
+            let pos = (i + 1, column + 1);
+            let c = rest.chars().next().unwrap();
+            if c.is_whitespace() {
+                column += c.len_utf8();
+                continue
+            }
+
+            let word_len = rest.find(|c: char| !c.is_ascii_alphanumeric() && c != '_').unwrap_or(rest.len());
+            let (kind, len) = if let Some(digits) = rest.strip_prefix("0x") {
+                let len = digits.find(|c: char| !c.is_ascii_hexdigit()).unwrap_or(digits.len());
+                let data = hex::decode(&digits[..len]).map_err(|_| error(pos, &format!("Invalid hex: 0x{}", &digits[..len])))?;
+                (TokenKind::Hex(data), len + 2)
+            } else if c.is_ascii_digit() {
+                let n: i64 = rest[..word_len].parse().map_err(|_| error(pos, &format!("Invalid number: {}", &rest[..word_len])))?;
+                // Arithmetic opcodes only take 4-byte Script Numbers
+                if n > i32::MAX as i64 {
+                    return Err(error(pos, &format!("Number out of range: {} (at most {})", n, i32::MAX)))
+                }
+                (TokenKind::Int(n), word_len)
+            } else if c.is_ascii_alphabetic() || c == '_' {
+                (TokenKind::Ident(rest[..word_len].to_string()), word_len)
+            } else {
+                let symbol = SYMBOLS.iter().find(|s| rest.starts_with(**s)).ok_or_else(|| error(pos, &format!("Unexpected character: {}", c)))?;
+                (TokenKind::Symbol(symbol), symbol.len())
+            };
+            tokens.push(Token { kind, pos });
+            column += len;
+        }
+    }
+    let end = (source.lines().count().max(1), 1);
+    tokens.push(Token { kind: TokenKind::End, pos: end });
+    Ok(tokens)
+}
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+enum BinOp {
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+    Add,
+    Sub,
+    And,
+    Or
+}
+
+impl BinOp {
+    // Binding strength, higher binds first
+    fn precedence(&self) -> u8 {
+        match self {
+            BinOp::Or => 1,
+            BinOp::And => 2,
+            BinOp::Eq | BinOp::Ne => 3,
+            BinOp::Lt | BinOp::Le | BinOp::Gt | BinOp::Ge => 4,
+            BinOp::Add | BinOp::Sub => 5
+        }
+    }
+
+    fn from_symbol(symbol: &str) -> Option<BinOp> {
+        Some(match symbol {
+            "==" => BinOp::Eq,
+            "!=" => BinOp::Ne,
+            "<" => BinOp::Lt,
+            "<=" => BinOp::Le,
+            ">" => BinOp::Gt,
+            ">=" => BinOp::Ge,
+            "+" => BinOp::Add,
+            "-" => BinOp::Sub,
+            "&&" => BinOp::And,
+            "||" => BinOp::Or,
+            _ => return None
+        })
+    }
+}
+
+#[derive(Debug, Clone)]
+enum ExprKind {
+    Int(i64),
+    Bool(bool),
+    Bytes(Vec<u8>),
+    // Set by the resolver
+    Var(String, VarId),
+    Call(String, Vec<Expr>),
+    Not(Box<Expr>),
+    Neg(Box<Expr>),
+    Binary(BinOp, Box<Expr>, Box<Expr>),
+    If(Box<Expr>, Box<Block>, Box<Block>)
+}
+
+#[derive(Debug, Clone)]
+struct Expr {
+    kind: ExprKind,
+    pos: (usize, usize)
+}
+
+#[derive(Debug, Clone)]
+enum StmtKind {
+    Let(String, VarId, Expr),
+    Const(String, VarId, Type, Expr),
+    Verify(Expr),
+    // if/else without a value
+    If(Expr)
+}
+
+#[derive(Debug, Clone)]
+struct Stmt {
+    kind: StmtKind,
+    pos: (usize, usize)
+}
+
+#[derive(Debug, Clone, Default)]
+struct Block {
+    stmts: Vec<Stmt>,
+    tail: Option<Expr>,
+    // Position of the closing brace
+    end: (usize, usize)
+}
+
+// Unique id of a binding, the parameters are 0..params.len()
+type VarId = usize;
+
+// Witness parameters with their type
+type Params = Vec<(String, Type)>;
+
+struct Parser {
+    tokens: Vec<Token>,
+    pos: usize
+}
+
+impl Parser {
+    fn peek(&self) -> &Token {
+        &self.tokens[self.pos]
+    }
+
+    fn next(&mut self) -> Token {
+        let token = self.tokens[self.pos].clone();
+        if token.kind != TokenKind::End {
+            self.pos += 1;
+        }
+        token
+    }
+
+    fn is_symbol(&self, symbol: &str) -> bool {
+        matches!(&self.peek().kind, TokenKind::Symbol(s) if *s == symbol)
+    }
+
+    fn is_keyword(&self, keyword: &str) -> bool {
+        matches!(&self.peek().kind, TokenKind::Ident(s) if s == keyword)
+    }
+
+    fn expect(&mut self, symbol: &str) -> Result<(), CompileError> {
+        match self.is_symbol(symbol) {
+            true => {
+                self.next();
+                Ok(())
+            }
+            false => Err(self.unexpected(&format!("`{}`", symbol)))
+        }
+    }
+
+    fn unexpected(&self, expected: &str) -> CompileError {
+        let found = match &self.peek().kind {
+            TokenKind::Ident(s) => s.clone(),
+            TokenKind::Int(n) => n.to_string(),
+            TokenKind::Hex(data) => format!("0x{}", hex::encode(data)),
+            TokenKind::Symbol(s) => s.to_string(),
+            TokenKind::End => String::from("end of file")
+        };
+        error(self.peek().pos, &format!("Expected {}, found {}", expected, found))
+    }
+
+    fn name(&mut self) -> Result<String, CompileError> {
+        match &self.peek().kind {
+            TokenKind::Ident(name) if !KEYWORDS.contains(&name.as_str()) => {
+                let name = name.clone();
+                self.next();
+                Ok(name)
+            }
+            _ => Err(self.unexpected("a name"))
+        }
+    }
+
+    fn ty(&mut self) -> Result<Type, CompileError> {
+        let pos = self.peek().pos;
+        let name = self.name()?;
+        name.parse().map_err(|e: String| error(pos, &e))
+    }
+
+    // contract Name(param: Type, ...) { ... }
+    fn contract(&mut self) -> Result<(String, Params, Block), CompileError> {
+        if !self.is_keyword("contract") {
+            return Err(self.unexpected("`contract`"))
+        }
+        self.next();
+        let name = self.name()?;
+        self.expect("(")?;
+        let mut params = Vec::new();
+        while !self.is_symbol(")") {
+            let pos = self.peek().pos;
+            let param = self.name()?;
+            if params.iter().any(|(p, _)| *p == param) {
+                return Err(error(pos, &format!("Duplicate parameter {}", param)))
+            }
+            self.expect(":")?;
+            params.push((param, self.ty()?));
+            if !self.is_symbol(")") {
+                self.expect(",")?;
+            }
+        }
+        self.next();
+        let body = self.block(true)?;
+        if self.peek().kind != TokenKind::End {
+            return Err(self.unexpected("end of file"))
+        }
+        Ok((name, params, body))
+    }
+
+    fn block(&mut self, top_level: bool) -> Result<Block, CompileError> {
+        self.expect("{")?;
+        let mut block = Block::default();
+        loop {
+            let pos = self.peek().pos;
+            if self.is_symbol("}") {
+                block.end = pos;
+                self.next();
+                return Ok(block)
+            }
+            if block.tail.is_some() {
+                return Err(self.unexpected("`}` after the value of the block"))
+            }
+
+            let kind = match &self.peek().kind {
+                TokenKind::Ident(k) if k == "let" => {
+                    self.next();
+                    let name = self.name()?;
+                    self.expect("=")?;
+                    StmtKind::Let(name, 0, self.expr()?)
+                }
+                TokenKind::Ident(k) if k == "const" => {
+                    if !top_level {
+                        return Err(error(pos, "Constants can only be declared at the top level of the contract"))
+                    }
+                    self.next();
+                    let name = self.name()?;
+                    self.expect(":")?;
+                    let ty = self.ty()?;
+                    self.expect("=")?;
+                    StmtKind::Const(name, 0, ty, self.expr()?)
+                }
+                TokenKind::Ident(k) if k == "verify" => {
+                    self.next();
+                    StmtKind::Verify(self.expr()?)
+                }
+                TokenKind::Ident(k) if k == "if" => {
+                    let e = self.if_else()?;
+                    match self.is_symbol("}") {
+                        true => block.tail = Some(e),
+                        false => block.stmts.push(Stmt { kind: StmtKind::If(e), pos })
+                    }
+                    continue
+                }
+                _ => {
+                    block.tail = Some(self.expr()?);
+                    continue
+                }
+            };
+            self.expect(";")?;
+            block.stmts.push(Stmt { kind, pos });
+        }
+    }
+
+    // if c { ... } else if d { ... } else { ... }, the else branch is optional
+    fn if_else(&mut self) -> Result<Expr, CompileError> {
+        let pos = self.next().pos;
+        let cond = self.expr()?;
+        let then = self.block(false)?;
+        let other = match self.is_keyword("else") {
+            true => {
+                self.next();
+                match self.is_keyword("if") {
+                    true => {
+                        let nested = self.if_else()?;
+                        Block { stmts: Vec::new(), end: nested.pos, tail: Some(nested) }
+                    }
+                    false => self.block(false)?
+                }
+            }
+            false => Block { end: then.end, ..Block::default() }
+        };
+        Ok(Expr { kind: ExprKind::If(Box::new(cond), Box::new(then), Box::new(other)), pos })
+    }
+
+    fn expr(&mut self) -> Result<Expr, CompileError> {
+        self.binary(1)
+    }
+
+    // Precedence climbing, operators of the same precedence are left associative
+    fn binary(&mut self, min_precedence: u8) -> Result<Expr, CompileError> {
+        let mut lhs = self.unary()?;
+        loop {
+            let op = match &self.peek().kind {
+                TokenKind::Symbol(s) => BinOp::from_symbol(s),
+                _ => None
+            };
+            let op = match op {
+                Some(op) if op.precedence() >= min_precedence => op,
+                _ => return Ok(lhs)
+            };
+            let pos = self.next().pos;
+            let rhs = self.binary(op.precedence() + 1)?;
+            lhs = Expr { kind: ExprKind::Binary(op, Box::new(lhs), Box::new(rhs)), pos };
+        }
+    }
+
+    fn unary(&mut self) -> Result<Expr, CompileError> {
+        let pos = self.peek().pos;
+        if self.is_symbol("!") {
+            self.next();
+            return Ok(Expr { kind: ExprKind::Not(Box::new(self.unary()?)), pos })
+        }
+        if self.is_symbol("-") {
+            self.next();
+            return Ok(Expr { kind: ExprKind::Neg(Box::new(self.unary()?)), pos })
+        }
+        self.primary()
+    }
+
+    fn primary(&mut self) -> Result<Expr, CompileError> {
+        let pos = self.peek().pos;
+        let kind = match self.peek().kind.clone() {
+            TokenKind::Int(n) => {
+                self.next();
+                ExprKind::Int(n)
+            }
+            TokenKind::Hex(data) => {
+                self.next();
+                ExprKind::Bytes(data)
+            }
+            TokenKind::Symbol("(") => {
+                self.next();
+                let e = self.expr()?;
+                self.expect(")")?;
+                return Ok(e)
+            }
+            TokenKind::Ident(k) if k == "if" => return self.if_else(),
+            TokenKind::Ident(k) if k == "true" || k == "false" => {
+                self.next();
+                ExprKind::Bool(k == "true")
+            }
+            TokenKind::Ident(_) => {
+                let name = self.name()?;
+                match self.is_symbol("(") {
+                    true => {
+                        self.next();
+                        let mut args = Vec::new();
+                        while !self.is_symbol(")") {
+                            args.push(self.expr()?);
+                            if !self.is_symbol(")") {
+                                self.expect(",")?;
+                            }
+                        }
+                        self.next();
+                        ExprKind::Call(name, args)
+                    }
+                    false => ExprKind::Var(name, 0)
+                }
+            }
+            _ => return Err(self.unexpected("an expression"))
+        };
+        Ok(Expr { kind, pos })
+    }
+}
+
+// Variables used by an expression, a statement or a block
+fn expr_vars(e: &Expr, vars: &mut HashSet<VarId>) {
+    match &e.kind {
+        ExprKind::Int(_) | ExprKind::Bool(_) | ExprKind::Bytes(_) => {}
+        ExprKind::Var(_, id) => {
+            vars.insert(*id);
+        }
+        ExprKind::Call(_, args) => args.iter().for_each(|a| expr_vars(a, vars)),
+        ExprKind::Not(e) | ExprKind::Neg(e) => expr_vars(e, vars),
+        ExprKind::Binary(_, a, b) => {
+            expr_vars(a, vars);
+            expr_vars(b, vars);
+        }
+        ExprKind::If(c, then, other) => {
+            expr_vars(c, vars);
+            block_vars(then, vars);
+            block_vars(other, vars);
+        }
+    }
+}
+
+fn stmt_vars(stmt: &Stmt, vars: &mut HashSet<VarId>) {
+    match &stmt.kind {
+        StmtKind::Let(_, _, e) | StmtKind::Const(_, _, _, e) | StmtKind::Verify(e) | StmtKind::If(e) => expr_vars(e, vars)
+    }
+}
+
+fn block_vars(block: &Block, vars: &mut HashSet<VarId>) {
+    block.stmts.iter().for_each(|s| stmt_vars(s, vars));
+    if let Some(tail) = &block.tail {
+        expr_vars(tail, vars);
+    }
+}
+
+// after(n) or older(n) with their opcode
+fn timelock(e: &Expr) -> Option<(Opcode, &Expr)> {
+    match &e.kind {
+        ExprKind::Call(name, args) if name == "after" && args.len() == 1 => Some((OP_CHECKLOCKTIMEVERIFY, &args[0])),
+        ExprKind::Call(name, args) if name == "older" && args.len() == 1 => Some((OP_CHECKSEQUENCEVERIFY, &args[0])),
+        _ => None
+    }
+}
+
+// Gives every binding a unique id, so a name declared again after the end of its scope is another
+// variable for the liveness analysis
+struct Resolver {
+    // Variables in scope by name, innermost last
+    scopes: Vec<HashMap<String, VarId>>,
+    next: VarId
+}
+
+impl Resolver {
+    fn bind(&mut self, name: &str, pos: (usize, usize)) -> Result<VarId, CompileError> {
+        if self.scopes.iter().any(|scope| scope.contains_key(name)) {
+            return Err(error(pos, &format!("{} is already defined", name)))
+        }
+        let id = self.next;
+        self.next += 1;
+        self.scopes.last_mut().unwrap().insert(name.to_string(), id);
+        Ok(id)
+    }
+
+    fn block(&mut self, block: &mut Block) -> Result<(), CompileError> {
+        self.scopes.push(HashMap::new());
+        for stmt in &mut block.stmts {
+            match &mut stmt.kind {
+                StmtKind::Let(name, id, e) => {
+                    self.expr(e)?;
+                    *id = self.bind(name, stmt.pos)?;
+                }
+                // The value must be a literal, the compiler checks it
+                StmtKind::Const(name, id, _, _) => *id = self.bind(name, stmt.pos)?,
+                StmtKind::Verify(e) | StmtKind::If(e) => self.expr(e)?
+            }
+        }
+        if let Some(tail) = &mut block.tail {
+            self.expr(tail)?;
+        }
+        self.scopes.pop();
+        Ok(())
+    }
+
+    fn expr(&mut self, e: &mut Expr) -> Result<(), CompileError> {
+        match &mut e.kind {
+            ExprKind::Int(_) | ExprKind::Bool(_) | ExprKind::Bytes(_) => {}
+            ExprKind::Var(name, id) => {
+                *id = self.scopes.iter().rev().find_map(|scope| scope.get(name.as_str()).copied())
+                    .ok_or_else(|| error(e.pos, &format!("Unknown variable {}", name)))?;
+            }
+            ExprKind::Call(_, args) => args.iter_mut().try_for_each(|a| self.expr(a))?,
+            ExprKind::Not(e) | ExprKind::Neg(e) => self.expr(e)?,
+            ExprKind::Binary(_, a, b) => {
+                self.expr(a)?;
+                self.expr(b)?;
+            }
+            ExprKind::If(c, then, other) => {
+                self.expr(c)?;
+                self.block(then)?;
+                self.block(other)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+struct Compiler {
+    builder: Builder,
+    // Model of the stack, bottom first: the variable held by every element, None for temporaries
+    stack: Vec<Option<VarId>>,
+    // Types of the variables declared so far
+    types: HashMap<VarId, Type>,
+    constants: HashMap<VarId, (Type, Expr)>
+}
+
+impl Compiler {
+    fn emit(&mut self, f: impl FnOnce(Builder) -> Builder) {
+        self.builder = f(mem::take(&mut self.builder));
+    }
+
+    fn op(&mut self, op: Opcode) {
+        self.emit(|b| b.push_opcode(op));
+    }
+
+    // Replace the `n` values on top of the stack by the result of an operation
+    fn reduce(&mut self, n: usize) {
+        self.stack.truncate(self.stack.len() - n);
+        self.stack.push(None);
+    }
+
+    // Remove the variables that are no longer used, closest to the top first
+    fn drop_dead(&mut self, live: &HashSet<VarId>) {
+        while let Some(i) = self.stack.iter().rposition(|s| matches!(s, Some(v) if !live.contains(v))) {
+            let depth = self.stack.len() - 1 - i;
+            self.stack.remove(i);
+            match depth {
+                0 => self.op(OP_DROP),
+                1 => self.op(OP_NIP),
+                _ => self.emit(|b| b.push_int(depth as i64).push_opcode(OP_ROLL).push_opcode(OP_DROP))
+            }
+        }
+    }
+
+    fn block(&mut self, block: &Block, live: &HashSet<VarId>) -> Result<Option<Type>, CompileError> {
+        for (i, stmt) in block.stmts.iter().enumerate() {
+            let mut after = live.clone();
+            block.stmts[i + 1..].iter().for_each(|s| stmt_vars(s, &mut after));
+            if let Some(tail) = &block.tail {
+                expr_vars(tail, &mut after);
+            }
+            let mut needed = after.clone();
+            stmt_vars(stmt, &mut needed);
+            self.drop_dead(&needed);
+            self.stmt(stmt, &after)?;
+        }
+
+        let mut needed = live.clone();
+        if let Some(tail) = &block.tail {
+            expr_vars(tail, &mut needed);
+        }
+        self.drop_dead(&needed);
+        let ty = match &block.tail {
+            Some(Expr { kind: ExprKind::If(c, then, other), .. }) => self.if_else(c, then, other, live)?,
+            Some(tail) => Some(self.expr(tail, live)?),
+            None => None
+        };
+        self.drop_dead(live);
+        Ok(ty)
+    }
+
+    fn stmt(&mut self, stmt: &Stmt, after: &HashSet<VarId>) -> Result<(), CompileError> {
+        match &stmt.kind {
+            StmtKind::Let(_, id, e) => {
+                let ty = self.expr(e, after)?;
+                *self.stack.last_mut().unwrap() = Some(*id);
+                self.types.insert(*id, ty);
+            }
+            StmtKind::Const(name, id, ty, value) => {
+                let valid = match (&value.kind, ty) {
+                    (ExprKind::Int(_), Type::Int) | (ExprKind::Bool(_), Type::Bool) => true,
+                    (ExprKind::Neg(n), Type::Int) => matches!(n.kind, ExprKind::Int(_)),
+                    (ExprKind::Bytes(data), ty) if ty.is_bytes() => {
+                        ty.check_value(data).map_err(|e| error(value.pos, &e))?;
+                        true
+                    }
+                    _ => false
+                };
+                if !valid {
+                    return Err(error(value.pos, &format!("The value of {} must be a {} literal", name, ty)))
+                }
+                self.constants.insert(*id, (*ty, value.clone()));
+            }
+            StmtKind::Verify(e) => match timelock(e) {
+                Some((op, arg)) => {
+                    let n = self.constant_int(arg)
+                        .ok_or_else(|| error(arg.pos, "Timelocks must be numbers or Int constants"))?;
+                    if !(1..0x80000000).contains(&n) {
+                        return Err(error(arg.pos, &format!("Timelock out of range: {}", n)))
+                    }
+                    self.emit(|b| b.push_int(n).push_opcode(op).push_opcode(OP_DROP));
+                }
+                None => {
+                    let ty = self.expr(e, after)?;
+                    if ty != Type::Bool {
+                        return Err(error(e.pos, &format!("verify expects a Bool, not {}", ty)))
+                    }
+                    self.op(OP_VERIFY);
+                    self.stack.pop();
+                }
+            },
+            StmtKind::If(e) => {
+                if let ExprKind::If(c, then, other) = &e.kind {
+                    if self.if_else(c, then, other, after)?.is_some() {
+                        return Err(error(e.pos, "The value of this if is unused, check it with verify"))
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn constant_int(&self, e: &Expr) -> Option<i64> {
+        match &e.kind {
+            ExprKind::Int(n) => Some(*n),
+            ExprKind::Neg(e) => self.constant_int(e).map(|n| -n),
+            ExprKind::Var(_, id) => self.constant_int(&self.constants.get(id)?.1),
+            _ => None
+        }
+    }
+
+    // Both branches leave the stack with the same layout: the variables used after the if, in the
+    // same order, and the value of the branches on top
+    fn if_else(&mut self, cond: &Expr, then: &Block, other: &Block, live: &HashSet<VarId>) -> Result<Option<Type>, CompileError> {
+        let mut branch_live = live.clone();
+        block_vars(then, &mut branch_live);
+        block_vars(other, &mut branch_live);
+
+        let (cond, op) = match &cond.kind {
+            ExprKind::Not(inner) => (inner.as_ref(), OP_NOTIF),
+            _ => (cond, OP_IF)
+        };
+        let ty = self.expr(cond, &branch_live)?;
+        if ty != Type::Bool {
+            return Err(error(cond.pos, &format!("if expects a Bool condition, not {}", ty)))
+        }
+        self.op(op);
+        self.stack.pop();
+
+        let before = self.stack.clone();
+        let then_ty = self.block(then, live)?;
+        let then_stack = mem::replace(&mut self.stack, before);
+        let then_len = self.builder.as_script().len();
+        self.op(OP_ELSE);
+        let other_ty = self.block(other, live)?;
+        if self.builder.as_script().len() == then_len + 1 {
+            let mut script = mem::take(&mut self.builder).into_script();
+            script.pop();
+            self.builder = Builder::new().push_script(&script);
+        }
+        self.op(OP_ENDIF);
+
+        if then_ty != other_ty {
+            let show = |ty: Option<Type>| ty.map_or(String::from("no value"), |t| t.to_string());
+            return Err(error(other.end, &format!("The branches of this if give {} and {}", show(then_ty), show(other_ty))))
+        }
+        if then_stack != self.stack {
+            return Err(error(other.end, "The branches of this if leave different variables on the stack"))
+        }
+        Ok(then_ty)
+    }
+
+    // Push the value of an expression, moving the variables used for the last time instead of
+    // copying them
+    fn expr(&mut self, e: &Expr, live: &HashSet<VarId>) -> Result<Type, CompileError> {
+        let ty = match &e.kind {
+            ExprKind::Int(n) => {
+                self.emit(|b| b.push_int(*n));
+                self.stack.push(None);
+                Type::Int
+            }
+            ExprKind::Bool(value) => {
+                self.emit(|b| b.push_int(*value as i64));
+                self.stack.push(None);
+                Type::Bool
+            }
+            ExprKind::Bytes(data) => {
+                self.emit(|b| b.push_slice(data));
+                self.stack.push(None);
+                Type::Bytes
+            }
+            ExprKind::Var(name, id) => return self.var(name, *id, e.pos, live),
+            ExprKind::Not(inner) => {
+                let ty = self.expr(inner, live)?;
+                if ty != Type::Bool {
+                    return Err(error(e.pos, &format!("! expects a Bool, not {}", ty)))
+                }
+                self.op(OP_NOT);
+                self.reduce(1);
+                Type::Bool
+            }
+            ExprKind::Neg(inner) => {
+                if let ExprKind::Int(n) = inner.kind {
+                    return self.expr(&Expr { kind: ExprKind::Int(-n), pos: e.pos }, live)
+                }
+                let ty = self.expr(inner, live)?;
+                if ty != Type::Int {
+                    return Err(error(e.pos, &format!("- expects an Int, not {}", ty)))
+                }
+                self.op(OP_NEGATE);
+                self.reduce(1);
+                Type::Int
+            }
+            ExprKind::Binary(op, a, b) => {
+                let mut live_a = live.clone();
+                expr_vars(b, &mut live_a);
+                let ta = self.expr(a, &live_a)?;
+                let tb = self.expr(b, live)?;
+                let (opcodes, ty): (&[Opcode], Type) = match (op, ta, tb) {
+                    (BinOp::Eq, Type::Int, Type::Int) => (&[OP_NUMEQUAL], Type::Bool),
+                    (BinOp::Ne, Type::Int, Type::Int) => (&[OP_NUMNOTEQUAL], Type::Bool),
+                    (BinOp::Eq, _, _) if ta == tb || (ta.is_bytes() && tb.is_bytes()) => (&[OP_EQUAL], Type::Bool),
+                    (BinOp::Ne, _, _) if ta == tb || (ta.is_bytes() && tb.is_bytes()) => (&[OP_EQUAL, OP_NOT], Type::Bool),
+                    (BinOp::Lt, Type::Int, Type::Int) => (&[OP_LESSTHAN], Type::Bool),
+                    (BinOp::Le, Type::Int, Type::Int) => (&[OP_LESSTHANOREQUAL], Type::Bool),
+                    (BinOp::Gt, Type::Int, Type::Int) => (&[OP_GREATERTHAN], Type::Bool),
+                    (BinOp::Ge, Type::Int, Type::Int) => (&[OP_GREATERTHANOREQUAL], Type::Bool),
+                    (BinOp::Add, Type::Int, Type::Int) => (&[OP_ADD], Type::Int),
+                    (BinOp::Sub, Type::Int, Type::Int) => (&[OP_SUB], Type::Int),
+                    (BinOp::And, Type::Bool, Type::Bool) => (&[OP_BOOLAND], Type::Bool),
+                    (BinOp::Or, Type::Bool, Type::Bool) => (&[OP_BOOLOR], Type::Bool),
+                    _ => return Err(error(e.pos, &format!("Can't apply {:?} to {} and {}", op, ta, tb)))
+                };
+                for op in opcodes {
+                    self.op(*op);
+                }
+                self.reduce(2);
+                ty
+            }
+            ExprKind::Call(name, args) => return self.call(name, args, e.pos, live),
+            ExprKind::If(c, then, other) => match self.if_else(c, then, other, live)? {
+                Some(ty) => ty,
+                None => return Err(error(e.pos, "This if gives no value, both branches must end with an expression"))
+            }
+        };
+        Ok(ty)
+    }
+
+    fn var(&mut self, name: &str, id: VarId, pos: (usize, usize), live: &HashSet<VarId>) -> Result<Type, CompileError> {
+        if let Some((ty, value)) = self.constants.get(&id).cloned() {
+            self.expr(&value, live)?;
+            return Ok(ty)
+        }
+        let ty = *self.types.get(&id).ok_or_else(|| error(pos, &format!("Unknown variable {}", name)))?;
+        let i = self.stack.iter().rposition(|s| *s == Some(id))
+            .ok_or_else(|| error(pos, &format!("{} is no longer on the stack", name)))?;
+        let depth = self.stack.len() - 1 - i;
+        match (live.contains(&id), depth) {
+            (true, 0) => self.op(OP_DUP),
+            (true, 1) => self.op(OP_OVER),
+            (true, _) => self.emit(|b| b.push_int(depth as i64).push_opcode(OP_PICK)),
+            (false, 0) => {}
+            (false, 1) => self.op(OP_SWAP),
+            (false, 2) => self.op(OP_ROT),
+            (false, _) => self.emit(|b| b.push_int(depth as i64).push_opcode(OP_ROLL))
+        }
+        if !live.contains(&id) {
+            self.stack.remove(i);
+        }
+        self.stack.push(None);
+        Ok(ty)
+    }
+
+    fn call(&mut self, name: &str, args: &[Expr], pos: (usize, usize), live: &HashSet<VarId>) -> Result<Type, CompileError> {
+        let arity = match name {
+            "checkSig" => 2,
+            "sha256" => 1,
+            "after" | "older" => return Err(error(pos, &format!("{} can only be used as `verify {}(n);`", name, name))),
+            _ => return Err(error(pos, &format!("Unknown function {}", name)))
+        };
+        if args.len() != arity {
+            return Err(error(pos, &format!("{} expects {} arguments, got {}", name, arity, args.len())))
+        }
+
+        match name {
+            // <sig> <key> OP_CHECKSIG
+            "checkSig" => {
+                let mut live_sig = live.clone();
+                expr_vars(&args[0], &mut live_sig);
+                let sig = self.expr(&args[1], &live_sig)?;
+                let key = self.expr(&args[0], live)?;
+                if key != Type::PubKey || sig != Type::Sig {
+                    return Err(error(pos, &format!("checkSig expects a PubKey and a Sig, not {} and {}", key, sig)))
+                }
+                self.op(OP_CHECKSIG);
+                self.reduce(2);
+                Ok(Type::Bool)
+            }
+            _ => {
+                let ty = self.expr(&args[0], live)?;
+                if !ty.is_bytes() {
+                    return Err(error(pos, &format!("sha256 expects bytes, not {}", ty)))
+                }
+                self.op(OP_SHA256);
+                self.reduce(1);
+                Ok(Type::Hash)
+            }
+        }
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::interpret::Interpreter;
+    use crate::transaction::*;
+    use secp256k1::SecretKey;
+
+    const HTLC: &str = "
+        contract HashTimeLock(sig: Sig, preimage: Bytes, claim: Bool) {
+            const alice: PubKey = 0x031b84c5567b126440995d3ed5aaba0565d71e1834604819ff9c17f5e9d5dd078f;
+            const bob: PubKey = 0x024d4b6cd1361032ca9bd2aeb9d900aa4d45d9ead80ac9423374c451a7254d0766;
+            const hash: Hash = 0x2bb80d537b1da3e38bd30361aa855686bde0eacd7162fef6a25fe97bf527a25b;
+
+            if claim {
+                verify sha256(preimage) == hash;
+                checkSig(bob, sig)
+            } else {
+                verify older(144);
+                checkSig(alice, sig)
+            }
+        }";
+
+    fn compiled(source: &str) -> String {
+        hex::encode(serialize(&compile(source).unwrap().script))
+    }
+
+    fn compile_error(source: &str) -> String {
+        compile(source).unwrap_err().to_string()
+    }
+
+    fn spend(contract: &Contract, sequence: u32, secret: u8, values: &[(&str, Vec<u8>)]) -> bool {
+        let tx = Transaction {
+            version: 2,
+            inputs: vec![TxIn { previous_output: OutPoint::default(), script_sig: Vec::new(), sequence, witness: Vec::new() }],
+            outputs: vec![TxOut { value: 0, script_pubkey: vec![OP_RETURN.code] }],
+            lock_time: 0
+        };
+        let context = TransactionContext::new(tx, 0, 50_000, SigVersion::WitnessV0);
+        let script = serialize(&contract.script);
+        let sig = context.sign(&SecretKey::from_slice(&[secret; 32]).unwrap(), &script, SIGHASH_ALL);
+        let mut values = values.to_vec();
+        values.push(("sig", sig));
+
+        let mut interpreter = Interpreter::new(&script).with_transaction(context).clean_stack(true);
+        interpreter.stack_mut().main = contract.witness(&values).unwrap();
+        interpreter.run().success
+    }
+
+    #[test]
+    fn compiles_the_hash_time_lock() {
+        let contract = compile(HTLC).unwrap();
+        assert_eq!(contract.name, "HashTimeLock");
+        assert_eq!(contract.params, vec![
+            (String::from("sig"), Type::Sig),
+            (String::from("preimage"), Type::Bytes),
+            (String::from("claim"), Type::Bool)
+        ]);
+        // The preimage is dropped on the refund branch
+        assert_eq!(hex::encode(serialize(&contract.script)), concat!(
+            "63a8202bb80d537b1da3e38bd30361aa855686bde0eacd7162fef6a25fe97bf527a25b88",
+            "21024d4b6cd1361032ca9bd2aeb9d900aa4d45d9ead80ac9423374c451a7254d0766ac",
+            "6775029000b275",
+            "21031b84c5567b126440995d3ed5aaba0565d71e1834604819ff9c17f5e9d5dd078fac68"
+        ));
+    }
+
+    #[test]
+    fn spends_the_hash_time_lock() {
+        let contract = compile(HTLC).unwrap();
+        let claim = [("preimage", b"secret".to_vec()), ("claim", vec![1])];
+        assert!(spend(&contract, SEQUENCE_FINAL, 2, &claim));
+        assert!(!spend(&contract, SEQUENCE_FINAL, 1, &claim));
+        assert!(!spend(&contract, SEQUENCE_FINAL, 2, &[("preimage", b"secreT".to_vec()), ("claim", vec![1])]));
+
+        let refund = [("preimage", Vec::new()), ("claim", Vec::new())];
+        assert!(spend(&contract, 144, 1, &refund));
+        assert!(!spend(&contract, 143, 1, &refund));
+        assert!(!spend(&contract, 144, 2, &refund));
+    }
+
+    #[test]
+    fn abi_and_witness() {
+        let contract = compile(HTLC).unwrap();
+        let abi = contract.abi();
+        assert_eq!(abi["contract"], "HashTimeLock");
+        assert_eq!(abi["script"], hex::encode(serialize(&contract.script)));
+        assert_eq!(abi["witness"][1], json!({
+            "index": 1,
+            "name": "preimage",
+            "type": "Bytes",
+            "encoding": "any data up to 520 bytes"
+        }));
+        assert_eq!(abi["witness"].as_array().unwrap().len(), 3);
+
+        // Values are put in parameter order, whatever order they are given in
+        let witness = contract.witness(&[("claim", vec![1]), ("sig", Vec::new()), ("preimage", b"secret".to_vec())]);
+        assert_eq!(witness, Ok(vec![Vec::new(), b"secret".to_vec(), vec![1]]));
+
+        assert_eq!(contract.witness(&[("sig", Vec::new()), ("preimage", Vec::new())]), Err(String::from("Missing value for claim")));
+        assert_eq!(contract.witness(&[("key", Vec::new())]), Err(String::from("HashTimeLock has no parameter key")));
+        assert_eq!(contract.witness(&[("sig", Vec::new()), ("preimage", Vec::new()), ("claim", vec![2])]),
+            Err(String::from("claim: 0x02 is not a valid Bool (empty for false, 0x01 for true)")));
+    }
+
+    #[test]
+    fn checks_values() {
+        assert!(Type::Int.check_value(&[]).is_ok());
+        assert!(Type::Int.check_value(&[0x90, 0x00]).is_ok());
+        assert!(Type::Int.check_value(&[0x00]).is_err());
+        assert!(Type::Int.check_value(&[0x01, 0x00]).is_err());
+        assert!(Type::Bool.check_value(&[1]).is_ok());
+        assert!(Type::Bool.check_value(&[0]).is_err());
+        assert!(Type::Bytes.check_value(&[0; 520]).is_ok());
+        assert!(Type::Bytes.check_value(&[0; 521]).is_err());
+        let key = hex::decode("031b84c5567b126440995d3ed5aaba0565d71e1834604819ff9c17f5e9d5dd078f").unwrap();
+        assert!(Type::PubKey.check_value(&key).is_ok());
+        assert!(Type::PubKey.check_value(&key[..32]).is_err());
+        assert!(Type::PubKey.check_value(&[&[0x05], &key[1..]].concat()).is_err());
+        assert!(Type::Sig.check_value(&[]).is_ok());
+        assert!(Type::Sig.check_value(&[0x31, 0x01]).is_err());
+        assert!(Type::Hash.check_value(&[0; 32]).is_ok());
+        assert!(Type::Hash.check_value(&[0; 20]).is_err());
+
+        assert_eq!("PubKey".parse(), Ok(Type::PubKey));
+        assert!("pubkey".parse::<Type>().is_err());
+    }
+
+    #[test]
+    fn stack_scheduling() {
+        assert_eq!(compiled("contract A(x: Int) { x + 1 == 3 }"), "5193539c");
+        // Values still used later are copied, the last use moves them
+        assert_eq!(compiled("contract A(x: Int, y: Int) { verify x < y; y - x == 2 }"), "6e9f697c94529c");
+        assert_eq!(compiled("contract A(x: Int) { let y = x + x; y == x }"), "7678939c");
+        assert_eq!(compiled("contract A(b: Bool, x: Int) { if b { x == 1 } else { x == 2 } }"), "7c63519c67529c68");
+    }
+
+    #[test]
+    fn branch_bindings_are_scoped() {
+        // The t of the first branch is dropped before the if ends, the second t is another variable
+        let contract = compile("contract A(b: Bool, x: Int) {
+            if b { let t = x + 1; verify t == 2; } else { verify x == 3; }
+            let t = 5;
+            t == 5
+        }").unwrap();
+        for (b, x, success) in [(vec![1], 1, true), (Vec::new(), 3, true), (vec![1], 3, false)] {
+            let mut interpreter = Interpreter::new(&serialize(&contract.script)).clean_stack(true);
+            interpreter.stack_mut().main = contract.witness(&[("b", b), ("x", to_script_nb(x))]).unwrap();
+            assert_eq!(interpreter.run().success, success);
+        }
+        assert_eq!(compile_error("contract A(x: Int) { if x == 1 { let t = 1; } else { } t == 1 }"), "1:56: Unknown variable t");
+    }
+
+    #[test]
+    fn numbers_fit_in_four_bytes() {
+        assert_eq!(compiled("contract A(x: Int) { x == 2147483647 }"), "04ffffff7f9c");
+        assert_eq!(compiled("contract A(x: Int) { x == -2147483647 }"), "04ffffffff9c");
+        assert_eq!(compile_error("contract A(x: Int) { x == 2147483648 }"), "1:27: Number out of range: 2147483648 (at most 2147483647)");
+        assert_eq!(compile_error("contract A() { const c: Int = 4294967296; true }"),
+            "1:31: Number out of range: 4294967296 (at most 2147483647)");
+    }
+
+    #[test]
+    fn timelocks() {
+        // The number left by a final timelock is the result of the contract
+        assert_eq!(compiled("contract A() { verify older(5); }"), "55b2");
+        assert_eq!(compiled("contract A(x: Int) { verify after(500000); }"), "750320a107b1");
+        assert_eq!(compile_error("contract A() { older(5) }"), "1:16: older can only be used as `verify older(n);`");
+    }
+
+    #[test]
+    fn errors() {
+        assert_eq!(compile_error("contract A(x: Int) { x }"), "1:24: The contract must give a Bool, not Int");
+        assert_eq!(compile_error("contract A(x: Int) {\n  verify x;\n}"), "3:1: The contract must give a Bool, not Int");
+        assert_eq!(compile_error("contract A(x: Foo) { true }"),
+            "1:15: Unknown type: Foo (expected Int, Bool, Bytes, PubKey, Sig or Hash)");
+        assert_eq!(compile_error("contract A(x: Int, x: Int) { true }"), "1:20: Duplicate parameter x");
+        assert_eq!(compile_error("contract A() { if true { const c: Int = 1; } true }"),
+            "1:26: Constants can only be declared at the top level of the contract");
+        assert_eq!(compile_error("contract A() { checkSig(1) }"), "1:16: checkSig expects 2 arguments, got 1");
+        assert_eq!(compile_error("contract A() { y }"), "1:16: Unknown variable y");
+        assert_eq!(compile_error("contract A() { 1 + true }"), "1:18: Can't apply Add to Int and Bool");
+        assert_eq!(compile_error("contract A() { const k: PubKey = 0x00; true }"),
+            "1:34: 0x00 is not a valid PubKey (compressed public key, 33 bytes)");
+        assert_eq!(compile_error("contract A() { true } x"), "1:23: Expected end of file, found x");
+        assert_eq!(compile_error("contract A() { $ }"), "1:16: Unexpected character: $");
+        assert_eq!(compile_error("contract A(b: Bool) { if b { 1 } else { true } }"), "1:46: The branches of this if give Int and Bool");
+        assert_eq!(compile("").unwrap_err(), CompileError { line: 1, column: 1, message: String::from("Expected `contract`, found end of file") });
+    }
+}
@@ -0,0 +1,442 @@
+use std::collections::HashMap;
+use std::io::{self, BufRead, Write};
+use std::path::Path;
+
+use serde_json::{json, Value};
+
+use crate::asm::SourceMap;
+use crate::debugger::{format_element, Breakpoint, Debugger, ElementFormat};
+use crate::macro_asm::assemble_file;
+use crate::opcodes::{Opcode, OP_IF, OP_NOTIF};
+use crate::protocol::{read_message, write_message};
+
+const THREAD_ID: i64 = 1;
+const MAIN_STACK_REF: i64 = 1;
+const ALT_STACK_REF: i64 = 2;
+const CONDITION_STACK_REF: i64 = 3;
+
+// Script being debugged, loaded from an ASM source file
+struct Session {
+    debugger: Debugger,
+    source_map: SourceMap,
+    path: String
+}
+
+// Debug Adapter Protocol server, lets editors such as VS Code drive the debugger
+pub struct DapServer<W: Write> {
+    writer: W,
+    seq: i64,
+    session: Option<Session>,
+    stop_on_entry: bool,
+    // Lines requested by the editor for every source path, kept to resolve them again if the
+    // program is reloaded
+    breakpoint_lines: HashMap<String, Vec<usize>>
+}
+
+impl Session {
+    // Index in the source map of the file at `path`, the assembled file or one it includes
+    fn file_index(&self, path: &str) -> Option<usize> {
+        let canonical = |p: &str| Path::new(p).canonicalize().ok();
+        self.source_map.files().iter().position(|(file, _)| {
+            file == path || (canonical(file).is_some() && canonical(file) == canonical(path))
+        })
+    }
+
+    // Offsets of the instructions a breakpoint on `line` of `path` stops at, with the actual line
+    fn resolve(&self, path: &str, line: usize) -> Option<(Vec<usize>, usize)> {
+        self.source_map.offsets_in_file(self.file_index(path)?, line)
+    }
+}
+
+impl<W: Write> DapServer<W> {
+    pub fn new(writer: W) -> DapServer<W> {
+        DapServer { writer, seq: 0, session: None, stop_on_entry: true, breakpoint_lines: HashMap::new() }
+    }
+
+    // Handle requests until the client disconnects
+    pub fn serve(&mut self, reader: &mut impl BufRead) -> io::Result<()> {
+        while let Some(request) = read_message(reader)? {
+            if !self.handle(&request)? {
+                break
+            }
+        }
+        Ok(())
+    }
+
+    // Returns false once the client asked to disconnect
+    pub fn handle(&mut self, request: &Value) -> io::Result<bool> {
+        let command = request["command"].as_str().unwrap_or("");
+        let args = &request["arguments"];
+
+        let result = match command {
+            "initialize" => Ok(json!({
+                "supportsConfigurationDoneRequest": true,
+                "supportsStepBack": true,
+                "supportsRestartRequest": true
+            })),
+            "launch" => self.launch(args),
+            "setBreakpoints" => Ok(self.set_breakpoints(args)),
+            "setExceptionBreakpoints" => Ok(json!({})),
+            "configurationDone" => {
+                self.respond(request, Ok(json!({})))?;
+                if self.stop_on_entry {
+                    self.stopped("entry")?;
+                } else {
+                    // A breakpoint on the first instruction stops before it is executed
+                    self.resume(|d| if !d.at_breakpoint() { d.continue_execution(); })?;
+                }
+                return Ok(true)
+            }
+            "threads" => Ok(json!({"threads": [{"id": THREAD_ID, "name": "script"}]})),
+            "stackTrace" => self.stack_trace(),
+            "scopes" => Ok(json!({"scopes": [
+                {"name": "Main Stack", "variablesReference": MAIN_STACK_REF, "expensive": false},
+                {"name": "Alt Stack", "variablesReference": ALT_STACK_REF, "expensive": false},
+                {"name": "Condition Stack", "variablesReference": CONDITION_STACK_REF, "expensive": false}
+            ]})),
+            "variables" => self.variables(args["variablesReference"].as_i64().unwrap_or(0)),
+            "continue" | "next" | "stepIn" | "stepOut" | "stepBack" | "reverseContinue" => {
+                if self.session.is_none() {
+                    Err(String::from("No program loaded"))
+                } else {
+                    self.respond(request, Ok(json!({"allThreadsContinued": true})))?;
+                    match command {
+                        "continue" => self.resume(|d| { d.continue_execution(); })?,
+                        "next" => self.resume(step_over)?,
+                        "stepIn" => self.resume(|d| { d.step(1); })?,
+                        "stepOut" => self.resume(step_out)?,
+                        "stepBack" => self.resume(|d| d.back(1))?,
+                        _ => self.resume(reverse_continue)?
+                    }
+                    return Ok(true)
+                }
+            }
+            "restart" => {
+                if let Some(session) = &mut self.session {
+                    session.debugger.goto(0);
+                }
+                self.respond(request, Ok(json!({})))?;
+                self.stopped("entry")?;
+                return Ok(true)
+            }
+            "disconnect" | "terminate" => {
+                self.respond(request, Ok(json!({})))?;
+                return Ok(false)
+            }
+            _ => Err(format!("Unsupported request: {}", command))
+        };
+
+        self.respond(request, result)?;
+        if command == "launch" && self.session.is_some() {
+            // Breakpoints can only be resolved once the source is known
+            self.event("initialized", json!({}))?;
+        }
+        Ok(true)
+    }
+
+    fn launch(&mut self, args: &Value) -> Result<Value, String> {
+        let path = args["program"].as_str().ok_or("Missing program path")?.to_string();
+        let (script, source_map) = assemble_file(Path::new(&path)).map_err(|e| e.to_string())?;
+
+        self.stop_on_entry = args["stopOnEntry"].as_bool().unwrap_or(true);
+        self.session = Some(Session { debugger: Debugger::new(&script), source_map, path });
+        self.resolve_breakpoints();
+        Ok(json!({}))
+    }
+
+    // Breakpoints replace the previous ones of the same source only
+    fn set_breakpoints(&mut self, args: &Value) -> Value {
+        let path = args["source"]["path"].as_str().unwrap_or_default().to_string();
+        let lines: Vec<usize> = args["breakpoints"].as_array()
+            .map(|b| b.iter().filter_map(|b| b["line"].as_u64()).map(|l| l as usize).collect())
+            .unwrap_or_default();
+        self.breakpoint_lines.insert(path.clone(), lines.clone());
+        self.resolve_breakpoints();
+
+        let breakpoints: Vec<Value> = lines.iter().map(|line| {
+            match self.session.as_ref().and_then(|session| session.resolve(&path, *line)) {
+                Some((_, actual)) => json!({"verified": true, "line": actual}),
+                None => json!({"verified": false, "line": line, "message": "No instruction on or after this line"})
+            }
+        }).collect();
+        json!({"breakpoints": breakpoints})
+    }
+
+    // Map every requested line of every source to the offsets of its instructions
+    fn resolve_breakpoints(&mut self) {
+        let session = match &mut self.session {
+            Some(session) => session,
+            None => return
+        };
+        let offsets: Vec<usize> = self.breakpoint_lines.iter()
+            .flat_map(|(path, lines)| lines.iter().map(move |line| (path, *line)))
+            .filter_map(|(path, line)| session.resolve(path, line))
+            .flat_map(|(offsets, _)| offsets)
+            .collect();
+        session.debugger.clear_breakpoints();
+        for offset in offsets {
+            session.debugger.add_breakpoint(Breakpoint::Offset(offset));
+        }
+    }
+
+    fn stack_trace(&self) -> Result<Value, String> {
+        let session = self.session.as_ref().ok_or("No program loaded")?;
+        let state = session.debugger.interpreter().state();
+        let name = match state.script().get(state.pc()) {
+            Some(b) => Opcode::from(*b).to_string(),
+            None => String::from("end of script")
+        };
+        // Code expanded from a macro or an include is shown where it is written, past the end of
+        // the script on the line of the last instruction. A script without instructions has no frame.
+        let (path, line) = match session.source_map.location_of(state.pc()) {
+            Some(location) => (location.file.unwrap_or(&session.path), location.line),
+            None => return Ok(json!({"stackFrames": [], "totalFrames": 0}))
+        };
+        Ok(json!({
+            "stackFrames": [{
+                "id": 1,
+                "name": name,
+                "source": {"name": path.rsplit('/').next(), "path": path},
+                "line": line,
+                "column": 1
+            }],
+            "totalFrames": 1
+        }))
+    }
+
+    fn variables(&self, reference: i64) -> Result<Value, String> {
+        let session = self.session.as_ref().ok_or("No program loaded")?;
+        let state = session.debugger.interpreter().state();
+        let element = |(i, bytes): (usize, &Vec<u8>)| json!({
+            "name": format!("[{}]", i),
+            "value": format_element(bytes, ElementFormat::Hex),
+            "type": format!("{} bytes, number {}", bytes.len(), format_element(bytes, ElementFormat::Number)),
+            "variablesReference": 0
+        });
+
+        // Elements are listed from the top of the stack
+        let variables: Vec<Value> = match reference {
+            MAIN_STACK_REF => state.main_stack().iter().rev().enumerate().map(element).collect(),
+            ALT_STACK_REF => state.alt_stack().iter().rev().enumerate().map(element).collect(),
+            CONDITION_STACK_REF => state.condition_stack().iter().rev().enumerate().map(|(i, c)| json!({
+                "name": format!("[{}]", i),
+                "value": c.to_string(),
+                "variablesReference": 0
+            })).collect(),
+            _ => Vec::new()
+        };
+        Ok(json!({"variables": variables}))
+    }
+
+    // Run an execution command then tell the client where it stopped
+    fn resume(&mut self, action: impl FnOnce(&mut Debugger)) -> io::Result<()> {
+        let session = match &mut self.session {
+            Some(session) => session,
+            None => return Ok(())
+        };
+        action(&mut session.debugger);
+
+        let debugger = &session.debugger;
+        if debugger.interpreter().state().is_finished() {
+            match debugger.result().error {
+                Some(e) => {
+                    let text = format!("{} ({}) at offset {}", e.error, e.error.name(), e.offset);
+                    self.output(&format!("Script failed: {}\n", text))?;
+                    self.event("stopped", json!({"reason": "exception", "description": text, "threadId": THREAD_ID}))
+                }
+                None => {
+                    self.output("Script succeeded\n")?;
+                    self.event("terminated", json!({}))
+                }
+            }
+        } else if debugger.at_breakpoint() {
+            self.stopped("breakpoint")
+        } else {
+            self.stopped("step")
+        }
+    }
+
+    fn stopped(&mut self, reason: &str) -> io::Result<()> {
+        self.event("stopped", json!({"reason": reason, "threadId": THREAD_ID, "allThreadsStopped": true}))
+    }
+
+    fn output(&mut self, text: &str) -> io::Result<()> {
+        self.event("output", json!({"category": "console", "output": text}))
+    }
+
+    fn respond(&mut self, request: &Value, result: Result<Value, String>) -> io::Result<()> {
+        let mut response = json!({
+            "seq": self.next_seq(),
+            "type": "response",
+            "request_seq": request["seq"],
+            "command": request["command"],
+            "success": result.is_ok()
+        });
+        match result {
+            Ok(body) => response["body"] = body,
+            Err(message) => response["message"] = json!(message)
+        }
+        write_message(&mut self.writer, &response)
+    }
+
+    fn event(&mut self, event: &str, body: Value) -> io::Result<()> {
+        let message = json!({"seq": self.next_seq(), "type": "event", "event": event, "body": body});
+        write_message(&mut self.writer, &message)
+    }
+
+    fn next_seq(&mut self) -> i64 {
+        self.seq += 1;
+        self.seq
+    }
+}
+
+// Step over a whole OP_IF/OP_NOTIF block, or a single instruction otherwise
+fn step_over(debugger: &mut Debugger) {
+    let state = debugger.interpreter().state();
+    let next = state.script().get(state.pc()).map(|b| Opcode::from(*b));
+    let depth = state.condition_stack().len();
+
+    if !debugger.step(1) || !matches!(next, Some(OP_IF) | Some(OP_NOTIF)) {
+        return
+    }
+    while debugger.interpreter().state().condition_stack().len() > depth
+        && !debugger.at_breakpoint() && debugger.step(1) {}
+}
+
+// Run until the end of the current OP_IF block
+fn step_out(debugger: &mut Debugger) {
+    let depth = debugger.interpreter().state().condition_stack().len();
+    if depth == 0 {
+        debugger.continue_execution();
+        return
+    }
+    while debugger.step(1) && debugger.interpreter().state().condition_stack().len() >= depth
+        && !debugger.at_breakpoint() {}
+}
+
+// Go back until the previous breakpoint or the start of the script
+fn reverse_continue(debugger: &mut Debugger) {
+    loop {
+        debugger.back(1);
+        if debugger.at_breakpoint() || debugger.interpreter().state().steps() == 0 {
+            return
+        }
+    }
+}
+
+// Serve DAP requests on stdin and stdout
+pub fn run_stdio() -> io::Result<()> {
+    let stdin = io::stdin();
+    DapServer::new(io::stdout()).serve(&mut stdin.lock())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+    use std::io::Cursor;
+    use std::path::PathBuf;
+
+    use super::*;
+
+    const MAIN: &str = "include \"lib.asm\"\nOP_1 OP_2\nadd_check(3)\nOP_1\n";
+    const LIB: &str = "macro add_check(sum)\n    OP_ADD\n    sum OP_EQUALVERIFY\nendmacro\n";
+
+    // Directory holding main.asm, which includes lib.asm
+    fn program(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("dap-{}-{}", name, std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(dir.join("main.asm"), MAIN).unwrap();
+        fs::write(dir.join("lib.asm"), LIB).unwrap();
+        dir
+    }
+
+    // Messages sent back by the server for one request
+    fn request(server: &mut DapServer<Vec<u8>>, command: &str, arguments: Value) -> Vec<Value> {
+        server.writer.clear();
+        server.handle(&json!({"seq": 1, "type": "request", "command": command, "arguments": arguments})).unwrap();
+        let mut reader = Cursor::new(server.writer.clone());
+        std::iter::from_fn(|| read_message(&mut reader).unwrap()).collect()
+    }
+
+    fn launch(dir: &Path, stop_on_entry: bool, breakpoints: &[(&str, usize)]) -> DapServer<Vec<u8>> {
+        let mut server = DapServer::new(Vec::new());
+        let program = dir.join("main.asm").display().to_string();
+        request(&mut server, "launch", json!({"program": program, "stopOnEntry": stop_on_entry}));
+        for file in ["main.asm", "lib.asm"] {
+            let lines: Vec<Value> = breakpoints.iter().filter(|(f, _)| *f == file).map(|(_, l)| json!({"line": l})).collect();
+            request(&mut server, "setBreakpoints", json!({"source": {"path": dir.join(file)}, "breakpoints": lines}));
+        }
+        server
+    }
+
+    // (file name, line) of the only frame
+    fn location(server: &mut DapServer<Vec<u8>>) -> (String, u64) {
+        let frame = &request(server, "stackTrace", json!({}))[0]["body"]["stackFrames"][0];
+        (frame["source"]["name"].as_str().unwrap().to_string(), frame["line"].as_u64().unwrap())
+    }
+
+    fn pc(server: &DapServer<Vec<u8>>) -> usize {
+        server.session.as_ref().unwrap().debugger.interpreter().state().pc()
+    }
+
+    #[test]
+    fn breakpoints_are_kept_per_source() {
+        let dir = program("sources");
+        let mut server = launch(&dir, true, &[("lib.asm", 3), ("main.asm", 4)]);
+        let response = request(&mut server, "setBreakpoints", json!({
+            "source": {"path": dir.join("lib.asm")},
+            "breakpoints": [{"line": 3}, {"line": 5}]
+        }));
+        assert_eq!(response[0]["body"]["breakpoints"], json!([
+            {"verified": true, "line": 3},
+            {"verified": false, "line": 5, "message": "No instruction on or after this line"}
+        ]));
+
+        request(&mut server, "configurationDone", json!({}));
+        let events = request(&mut server, "continue", json!({}));
+        assert_eq!(events[1]["body"]["reason"], "breakpoint");
+        assert_eq!(pc(&server), 3);
+        assert_eq!(location(&mut server), (String::from("lib.asm"), 3));
+
+        request(&mut server, "continue", json!({}));
+        assert_eq!(pc(&server), 5);
+        assert_eq!(location(&mut server), (String::from("main.asm"), 4));
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn macro_call_lines_stop_on_the_expansion() {
+        let dir = program("calls");
+        let mut server = launch(&dir, true, &[("main.asm", 3)]);
+        let offsets: Vec<Breakpoint> = server.session.as_ref().unwrap().debugger.breakpoints().to_vec();
+        assert_eq!(offsets, vec![Breakpoint::Offset(2)]);
+        request(&mut server, "continue", json!({}));
+        assert_eq!(location(&mut server), (String::from("lib.asm"), 2));
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn breakpoint_on_the_first_instruction_without_stop_on_entry() {
+        let dir = program("entry");
+        let mut server = launch(&dir, false, &[("main.asm", 2)]);
+        let events = request(&mut server, "configurationDone", json!({}));
+        assert_eq!(events[1]["body"]["reason"], "breakpoint");
+        assert_eq!(pc(&server), 0);
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn end_of_script_is_on_the_last_line() {
+        let dir = program("end");
+        let mut server = launch(&dir, false, &[]);
+        let events = request(&mut server, "configurationDone", json!({}));
+        assert_eq!(events.last().unwrap()["event"], "terminated");
+        let frame = &request(&mut server, "stackTrace", json!({}))[0]["body"]["stackFrames"][0];
+        assert_eq!((frame["name"].as_str(), frame["line"].as_u64()), (Some("end of script"), Some(4)));
+
+        fs::write(dir.join("main.asm"), "# nothing\n").unwrap();
+        let mut server = launch(&dir, true, &[]);
+        assert_eq!(request(&mut server, "stackTrace", json!({}))[0]["body"], json!({"stackFrames": [], "totalFrames": 0}));
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
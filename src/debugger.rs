@@ -0,0 +1,668 @@
+use std::collections::BTreeSet;
+use std::fmt;
+use std::io::{self, BufRead, Write};
+use std::str::FromStr;
+
+use colored::Colorize;
+
+use crate::asm::SourceMap;
+use crate::interpret::{ExecutionResult, ExecutionState, Interpreter, Stack};
+use crate::observer::print_state;
+use crate::opcodes::{Opcode, OP_ELSE, OP_IF, OP_NOTIF};
+use crate::script::{as_script_nb, ScriptItem};
+
+const HELP: &str = "Commands:
+    s, step [n]                     Execute the next n instructions (default 1)
+    c, continue                     Run until the next breakpoint or the end of the script
+    back [n]                        Go back n steps (default 1)
+    g, goto <step>                  Go to the state after the given step number
+    rewind                          Go back to the last branch decision (OP_IF, OP_NOTIF, OP_ELSE)
+    b, break <offset|opcode>        Add a breakpoint on a byte offset or an opcode (e.g. OP_CHECKSIG)
+    d, delete <offset|opcode>       Remove a breakpoint
+    bl, breakpoints                 List breakpoints
+    p, print <main|alt> <i> [fmt]   Show element i (0 is the top) as num, hex or ascii (default all)
+    set <main|alt> <i> <hex>        Replace element i (0 is the top)
+    push <main|alt> <hex>           Push an element
+    pop <main|alt>                  Pop the top element
+    state                           Show the remaining script and the stacks
+    h, help                         Show this help
+    q, quit                         Exit the debugger";
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum Breakpoint {
+    Offset(usize),
+    Opcode(Opcode)
+}
+
+impl FromStr for Breakpoint {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.parse::<usize>() {
+            Ok(offset) => Ok(Breakpoint::Offset(offset)),
+            Err(_) => Ok(Breakpoint::Opcode(s.parse()?))
+        }
+    }
+}
+
+impl fmt::Display for Breakpoint {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Breakpoint::Offset(offset) => write!(f, "offset {}", offset),
+            Breakpoint::Opcode(op) => write!(f, "{}", op)
+        }
+    }
+}
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum ElementFormat {
+    Number,
+    Hex,
+    Ascii
+}
+
+impl FromStr for ElementFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "num" | "number" => Ok(ElementFormat::Number),
+            "hex" => Ok(ElementFormat::Hex),
+            "ascii" | "str" => Ok(ElementFormat::Ascii),
+            _ => Err(format!("Unknown format: {}", s))
+        }
+    }
+}
+
+// Decode a stack element for display
+pub fn format_element(bytes: &[u8], format: ElementFormat) -> String {
+    match format {
+        ElementFormat::Number => match as_script_nb(bytes) {
+            Ok(n) => n.to_string(),
+            Err(_) => String::from("<not a number>")
+        },
+        ElementFormat::Hex => format!("0x{}", hex::encode(bytes)),
+        ElementFormat::Ascii => bytes.iter().map(|b| {
+            if b.is_ascii_graphic() || *b == b' ' {
+                (*b as char).to_string()
+            } else {
+                format!("\\x{:02x}", b)
+            }
+        }).collect()
+    }
+}
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub(crate) enum StackName {
+    Main,
+    Alt
+}
+
+impl FromStr for StackName {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "main" | "m" => Ok(StackName::Main),
+            "alt" | "a" => Ok(StackName::Alt),
+            _ => Err(format!("Unknown stack: {} (expected main or alt)", s))
+        }
+    }
+}
+
+// Default number of steps between two checkpoints, doubled whenever there are too many of them
+const CHECKPOINT_INTERVAL: usize = 16;
+const MAX_CHECKPOINTS: usize = 64;
+
+struct Checkpoint {
+    state: ExecutionState,
+    // Checkpoints taken after the stacks were edited by hand, they can't be replayed
+    pinned: bool
+}
+
+// Snapshots of the execution used to step backwards
+// Only one state every `interval` steps is kept, other states are rebuilt by replaying the
+// script from the closest checkpoint, which keeps memory bounded for long scripts
+pub struct History {
+    checkpoints: Vec<Checkpoint>,
+    interval: usize,
+    max_checkpoints: usize,
+    // Step numbers right before an executed OP_IF, OP_NOTIF or OP_ELSE
+    branches: BTreeSet<usize>
+}
+
+impl History {
+    pub fn new(initial: ExecutionState, interval: usize, max_checkpoints: usize) -> History {
+        History {
+            checkpoints: vec![Checkpoint { state: initial, pinned: false }],
+            interval: interval.max(1),
+            max_checkpoints: max_checkpoints.max(1),
+            branches: BTreeSet::new()
+        }
+    }
+
+    pub fn checkpoint_count(&self) -> usize {
+        self.checkpoints.len()
+    }
+
+    pub fn branches(&self) -> &BTreeSet<usize> {
+        &self.branches
+    }
+
+    // Called after every step going forward
+    // Returns the state to continue from when the stacks were edited at this step
+    fn record(&mut self, state: &ExecutionState) -> Option<ExecutionState> {
+        let steps = state.steps();
+        if let Some(checkpoint) = self.checkpoints.iter().find(|c| c.state.steps() == steps) {
+            return checkpoint.pinned.then(|| checkpoint.state.clone())
+        }
+
+        if steps.is_multiple_of(self.interval) {
+            let idx = self.checkpoints.partition_point(|c| c.state.steps() < steps);
+            self.checkpoints.insert(idx, Checkpoint { state: state.clone(), pinned: false });
+            self.thin();
+        }
+        None
+    }
+
+    fn record_branch(&mut self, steps: usize) {
+        self.branches.insert(steps);
+    }
+
+    // The stacks were edited: everything recorded after this step belongs to another execution
+    fn record_edit(&mut self, state: &ExecutionState) {
+        let steps = state.steps();
+        self.checkpoints.retain(|c| c.state.steps() < steps);
+        self.checkpoints.push(Checkpoint { state: state.clone(), pinned: true });
+        self.branches.retain(|b| *b <= steps);
+    }
+
+    // Drop every other checkpoint until there are few enough of them
+    fn thin(&mut self) {
+        while self.checkpoints.len() > self.max_checkpoints
+            && self.checkpoints.iter().any(|c| !c.pinned && !c.state.steps().is_multiple_of(self.interval * 2)) {
+            self.interval *= 2;
+            let interval = self.interval;
+            self.checkpoints.retain(|c| c.pinned || c.state.steps().is_multiple_of(interval));
+        }
+    }
+
+    // Latest checkpoint at or before the given step
+    fn checkpoint_before(&self, steps: usize) -> ExecutionState {
+        let idx = self.checkpoints.partition_point(|c| c.state.steps() <= steps);
+        self.checkpoints[idx.saturating_sub(1)].state.clone()
+    }
+}
+
+// Result of a debugger command, tells the REPL whether to keep going
+#[derive(Debug, Eq, PartialEq)]
+pub enum CommandOutcome {
+    Continue,
+    Quit
+}
+
+pub struct Debugger {
+    interpreter: Interpreter,
+    breakpoints: Vec<Breakpoint>,
+    history: History,
+    // Offsets of the instructions skipped by a false branch so far
+    skipped: BTreeSet<usize>,
+    // Lines of the source for assembled scripts
+    source_map: Option<SourceMap>
+}
+
+impl Debugger {
+    pub fn new(script: &[u8]) -> Debugger {
+        let interpreter = Interpreter::new(script);
+        let history = History::new(interpreter.snapshot(), CHECKPOINT_INTERVAL, MAX_CHECKPOINTS);
+        Debugger { interpreter, breakpoints: Vec::new(), history, skipped: BTreeSet::new(), source_map: None }
+    }
+
+    // Show where instructions are written in the source when printing the state
+    pub fn with_source_map(mut self, source_map: SourceMap) -> Debugger {
+        self.source_map = Some(source_map);
+        self
+    }
+
+    pub fn history(&self) -> &History {
+        &self.history
+    }
+
+    pub fn skipped(&self) -> &BTreeSet<usize> {
+        &self.skipped
+    }
+
+    pub fn interpreter(&self) -> &Interpreter {
+        &self.interpreter
+    }
+
+    pub fn breakpoints(&self) -> &[Breakpoint] {
+        &self.breakpoints
+    }
+
+    pub fn add_breakpoint(&mut self, breakpoint: Breakpoint) {
+        if !self.breakpoints.contains(&breakpoint) {
+            self.breakpoints.push(breakpoint);
+        }
+    }
+
+    pub fn clear_breakpoints(&mut self) {
+        self.breakpoints.clear();
+    }
+
+    pub fn remove_breakpoint(&mut self, breakpoint: Breakpoint) -> bool {
+        let len = self.breakpoints.len();
+        self.breakpoints.retain(|b| *b != breakpoint);
+        len != self.breakpoints.len()
+    }
+
+    // Whether the next instruction matches a breakpoint
+    pub fn at_breakpoint(&self) -> bool {
+        let state = self.interpreter.state();
+        let next = state.script().get(state.pc()).map(|b| Opcode::from(*b));
+        self.breakpoints.iter().any(|b| match b {
+            Breakpoint::Offset(offset) => *offset == state.pc(),
+            Breakpoint::Opcode(op) => Some(*op) == next
+        })
+    }
+
+    // Execute up to `n` instructions, returns false once the execution is over
+    pub fn step(&mut self, n: usize) -> bool {
+        for _ in 0..n {
+            if !self.step_once() {
+                return false
+            }
+        }
+        !self.interpreter.state().is_finished()
+    }
+
+    fn step_once(&mut self) -> bool {
+        let state = self.interpreter.state();
+        let next = state.script().get(state.pc()).map(|b| Opcode::from(*b));
+        let outer_executing = state.condition_stack().iter().rev().skip(1).all(|c| *c);
+        let decision = match next {
+            Some(OP_IF) | Some(OP_NOTIF) => state.executing(),
+            Some(OP_ELSE) => outer_executing,
+            _ => false
+        };
+        if decision {
+            self.history.record_branch(state.steps());
+        }
+        match next {
+            Some(op) if !state.will_execute(&ScriptItem::Opcode(op)) => self.skipped.insert(state.pc()),
+            _ => self.skipped.remove(&state.pc())
+        };
+
+        if !matches!(self.interpreter.step(), Ok(true)) {
+            return false
+        }
+        if let Some(edited) = self.history.record(self.interpreter.state()) {
+            self.interpreter.restore(edited);
+        }
+        true
+    }
+
+    // Go to the state right after the given step, replaying from the closest checkpoint
+    // when going backwards
+    pub fn goto(&mut self, steps: usize) {
+        let current = self.interpreter.state().steps();
+        if steps < current {
+            self.interpreter.restore(self.history.checkpoint_before(steps));
+            let pc = self.interpreter.state().pc();
+            self.skipped.retain(|offset| *offset < pc);
+        }
+        while self.interpreter.state().steps() < steps {
+            if !self.step_once() {
+                break
+            }
+        }
+    }
+
+    pub fn back(&mut self, n: usize) {
+        let current = self.interpreter.state().steps();
+        self.goto(current.saturating_sub(n));
+    }
+
+    // Go back right before the last executed branch decision, returns false if there is none
+    pub fn rewind_to_branch(&mut self) -> bool {
+        let current = self.interpreter.state().steps();
+        match self.history.branches().range(..current).next_back() {
+            Some(steps) => {
+                self.goto(*steps);
+                true
+            }
+            None => false
+        }
+    }
+
+    // Run until the next breakpoint, always executing at least one instruction
+    pub fn continue_execution(&mut self) -> bool {
+        while self.step(1) {
+            if self.at_breakpoint() {
+                return true
+            }
+        }
+        false
+    }
+
+    pub fn result(&self) -> ExecutionResult {
+        self.interpreter.state().result()
+    }
+
+    pub fn print_state(&self) {
+        let state = self.interpreter.state();
+        print_state(state.stack(), &state.remaining_script(), state.steps());
+        let location = |offset| self.source_map.as_ref().and_then(|map| map.location_of(offset));
+        if state.is_finished() {
+            match self.result().error {
+                Some(e) => {
+                    println!("{} {} ({}) at offset {}", "Error:".bold().red(), e.error, e.error.name(), e.offset);
+                    if let Some(location) = location(e.offset) {
+                        println!("{} {}", "Source:".bold(), location);
+                    }
+                }
+                None => println!("{}", "Script succeeded".bold().green())
+            }
+        } else if let Some(location) = location(state.pc()) {
+            println!("{} {}", "Next:".bold(), location);
+        }
+    }
+
+    // Run one command line, the output is printed to stdout
+    pub fn execute_command(&mut self, line: &str) -> Result<CommandOutcome, String> {
+        let words: Vec<&str> = line.split_whitespace().collect();
+        let arg = |i: usize| words.get(i).copied().ok_or_else(|| String::from("Missing argument, see help"));
+
+        match words.first().copied().unwrap_or("") {
+            "" => {}
+            "s" | "step" => {
+                let n = match words.get(1) {
+                    Some(n) => n.parse().map_err(|_| format!("Invalid step count: {}", n))?,
+                    None => 1
+                };
+                self.step(n);
+                self.print_state();
+            }
+            "c" | "continue" => {
+                if self.continue_execution() {
+                    println!("Breakpoint reached at offset {}", self.interpreter.state().pc());
+                }
+                self.print_state();
+            }
+            "back" => {
+                let n = match words.get(1) {
+                    Some(n) => n.parse().map_err(|_| format!("Invalid step count: {}", n))?,
+                    None => 1
+                };
+                self.back(n);
+                self.print_state();
+            }
+            "g" | "goto" => {
+                let steps = arg(1)?.parse().map_err(|_| String::from("Invalid step number"))?;
+                self.goto(steps);
+                self.print_state();
+            }
+            "rewind" => {
+                if !self.rewind_to_branch() {
+                    return Err(String::from("No branch decision before this step"))
+                }
+                self.print_state();
+            }
+            "b" | "break" => {
+                let breakpoint = arg(1)?.parse()?;
+                self.add_breakpoint(breakpoint);
+                println!("Breakpoint added on {}", breakpoint);
+            }
+            "d" | "delete" => {
+                let breakpoint = arg(1)?.parse()?;
+                if !self.remove_breakpoint(breakpoint) {
+                    return Err(format!("No breakpoint on {}", breakpoint))
+                }
+            }
+            "bl" | "breakpoints" => {
+                for breakpoint in &self.breakpoints {
+                    println!("{}", breakpoint);
+                }
+            }
+            "p" | "print" => {
+                let stack = self.stack(arg(1)?.parse()?);
+                let element = element(stack, arg(2)?)?;
+                match words.get(3) {
+                    Some(format) => println!("{}", format_element(element, format.parse()?)),
+                    None => {
+                        for format in [ElementFormat::Hex, ElementFormat::Number, ElementFormat::Ascii] {
+                            println!("{:?}: {}", format, format_element(element, format));
+                        }
+                    }
+                }
+            }
+            "set" => {
+                let name = arg(1)?.parse()?;
+                let bytes = hex_arg(arg(3)?)?;
+                let stack = self.stack_mut(name);
+                let idx = index(stack, arg(2)?)?;
+                stack[idx] = bytes;
+                self.history.record_edit(self.interpreter.state());
+            }
+            "push" => {
+                let name = arg(1)?.parse()?;
+                let bytes = hex_arg(arg(2)?)?;
+                self.stack_mut(name).push(bytes);
+                self.history.record_edit(self.interpreter.state());
+            }
+            "pop" => {
+                let bytes = self.stack_mut(arg(1)?.parse()?).pop().ok_or("Stack is empty")?;
+                self.history.record_edit(self.interpreter.state());
+                println!("0x{}", hex::encode(bytes));
+            }
+            "state" => self.print_state(),
+            "h" | "help" => println!("{}", HELP),
+            "q" | "quit" => return Ok(CommandOutcome::Quit),
+            cmd => return Err(format!("Unknown command: {}, type help for the list of commands", cmd))
+        }
+        Ok(CommandOutcome::Continue)
+    }
+
+    // Read commands from stdin until quit or end of input
+    pub fn repl(&mut self) -> io::Result<()> {
+        self.print_state();
+        println!("\nType help for the list of commands");
+
+        let stdin = io::stdin();
+        loop {
+            print!("(debug) ");
+            io::stdout().flush()?;
+
+            let mut line = String::new();
+            if stdin.lock().read_line(&mut line)? == 0 {
+                return Ok(())
+            }
+            match self.execute_command(line.trim()) {
+                Ok(CommandOutcome::Quit) => return Ok(()),
+                Ok(CommandOutcome::Continue) => {}
+                Err(msg) => println!("{}", msg.red())
+            }
+        }
+    }
+
+    fn stack(&self, name: StackName) -> &Vec<Vec<u8>> {
+        match name {
+            StackName::Main => self.interpreter.state().main_stack(),
+            StackName::Alt => self.interpreter.state().alt_stack()
+        }
+    }
+
+    fn stack_mut(&mut self, name: StackName) -> &mut Vec<Vec<u8>> {
+        let stack: &mut Stack = self.interpreter.stack_mut();
+        match name {
+            StackName::Main => &mut stack.main,
+            StackName::Alt => &mut stack.alt
+        }
+    }
+}
+
+// Index in the Vec of the i-th element from the top of the stack
+fn index(stack: &[Vec<u8>], i: &str) -> Result<usize, String> {
+    let i: usize = i.parse().map_err(|_| format!("Invalid index: {}", i))?;
+    if i >= stack.len() {
+        return Err(format!("Index {} out of range, the stack has {} elements", i, stack.len()))
+    }
+    Ok(stack.len() - 1 - i)
+}
+
+fn element<'a>(stack: &'a [Vec<u8>], i: &str) -> Result<&'a [u8], String> {
+    Ok(&stack[index(stack, i)?])
+}
+
+fn hex_arg(s: &str) -> Result<Vec<u8>, String> {
+    hex::decode(s.trim_start_matches("0x")).map_err(|_| format!("Invalid hex: {}", s))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::opcodes::{OP_ADD, OP_CHECKSIG};
+
+    fn debugger(script: &str) -> Debugger {
+        Debugger::new(&hex::decode(script).unwrap())
+    }
+
+    fn main_stack(debugger: &Debugger) -> Vec<Vec<u8>> {
+        debugger.interpreter().state().main_stack().clone()
+    }
+
+    #[test]
+    fn breakpoint_syntax() {
+        assert_eq!("12".parse(), Ok(Breakpoint::Offset(12)));
+        assert_eq!("OP_CHECKSIG".parse(), Ok(Breakpoint::Opcode(OP_CHECKSIG)));
+        assert!("nope".parse::<Breakpoint>().is_err());
+        assert_eq!(Breakpoint::Offset(3).to_string(), "offset 3");
+        assert_eq!(Breakpoint::Opcode(OP_CHECKSIG).to_string(), "OP_CHECKSIG");
+    }
+
+    #[test]
+    fn continue_stops_on_breakpoints() {
+        // OP_1 OP_2 OP_ADD OP_3 OP_ADD
+        let mut debugger = debugger("5152935393");
+        debugger.add_breakpoint(Breakpoint::Offset(1));
+        debugger.add_breakpoint(Breakpoint::Opcode(OP_ADD));
+        debugger.add_breakpoint(Breakpoint::Offset(1));
+        assert_eq!(debugger.breakpoints().len(), 2);
+
+        let stops: Vec<usize> = std::iter::from_fn(|| {
+            debugger.continue_execution().then(|| debugger.interpreter().state().pc())
+        }).collect();
+        assert_eq!(stops, vec![1, 2, 4]);
+        assert!(debugger.result().success);
+
+        assert!(debugger.remove_breakpoint(Breakpoint::Offset(1)));
+        assert!(!debugger.remove_breakpoint(Breakpoint::Offset(1)));
+    }
+
+    #[test]
+    fn stack_editing_commands() {
+        // OP_1 OP_2 OP_ADD OP_4 OP_EQUAL
+        let mut debugger = debugger("5152935487");
+        debugger.execute_command("step 2").unwrap();
+        assert_eq!(main_stack(&debugger), vec![vec![1], vec![2]]);
+        debugger.execute_command("set main 0 03").unwrap();
+        debugger.execute_command("push alt 0xff").unwrap();
+        debugger.execute_command("pop alt").unwrap();
+        assert_eq!(debugger.execute_command("c"), Ok(CommandOutcome::Continue));
+        assert!(debugger.result().success);
+
+        assert_eq!(debugger.execute_command("set main 5 00"), Err(String::from("Index 5 out of range, the stack has 1 elements")));
+        assert_eq!(debugger.execute_command("pop alt"), Err(String::from("Stack is empty")));
+        assert_eq!(debugger.execute_command("push side 00"), Err(String::from("Unknown stack: side (expected main or alt)")));
+        assert_eq!(debugger.execute_command("delete 7"), Err(String::from("No breakpoint on offset 7")));
+        assert!(debugger.execute_command("jump").unwrap_err().starts_with("Unknown command: jump"));
+        assert_eq!(debugger.execute_command("quit"), Ok(CommandOutcome::Quit));
+    }
+
+    #[test]
+    fn element_formats() {
+        assert_eq!(format_element(&[0x90, 0x00], ElementFormat::Number), "144");
+        assert_eq!(format_element(&[0; 5], ElementFormat::Number), "<not a number>");
+        assert_eq!(format_element(b"hi\n", ElementFormat::Ascii), "hi\\x0a");
+        assert_eq!(format_element(&[0xab], ElementFormat::Hex), "0xab");
+        assert_eq!("str".parse(), Ok(ElementFormat::Ascii));
+    }
+
+    // What a replayed state must agree on with the original execution
+    fn position(debugger: &Debugger) -> (usize, usize, Vec<Vec<u8>>, Vec<bool>) {
+        let state = debugger.interpreter().state();
+        (state.pc(), state.steps(), state.main_stack().clone(), state.condition_stack().clone())
+    }
+
+    #[test]
+    fn back_replays_the_same_states() {
+        // 30 times OP_1 OP_DUP OP_DROP, then OP_DEPTH
+        let script = format!("{}74", "517675".repeat(30));
+        let mut debugger = debugger(&script);
+        // Keep few checkpoints so going back has to replay and thinning kicks in
+        debugger.history = History::new(debugger.interpreter().snapshot(), 1, 4);
+
+        let mut forward = Vec::new();
+        while debugger.step(1) {
+            forward.push(position(&debugger));
+            assert!(debugger.history().checkpoint_count() <= 4);
+        }
+        if forward.last() != Some(&position(&debugger)) {
+            forward.push(position(&debugger));
+        }
+        assert_eq!(main_stack(&debugger).len(), 31);
+
+        for expected in forward.iter().rev().skip(1) {
+            debugger.back(1);
+            assert_eq!(&position(&debugger), expected);
+        }
+        debugger.goto(45);
+        assert_eq!(position(&debugger), forward[44]);
+        debugger.goto(2);
+        assert_eq!(position(&debugger), forward[1]);
+        debugger.back(10);
+        assert_eq!(debugger.interpreter().state().steps(), 0);
+    }
+
+    #[test]
+    fn rewinds_to_branch_decisions() {
+        // OP_1 OP_IF OP_2 OP_ELSE OP_3 OP_ENDIF OP_4
+        let mut debugger = debugger("51635267536854");
+        assert!(!debugger.continue_execution());
+        assert_eq!(main_stack(&debugger), vec![vec![2], vec![4]]);
+        assert!(debugger.skipped().contains(&4));
+        assert_eq!(debugger.history().branches().iter().copied().collect::<Vec<_>>(), vec![1, 3]);
+
+        assert!(debugger.rewind_to_branch());
+        assert_eq!(debugger.interpreter().state().pc(), 3);
+        assert!(debugger.skipped().is_empty());
+        assert!(debugger.rewind_to_branch());
+        assert_eq!(debugger.interpreter().state().pc(), 1);
+        assert!(!debugger.rewind_to_branch());
+    }
+
+    #[test]
+    fn edits_survive_going_back() {
+        // OP_1 OP_2 OP_ADD OP_3 OP_EQUAL
+        let mut debugger = debugger("5152935387");
+        debugger.step(2);
+        debugger.execute_command("set main 0 05").unwrap();
+        debugger.execute_command("continue").unwrap();
+        assert!(!debugger.result().success);
+
+        // Replaying past the edited step picks the edited stacks up again
+        debugger.back(4);
+        assert_eq!(main_stack(&debugger), vec![vec![1]]);
+        debugger.step(1);
+        assert_eq!(main_stack(&debugger), vec![vec![1], vec![5]]);
+        debugger.goto(3);
+        assert_eq!(main_stack(&debugger), vec![vec![6]]);
+
+        // An edit before the branch decisions forgets them
+        let mut debugger = self::debugger("51635267536854");
+        debugger.continue_execution();
+        debugger.goto(1);
+        debugger.execute_command("push main 00").unwrap();
+        assert!(debugger.history().branches().iter().all(|b| *b <= 1));
+    }
+}
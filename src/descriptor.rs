@@ -0,0 +1,675 @@
+use std::fmt;
+use std::str::FromStr;
+
+use bitcoin_hashes::{hash160, sha256, Hash, HashEngine};
+use secp256k1::{PublicKey, Scalar, Secp256k1, SecretKey, XOnlyPublicKey};
+
+use crate::address::{base58check_decode, Address};
+use crate::bip32::{parse_path, ExtendedKey, HARDENED};
+use crate::builder::Builder;
+use crate::opcodes::*;
+use crate::miniscript::Miniscript;
+use crate::script::{serialize, MAX_SCRIPT_ELEMENT_SIZE};
+use crate::solver::{solve, Template};
+use crate::transaction::write_compact_size;
+
+// Characters allowed in descriptors, grouped so that the checksum catches case errors
+const INPUT_CHARSET: &str = "0123456789()[],'/*abcdefgh@:$%{}IJKLMNOPQRSTUVWXYZ&+-.;<=>?!^_|~ijklmnopqrstuvwxyzABCDEFGH`#\"\\ ";
+const CHECKSUM_CHARSET: &[u8] = b"qpzry9x8gf2tvdw0s3jn54khce6mua7l";
+const TAPROOT_LEAF_TAPSCRIPT: u8 = 0xc0;
+const TAPROOT_MAX_DEPTH: usize = 128;
+const MAX_PUBKEYS_PER_MULTISIG: usize = 20;
+const MAX_PUBKEYS_PER_MULTI_A: usize = 999;
+
+fn polymod(symbols: &[u64]) -> u64 {
+    const GENERATOR: [u64; 5] = [0xf5dee51989, 0xa9fdca3312, 0x1bab10e32d, 0x3706b1677a, 0x644d626ffd];
+    let mut checksum = 1u64;
+    for value in symbols {
+        let top = checksum >> 35;
+        checksum = (checksum & 0x7ffffffff) << 5 ^ value;
+        for (i, g) in GENERATOR.iter().enumerate() {
+            if (top >> i) & 1 == 1 {
+                checksum ^= g;
+            }
+        }
+    }
+    checksum
+}
+
+// Checksum appended to descriptors after a '#'
+pub fn checksum(descriptor: &str) -> Result<String, String> {
+    let mut symbols = Vec::new();
+    let mut groups = Vec::new();
+    for c in descriptor.chars() {
+        let value = INPUT_CHARSET.find(c).ok_or(format!("Invalid character '{}' in descriptor", c))? as u64;
+        symbols.push(value & 31);
+        groups.push(value >> 5);
+        if groups.len() == 3 {
+            symbols.push(groups[0] * 9 + groups[1] * 3 + groups[2]);
+            groups.clear();
+        }
+    }
+    match groups[..] {
+        [a] => symbols.push(a),
+        [a, b] => symbols.push(a * 3 + b),
+        _ => ()
+    }
+    symbols.extend([0; 8]);
+    let checksum = polymod(&symbols) ^ 1;
+    Ok((0..8).map(|i| CHECKSUM_CHARSET[((checksum >> (5 * (7 - i))) & 31) as usize] as char).collect())
+}
+
+// Where a key comes from
+#[derive(Debug, Clone, Eq, PartialEq)]
+enum KeySource {
+    // Hex encoded public key, 32 bytes x-only keys are only allowed in taproot descriptors
+    Public(Vec<u8>),
+    // WIF private key
+    Private { key: SecretKey, compressed: bool },
+    // Extended key followed by a derivation path and, for ranged descriptors, a wildcard
+    // (0 for an unhardened one, HARDENED for a hardened one)
+    Extended { key: ExtendedKey, path: Vec<u32>, wildcard: Option<u32> }
+}
+
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct DescriptorKey {
+    // Key expression as written, with its origin
+    text: String,
+    source: KeySource
+}
+
+impl DescriptorKey {
+    fn parse(text: &str, x_only: bool) -> Result<DescriptorKey, String> {
+        // [fingerprint/origin/path] is informative only
+        let key = match text.strip_prefix('[') {
+            Some(rest) => rest.split_once(']').ok_or(format!("Missing ']' in key {}", text))?.1,
+            None => text
+        };
+
+        let source = if let Ok(bytes) = hex::decode(key) {
+            match bytes.len() {
+                32 if x_only => XOnlyPublicKey::from_slice(&bytes).map(|_| ()).map_err(|_| format!("Invalid x-only public key {}", key))?,
+                33 | 65 => PublicKey::from_slice(&bytes).map(|_| ()).map_err(|_| format!("Invalid public key {}", key))?,
+                _ => return Err(format!("Invalid public key {}", key))
+            }
+            KeySource::Public(bytes)
+        } else if !key.contains('/') && key.len() < 60 {
+            // WIF: version byte, 32 bytes key and 0x01 for compressed public keys
+            let data = base58check_decode(key).map_err(|e| format!("Invalid private key {}: {}", key, e))?;
+            let compressed = match (data.first(), data.len()) {
+                (Some(0x80 | 0xef), 33) => false,
+                (Some(0x80 | 0xef), 34) if data[33] == 1 => true,
+                _ => return Err(format!("Invalid private key {}", key))
+            };
+            let key = SecretKey::from_slice(&data[1..33]).map_err(|_| format!("Invalid private key {}", key))?;
+            KeySource::Private { key, compressed }
+        } else {
+            let (key, mut path) = key.split_once('/').unwrap_or((key, ""));
+            let mut wildcard = None;
+            for (suffix, hardened) in [("*", 0), ("*'", HARDENED), ("*h", HARDENED)] {
+                if let Some(rest) = path.strip_suffix(suffix) {
+                    if rest.is_empty() || rest.ends_with('/') {
+                        path = rest;
+                        wildcard = Some(hardened);
+                    }
+                }
+            }
+            let key: ExtendedKey = key.parse()?;
+            let path = parse_path(path)?;
+            // Hardened steps of xpubs can't ever be derived
+            if key.secret_key().is_none() && (path.iter().any(|c| *c >= HARDENED) || wildcard == Some(HARDENED)) {
+                return Err(format!("Hardened derivation from a public extended key in {}", text))
+            }
+            KeySource::Extended { key, path, wildcard }
+        };
+        Ok(DescriptorKey { text: text.to_string(), source })
+    }
+
+    // Whether the key changes with the derivation index
+    pub fn is_ranged(&self) -> bool {
+        matches!(self.source, KeySource::Extended { wildcard: Some(_), .. })
+    }
+
+    fn extended(&self, index: u32) -> Result<Option<ExtendedKey>, String> {
+        match &self.source {
+            KeySource::Extended { key, path, wildcard } => {
+                let key = key.derive_path(path)?;
+                match wildcard {
+                    Some(_) if index >= HARDENED => Err(format!("Derivation index {} is too large", index)),
+                    Some(hardened) => key.derive(index | hardened).map(Some),
+                    None => Ok(Some(key))
+                }
+            }
+            _ => Ok(None)
+        }
+    }
+
+    // Serialized public key at `index`, 32 bytes x-only keys are returned as is
+    pub fn public_key(&self, index: u32) -> Result<Vec<u8>, String> {
+        match &self.source {
+            KeySource::Public(bytes) => Ok(bytes.clone()),
+            KeySource::Private { key, compressed } => {
+                let public = PublicKey::from_secret_key(&Secp256k1::signing_only(), key);
+                Ok(if *compressed { public.serialize().to_vec() } else { public.serialize_uncompressed().to_vec() })
+            }
+            KeySource::Extended { .. } => Ok(self.extended(index)?.unwrap().public_key().serialize().to_vec())
+        }
+    }
+
+    pub fn x_only_public_key(&self, index: u32) -> Result<Vec<u8>, String> {
+        let key = self.public_key(index)?;
+        if key.len() == 32 {
+            return Ok(key)
+        }
+        Ok(PublicKey::from_slice(&key).unwrap().x_only_public_key().0.serialize().to_vec())
+    }
+
+    // Private key at `index`, when the expression holds one
+    pub fn secret_key(&self, index: u32) -> Result<Option<SecretKey>, String> {
+        match &self.source {
+            KeySource::Private { key, .. } => Ok(Some(*key)),
+            _ => Ok(self.extended(index)?.and_then(|key| key.secret_key()))
+        }
+    }
+}
+
+impl fmt::Display for DescriptorKey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.text)
+    }
+}
+
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub enum Descriptor {
+    Pk(DescriptorKey),
+    Pkh(DescriptorKey),
+    Wpkh(DescriptorKey),
+    Sh(Box<Descriptor>),
+    Wsh(Box<Descriptor>),
+    Multi { sorted: bool, required: usize, keys: Vec<DescriptorKey> },
+    // CHECKSIGADD based multisig, in taproot leaves only
+    MultiA { sorted: bool, required: usize, keys: Vec<DescriptorKey> },
+    Tr { internal: DescriptorKey, tree: Option<TapTree> },
+    Raw(Vec<u8>),
+    Addr(Address),
+    // Any other Miniscript expression inside wsh(), with hex public keys
+    Miniscript(Miniscript)
+}
+
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub enum TapTree {
+    Leaf(Box<Descriptor>),
+    Branch(Box<TapTree>, Box<TapTree>)
+}
+
+// Where a descriptor appears, deciding which descriptors and keys are allowed
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+enum Context {
+    Top,
+    Sh,
+    Wsh,
+    Tap
+}
+
+// Split "a,f(b,c),{d,e}" at the commas outside of parentheses and braces
+pub(crate) fn split_args(args: &str) -> Vec<&str> {
+    let mut parts = Vec::new();
+    let mut depth = 0;
+    let mut start = 0;
+    for (i, c) in args.char_indices() {
+        match c {
+            '(' | '{' | '[' => depth += 1,
+            ')' | '}' | ']' => depth -= 1,
+            ',' if depth == 0 => {
+                parts.push(&args[start..i]);
+                start = i + 1;
+            }
+            _ => ()
+        }
+    }
+    parts.push(&args[start..]);
+    parts
+}
+
+fn parse_tree(text: &str, depth: usize) -> Result<TapTree, String> {
+    if depth > TAPROOT_MAX_DEPTH {
+        return Err(format!("Taproot tree deeper than {}", TAPROOT_MAX_DEPTH))
+    }
+    match text.strip_prefix('{').and_then(|t| t.strip_suffix('}')) {
+        Some(inner) => match split_args(inner)[..] {
+            [left, right] => Ok(TapTree::Branch(Box::new(parse_tree(left, depth + 1)?), Box::new(parse_tree(right, depth + 1)?))),
+            _ => Err(format!("Taproot tree branches have two children: {}", text))
+        },
+        None => Ok(TapTree::Leaf(Box::new(parse(text, Context::Tap)?)))
+    }
+}
+
+fn parse_multi(args: &[&str], sorted: bool, context: Context) -> Result<(bool, usize, Vec<DescriptorKey>), String> {
+    let (required, keys) = args.split_first().ok_or("Missing threshold")?;
+    let required: usize = required.parse().map_err(|_| format!("Invalid threshold {}", required))?;
+    let keys = keys.iter().map(|k| DescriptorKey::parse(k, context == Context::Tap)).collect::<Result<Vec<_>, _>>()?;
+    let max = if context == Context::Tap { MAX_PUBKEYS_PER_MULTI_A } else { MAX_PUBKEYS_PER_MULTISIG };
+    if keys.is_empty() || keys.len() > max {
+        return Err(format!("Multisig needs between 1 and {} keys", max))
+    }
+    if required == 0 || required > keys.len() {
+        return Err(format!("Threshold {} out of range for {} keys", required, keys.len()))
+    }
+    Ok((sorted, required, keys))
+}
+
+fn parse(text: &str, context: Context) -> Result<Descriptor, String> {
+    let (name, args) = text.split_once('(').ok_or(format!("Expected name(arguments): {}", text))?;
+    let args = args.strip_suffix(')').ok_or(format!("Missing ')' in {}", text))?;
+    let split = split_args(args);
+    let one = || match split[..] {
+        [arg] => Ok(arg),
+        _ => Err(format!("{}() takes one argument", name))
+    };
+    let key = |x_only: bool| DescriptorKey::parse(one()?, x_only);
+    let allowed = match name {
+        "pk" => true,
+        "pkh" | "multi" | "sortedmulti" => context != Context::Tap,
+        "wpkh" => context == Context::Top || context == Context::Sh,
+        "wsh" => context == Context::Top || context == Context::Sh,
+        "multi_a" | "sortedmulti_a" => context == Context::Tap,
+        "sh" | "tr" | "raw" | "addr" => context == Context::Top,
+        _ if context == Context::Wsh => return Ok(Descriptor::Miniscript(text.parse()?)),
+        _ => return Err(format!("Unknown descriptor {}()", name))
+    };
+    if !allowed {
+        return Err(format!("{}() is not allowed here: {}", name, text))
+    }
+
+    let descriptor = match name {
+        "pk" => Descriptor::Pk(key(context == Context::Tap)?),
+        "pkh" => Descriptor::Pkh(key(false)?),
+        "wpkh" => Descriptor::Wpkh(key(false)?),
+        "sh" => Descriptor::Sh(Box::new(parse(one()?, Context::Sh)?)),
+        "wsh" => Descriptor::Wsh(Box::new(parse(one()?, Context::Wsh)?)),
+        "multi" | "sortedmulti" => {
+            let (sorted, required, keys) = parse_multi(&split, name == "sortedmulti", context)?;
+            Descriptor::Multi { sorted, required, keys }
+        }
+        "multi_a" | "sortedmulti_a" => {
+            let (sorted, required, keys) = parse_multi(&split, name == "sortedmulti_a", context)?;
+            Descriptor::MultiA { sorted, required, keys }
+        }
+        "tr" => match split[..] {
+            [internal] => Descriptor::Tr { internal: DescriptorKey::parse(internal, true)?, tree: None },
+            [internal, tree] => Descriptor::Tr { internal: DescriptorKey::parse(internal, true)?, tree: Some(parse_tree(tree, 0)?) },
+            _ => return Err(String::from("tr() takes a key and an optional tree"))
+        },
+        "raw" => Descriptor::Raw(hex::decode(one()?).map_err(|_| String::from("raw() script must be hex encoded"))?),
+        _ => Descriptor::Addr(one()?.parse().map_err(|e| format!("Invalid address: {}", e))?)
+    };
+
+    // Segwit v0 only allows compressed public keys
+    if context == Context::Wsh || matches!(descriptor, Descriptor::Wpkh(_)) {
+        for key in descriptor.keys() {
+            if key.public_key(0)?.len() != 33 {
+                return Err(format!("Uncompressed key {} in a segwit descriptor", key))
+            }
+        }
+    }
+    Ok(descriptor)
+}
+
+impl FromStr for Descriptor {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (descriptor, expected) = match s.split_once('#') {
+            Some((descriptor, expected)) => (descriptor, Some(expected)),
+            None => (s, None)
+        };
+        let actual = checksum(descriptor)?;
+        if let Some(expected) = expected {
+            if expected != actual {
+                return Err(format!("Invalid checksum {}, expected {}", expected, actual))
+            }
+        }
+        parse(descriptor, Context::Top)
+    }
+}
+
+impl fmt::Display for TapTree {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TapTree::Leaf(descriptor) => write!(f, "{}", descriptor),
+            TapTree::Branch(left, right) => write!(f, "{{{},{}}}", left, right)
+        }
+    }
+}
+
+impl fmt::Display for Descriptor {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let multi = |f: &mut fmt::Formatter<'_>, name: &str, required: &usize, keys: &[DescriptorKey]| {
+            let keys: Vec<String> = keys.iter().map(|k| k.to_string()).collect();
+            write!(f, "{}({},{})", name, required, keys.join(","))
+        };
+        match self {
+            Descriptor::Pk(key) => write!(f, "pk({})", key),
+            Descriptor::Pkh(key) => write!(f, "pkh({})", key),
+            Descriptor::Wpkh(key) => write!(f, "wpkh({})", key),
+            Descriptor::Sh(inner) => write!(f, "sh({})", inner),
+            Descriptor::Wsh(inner) => write!(f, "wsh({})", inner),
+            Descriptor::Multi { sorted, required, keys } => multi(f, if *sorted { "sortedmulti" } else { "multi" }, required, keys),
+            Descriptor::MultiA { sorted, required, keys } => multi(f, if *sorted { "sortedmulti_a" } else { "multi_a" }, required, keys),
+            Descriptor::Tr { internal, tree: None } => write!(f, "tr({})", internal),
+            Descriptor::Tr { internal, tree: Some(tree) } => write!(f, "tr({},{})", internal, tree),
+            Descriptor::Raw(script) => write!(f, "raw({})", hex::encode(script)),
+            Descriptor::Addr(address) => write!(f, "addr({})", address),
+            Descriptor::Miniscript(miniscript) => write!(f, "{}", miniscript)
+        }
+    }
+}
+
+// Tapscript leaf of a taproot output, with the control block proving it is committed to
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct TapLeaf {
+    pub script: Vec<u8>,
+    pub control_block: Vec<u8>
+}
+
+// Scripts of a descriptor at one derivation index
+#[derive(Debug, Clone, Default, Eq, PartialEq)]
+pub struct Expansion {
+    pub script_pubkey: Vec<u8>,
+    // Script pushed last by the scriptSig of P2SH outputs
+    pub redeem_script: Option<Vec<u8>>,
+    // Script given last in the witness of P2WSH outputs
+    pub witness_script: Option<Vec<u8>>,
+    pub leaves: Vec<TapLeaf>
+}
+
+impl Expansion {
+    // Script run against the witness or the scriptSig: the witness or redeem script, the P2PKH
+    // script standing for P2WPKH programs, or the output script itself
+    // Taproot outputs have none, their leaves follow tapscript rules
+    pub fn executed_script(&self) -> Option<Vec<u8>> {
+        if let Some(script) = &self.witness_script {
+            return Some(script.clone())
+        }
+        let inner = self.redeem_script.as_ref().unwrap_or(&self.script_pubkey);
+        match solve(inner) {
+            Template::WitnessV0KeyHash { hash } => Some(Builder::p2pkh(&hash).into_bytes()),
+            Template::WitnessV1Taproot { .. } => None,
+            _ => Some(inner.clone())
+        }
+    }
+}
+
+fn tagged_hash(tag: &str, data: &[u8]) -> [u8; 32] {
+    let tag = sha256::Hash::hash(tag.as_bytes());
+    let mut engine = sha256::Hash::engine();
+    engine.input(&tag);
+    engine.input(&tag);
+    engine.input(data);
+    sha256::Hash::from_engine(engine).into_inner()
+}
+
+// Leaf script with the hashes of its merkle path, from the leaf up
+type MerklePath = (Vec<u8>, Vec<[u8; 32]>);
+
+// Merkle root of a tree and the path of every leaf
+fn tree_hashes(tree: &TapTree, index: u32) -> Result<([u8; 32], Vec<MerklePath>), String> {
+    match tree {
+        TapTree::Leaf(descriptor) => {
+            let script = match &**descriptor {
+                // Keys of taproot leaves are x-only
+                Descriptor::Pk(key) => Builder::p2pk(&key.x_only_public_key(index)?).into_bytes(),
+                descriptor => descriptor.script(index)?
+            };
+            let mut data = vec![TAPROOT_LEAF_TAPSCRIPT];
+            write_compact_size(&mut data, script.len());
+            data.extend(&script);
+            Ok((tagged_hash("TapLeaf", &data), vec![(script, Vec::new())]))
+        }
+        TapTree::Branch(left, right) => {
+            let (left_hash, mut left_leaves) = tree_hashes(left, index)?;
+            let (right_hash, right_leaves) = tree_hashes(right, index)?;
+            left_leaves.iter_mut().for_each(|(_, path)| path.push(right_hash));
+            left_leaves.extend(right_leaves.into_iter().map(|(script, mut path)| {
+                path.push(left_hash);
+                (script, path)
+            }));
+            let (a, b) = (left_hash.min(right_hash), left_hash.max(right_hash));
+            Ok((tagged_hash("TapBranch", &[a, b].concat()), left_leaves))
+        }
+    }
+}
+
+impl Descriptor {
+    // Every key expression, in order
+    pub fn keys(&self) -> Vec<&DescriptorKey> {
+        match self {
+            Descriptor::Pk(key) | Descriptor::Pkh(key) | Descriptor::Wpkh(key) => vec![key],
+            Descriptor::Sh(inner) | Descriptor::Wsh(inner) => inner.keys(),
+            Descriptor::Multi { keys, .. } | Descriptor::MultiA { keys, .. } => keys.iter().collect(),
+            Descriptor::Tr { internal, tree } => {
+                let mut keys = vec![internal];
+                let mut pending: Vec<&TapTree> = tree.iter().collect();
+                while let Some(tree) = pending.pop() {
+                    match tree {
+                        TapTree::Leaf(descriptor) => keys.extend(descriptor.keys()),
+                        TapTree::Branch(left, right) => pending.extend([&**right, &**left])
+                    }
+                }
+                keys
+            }
+            Descriptor::Raw(_) | Descriptor::Addr(_) | Descriptor::Miniscript(_) => Vec::new()
+        }
+    }
+
+    // Whether the descriptor describes a range of scripts, one per derivation index
+    pub fn is_ranged(&self) -> bool {
+        self.keys().iter().any(|key| key.is_ranged())
+    }
+
+    // Output script, or script of a taproot leaf for multi_a
+    fn script(&self, index: u32) -> Result<Vec<u8>, String> {
+        let hash_key = |key: &DescriptorKey| Ok::<_, String>(hash160::Hash::hash(&key.public_key(index)?).to_vec());
+        let sorted_keys = |keys: &[DescriptorKey], sorted: bool, x_only: bool| {
+            let mut keys = keys.iter()
+                .map(|k| if x_only { k.x_only_public_key(index) } else { k.public_key(index) })
+                .collect::<Result<Vec<_>, _>>()?;
+            if sorted {
+                keys.sort();
+            }
+            Ok::<_, String>(keys)
+        };
+
+        Ok(match self {
+            Descriptor::Pk(key) => Builder::p2pk(&key.public_key(index)?).into_bytes(),
+            Descriptor::Pkh(key) => Builder::p2pkh(&hash_key(key)?).into_bytes(),
+            Descriptor::Wpkh(key) => Builder::p2wpkh(&hash_key(key)?).into_bytes(),
+            Descriptor::Sh(inner) => {
+                let redeem_script = inner.script(index)?;
+                if redeem_script.len() > MAX_SCRIPT_ELEMENT_SIZE {
+                    return Err(format!("Redeem script of {} bytes is larger than {}", redeem_script.len(), MAX_SCRIPT_ELEMENT_SIZE))
+                }
+                Builder::p2sh(&hash160::Hash::hash(&redeem_script)).into_bytes()
+            }
+            Descriptor::Wsh(inner) => Builder::p2wsh(&sha256::Hash::hash(&inner.script(index)?)).into_bytes(),
+            Descriptor::Multi { sorted, required, keys } => Builder::multisig(*required, &sorted_keys(keys, *sorted, false)?).into_bytes(),
+            Descriptor::MultiA { sorted, required, keys } => {
+                // <key> OP_CHECKSIG <key> OP_CHECKSIGADD ... <m> OP_NUMEQUAL
+                let keys = sorted_keys(keys, *sorted, true)?;
+                let builder = keys.iter().enumerate().fold(Builder::new(), |builder, (i, key)| {
+                    builder.push_slice(key).push_opcode(if i == 0 { OP_CHECKSIG } else { OP_CHECKSIGADD })
+                });
+                builder.push_int(*required as i64).push_opcode(OP_NUMEQUAL).into_bytes()
+            }
+            Descriptor::Tr { .. } => self.expand(index)?.script_pubkey,
+            Descriptor::Raw(script) => script.clone(),
+            Descriptor::Addr(address) => address.script_pubkey.clone(),
+            Descriptor::Miniscript(miniscript) => serialize(&miniscript.encode())
+        })
+    }
+
+    // Output script and the scripts needed to spend it, at derivation index `index`
+    // (ignored by descriptors without ranged keys)
+    pub fn expand(&self, index: u32) -> Result<Expansion, String> {
+        match self {
+            Descriptor::Sh(inner) => {
+                let inner = inner.expand(index)?;
+                Ok(Expansion {
+                    script_pubkey: self.script(index)?,
+                    redeem_script: Some(inner.script_pubkey),
+                    witness_script: inner.witness_script,
+                    leaves: Vec::new()
+                })
+            }
+            Descriptor::Wsh(inner) => Ok(Expansion {
+                script_pubkey: self.script(index)?,
+                witness_script: Some(inner.script(index)?),
+                ..Expansion::default()
+            }),
+            Descriptor::Tr { internal, tree } => {
+                let internal = XOnlyPublicKey::from_slice(&internal.x_only_public_key(index)?).unwrap();
+                let (root, leaves) = match tree {
+                    Some(tree) => {
+                        let (root, leaves) = tree_hashes(tree, index)?;
+                        (Some(root), leaves)
+                    }
+                    None => (None, Vec::new())
+                };
+                let mut data = internal.serialize().to_vec();
+                data.extend(root.iter().flatten());
+                let tweak = Scalar::from_be_bytes(tagged_hash("TapTweak", &data)).map_err(|_| String::from("Invalid taproot tweak"))?;
+                let (output, parity) = internal.add_tweak(&Secp256k1::verification_only(), &tweak)
+                    .map_err(|_| String::from("Invalid taproot tweak"))?;
+
+                let leaves = leaves.into_iter().map(|(script, path)| {
+                    let mut control_block = vec![TAPROOT_LEAF_TAPSCRIPT | parity.to_u8()];
+                    control_block.extend(internal.serialize());
+                    path.iter().for_each(|hash| control_block.extend(hash));
+                    TapLeaf { script, control_block }
+                }).collect();
+                Ok(Expansion {
+                    script_pubkey: Builder::p2tr(&output.serialize()).into_bytes(),
+                    leaves,
+                    ..Expansion::default()
+                })
+            }
+            _ => Ok(Expansion { script_pubkey: self.script(index)?, ..Expansion::default() })
+        }
+    }
+
+    // Descriptor string followed by its checksum
+    pub fn to_string_with_checksum(&self) -> String {
+        let descriptor = self.to_string();
+        let checksum = checksum(&descriptor).unwrap();
+        format!("{}#{}", descriptor, checksum)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::address::Network;
+
+    const G: &str = "0279be667ef9dcbbac55a06295ce870b07029bfcdb2dce28d959f2815b16f81798";
+    // m of the "abandon ... about" mnemonic
+    const ROOT: &str = "xprv9s21ZrQH143K3GJpoapnV8SFfukcVBSfeCficPSGfubmSFDxo1kuHnLisriDvSnRRuL2Qrg5ggqHKNVpxR86QEC8w35uxmGoggxtQTPvfUu";
+
+    fn script_pubkey(descriptor: &str) -> String {
+        hex::encode(descriptor.parse::<Descriptor>().unwrap().expand(0).unwrap().script_pubkey)
+    }
+
+    fn address(descriptor: &str, index: u32) -> String {
+        let script_pubkey = descriptor.parse::<Descriptor>().unwrap().expand(index).unwrap().script_pubkey;
+        Address { network: Network::Mainnet, script_pubkey }.to_string()
+    }
+
+    #[test]
+    fn bip380_checksums() {
+        assert_eq!(checksum("raw(deadbeef)"), Ok(String::from("89f8spxm")));
+        assert!("raw(deadbeef)#89f8spxm".parse::<Descriptor>().is_ok());
+        for invalid in ["raw(deadbeef)#", "raw(deadbeef)#89f8spxmx", "raw(deadbeef)#89f8spx", "raw(deedbeef)#89f8spxm", "raw(deadbeef)##9f8spxm"] {
+            assert!(invalid.parse::<Descriptor>().is_err(), "{}", invalid);
+        }
+        assert_eq!("raw(deadbeef)#8ßf8spxm".parse::<Descriptor>().unwrap_err(), "Invalid checksum 8ßf8spxm, expected 89f8spxm");
+        assert_eq!(checksum("raw(ß)"), Err(String::from("Invalid character 'ß' in descriptor")));
+        let descriptor: Descriptor = format!("wpkh({})", G).parse().unwrap();
+        let with_checksum = descriptor.to_string_with_checksum();
+        assert_eq!(with_checksum.parse(), Ok(descriptor));
+    }
+
+    #[test]
+    fn key_descriptors() {
+        assert_eq!(script_pubkey(&format!("pk({})", G)), format!("21{}ac", G));
+        assert_eq!(script_pubkey(&format!("pkh({})", G)), "76a914751e76e8199196d454941c45d1b3a323f1433bd688ac");
+        assert_eq!(script_pubkey(&format!("wpkh({})", G)), "0014751e76e8199196d454941c45d1b3a323f1433bd6");
+        // WIF of the private key 1 gives the same scripts
+        assert_eq!(script_pubkey("pkh(KwDiBf89QgGbjEhKnhXJuH7LrciVrZi3qYjgd9M7rFU73sVHnoWn)"), script_pubkey(&format!("pkh({})", G)));
+        assert_eq!(script_pubkey("addr(bc1qw508d6qejxtdg4y5r3zarvary0c5xw7kv8f3t4)"), "0014751e76e8199196d454941c45d1b3a323f1433bd6");
+        assert_eq!(script_pubkey("raw(6a)"), "6a");
+    }
+
+    #[test]
+    fn nested_descriptors() {
+        let key_b = "024d4b6cd1361032ca9bd2aeb9d900aa4d45d9ead80ac9423374c451a7254d0766";
+        let multi = format!("5221{}21{}52ae", G, key_b);
+        let sorted = format!("5221{}21{}52ae", key_b, G);
+
+        let expansion = format!("sh(multi(2,{},{}))", G, key_b).parse::<Descriptor>().unwrap().expand(0).unwrap();
+        assert_eq!(expansion.redeem_script.map(hex::encode), Some(multi.clone()));
+        assert_eq!(hex::encode(&expansion.script_pubkey[2..22]), hash160::Hash::hash(&hex::decode(&multi).unwrap()).to_string());
+
+        let expansion = format!("sh(wsh(sortedmulti(2,{},{})))", G, key_b).parse::<Descriptor>().unwrap().expand(0).unwrap();
+        assert_eq!(expansion.witness_script.as_ref().map(hex::encode), Some(sorted.clone()));
+        let program = sha256::Hash::hash(&hex::decode(&sorted).unwrap());
+        assert_eq!(expansion.redeem_script.as_ref().map(hex::encode), Some(format!("0020{}", program)));
+        assert_eq!(expansion.executed_script().map(hex::encode), Some(sorted));
+
+        // P2WPKH runs the P2PKH script of its key hash
+        let expansion = format!("sh(wpkh({}))", G).parse::<Descriptor>().unwrap().expand(0).unwrap();
+        assert_eq!(expansion.executed_script().map(hex::encode), Some(String::from("76a914751e76e8199196d454941c45d1b3a323f1433bd688ac")));
+    }
+
+    #[test]
+    fn bip86_taproot() {
+        // First receiving address of BIP 86
+        let descriptor = format!("tr({}/86'/0'/0'/0/*)", ROOT);
+        assert!(descriptor.parse::<Descriptor>().unwrap().is_ranged());
+        assert_eq!(address(&descriptor, 0), "bc1p5cyxnuxmeuwuvkwfem96lqzszd02n6xdcjrs20cac6yqjjwudpxqkedrcr");
+        assert_eq!(address(&descriptor, 1), "bc1p4qhjn9zdvkux4e44uhx8tc55attvtyu358kutcqkudyccelu0was9fqzwh");
+    }
+
+    #[test]
+    fn taproot_trees() {
+        let descriptor: Descriptor = format!("tr({},{{pk({}),multi_a(1,{},{})}})", G, &G[2..], &G[2..], G).parse().unwrap();
+        let expansion = descriptor.expand(0).unwrap();
+        assert_eq!(expansion.leaves.len(), 2);
+        assert_eq!(hex::encode(&expansion.leaves[0].script), format!("20{}ac", &G[2..]));
+        assert_eq!(hex::encode(&expansion.leaves[1].script), format!("20{}ac20{}ba519c", &G[2..], &G[2..]));
+        for leaf in &expansion.leaves {
+            // Leaf version and parity, internal key, one sibling hash
+            assert_eq!(leaf.control_block.len(), 1 + 32 + 32);
+            assert_eq!(leaf.control_block[0] & 0xfe, TAPROOT_LEAF_TAPSCRIPT);
+            assert_eq!(hex::encode(&leaf.control_block[1..33]), &G[2..]);
+        }
+        assert_eq!(expansion.executed_script(), None);
+        assert_eq!(descriptor.keys().len(), 4);
+    }
+
+    #[test]
+    fn invalid_descriptors() {
+        let xpub = "xpub661MyMwAqRbcFtXgS5sYJABqqG9YLmC4Q1Rdap9gSE8NqtwybGhePY2gZ29ESFjqJoCu1Rupje8YtGqsefD265TMg7usUDFdp6W1EGMcet8";
+        let uncompressed = "0479be667ef9dcbbac55a06295ce870b07029bfcdb2dce28d959f2815b16f81798483ada7726a3c4655da4fbfc0e1108a8fd17b448a68554199c47d08ffb10d4b8";
+        for (descriptor, error) in [
+            (format!("wpkh({})", uncompressed), format!("Uncompressed key {} in a segwit descriptor", uncompressed)),
+            (format!("sh(sh(pk({})))", G), format!("sh() is not allowed here: sh(pk({}))", G)),
+            (format!("multi_a(1,{})", G), format!("multi_a() is not allowed here: multi_a(1,{})", G)),
+            (format!("multi(3,{},{})", G, G), String::from("Threshold 3 out of range for 2 keys")),
+            (format!("pkh({}/0'/*)", xpub), format!("Hardened derivation from a public extended key in {}/0'/*", xpub)),
+            (format!("tr({},{{pk({})}})", G, G), format!("Taproot tree branches have two children: {{pk({})}}", G)),
+            (String::from("foo(00)"), String::from("Unknown descriptor foo()")),
+            (String::from("pk(00)"), String::from("Invalid public key 00"))
+        ] {
+            assert_eq!(descriptor.parse::<Descriptor>(), Err(error));
+        }
+    }
+}
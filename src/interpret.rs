@@ -2,16 +2,14 @@ use std::cmp::{max, min};
 
 use bitcoin_hashes::{hash160, ripemd160, sha1, sha256, sha256d};
 use bitcoin_hashes::Hash;
-use colored::Colorize;
-use tabled::{Alignment, MaxWidth, MinWidth, Modify, Style};
-use tabled::builder::Builder;
-use tabled::object::Rows;
 
-use crate::{as_bool, as_script_nb, MAX_SCRIPT_DISPLAY_WIDTH, MIN_SCRIPT_DISPLAY_WIDTH};
+use crate::observer::{ExecutionObserver, NoopObserver, TerminalObserver};
 use crate::opcodes::*;
-use crate::parse::{parse_one_op, parse_script};
+use crate::parse::parse_one_op;
 use crate::script::*;
+use crate::transaction::{find_and_delete, SigVersion, TransactionContext, SEQUENCE_DISABLE_FLAG};
 
+#[derive(Clone)]
 pub struct Stack {
     pub main: Vec<Vec<u8>>,
     pub alt: Vec<Vec<u8>>
@@ -20,370 +18,581 @@ pub struct Stack {
 impl Stack {
     fn push(&mut self, bytes: Vec<u8>) -> Result<(), ScriptError> {
         if bytes.len() > MAX_SCRIPT_ELEMENT_SIZE {
-            return Err(ScriptError::PushSizeErr)
+            return Err(ScriptError::PushSize)
         }
         self.main.push(bytes);
 
         if self.main.len() + self.alt.len() >= MAX_STACK_SIZE {
-            return Err(ScriptError::StackOverflowErr)
+            return Err(ScriptError::StackSize)
         }
         Ok(())
     }
 
     fn push_alt(&mut self, bytes: Vec<u8>) -> Result<(), ScriptError> {
         if bytes.len() > MAX_SCRIPT_ELEMENT_SIZE {
-            return Err(ScriptError::PushSizeErr)
+            return Err(ScriptError::PushSize)
         }
         self.alt.push(bytes);
 
         if self.main.len() + self.alt.len() >= MAX_STACK_SIZE {
-            return Err(ScriptError::StackOverflowErr)
+            return Err(ScriptError::StackSize)
         }
         Ok(())
     }
 
     fn pop(&mut self) -> Result<Vec<u8>, ScriptError> {
-        Ok(self.main.pop().ok_or(ScriptError::InvalidStackOperationErr)?)
+        self.main.pop().ok_or(ScriptError::InvalidStackOperation)
     }
 
     fn pop_alt(&mut self) -> Result<Vec<u8>, ScriptError> {
-        Ok(self.alt.pop().ok_or(ScriptError::InvalidAltStackOperationErr)?)
+        self.alt.pop().ok_or(ScriptError::InvalidAltstackOperation)
+    }
+
+    // Index in `main` of the element at `pos`, positions past either end of the stack are errors
+    fn index(&self, pos: i64) -> Result<usize, ScriptError> {
+        let idx = (self.main.len() as i64 - 1).checked_add(pos).ok_or(ScriptError::InvalidStackOperation)?;
+        if pos > 0 || idx < 0 {
+            return Err(ScriptError::InvalidStackOperation)
+        }
+        Ok(idx as usize)
     }
 
     // Usage: stack.top(0) to get last element or stack.top(-1) to get 2nd element from the end
     fn top(&self, pos: i64) -> Result<Vec<u8>, ScriptError> {
-        if pos > 0 {
-            panic!("Wrong index given (positive)")
-        }
-        let idx = self.main.len() as i64 - 1 + pos;
-        if idx < 0 {
-            return Err(ScriptError::InvalidStackOperationErr)
-        }
-        Ok(self.main.get(idx as usize).ok_or(ScriptError::InvalidStackOperationErr)?.to_vec())
+        Ok(self.main[self.index(pos)?].to_vec())
     }
 
     fn rm_top(&mut self, pos: i64) -> Result<Vec<u8>, ScriptError> {
-        if pos > 0 {
-            panic!("Wrong index given (positive)")
+        let idx = self.index(pos)?;
+        Ok(self.main.remove(idx))
+    }
+
+    fn swap_top(&mut self, a: i64, b: i64) -> Result<(), ScriptError> {
+        let (idx_a, idx_b) = (self.index(a)?, self.index(b)?);
+        self.main.swap(idx_a, idx_b);
+        Ok(())
+    }
+}
+
+#[derive(Debug, Copy, Clone)]
+pub struct ExecutionError {
+    pub error: ScriptError,
+    // None when the error is not tied to an opcode: script too big, unbalanced conditional
+    // detected at the end of the script, or a failing final verdict (EVAL_FALSE, CLEANSTACK)
+    pub opcode: Option<Opcode>,
+    pub offset: usize
+}
+
+#[derive(Debug)]
+pub struct ExecutionResult {
+    pub main_stack: Vec<Vec<u8>>,
+    pub alt_stack: Vec<Vec<u8>>,
+    pub success: bool,
+    pub op_count: usize,
+    pub steps: usize,
+    pub error: Option<ExecutionError>
+}
+
+// Everything the interpreter knows about a running script
+// Cheap enough to clone, which is how snapshots of an execution are taken
+#[derive(Clone)]
+pub struct ExecutionState {
+    script: Vec<u8>,
+    stack: Stack,
+    condition_stack: Vec<bool>,
+    op_count: usize,
+    pc: usize,
+    code_hash_start: usize,
+    steps: usize,
+    error: Option<ExecutionError>,
+    finished: bool,
+    clean_stack: bool,
+    null_fail: bool,
+    null_dummy: bool,
+    transaction: Option<TransactionContext>
+}
+
+impl ExecutionState {
+    pub fn script(&self) -> &[u8] {
+        &self.script
+    }
+
+    pub fn stack(&self) -> &Stack {
+        &self.stack
+    }
+
+    pub fn main_stack(&self) -> &Vec<Vec<u8>> {
+        &self.stack.main
+    }
+
+    pub fn alt_stack(&self) -> &Vec<Vec<u8>> {
+        &self.stack.alt
+    }
+
+    pub fn condition_stack(&self) -> &Vec<bool> {
+        &self.condition_stack
+    }
+
+    // Byte offset of the next opcode to execute
+    pub fn pc(&self) -> usize {
+        self.pc
+    }
+
+    pub fn op_count(&self) -> usize {
+        self.op_count
+    }
+
+    pub fn steps(&self) -> usize {
+        self.steps
+    }
+
+    pub fn code_hash_start(&self) -> usize {
+        self.code_hash_start
+    }
+
+    pub fn error(&self) -> Option<&ExecutionError> {
+        self.error.as_ref()
+    }
+
+    pub fn is_finished(&self) -> bool {
+        self.finished
+    }
+
+    pub fn transaction(&self) -> Option<&TransactionContext> {
+        self.transaction.as_ref()
+    }
+
+    // Whether the current branch is executed (no false value on the condition stack)
+    pub fn executing(&self) -> bool {
+        !self.condition_stack.contains(&false)
+    }
+
+    // Whether `item` is executed in the current branch
+    // Flow control opcodes are always evaluated to keep track of nested conditions
+    pub fn will_execute(&self, item: &ScriptItem) -> bool {
+        match item {
+            ScriptItem::Opcode(op) if (OP_IF.code..=OP_ENDIF.code).contains(&op.code) => true,
+            _ => self.executing()
         }
-        let idx = self.main.len() as i64 - 1 + pos;
-        if idx < 0 {
-            return Err(ScriptError::InvalidStackOperationErr)
+    }
+
+    // Instructions that have not been executed yet
+    // Stops at the first instruction that can't be parsed
+    pub fn remaining_script(&self) -> Script {
+        let mut script = Script::new();
+        let mut cursor = self.pc;
+        while let Ok(Some(item)) = parse_one_op(&self.script, &mut cursor) {
+            script.push(item);
         }
-        Ok(self.main.remove(idx as usize))
+        script
     }
 
-    fn swap_top(&mut self, a: i64, b: i64) -> Result<(), ScriptError> {
-        if a > 0 || b > 0 {
-            panic!("Wrong index given (positive)")
+    // Core's success verdict, only applied once the execution is finished:
+    // non-empty stack with a true top element (EVAL_FALSE) and, if `clean_stack` is set,
+    // exactly one element left on the main stack (CLEANSTACK)
+    fn verdict(&self) -> Option<ExecutionError> {
+        let error = |error| Some(ExecutionError { error, opcode: None, offset: self.script.len() });
+        match self.error {
+            Some(e) => Some(e),
+            None if !self.finished => None,
+            None if !self.stack.main.last().is_some_and(|top| as_bool(top)) => error(ScriptError::EvalFalse),
+            None if self.clean_stack && self.stack.main.len() != 1 => error(ScriptError::CleanStack),
+            None => None
         }
-        let idx_a = self.main.len() as i64 - 1 + a;
-        let idx_b = self.main.len() as i64 - 1 + b;
-        if idx_a < 0 || idx_b < 0 {
-            return Err(ScriptError::InvalidStackOperationErr)
+    }
+
+    // State of the execution so far
+    pub fn result(&self) -> ExecutionResult {
+        let error = self.verdict();
+
+        ExecutionResult {
+            main_stack: self.stack.main.clone(),
+            alt_stack: self.stack.alt.clone(),
+            success: self.finished && error.is_none(),
+            op_count: self.op_count,
+            steps: self.steps,
+            error
         }
-        Ok(self.main.swap(idx_a as usize, idx_b as usize))
     }
 }
 
-fn print_stack(stack: &Vec<Vec<u8>>, title: &str, min_width: usize, max_width: usize) {
-    let mut hex_stack = {
-        let mut vec = Vec::new();
-        for elem in stack {
-            vec.push(format!("0x{}", hex::encode(elem)))
-        }
-        if stack.is_empty() {
-            vec.push(String::from(""));
+pub struct Interpreter<O: ExecutionObserver = NoopObserver> {
+    state: ExecutionState,
+    observer: O
+}
+
+impl Interpreter {
+    pub fn new(script: &[u8]) -> Interpreter {
+        Interpreter {
+            state: ExecutionState {
+                script: script.to_vec(),
+                stack: Stack {main: Vec::with_capacity(20), alt: Vec::with_capacity(20)},
+                condition_stack: Vec::with_capacity(10),
+                op_count: 0,
+                pc: 0,
+                code_hash_start: 0,
+                steps: 0,
+                error: None,
+                finished: false,
+                clean_stack: false,
+                null_fail: false,
+                null_dummy: false,
+                transaction: None
+            },
+            observer: NoopObserver
         }
-        vec
-    };
-    hex_stack.reverse();
+    }
+}
 
-    let mut table_builder = Builder::default().set_columns([title]);
-    for item in &hex_stack {
-        table_builder = table_builder.add_record([item]);
+impl<O: ExecutionObserver> Interpreter<O> {
+    // Replace the observer notified of every step of the execution
+    pub fn with_observer<P: ExecutionObserver>(self, observer: P) -> Interpreter<P> {
+        Interpreter { state: self.state, observer }
     }
 
-    let table = table_builder.build().with(Style::modern())
-        .with(MaxWidth::wrapping(max_width))
-        .with(MinWidth::new(min_width))
-        .with(Modify::new(Rows::new(1..))
-            .with(Alignment::left()));
+    // Require exactly one element on the main stack at the end of the execution
+    pub fn clean_stack(mut self, clean_stack: bool) -> Interpreter<O> {
+        self.state.clean_stack = clean_stack;
+        self
+    }
 
-    print!("{}", &table.to_string());
-}
+    // Require failing CHECKSIG and CHECKMULTISIG signatures to be empty (NULLFAIL)
+    pub fn null_fail(mut self, null_fail: bool) -> Interpreter<O> {
+        self.state.null_fail = null_fail;
+        self
+    }
 
-fn print_state(stack: &Stack, script: &Script, step_nb: usize) {
-    let mut display_max_width = MAX_SCRIPT_DISPLAY_WIDTH;
-    let display_min_width = MIN_SCRIPT_DISPLAY_WIDTH;
+    // Require the extra CHECKMULTISIG argument to be empty (NULLDUMMY)
+    pub fn null_dummy(mut self, null_dummy: bool) -> Interpreter<O> {
+        self.state.null_dummy = null_dummy;
+        self
+    }
 
-    if let Some((w, _)) = term_size::dimensions() {
-        if w > MIN_SCRIPT_DISPLAY_WIDTH && w < MAX_SCRIPT_DISPLAY_WIDTH {
-            display_max_width = w;
-        }
+    // Check signatures and timelocks against an input of a transaction
+    // Without one, any non-empty signature is valid and timelocks are not checked
+    pub fn with_transaction(mut self, transaction: TransactionContext) -> Interpreter<O> {
+        self.state.transaction = Some(transaction);
+        self
     }
 
-    println!("\n\n");
+    pub fn state(&self) -> &ExecutionState {
+        &self.state
+    }
 
-    // Print remaining script instructions
-    if !script.is_empty() {
-        println!("{} (Step {})\n", "Script".bold(), step_nb);
+    // Copy of the current state, which can be given back to `restore` to rewind the execution
+    pub fn snapshot(&self) -> ExecutionState {
+        self.state.clone()
+    }
 
-        let colors = ["green", "yellow", "magenta", "cyan", "white"];
-        let mut line_len = 0;
-        for i in 0..script.len() {
-            let mut item_str = format!("{:?}", script[i]);
-            if line_len + item_str.len() > display_max_width && line_len > 0 {
-                println!("\n");
-                line_len = 0;
-            }
+    pub fn restore(&mut self, state: ExecutionState) {
+        self.state = state;
+    }
 
-            let color = String::from("bright ") + match script[i] {
-                ScriptItem::ByteArray(..) => "blue",
-                ScriptItem::Opcode(op) => {
-                    colors[(op.code % colors.len() as u8) as usize]
-                }
-            };
+    // Direct access to the stacks, to alter them between two steps
+    pub fn stack_mut(&mut self) -> &mut Stack {
+        &mut self.state.stack
+    }
 
-            if item_str.len() > display_max_width {
-                while item_str.len() > display_max_width {
-                    let sub_str = &item_str[..display_max_width];
-                    println!("{}", sub_str.bold().black().on_color(color.as_str()));
-                    item_str = String::from(&item_str[display_max_width..])
-                }
-                println!("{}\n", item_str.bold().black().on_color(color));
-                line_len = 0;
-            } else {
-                print!("{}", item_str.bold().black().on_color(color));
-                line_len += item_str.len();
-
-                if i != script.len() - 1 {
-                    print!(" ");
-                    line_len += 1;
+    pub fn observer(&self) -> &O {
+        &self.observer
+    }
+
+    pub fn observer_mut(&mut self) -> &mut O {
+        &mut self.observer
+    }
+
+    pub fn into_observer(self) -> O {
+        self.observer
+    }
+
+    // Execute the next instruction
+    // Returns Ok(false) once the end of the script has been reached and the script is balanced
+    pub fn step(&mut self) -> Result<bool, ScriptError> {
+        if let Some(e) = &self.state.error {
+            return Err(e.error)
+        }
+        if self.state.finished {
+            return Ok(false)
+        }
+
+        let offset = self.state.pc;
+        match self.next_item() {
+            Ok(more) => {
+                self.state.finished = !more;
+                if !more {
+                    if let Some(error) = self.state.verdict() {
+                        self.observer.on_error(&self.state, &error);
+                    }
                 }
+                Ok(more)
+            }
+            Err(error) => {
+                let error = ExecutionError {
+                    error,
+                    opcode: self.state.script.get(offset).map(|b| Opcode::from(*b)),
+                    offset
+                };
+                self.state.finished = true;
+                self.state.error = Some(error);
+                self.observer.on_error(&self.state, &error);
+                Err(error.error)
             }
         }
-        println!();
-    } else {
-        println!("{}", "Final state".bold());
     }
 
-    print_stack(&stack.main, "Main Stack", display_min_width, display_max_width);
-
-    if !&stack.alt.is_empty() {
-        print_stack(&stack.alt, "Alt Stack", display_min_width, display_max_width);
+    // Execute instructions until the program counter reaches `pc` (or goes past it)
+    pub fn run_until(&mut self, pc: usize) -> Result<bool, ScriptError> {
+        while self.state.pc < pc {
+            if !self.step()? {
+                return Ok(false)
+            }
+        }
+        Ok(!self.state.finished)
     }
-}
 
-pub fn interpret(script: &[u8], verbose: bool) -> Result<(), ScriptError> {
-    const SCRIPT_FALSE: [u8; 0] = [];
-    const SCRIPT_TRUE: [u8; 1] = [0x01];
+    // Execute the remaining instructions and return the final state
+    pub fn run(&mut self) -> ExecutionResult {
+        while let Ok(true) = self.step() {}
+        self.state.result()
+    }
 
-    let mut stack = Stack {main: Vec::with_capacity(20), alt: Vec::with_capacity(20)};
-    let mut condition_stack: Vec<bool> = Vec::with_capacity(10);
-    let mut execute: bool;
-    let mut op_count: usize = 0;
-    let mut pc: usize = 0;
-    let mut code_hash_start: usize = 0;
+    // Parse and execute one instruction, returns Ok(false) at the end of the script
+    fn next_item(&mut self) -> Result<bool, ScriptError> {
+        if self.state.script.len() > MAX_SCRIPT_SIZE {
+            return Err(ScriptError::ScriptSize)
+        }
 
-    if script.len() > MAX_SCRIPT_SIZE {
-        return Err(ScriptError::ScriptSizeErr)
+        let mut cursor = self.state.pc;
+        let item = match parse_one_op(&self.state.script, &mut cursor)? {
+            Some(item) => item,
+            None => {
+                if !self.state.condition_stack.is_empty() {
+                    return Err(ScriptError::UnbalancedConditional)
+                }
+                return Ok(false)
+            }
+        };
+
+        self.observer.before_step(&self.state, &item);
+        self.state.pc = cursor;
+        self.execute(&item)?;
+        self.state.steps += 1;
+        self.observer.after_step(&self.state, &item);
+        Ok(true)
     }
 
-    let mut display_script: Script = Script::new();
-    let mut step_nb: usize = 0;
-    if verbose {
-        display_script = parse_script(&script)?;
-        print_state(&stack, &display_script, step_nb);
+    fn push(&mut self, bytes: Vec<u8>) -> Result<(), ScriptError> {
+        self.state.stack.push(bytes)?;
+        self.observer.on_push(&self.state, self.state.stack.main.last().unwrap());
+        Ok(())
     }
 
-    let mut item = parse_one_op(script, &mut pc)?;
-    while item.is_some() {
-        execute = !condition_stack.contains(&false);
+    fn execute(&mut self, item: &ScriptItem) -> Result<(), ScriptError> {
+        const SCRIPT_FALSE: [u8; 0] = [];
+        const SCRIPT_TRUE: [u8; 1] = [0x01];
 
-        match item.unwrap() {
+        match item {
             ScriptItem::ByteArray(b) => {
                 if b.len() > MAX_SCRIPT_ELEMENT_SIZE {
-                    return Err(ScriptError::PushSizeErr)
+                    return Err(ScriptError::PushSize)
                 }
-                if execute {
-                    stack.push(b)?
+                if self.state.executing() {
+                    self.push(b.clone())?
                 }
             },
             ScriptItem::Opcode(op) => {
-                if DISABLED_OPCODES.contains(&op) {
-                    return Err(ScriptError::DisabledOpcodeErr)
+                let op = *op;
+                if op.is_disabled() {
+                    return Err(ScriptError::DisabledOpcode)
                 }
 
-                if op.code > OP_16.code {
-                    op_count += 1;
+                if op.counts_toward_op_limit() {
+                    self.state.op_count += 1;
                 }
-                if op_count > MAX_OPS_PER_SCRIPT {
-                    return Err(ScriptError::OpCountErr)
+                if self.state.op_count > MAX_OPS_PER_SCRIPT {
+                    return Err(ScriptError::OpCount)
                 }
 
-                if execute || (OP_IF.code <= op.code && op.code <= OP_ENDIF.code) {
+                if self.state.will_execute(item) {
                     match op {
                         //
                         // Data Push
                         //
-                        OP_0 => stack.push(to_script_nb(0))?,
-                        OP_1NEGATE => stack.push(to_script_nb(-1))?,
-                        Opcode { code: c } if c >= OP_1.code && c <= OP_16.code => stack.push(to_script_nb((c - OP_1.code + 1) as i64))?,
+                        OP_0 => self.push(to_script_nb(0))?,
+                        OP_1NEGATE => self.push(to_script_nb(-1))?,
+                        Opcode { code: c } if (OP_1.code..=OP_16.code).contains(&c) => self.push(to_script_nb((c - OP_1.code + 1) as i64))?,
 
                         //
                         // Flow Control
                         //
                         OP_NOP => {}
-                        OP_CHECKSEQUENCEVERIFY => {}
-                        OP_CHECKLOCKTIMEVERIFY => {}
+                        OP_CHECKLOCKTIMEVERIFY | OP_CHECKSEQUENCEVERIFY => {
+                            if let Some(transaction) = &self.state.transaction {
+                                let value = as_script_nb_sized(&self.state.stack.top(0)?, 5)?;
+                                if value < 0 {
+                                    return Err(ScriptError::NegativeLocktime)
+                                }
+                                let satisfied = match op {
+                                    OP_CHECKLOCKTIMEVERIFY => transaction.check_lock_time(value),
+                                    _ => value & SEQUENCE_DISABLE_FLAG != 0 || transaction.check_sequence(value)
+                                };
+                                if !satisfied {
+                                    return Err(ScriptError::UnsatisfiedLocktime)
+                                }
+                            }
+                        }
                         OP_NOP1 | OP_NOP4 | OP_NOP5 | OP_NOP6 |
                         OP_NOP7 | OP_NOP8 | OP_NOP9 | OP_NOP10 => {}
                         OP_IF | OP_NOTIF => {
                             let mut condition = false;
-                            if execute {
-                                condition = as_bool(&stack.pop()?);
+                            if self.state.executing() {
+                                condition = as_bool(&self.state.stack.pop()?);
                                 if op == OP_NOTIF {
                                     condition = !condition;
                                 }
                             }
-                            condition_stack.push(condition);
+                            self.state.condition_stack.push(condition);
+                            self.observer.on_branch(&self.state, op);
                         }
                         OP_ELSE => {
-                            if condition_stack.is_empty() {
-                                return Err(ScriptError::UnbalancedConditionalErr)
+                            if self.state.condition_stack.is_empty() {
+                                return Err(ScriptError::UnbalancedConditional)
                             }
-                            let last = condition_stack.last_mut().unwrap();
+                            let last = self.state.condition_stack.last_mut().unwrap();
                             *last = !*last;
+                            self.observer.on_branch(&self.state, op);
                         }
                         OP_ENDIF => {
-                            if condition_stack.is_empty() {
-                                return Err(ScriptError::UnbalancedConditionalErr)
+                            if self.state.condition_stack.is_empty() {
+                                return Err(ScriptError::UnbalancedConditional)
                             }
-                            condition_stack.pop();
+                            self.state.condition_stack.pop();
+                            self.observer.on_branch(&self.state, op);
                         }
                         OP_VERIFY => {
-                            let v = as_bool(&stack.pop()?);
+                            let v = as_bool(&self.state.stack.pop()?);
                             if !v {
-                                return Err(ScriptError::VerifyErr)
+                                return Err(ScriptError::Verify)
                             }
                         }
-                        OP_RETURN => return Err(ScriptError::OpReturnErr),
+                        OP_RETURN => return Err(ScriptError::OpReturn),
 
                         //
                         // Stack
                         //
                         OP_TOALTSTACK => {
-                            let v = stack.pop()?;
-                            stack.push_alt(v)?
+                            let v = self.state.stack.pop()?;
+                            self.state.stack.push_alt(v)?
                         }
                         OP_FROMALTSTACK => {
-                            let v = stack.pop_alt()?;
-                            stack.push(v)?
+                            let v = self.state.stack.pop_alt()?;
+                            self.push(v)?
                         }
                         OP_2DROP => {
-                            stack.pop()?;
-                            stack.pop()?;
+                            self.state.stack.pop()?;
+                            self.state.stack.pop()?;
                         }
                         OP_2DUP => {
-                            let v1 = stack.top(-1)?;
-                            let v2 = stack.top(0)?;
-                            stack.push(v1)?;
-                            stack.push(v2)?
+                            let v1 = self.state.stack.top(-1)?;
+                            let v2 = self.state.stack.top(0)?;
+                            self.push(v1)?;
+                            self.push(v2)?
                         }
                         OP_3DUP => {
-                            let v1 = stack.top(-2)?;
-                            let v2 = stack.top(-1)?;
-                            let v3 = stack.top(0)?;
-                            stack.push(v1)?;
-                            stack.push(v2)?;
-                            stack.push(v3)?
+                            let v1 = self.state.stack.top(-2)?;
+                            let v2 = self.state.stack.top(-1)?;
+                            let v3 = self.state.stack.top(0)?;
+                            self.push(v1)?;
+                            self.push(v2)?;
+                            self.push(v3)?
                         }
                         OP_2OVER => {
-                            let v1 = stack.top(-3)?;
-                            let v2 = stack.top(-2)?;
-                            stack.push(v1)?;
-                            stack.push(v2)?
+                            let v1 = self.state.stack.top(-3)?;
+                            let v2 = self.state.stack.top(-2)?;
+                            self.push(v1)?;
+                            self.push(v2)?
                         }
                         OP_2ROT => {
-                            let v1 = stack.rm_top(-5)?;
-                            let v2 = stack.rm_top(-4)?;
-                            stack.push(v1)?;
-                            stack.push(v2)?
+                            let v1 = self.state.stack.rm_top(-5)?;
+                            let v2 = self.state.stack.rm_top(-4)?;
+                            self.push(v1)?;
+                            self.push(v2)?
                         }
                         OP_2SWAP => {
-                            stack.swap_top(0, -2)?;
-                            stack.swap_top(-1, -3)?
+                            self.state.stack.swap_top(0, -2)?;
+                            self.state.stack.swap_top(-1, -3)?
                         }
                         OP_IFDUP => {
-                            let v = stack.top(0)?;
+                            let v = self.state.stack.top(0)?;
                             if as_bool(&v) {
-                                stack.push(v)?
+                                self.push(v)?
                             }
                         }
                         OP_DEPTH => {
-                            let v = to_script_nb(stack.main.len() as i64);
-                            stack.push(v)?
+                            let v = to_script_nb(self.state.stack.main.len() as i64);
+                            self.push(v)?
                         }
-                        OP_DROP => { stack.pop()?; }
+                        OP_DROP => { self.state.stack.pop()?; }
                         OP_DUP => {
-                            let v = stack.top(0)?;
-                            stack.push(v)?
+                            let v = self.state.stack.top(0)?;
+                            self.push(v)?
                         }
-                        OP_NIP => { stack.rm_top(-1)?; }
+                        OP_NIP => { self.state.stack.rm_top(-1)?; }
                         OP_OVER => {
-                            let v = stack.top(-1)?;
-                            stack.push(v)?
+                            let v = self.state.stack.top(-1)?;
+                            self.push(v)?
                         }
-                        OP_PICK => {
-                            let n = as_script_nb(&stack.pop()?)?;
-                            let v = stack.top(-n)?;
-                            stack.push(v)?
-                        }
-                        OP_ROLL => {
-                            let n = as_script_nb(&stack.pop()?)?;
-                            let v = stack.rm_top(-n)?;
-                            stack.push(v)?
+                        // n must be a position on the stack left once n is popped
+                        OP_PICK | OP_ROLL => {
+                            let n = as_script_nb(&self.state.stack.pop()?)?;
+                            if n < 0 || n >= self.state.stack.main.len() as i64 {
+                                return Err(ScriptError::InvalidStackOperation)
+                            }
+                            let v = match op {
+                                OP_PICK => self.state.stack.top(-n)?,
+                                _ => self.state.stack.rm_top(-n)?
+                            };
+                            self.push(v)?
                         }
                         OP_ROT => {
-                            let v = stack.rm_top(-2)?;
-                            stack.push(v)?
+                            let v = self.state.stack.rm_top(-2)?;
+                            self.push(v)?
                         }
-                        OP_SWAP => stack.swap_top(0, -1)?,
+                        OP_SWAP => self.state.stack.swap_top(0, -1)?,
                         OP_TUCK => {
-                            let v1 = stack.pop()?;
-                            let v2 = stack.pop()?;
+                            let v1 = self.state.stack.pop()?;
+                            let v2 = self.state.stack.pop()?;
                             let v3 = v1.clone();
-                            stack.push(v1)?;
-                            stack.push(v2)?;
-                            stack.push(v3)?
+                            self.push(v1)?;
+                            self.push(v2)?;
+                            self.push(v3)?
                         }
 
                         //
                         // Splice
                         //
                         OP_SIZE => {
-                            let v = to_script_nb(stack.top(0)?.len() as i64);
-                            stack.push(v)?
+                            let v = to_script_nb(self.state.stack.top(0)?.len() as i64);
+                            self.push(v)?
                         }
 
                         //
                         // Bitwise Logic
                         //
                         OP_EQUAL | OP_EQUALVERIFY => {
-                            let v1 = stack.pop()?;
-                            let v2 = stack.pop()?;
+                            let v1 = self.state.stack.pop()?;
+                            let v2 = self.state.stack.pop()?;
                             if v1 == v2 {
-                                stack.push(Vec::from(SCRIPT_TRUE))?
+                                self.push(Vec::from(SCRIPT_TRUE))?
                             } else {
-                                stack.push(Vec::from(SCRIPT_FALSE))?
+                                self.push(Vec::from(SCRIPT_FALSE))?
                             }
 
                             if op == OP_EQUALVERIFY {
                                 if v1 == v2 {
-                                    stack.pop()?;
+                                    self.state.stack.pop()?;
                                 } else {
-                                    return Err(ScriptError::EqualVerifyErr)
+                                    return Err(ScriptError::EqualVerify)
                                 }
                             }
                         }
@@ -392,7 +601,7 @@ pub fn interpret(script: &[u8], verbose: bool) -> Result<(), ScriptError> {
                         // Arithmetic
                         //
                         OP_1ADD | OP_1SUB | OP_NEGATE | OP_ABS | OP_NOT | OP_0NOTEQUAL => {
-                            let mut v = as_script_nb(&stack.pop()?)?;
+                            let mut v = as_script_nb(&self.state.stack.pop()?)?;
                             match op {
                                 OP_1ADD => v += 1,
                                 OP_1SUB => v -= 1,
@@ -402,13 +611,13 @@ pub fn interpret(script: &[u8], verbose: bool) -> Result<(), ScriptError> {
                                 OP_0NOTEQUAL => v = (v != 0) as i64,
                                 _ => panic!()
                             }
-                            stack.push(to_script_nb(v))?
+                            self.push(to_script_nb(v))?
                         }
                         OP_ADD | OP_SUB | OP_BOOLAND | OP_BOOLOR | OP_NUMEQUAL | OP_NUMEQUALVERIFY |
                         OP_NUMNOTEQUAL | OP_LESSTHAN | OP_GREATERTHAN | OP_LESSTHANOREQUAL |
                         OP_GREATERTHANOREQUAL | OP_MIN | OP_MAX => {
-                            let v2 = as_script_nb(&stack.pop()?)?;
-                            let v1 = as_script_nb(&stack.pop()?)?;
+                            let v2 = as_script_nb(&self.state.stack.pop()?)?;
+                            let v1 = as_script_nb(&self.state.stack.pop()?)?;
                             let res = match op {
                                 OP_ADD => v1 + v2,
                                 OP_SUB => v1 - v2,
@@ -424,29 +633,29 @@ pub fn interpret(script: &[u8], verbose: bool) -> Result<(), ScriptError> {
                                 OP_MAX => max(v1, v2),
                                 _ => panic!()
                             };
-                            stack.push(to_script_nb(res))?;
+                            self.push(to_script_nb(res))?;
 
                             if op == OP_NUMEQUALVERIFY {
                                 if v1 == v2 {
-                                    stack.pop()?;
+                                    self.state.stack.pop()?;
                                 } else {
-                                    return Err(ScriptError::NumEqualVerifyErr)
+                                    return Err(ScriptError::NumEqualVerify)
                                 }
                             }
                         }
                         OP_WITHIN => {
-                            let max = stack.pop()?;
-                            let min = stack.pop()?;
-                            let x = stack.pop()?;
+                            let max = self.state.stack.pop()?;
+                            let min = self.state.stack.pop()?;
+                            let x = self.state.stack.pop()?;
                             let res = (min <= x && x < max) as i64;
-                            stack.push(to_script_nb(res))?
+                            self.push(to_script_nb(res))?
                         }
 
                         //
                         // Crypto
                         //
                         OP_RIPEMD160 | OP_SHA1 | OP_SHA256 | OP_HASH160 | OP_HASH256 => {
-                            let v = stack.pop()?;
+                            let v = self.state.stack.pop()?;
                             let res = match op {
                                 OP_RIPEMD160 => ripemd160::Hash::hash(&v).to_vec(),
                                 OP_SHA1 => sha1::Hash::hash(&v).to_vec(),
@@ -455,34 +664,334 @@ pub fn interpret(script: &[u8], verbose: bool) -> Result<(), ScriptError> {
                                 OP_HASH256 => sha256d::Hash::hash(&v).to_vec(),
                                 _ => panic!()
                             };
-                            stack.push(res)?
+                            self.push(res)?
                         }
-                        OP_CODESEPARATOR => code_hash_start = pc,
+                        OP_CODESEPARATOR => self.state.code_hash_start = self.state.pc,
                         OP_CHECKSIG | OP_CHECKSIGVERIFY => {
-
+                            let pubkey = self.state.stack.top(0)?;
+                            let sig = self.state.stack.top(-1)?;
+                            let script_code = self.script_code(&[&sig]);
+                            let success = self.check_signature(&sig, &pubkey, &script_code)?;
+                            if self.state.null_fail && !success && !sig.is_empty() {
+                                return Err(ScriptError::SigNullFail)
+                            }
+                            self.state.stack.pop()?;
+                            self.state.stack.pop()?;
+                            if op == OP_CHECKSIGVERIFY {
+                                if !success {
+                                    return Err(ScriptError::CheckSigVerify)
+                                }
+                            } else {
+                                self.push(if success { Vec::from(SCRIPT_TRUE) } else { Vec::from(SCRIPT_FALSE) })?
+                            }
                         }
                         OP_CHECKMULTISIG | OP_CHECKMULTISIGVERIFY => {
+                            // dummy <sig>... <m> <key>... <n>
+                            let keys_count = as_script_nb(&self.state.stack.top(0)?)?;
+                            if !(0..=20).contains(&keys_count) {
+                                return Err(ScriptError::PubkeyCount)
+                            }
+                            let keys_count = keys_count as usize;
+                            self.state.op_count += keys_count;
+                            if self.state.op_count > MAX_OPS_PER_SCRIPT {
+                                return Err(ScriptError::OpCount)
+                            }
+                            let sigs_count = as_script_nb(&self.state.stack.top(-(keys_count as i64) - 1)?)?;
+                            if sigs_count < 0 || sigs_count as usize > keys_count {
+                                return Err(ScriptError::SigCount)
+                            }
+                            let sigs_count = sigs_count as usize;
+                            let len = keys_count + sigs_count + 3;
+                            if self.state.stack.main.len() < len {
+                                return Err(ScriptError::InvalidStackOperation)
+                            }
 
-                        }
+                            let args = self.state.stack.main.split_off(self.state.stack.main.len() - len);
+                            let (dummy, sigs, keys) = (&args[0], &args[1..1 + sigs_count], &args[2 + sigs_count..len - 1]);
+                            let script_code = self.script_code(&sigs.iter().collect::<Vec<_>>());
+
+                            // Signatures must match the keys in the same order, a key without
+                            // signature is skipped. Both are read from the top like Core does, which
+                            // decides which badly encoded signature or key is reported
+                            let (mut sig, mut key) = (sigs.len(), keys.len());
+                            let mut success = true;
+                            while success && sig > 0 {
+                                if self.check_signature(&sigs[sig - 1], &keys[key - 1], &script_code)? {
+                                    sig -= 1;
+                                }
+                                key -= 1;
+                                success = sig <= key;
+                            }
+                            if self.state.null_fail && !success && sigs.iter().any(|s| !s.is_empty()) {
+                                return Err(ScriptError::SigNullFail)
+                            }
+                            if self.state.null_dummy && !dummy.is_empty() {
+                                return Err(ScriptError::SigNullDummy)
+                            }
 
+                            if op == OP_CHECKMULTISIGVERIFY {
+                                if !success {
+                                    return Err(ScriptError::CheckMultisigVerify)
+                                }
+                            } else {
+                                self.push(if success { Vec::from(SCRIPT_TRUE) } else { Vec::from(SCRIPT_FALSE) })?
+                            }
+                        }
 
-                        _ => return Err(ScriptError::BadOpcodeErr)
+                        _ => return Err(ScriptError::BadOpcode)
                     }
                 }
             }
         }
-        item = parse_one_op(script, &mut pc)?;
+        Ok(())
+    }
+}
 
-        if verbose {
-            display_script.remove(0);
-            step_nb += 1;
-            print_state(&stack, &display_script, step_nb);
+impl<O: ExecutionObserver> Interpreter<O> {
+    // Script signed by signatures: everything after the last executed OP_CODESEPARATOR,
+    // legacy signatures are also removed from it (FindAndDelete)
+    fn script_code(&self, sigs: &[&Vec<u8>]) -> Vec<u8> {
+        let mut script_code = self.state.script[self.state.code_hash_start..].to_vec();
+        if let Some(TransactionContext { sig_version: SigVersion::Base, .. }) = self.state.transaction {
+            for sig in sigs {
+                script_code = find_and_delete(&script_code, sig);
+            }
         }
+        script_code
     }
 
-    if !condition_stack.is_empty() {
-        return Err(ScriptError::UnbalancedConditionalErr)
+    // Without a transaction only empty signatures have a known outcome
+    fn check_signature(&self, sig: &[u8], pubkey: &[u8], script_code: &[u8]) -> Result<bool, ScriptError> {
+        match &self.state.transaction {
+            Some(transaction) => transaction.check_signature(sig, pubkey, script_code),
+            None if sig.is_empty() => Ok(false),
+            None => Err(ScriptError::SigUnverifiable)
+        }
+    }
+}
+
+// Execute the script and return its final state
+pub fn interpret(script: &[u8], verbose: bool, clean_stack: bool) -> ExecutionResult {
+    let mut interpreter = Interpreter::new(script).clean_stack(clean_stack);
+    if verbose {
+        interpreter.with_observer(TerminalObserver).run()
+    } else {
+        interpreter.run()
+    }
+}
+
+// Execute the script on the pushes of a witness or scriptSig spending an input of a transaction,
+// with the rules relay policy adds (CLEANSTACK, NULLFAIL, NULLDUMMY)
+pub fn interpret_input(script: &[u8], stack: Vec<Vec<u8>>, transaction: &TransactionContext) -> ExecutionResult {
+    let mut interpreter = Interpreter::new(script).with_transaction(transaction.clone())
+        .clean_stack(true)
+        .null_fail(true)
+        .null_dummy(true);
+    interpreter.stack_mut().main = stack;
+    interpreter.run()
+}
+
+#[cfg(test)]
+mod tests {
+    use secp256k1::SecretKey;
+
+    use super::*;
+    use crate::transaction::{OutPoint, Transaction, TxIn, TxOut, SEQUENCE_FINAL, SIGHASH_ALL};
+
+    fn error_of(script: &str) -> Option<(ScriptError, usize)> {
+        interpret(&hex::decode(script).unwrap(), false, false).error.map(|e| (e.error, e.offset))
+    }
+
+    #[test]
+    fn pick_and_roll_reject_out_of_range_indices() {
+        // OP_1NEGATE OP_PICK, OP_1NEGATE OP_ROLL
+        assert_eq!(error_of("4f79"), Some((ScriptError::InvalidStackOperation, 1)));
+        assert_eq!(error_of("4f7a"), Some((ScriptError::InvalidStackOperation, 1)));
+        // OP_1 OP_PICK with nothing left under the index
+        assert_eq!(error_of("5179"), Some((ScriptError::InvalidStackOperation, 1)));
+        // OP_1 OP_1 OP_PICK: the index is the stack size once popped
+        assert_eq!(error_of("515179"), Some((ScriptError::InvalidStackOperation, 2)));
+        // OP_2 OP_0 OP_PICK picks the 2 under the index
+        assert_eq!(error_of("520079"), None);
+        let result = interpret(&hex::decode("53525179").unwrap(), false, false);
+        assert_eq!(result.main_stack, vec![vec![3], vec![2], vec![3]]);
+        let result = interpret(&hex::decode("5352517a").unwrap(), false, false);
+        assert_eq!(result.main_stack, vec![vec![2], vec![3]]);
     }
 
-    Ok(())
-}
\ No newline at end of file
+    #[test]
+    fn stack_positions_past_either_end_are_errors() {
+        let mut stack = Stack { main: vec![vec![1], vec![2]], alt: Vec::new() };
+        assert_eq!(stack.top(0), Ok(vec![2]));
+        assert_eq!(stack.top(-1), Ok(vec![1]));
+        assert_eq!(stack.top(-2), Err(ScriptError::InvalidStackOperation));
+        assert_eq!(stack.top(1), Err(ScriptError::InvalidStackOperation));
+        assert_eq!(stack.rm_top(1), Err(ScriptError::InvalidStackOperation));
+        assert_eq!(stack.swap_top(0, 1), Err(ScriptError::InvalidStackOperation));
+        assert_eq!(stack.top(i64::MIN), Err(ScriptError::InvalidStackOperation));
+        assert_eq!(stack.main, vec![vec![1], vec![2]]);
+    }
+
+    #[test]
+    fn results_hold_the_final_stacks_and_verdict() {
+        // OP_2 OP_3 OP_ADD OP_5 OP_EQUAL
+        let result = interpret(&hex::decode("5253935587").unwrap(), false, false);
+        assert!(result.success);
+        assert_eq!(result.main_stack, vec![vec![1]]);
+        assert_eq!((result.op_count, result.steps), (2, 5));
+        assert!(result.error.is_none());
+
+        // OP_1 OP_TOALTSTACK OP_0
+        let result = interpret(&hex::decode("516b00").unwrap(), false, false);
+        assert!(!result.success);
+        assert_eq!((result.main_stack, result.alt_stack), (vec![vec![]], vec![vec![1]]));
+        let error = result.error.unwrap();
+        assert_eq!((error.error, error.opcode, error.offset), (ScriptError::EvalFalse, None, 3));
+    }
+
+    #[test]
+    fn hashes() {
+        // OP_0 OP_SHA256
+        let result = interpret(&hex::decode("00a8").unwrap(), false, false);
+        assert_eq!(hex::encode(&result.main_stack[0]), "e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855");
+        // <generator point> OP_HASH160
+        let script = "210279be667ef9dcbbac55a06295ce870b07029bfcdb2dce28d959f2815b16f81798a9";
+        let result = interpret(&hex::decode(script).unwrap(), false, false);
+        assert_eq!(hex::encode(&result.main_stack[0]), "751e76e8199196d454941c45d1b3a323f1433bd6");
+    }
+
+    #[test]
+    fn errors() {
+        assert_eq!(error_of("517e"), Some((ScriptError::DisabledOpcode, 1)));
+        assert_eq!(error_of("516a"), Some((ScriptError::OpReturn, 1)));
+        assert_eq!(error_of("5163"), Some((ScriptError::UnbalancedConditional, 2)));
+        assert_eq!(error_of("68"), Some((ScriptError::UnbalancedConditional, 0)));
+        assert_eq!(error_of("5169"), Some((ScriptError::EvalFalse, 2)));
+        assert_eq!(error_of("0069"), Some((ScriptError::Verify, 1)));
+        assert_eq!(error_of("4c"), Some((ScriptError::BadOpcode, 0)));
+        // OP_0 OP_IF OP_VERIF OP_ENDIF fails even though the branch is not executed
+        assert_eq!(error_of("0063656851"), Some((ScriptError::BadOpcode, 2)));
+        let result = interpret(&hex::decode("5151").unwrap(), false, true);
+        assert_eq!(result.error.map(|e| (e.error, e.offset)), Some((ScriptError::CleanStack, 2)));
+    }
+
+    #[test]
+    fn stepping() {
+        // OP_1 OP_IF OP_2 OP_ELSE OP_3 OP_ENDIF
+        let mut interpreter = Interpreter::new(&hex::decode("635267536851").unwrap());
+        interpreter.stack_mut().main = vec![vec![1]];
+        assert_eq!(interpreter.step(), Ok(true));
+        assert_eq!(interpreter.state().condition_stack(), &vec![true]);
+        let snapshot = interpreter.snapshot();
+        assert_eq!(interpreter.run_until(4), Ok(true));
+        assert_eq!((interpreter.state().pc(), interpreter.state().steps()), (4, 4));
+        assert_eq!(interpreter.state().main_stack(), &vec![vec![2]]);
+        assert!(interpreter.run().success);
+        assert_eq!(interpreter.step(), Ok(false));
+
+        // Going back and taking the other branch
+        interpreter.restore(snapshot);
+        interpreter.stack_mut().main.clear();
+        interpreter.state.condition_stack = vec![false];
+        let result = interpreter.run();
+        assert_eq!(result.main_stack, vec![vec![3], vec![1]]);
+    }
+
+    #[test]
+    fn errors_are_sticky() {
+        let mut interpreter = Interpreter::new(&hex::decode("6a51").unwrap());
+        assert_eq!(interpreter.step(), Err(ScriptError::OpReturn));
+        assert_eq!(interpreter.step(), Err(ScriptError::OpReturn));
+        assert!(interpreter.state().is_finished());
+        assert_eq!(interpreter.state().error().map(|e| e.offset), Some(0));
+    }
+
+    fn spending_context() -> TransactionContext {
+        let tx = Transaction {
+            version: 1,
+            inputs: vec![TxIn { previous_output: OutPoint::default(), script_sig: Vec::new(), sequence: SEQUENCE_FINAL, witness: Vec::new() }],
+            outputs: vec![TxOut { value: 0, script_pubkey: vec![OP_RETURN.code] }],
+            lock_time: 0
+        };
+        TransactionContext::new(tx, 0, 0, SigVersion::Base)
+    }
+
+    #[test]
+    fn null_fail_is_a_flag() {
+        // <pubkey of secret 0x01..01> OP_CHECKSIG OP_NOT, spent with a valid signature of another key
+        let script = hex::decode("21031b84c5567b126440995d3ed5aaba0565d71e1834604819ff9c17f5e9d5dd078fac91").unwrap();
+        let context = spending_context();
+        let other = SecretKey::from_slice(&[2; 32]).unwrap();
+        let sig = context.sign(&other, &script, SIGHASH_ALL);
+        let run = |null_fail: bool, sig: &[u8]| {
+            let mut interpreter = Interpreter::new(&script).with_transaction(context.clone()).null_fail(null_fail);
+            interpreter.stack_mut().main = vec![sig.to_vec()];
+            interpreter.run()
+        };
+
+        assert!(run(false, &sig).success);
+        let result = run(true, &sig);
+        assert_eq!(result.error.map(|e| (e.error, e.offset)), Some((ScriptError::SigNullFail, 34)));
+        assert!(run(true, &[]).success);
+    }
+
+    #[test]
+    fn null_dummy_is_a_flag() {
+        // OP_1 OP_0 OP_0 OP_CHECKMULTISIG: 0-of-0 with a non-empty dummy
+        let script = hex::decode("510000ae").unwrap();
+        assert!(Interpreter::new(&script).run().success);
+        let result = Interpreter::new(&script).null_dummy(true).run();
+        assert_eq!(result.error.map(|e| (e.error, e.offset)), Some((ScriptError::SigNullDummy, 3)));
+        assert!(Interpreter::new(&hex::decode("000000ae").unwrap()).null_dummy(true).run().success);
+    }
+
+    #[test]
+    fn multisig_null_fail() {
+        // OP_0 <non-empty signature> OP_1 <key> OP_1 OP_CHECKMULTISIG OP_NOT
+        let context = spending_context();
+        let script = hex::decode("5121031b84c5567b126440995d3ed5aaba0565d71e1834604819ff9c17f5e9d5dd078f51ae91").unwrap();
+        let sig = context.sign(&SecretKey::from_slice(&[2; 32]).unwrap(), &script, SIGHASH_ALL);
+        let run = |null_fail: bool| {
+            let mut interpreter = Interpreter::new(&script).with_transaction(context.clone()).null_fail(null_fail);
+            interpreter.stack_mut().main = vec![Vec::new(), sig.clone()];
+            interpreter.run()
+        };
+        assert!(run(false).success);
+        assert_eq!(run(true).error.map(|e| e.error), Some(ScriptError::SigNullFail));
+    }
+
+    #[test]
+    fn signatures_need_a_transaction() {
+        // <pubkey of secret 0x01..01> OP_CHECKSIG
+        let script = hex::decode("21031b84c5567b126440995d3ed5aaba0565d71e1834604819ff9c17f5e9d5dd078fac").unwrap();
+        let run = |sig: Vec<u8>| {
+            let mut interpreter = Interpreter::new(&script);
+            interpreter.stack_mut().main = vec![sig];
+            interpreter.run()
+        };
+        let sig = spending_context().sign(&SecretKey::from_slice(&[1; 32]).unwrap(), &script, SIGHASH_ALL);
+        assert_eq!(run(sig).error.map(|e| (e.error, e.offset)), Some((ScriptError::SigUnverifiable, 34)));
+        // An empty signature fails without checking anything
+        let result = run(Vec::new());
+        assert_eq!(result.main_stack, vec![Vec::<u8>::new()]);
+        assert_eq!(result.error.map(|e| e.error), Some(ScriptError::EvalFalse));
+    }
+
+    #[test]
+    fn multisig_reads_keys_and_signatures_from_the_top() {
+        // OP_1 <invalid key> <pubkey of secret 0x01..01> OP_2 OP_CHECKMULTISIG: the signature is checked
+        // against the last key first, the invalid one is never parsed
+        let script = hex::decode(format!("5121{}21031b84c5567b126440995d3ed5aaba0565d71e1834604819ff9c17f5e9d5dd078f52ae", "05".repeat(33))).unwrap();
+        let context = spending_context();
+        let sig = context.sign(&SecretKey::from_slice(&[1; 32]).unwrap(), &script, SIGHASH_ALL);
+        let mut interpreter = Interpreter::new(&script).with_transaction(context.clone());
+        interpreter.stack_mut().main = vec![Vec::new(), sig];
+        assert!(interpreter.run().success);
+
+        // With a signature of another key, the invalid key is reached
+        let other = context.sign(&SecretKey::from_slice(&[2; 32]).unwrap(), &script, SIGHASH_ALL);
+        let mut interpreter = Interpreter::new(&script).with_transaction(context);
+        interpreter.stack_mut().main = vec![Vec::new(), other];
+        assert_eq!(interpreter.run().error.map(|e| e.error), Some(ScriptError::PubkeyType));
+    }
+}
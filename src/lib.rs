@@ -0,0 +1,36 @@
+pub mod address;
+pub mod analyze;
+pub mod asm;
+pub mod bip32;
+pub mod builder;
+pub mod contract;
+pub mod dap;
+pub mod lint;
+pub mod miniscript;
+pub mod lsp;
+pub mod macro_asm;
+pub mod opcodes;
+pub mod parse;
+pub mod policy;
+pub mod pretty;
+pub mod script;
+pub mod solver;
+pub mod interpret;
+pub mod observer;
+pub mod optimize;
+pub mod satisfy;
+pub mod protocol;
+pub mod symbolic;
+pub mod transaction;
+pub mod debugger;
+pub mod descriptor;
+pub mod tui;
+
+// script! and script_bytes! refer to the crate by name, also from inside it
+extern crate self as btc_script_interpreter;
+pub use btc_script_macro::{script, script_bytes};
+
+
+// Constants to configure step-by-step script execution display
+pub const MAX_SCRIPT_DISPLAY_WIDTH:usize = 80;
+pub const MIN_SCRIPT_DISPLAY_WIDTH:usize = 30;
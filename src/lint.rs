@@ -0,0 +1,336 @@
+use std::collections::HashMap;
+use std::fmt;
+use std::str::FromStr;
+
+use crate::opcodes::*;
+use crate::parse::parse_script_with_offsets;
+use crate::script::{minimal_push, ScriptError, ScriptItem};
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Hash)]
+pub enum Lint {
+    NonMinimalPush,
+    UnreachableCode,
+    SeparateVerify,
+    MalleableIf,
+    NonZeroDummy,
+    DisabledOpcode,
+    UpgradableNop
+}
+
+impl Lint {
+    pub const ALL: [Lint; 7] = [Lint::NonMinimalPush, Lint::UnreachableCode, Lint::SeparateVerify,
+        Lint::MalleableIf, Lint::NonZeroDummy, Lint::DisabledOpcode, Lint::UpgradableNop];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Lint::NonMinimalPush => "non_minimal_push",
+            Lint::UnreachableCode => "unreachable_code",
+            Lint::SeparateVerify => "separate_verify",
+            Lint::MalleableIf => "malleable_if",
+            Lint::NonZeroDummy => "non_zero_dummy",
+            Lint::DisabledOpcode => "disabled_opcode",
+            Lint::UpgradableNop => "upgradable_nop"
+        }
+    }
+
+    pub fn default_level(&self) -> LintLevel {
+        match self {
+            Lint::DisabledOpcode => LintLevel::Deny,
+            _ => LintLevel::Warn
+        }
+    }
+}
+
+impl fmt::Display for Lint {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.name())
+    }
+}
+
+impl FromStr for Lint {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Lint::ALL.into_iter()
+            .find(|l| l.name() == s.replace('-', "_"))
+            .ok_or(format!("Unknown lint: {}", s))
+    }
+}
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Ord, PartialOrd, Hash)]
+pub enum LintLevel {
+    Allow,
+    Warn,
+    Deny
+}
+
+impl fmt::Display for LintLevel {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            LintLevel::Allow => write!(f, "allow"),
+            LintLevel::Warn => write!(f, "warning"),
+            LintLevel::Deny => write!(f, "error")
+        }
+    }
+}
+
+// Level of every lint, lints not set explicitly use their default level
+#[derive(Debug, Clone, Default)]
+pub struct LintConfig {
+    levels: HashMap<Lint, LintLevel>
+}
+
+impl LintConfig {
+    pub fn new() -> LintConfig {
+        LintConfig::default()
+    }
+
+    pub fn set(&mut self, lint: Lint, level: LintLevel) -> &mut LintConfig {
+        self.levels.insert(lint, level);
+        self
+    }
+
+    pub fn level(&self, lint: Lint) -> LintLevel {
+        self.levels.get(&lint).copied().unwrap_or_else(|| lint.default_level())
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Warning {
+    pub lint: Lint,
+    pub level: LintLevel,
+    pub offset: usize,
+    pub message: String,
+    pub suggestion: String
+}
+
+impl fmt::Display for Warning {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}[{}] at offset {}: {}\n  fix: {}", self.level, self.lint, self.offset, self.message, self.suggestion)
+    }
+}
+
+// Opcodes leaving exactly 0 or 1 on the stack
+const BOOLEAN_OPCODES: [Opcode; 16] = [OP_0, OP_1, OP_EQUAL, OP_NUMEQUAL, OP_NUMNOTEQUAL, OP_LESSTHAN, OP_GREATERTHAN,
+    OP_LESSTHANOREQUAL, OP_GREATERTHANOREQUAL, OP_WITHIN, OP_NOT, OP_0NOTEQUAL, OP_BOOLAND, OP_BOOLOR,
+    OP_CHECKSIG, OP_CHECKMULTISIG];
+
+// Lint a serialized script, warnings are sorted by offset and allowed lints are left out
+pub fn lint(bytes: &[u8], config: &LintConfig) -> Result<Vec<Warning>, ScriptError> {
+    let items = parse_script_with_offsets(bytes)?;
+    let mut warnings = Vec::new();
+    let mut warn = |lint: Lint, offset: usize, message: String, suggestion: String| {
+        let level = config.level(lint);
+        if level != LintLevel::Allow {
+            warnings.push(Warning { lint, level, offset, message, suggestion });
+        }
+    };
+    for (i, (offset, item)) in items.iter().enumerate() {
+        let offset = *offset;
+        let end = items.get(i + 1).map_or(bytes.len(), |(o, _)| *o);
+        let previous = i.checked_sub(1).map(|p| &items[p].1);
+
+        let op = match item {
+            ScriptItem::ByteArray(data) => {
+                let minimal = minimal_push(data);
+                if bytes[offset..end] != minimal[..] {
+                    warn(Lint::NonMinimalPush, offset,
+                        format!("Push of {} bytes encoded with {} bytes instead of {}", data.len(), end - offset, minimal.len()),
+                        format!("encode it as {}", describe_push(&minimal)));
+                }
+                continue
+            }
+            ScriptItem::Opcode(op) => *op
+        };
+
+        if op.is_disabled() {
+            warn(Lint::DisabledOpcode, offset, format!("{} is disabled, any script containing it fails", op),
+                format!("remove {}", op));
+        }
+        if op.behaviour(ScriptContext::Legacy) == Behaviour::UpgradableNop {
+            warn(Lint::UpgradableNop, offset,
+                format!("{} is reserved for upgrades and non-standard under DISCOURAGE_UPGRADABLE_NOPS", op),
+                format!("remove {}", op));
+        }
+
+        match op {
+            OP_IF | OP_NOTIF => {
+                let boolean = matches!(previous, Some(ScriptItem::Opcode(p)) if BOOLEAN_OPCODES.contains(p));
+                if !boolean {
+                    warn(Lint::MalleableIf, offset,
+                        format!("{} condition may come from the witness, any non-zero value takes the same branch", op),
+                        String::from("compute the condition with a boolean opcode such as OP_EQUAL or OP_CHECKSIG, or rely on MINIMALIF"));
+                }
+            }
+            OP_VERIFY => {
+                let fused = match previous {
+                    Some(ScriptItem::Opcode(p)) => match *p {
+                        OP_EQUAL => Some(OP_EQUALVERIFY),
+                        OP_NUMEQUAL => Some(OP_NUMEQUALVERIFY),
+                        OP_CHECKSIG => Some(OP_CHECKSIGVERIFY),
+                        OP_CHECKMULTISIG => Some(OP_CHECKMULTISIGVERIFY),
+                        _ => None
+                    },
+                    _ => None
+                };
+                if let (Some(fused), Some(ScriptItem::Opcode(p))) = (fused, previous) {
+                    warn(Lint::SeparateVerify, items[i - 1].0, format!("{} OP_VERIFY can be written in one opcode", p),
+                        format!("replace with {}", fused));
+                }
+            }
+            OP_RETURN => {
+                // Everything up to the end of its branch, or of the script at top level
+                let end = branch_end(&items, i + 1);
+                let unreachable = &items[i + 1..end];
+                // OP_RETURN followed by pushes is the null data output template
+                let null_data = i == 0 && unreachable.iter().all(|(_, item)| is_push(item));
+                if !unreachable.is_empty() && !null_data {
+                    let end_offset = items.get(end).map_or(bytes.len(), |(o, _)| *o);
+                    warn(Lint::UnreachableCode, unreachable[0].0,
+                        format!("{} items after OP_RETURN are never executed", unreachable.len()),
+                        format!("remove the {} bytes after OP_RETURN", end_offset - unreachable[0].0));
+                }
+            }
+            OP_CHECKMULTISIG | OP_CHECKMULTISIGVERIFY => {
+                if let Some(dummy) = multisig_dummy(&items[..i]) {
+                    let (dummy_offset, dummy) = &items[dummy];
+                    let empty = matches!(dummy, ScriptItem::Opcode(OP_0)) || *dummy == ScriptItem::ByteArray(Vec::new());
+                    if !empty {
+                        warn(Lint::NonZeroDummy, *dummy_offset,
+                            format!("{} dummy element is {}, anyone relaying it can change it", op, dummy),
+                            String::from("push OP_0 as the dummy element (NULLDUMMY)"));
+                    }
+                }
+            }
+            _ => ()
+        }
+    }
+
+    warnings.sort_by_key(|w| w.offset);
+    Ok(warnings)
+}
+
+// Index of the OP_ELSE or OP_ENDIF closing the branch that contains `start`, or the end of the script
+fn branch_end(items: &[(usize, ScriptItem)], start: usize) -> usize {
+    let mut depth = 0;
+    for (i, (_, item)) in items.iter().enumerate().skip(start) {
+        match item {
+            ScriptItem::Opcode(OP_IF | OP_NOTIF) => depth += 1,
+            ScriptItem::Opcode(OP_ELSE) if depth == 0 => return i,
+            ScriptItem::Opcode(OP_ENDIF) if depth == 0 => return i,
+            ScriptItem::Opcode(OP_ENDIF) => depth -= 1,
+            _ => ()
+        }
+    }
+    items.len()
+}
+
+fn is_push(item: &ScriptItem) -> bool {
+    match item {
+        ScriptItem::ByteArray(_) => true,
+        ScriptItem::Opcode(op) => op.code <= OP_16.code && *op != OP_RESERVED
+    }
+}
+
+fn small_number(item: &ScriptItem) -> Option<usize> {
+    match item {
+        ScriptItem::Opcode(OP_0) => Some(0),
+        ScriptItem::Opcode(op) if (OP_1.code..=OP_16.code).contains(&op.code) => Some((op.code - OP_1.code + 1) as usize),
+        ScriptItem::ByteArray(data) if data.len() == 1 => Some(data[0] as usize),
+        _ => None
+    }
+}
+
+// Index of the dummy element of a CHECKMULTISIG, when the script pushes all of its arguments:
+// dummy <sig>... <m> <key>... <n>
+fn multisig_dummy(before: &[(usize, ScriptItem)]) -> Option<usize> {
+    let items: Vec<&ScriptItem> = before.iter().map(|(_, item)| item).collect();
+    let n_index = items.len().checked_sub(1)?;
+    let n = small_number(items[n_index])?;
+    let m_index = n_index.checked_sub(n + 1)?;
+    let m = small_number(items[m_index])?;
+    let dummy = m_index.checked_sub(m + 1)?;
+    if items[dummy..].iter().all(|item| is_push(item)) {
+        Some(dummy)
+    } else {
+        None
+    }
+}
+
+fn describe_push(push: &[u8]) -> String {
+    match push {
+        [code] => Opcode::from(*code).to_string(),
+        _ => format!("0x{}", hex::encode(push))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const KEY: &str = "21031b84c5567b126440995d3ed5aaba0565d71e1834604819ff9c17f5e9d5dd078f";
+
+    fn warnings(script: &str) -> Vec<(Lint, usize)> {
+        lint(&hex::decode(script).unwrap(), &LintConfig::new()).unwrap().iter().map(|w| (w.lint, w.offset)).collect()
+    }
+
+    #[test]
+    fn non_minimal_pushes() {
+        let warnings = lint(&hex::decode("4c01050101").unwrap(), &LintConfig::new()).unwrap();
+        assert_eq!(warnings.len(), 2);
+        assert_eq!(warnings[0].message, "Push of 1 bytes encoded with 3 bytes instead of 1");
+        assert_eq!(warnings[0].suggestion, "encode it as OP_5");
+        assert_eq!((warnings[1].offset, warnings[1].suggestion.as_str()), (3, "encode it as OP_1"));
+        assert_eq!(self::warnings(&format!("4c4b{}", "ab".repeat(0x4b))), vec![(Lint::NonMinimalPush, 0)]);
+        assert!(self::warnings(&format!("4c4c{}", "ab".repeat(0x4c))).is_empty());
+    }
+
+    #[test]
+    fn opcode_lints() {
+        // OP_EQUAL OP_VERIFY, OP_CHECKSIG OP_VERIFY
+        assert_eq!(warnings("8769ac69"), vec![(Lint::SeparateVerify, 0), (Lint::SeparateVerify, 2)]);
+        // OP_IF on a witness value, then on OP_EQUAL
+        assert_eq!(warnings("63688763516851"), vec![(Lint::MalleableIf, 0)]);
+        assert_eq!(warnings("7eb0"), vec![(Lint::DisabledOpcode, 0), (Lint::UpgradableNop, 1)]);
+    }
+
+    #[test]
+    fn unreachable_code() {
+        assert_eq!(warnings("516a5152"), vec![(Lint::UnreachableCode, 2)]);
+        // Null data outputs and OP_RETURN at the end of a branch are fine
+        assert!(warnings("6a04deadbeef").is_empty());
+        assert_eq!(warnings("8763516a6851"), Vec::new());
+        // OP_EQUAL OP_IF OP_RETURN OP_1 OP_0 OP_IF OP_2 OP_ENDIF OP_ELSE OP_3 OP_ENDIF: up to the OP_ELSE
+        let warnings = lint(&hex::decode("87636a510063526867536851").unwrap(), &LintConfig::new()).unwrap();
+        let unreachable: Vec<&Warning> = warnings.iter().filter(|w| w.lint == Lint::UnreachableCode).collect();
+        assert_eq!(unreachable.len(), 1);
+        assert_eq!((unreachable[0].offset, unreachable[0].message.as_str()), (3, "5 items after OP_RETURN are never executed"));
+        assert_eq!(unreachable[0].suggestion, "remove the 5 bytes after OP_RETURN");
+    }
+
+    #[test]
+    fn multisig_dummy() {
+        assert!(warnings(&format!("0001ab51{}51ae", KEY)).is_empty());
+        let warnings = lint(&hex::decode(format!("5101ab51{}51ae", KEY)).unwrap(), &LintConfig::new()).unwrap();
+        assert_eq!((warnings[0].lint, warnings[0].offset), (Lint::NonZeroDummy, 0));
+        assert_eq!(warnings[0].message, "OP_CHECKMULTISIG dummy element is OP_1, anyone relaying it can change it");
+        let warnings = lint(&hex::decode(format!("01cd01ab51{}51ae", KEY)).unwrap(), &LintConfig::new()).unwrap();
+        assert_eq!(warnings[0].message, "OP_CHECKMULTISIG dummy element is 0xcd, anyone relaying it can change it");
+        // Arguments coming from the witness are not checked
+        assert!(self::warnings(&format!("51{}51ae", KEY)).is_empty());
+    }
+
+    #[test]
+    fn configuration() {
+        assert_eq!("non-minimal-push".parse(), Ok(Lint::NonMinimalPush));
+        assert!("nope".parse::<Lint>().is_err());
+        assert_eq!(Lint::DisabledOpcode.default_level(), LintLevel::Deny);
+
+        let mut config = LintConfig::new();
+        config.set(Lint::DisabledOpcode, LintLevel::Allow).set(Lint::SeparateVerify, LintLevel::Deny);
+        let warnings = lint(&hex::decode("7e8769").unwrap(), &config).unwrap();
+        assert_eq!(warnings.len(), 1);
+        assert_eq!(warnings[0].to_string(), "error[separate_verify] at offset 1: OP_EQUAL OP_VERIFY can be written in one opcode\n  fix: replace with OP_EQUALVERIFY");
+        assert!(lint(&[0x4c], &config).is_err());
+    }
+}
@@ -0,0 +1,362 @@
+use std::collections::HashMap;
+use std::io::{self, BufRead, Write};
+use std::path::{Path, PathBuf};
+
+use serde_json::{json, Value};
+use url::Url;
+
+use crate::asm::{parse_token, tokenize, AsmError};
+use crate::macro_asm::assemble_source;
+use crate::opcodes::{Behaviour, Opcode, ScriptContext, OP_ELSE, OP_ENDIF, OP_IF, OP_NOTIF};
+use crate::protocol::{read_message, write_message};
+use crate::script::{ScriptItem, MAX_SCRIPT_ELEMENT_SIZE};
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum Severity {
+    Error,
+    Warning
+}
+
+// Problem found in an ASM source, lines and columns start at 1
+#[derive(Debug, Clone)]
+pub struct Diagnostic {
+    pub line: usize,
+    pub column: usize,
+    pub length: usize,
+    pub severity: Severity,
+    pub message: String
+}
+
+// Check a macro ASM source saved at `path` without running it: the first assembly error (unknown
+// opcode or name, bad macro call, missing include), disabled and reserved opcodes, unbalanced
+// OP_IF/OP_ENDIF and pushes over MAX_SCRIPT_ELEMENT_SIZE
+pub fn check(text: &str, path: &Path) -> Vec<Diagnostic> {
+    let mut diagnostics = Vec::new();
+    if let Err(e) = assemble_source(path, text) {
+        diagnostics.push(assembly_diagnostic(text, path, e));
+    }
+    // Open OP_IF/OP_NOTIF with their position
+    let mut branches: Vec<(usize, usize, usize, Opcode)> = Vec::new();
+
+    // Directives, names and macro calls are left to the assembler
+    for (line, column, token) in tokenize(text) {
+        let mut report = |severity, message| diagnostics.push(Diagnostic { line, column, length: token.len(), severity, message });
+
+        match parse_token(token) {
+            Err(_) => (),
+            Ok(ScriptItem::ByteArray(data)) if data.len() > MAX_SCRIPT_ELEMENT_SIZE => report(Severity::Error,
+                format!("Push of {} bytes exceeds the {} bytes limit", data.len(), MAX_SCRIPT_ELEMENT_SIZE)),
+            Ok(ScriptItem::ByteArray(_)) => (),
+            Ok(ScriptItem::Opcode(op)) => {
+                match op.behaviour(ScriptContext::Legacy) {
+                    _ if op.is_disabled() => report(Severity::Error, format!("{} is disabled, any script containing it fails", op)),
+                    Behaviour::AlwaysInvalid => report(Severity::Error, format!("{} fails the script even in an unexecuted branch", op)),
+                    Behaviour::Invalid => report(Severity::Error, format!("{} fails the script when executed", op)),
+                    Behaviour::UpgradableNop => report(Severity::Warning, format!("{} is reserved for future upgrades", op)),
+                    _ => ()
+                }
+                match op {
+                    OP_IF | OP_NOTIF => branches.push((line, column, token.len(), op)),
+                    OP_ELSE if branches.is_empty() => report(Severity::Error, String::from("OP_ELSE without OP_IF")),
+                    OP_ENDIF if branches.pop().is_none() => report(Severity::Error, String::from("OP_ENDIF without OP_IF")),
+                    _ => ()
+                }
+            }
+        }
+    }
+
+    for (line, column, length, op) in branches {
+        diagnostics.push(Diagnostic {
+            line,
+            column,
+            length,
+            severity: Severity::Error,
+            message: format!("{} is never closed by OP_ENDIF", op)
+        });
+    }
+    diagnostics
+}
+
+// Errors in an included file are shown on the first line
+fn assembly_diagnostic(text: &str, path: &Path, e: AsmError) -> Diagnostic {
+    let here = e.file.as_deref() == Some(&*path.display().to_string()) && e.line > 0;
+    let (line, column, message) = match here {
+        true => (e.line, e.column.max(1), e.message),
+        false => (1, 1, e.to_string())
+    };
+    let length = tokenize(text).into_iter()
+        .find(|(l, c, _)| *l == line && *c == column)
+        .map_or(1, |(_, _, token)| token.len());
+    Diagnostic { line, column, length, severity: Severity::Error, message }
+}
+
+// Markdown description of an opcode, shown on hover
+pub fn describe(op: Opcode) -> String {
+    let effect = match op.stack_effect() {
+        Some((inputs, outputs)) => format!("{} → {}", inputs, outputs),
+        None => String::from("depends on the stack content")
+    };
+    let mut text = format!("**{}** (0x{:02x})\n\nCategory: {}\n\nStack effect: {}", op, op.code, op.category(), effect);
+    if op.is_disabled() {
+        text.push_str("\n\n**Disabled**: any script containing it fails");
+    }
+    if op.behaviour(ScriptContext::Tapscript) == Behaviour::Success {
+        text.push_str("\n\nOP_SUCCESS in tapscript");
+    }
+    text
+}
+
+// Language server for .btcasm files
+pub struct LspServer<W: Write> {
+    writer: W,
+    documents: HashMap<String, String>
+}
+
+impl<W: Write> LspServer<W> {
+    pub fn new(writer: W) -> LspServer<W> {
+        LspServer { writer, documents: HashMap::new() }
+    }
+
+    // Handle messages until the client sends exit
+    pub fn serve(&mut self, reader: &mut impl BufRead) -> io::Result<()> {
+        while let Some(message) = read_message(reader)? {
+            if !self.handle(&message)? {
+                break
+            }
+        }
+        Ok(())
+    }
+
+    // Returns false once the client sent exit
+    pub fn handle(&mut self, message: &Value) -> io::Result<bool> {
+        let method = message["method"].as_str().unwrap_or("");
+        let params = &message["params"];
+
+        let result = match method {
+            "initialize" => json!({
+                "capabilities": {
+                    "textDocumentSync": 1,
+                    "hoverProvider": true,
+                    "completionProvider": {}
+                },
+                "serverInfo": {"name": "btc_script_interpreter"}
+            }),
+            "shutdown" => Value::Null,
+            "exit" => return Ok(false),
+            "textDocument/didOpen" => {
+                let document = &params["textDocument"];
+                return self.update(document["uri"].as_str().unwrap_or(""), document["text"].as_str().unwrap_or(""))
+            }
+            "textDocument/didChange" => {
+                // Only full document synchronisation is supported, the last change holds the whole text
+                let text = params["contentChanges"].as_array().and_then(|c| c.last()).and_then(|c| c["text"].as_str());
+                return self.update(params["textDocument"]["uri"].as_str().unwrap_or(""), text.unwrap_or(""))
+            }
+            "textDocument/didClose" => {
+                let uri = params["textDocument"]["uri"].as_str().unwrap_or("");
+                self.documents.remove(uri);
+                self.notify("textDocument/publishDiagnostics", json!({"uri": uri, "diagnostics": []}))?;
+                return Ok(true)
+            }
+            "textDocument/hover" => self.hover(params),
+            "textDocument/completion" => completion(),
+            _ if message.get("id").is_none() => return Ok(true),
+            _ => {
+                let error = json!({"code": -32601, "message": format!("Unsupported method: {}", method)});
+                write_message(&mut self.writer, &json!({"jsonrpc": "2.0", "id": message["id"], "error": error}))?;
+                return Ok(true)
+            }
+        };
+
+        write_message(&mut self.writer, &json!({"jsonrpc": "2.0", "id": message["id"], "result": result}))?;
+        Ok(true)
+    }
+
+    fn update(&mut self, uri: &str, text: &str) -> io::Result<bool> {
+        // Includes are resolved relative to the document, file URIs are percent-decoded
+        let path = Url::parse(uri).ok().and_then(|url| url.to_file_path().ok()).unwrap_or_else(|| PathBuf::from(uri));
+        let diagnostics: Vec<Value> = check(text, &path).iter().map(|d| json!({
+            "range": range(d.line, d.column, d.length),
+            "severity": match d.severity { Severity::Error => 1, Severity::Warning => 2 },
+            "source": "btcasm",
+            "message": d.message
+        })).collect();
+        self.documents.insert(uri.to_string(), text.to_string());
+        self.notify("textDocument/publishDiagnostics", json!({"uri": uri, "diagnostics": diagnostics}))?;
+        Ok(true)
+    }
+
+    fn hover(&self, params: &Value) -> Value {
+        let text = match self.documents.get(params["textDocument"]["uri"].as_str().unwrap_or("")) {
+            Some(text) => text,
+            None => return Value::Null
+        };
+        let line = params["position"]["line"].as_u64().unwrap_or(0) as usize + 1;
+        let character = params["position"]["character"].as_u64().unwrap_or(0) as usize + 1;
+
+        let token = tokenize(text).into_iter()
+            .find(|(l, c, t)| *l == line && (*c..=*c + t.len()).contains(&character));
+        let (line, column, token) = match token {
+            Some(token) => token,
+            None => return Value::Null
+        };
+        let contents = match parse_token(token) {
+            Ok(ScriptItem::Opcode(op)) => describe(op),
+            Ok(ScriptItem::ByteArray(data)) => format!("Push of {} bytes\n\n`{}`", data.len(), hex::encode(&data)),
+            Err(message) => message
+        };
+        json!({"contents": {"kind": "markdown", "value": contents}, "range": range(line, column, token.len())})
+    }
+
+    fn notify(&mut self, method: &str, params: Value) -> io::Result<()> {
+        write_message(&mut self.writer, &json!({"jsonrpc": "2.0", "method": method, "params": params}))
+    }
+}
+
+// Every opcode name usable in ASM, the PUSH_BYTES and PUSH_DATA opcodes are written as hex data
+fn completion() -> Value {
+    let items: Vec<Value> = (0..=255u8).map(Opcode::from)
+        .filter(|op| !(1..=0x4e).contains(&op.code))
+        .map(|op| json!({
+            "label": op.to_string(),
+            "kind": 14,
+            "detail": op.category().to_string(),
+            "documentation": {"kind": "markdown", "value": describe(op)}
+        }))
+        .collect();
+    json!(items)
+}
+
+// LSP positions start at 0
+fn range(line: usize, column: usize, length: usize) -> Value {
+    json!({
+        "start": {"line": line - 1, "character": column - 1},
+        "end": {"line": line - 1, "character": column - 1 + length}
+    })
+}
+
+// Serve LSP requests on stdin and stdout
+pub fn run_stdio() -> io::Result<()> {
+    let stdin = io::stdin();
+    LspServer::new(io::stdout()).serve(&mut stdin.lock())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+    use std::io::Cursor;
+
+    use super::*;
+
+    const MACROS: &str = "define ALICE 0x031b84c5567b126440995d3ed5aaba0565d71e1834604819ff9c17f5e9d5dd078f\n\
+        macro chk(key)\n    <key> OP_CHECKSIGVERIFY\nendmacro\nchk(ALICE) OP_1\n";
+
+    fn messages(check: &[Diagnostic]) -> Vec<(usize, usize, usize, &str)> {
+        check.iter().map(|d| (d.line, d.column, d.length, d.message.as_str())).collect()
+    }
+
+    #[test]
+    fn macro_files_have_no_false_positives() {
+        assert!(check(MACROS, Path::new("contract.btcasm")).is_empty());
+        assert!(check("OP_DUP OP_HASH160 0x00 OP_EQUALVERIFY # comment", Path::new("plain.btcasm")).is_empty());
+    }
+
+    #[test]
+    fn assembly_errors() {
+        let path = Path::new("test.btcasm");
+        assert_eq!(messages(&check("OP_1\nchk(BOB) OP_FOO", path)), vec![(2, 1, 8, "Unknown macro: chk")]);
+        assert_eq!(messages(&check("define A 1\nA CAFE", path)), vec![(2, 3, 4, "Undefined name: CAFE")]);
+        let missing = check("include \"missing.btcasm\"", path);
+        assert_eq!((missing[0].line, missing[0].column), (1, 1));
+        assert!(missing[0].message.starts_with("Can't read missing.btcasm"));
+    }
+
+    #[test]
+    fn opcode_checks() {
+        let path = Path::new("test.btcasm");
+        let diagnostics = check("OP_1 OP_IF OP_CAT OP_NOP1\nOP_ENDIF OP_ELSE", path);
+        assert_eq!(messages(&diagnostics), vec![
+            (1, 12, 6, "OP_CAT is disabled, any script containing it fails"),
+            (1, 19, 7, "OP_NOP1 is reserved for future upgrades"),
+            (2, 10, 7, "OP_ELSE without OP_IF")
+        ]);
+        assert_eq!(diagnostics[1].severity, Severity::Warning);
+        assert_eq!(messages(&check("OP_NOTIF", path)), vec![(1, 1, 8, "OP_NOTIF is never closed by OP_ENDIF")]);
+        let push = format!("0x{}", "00".repeat(521));
+        assert_eq!(check(&push, path)[0].message, "Push of 521 bytes exceeds the 520 bytes limit");
+    }
+
+    #[test]
+    fn includes_are_read_next_to_the_document() {
+        // Spaces are written %20 in the URI
+        let dir = std::env::temp_dir().join(format!("lsp documents {}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(dir.join("keys.btcasm"), "define ALICE 0x02aa\n").unwrap();
+        let uri = Url::from_file_path(dir.join("main.btcasm")).unwrap().to_string();
+        assert!(uri.contains("lsp%20documents"));
+
+        let mut server = LspServer::new(Vec::new());
+        let open = json!({"method": "textDocument/didOpen", "params": {"textDocument": {"uri": uri, "text": "include \"keys.btcasm\"\nALICE OP_DROP BOB"}}});
+        server.handle(&open).unwrap();
+        let notification = read_message(&mut Cursor::new(server.writer.clone())).unwrap().unwrap();
+        let diagnostics = notification["params"]["diagnostics"].as_array().unwrap();
+        assert_eq!(diagnostics.len(), 1);
+        assert_eq!(diagnostics[0]["message"], "Undefined name: BOB");
+        assert_eq!(diagnostics[0]["range"], range(2, 15, 3));
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn hover_describes_opcodes() {
+        let mut server = LspServer::new(Vec::new());
+        server.handle(&json!({"method": "textDocument/didOpen", "params": {"textDocument": {"uri": "a", "text": "OP_1 OP_CHECKSIG"}}})).unwrap();
+        let hover = server.hover(&json!({"textDocument": {"uri": "a"}, "position": {"line": 0, "character": 7}}));
+        assert!(hover["contents"]["value"].as_str().unwrap().starts_with("**OP_CHECKSIG** (0xac)"));
+        assert_eq!(hover["range"], range(1, 6, 11));
+    }
+
+    #[test]
+    fn describes_opcodes() {
+        assert_eq!(describe(Opcode::from(0x76)), "**OP_DUP** (0x76)\n\nCategory: stack\n\nStack effect: 1 → 2");
+        assert!(describe(Opcode::from(0x7e)).ends_with("**Disabled**: any script containing it fails\n\nOP_SUCCESS in tapscript"));
+    }
+
+    #[test]
+    fn serves_a_session() {
+        let mut input = Vec::new();
+        for message in [
+            json!({"id": 1, "method": "initialize", "params": {}}),
+            json!({"method": "initialized", "params": {}}),
+            json!({"method": "textDocument/didOpen", "params": {"textDocument": {"uri": "a", "text": "OP_1"}}}),
+            json!({"method": "textDocument/didChange", "params": {"textDocument": {"uri": "a"}, "contentChanges": [{"text": "OP_1\nOP_VER"}]}}),
+            json!({"id": 2, "method": "textDocument/completion", "params": {}}),
+            json!({"id": 3, "method": "workspace/symbol", "params": {}}),
+            json!({"method": "textDocument/didClose", "params": {"textDocument": {"uri": "a"}}}),
+            json!({"id": 4, "method": "shutdown"}),
+            json!({"method": "exit"}),
+            json!({"id": 5, "method": "shutdown"})
+        ] {
+            write_message(&mut input, &message).unwrap();
+        }
+        let mut server = LspServer::new(Vec::new());
+        server.serve(&mut Cursor::new(input)).unwrap();
+        assert!(server.documents.is_empty());
+
+        let mut output = Cursor::new(server.writer);
+        let replies: Vec<Value> = std::iter::from_fn(|| read_message(&mut output).unwrap()).collect();
+        assert_eq!(replies.len(), 7);
+        assert_eq!(replies[0]["result"]["capabilities"]["hoverProvider"], true);
+        assert_eq!(replies[1]["params"]["diagnostics"], json!([]));
+        assert_eq!(replies[2]["params"]["diagnostics"][0]["range"], range(2, 1, 6));
+        assert_eq!(replies[2]["params"]["diagnostics"][0]["severity"], 1);
+
+        // PUSH_BYTES and PUSH_DATA opcodes are written as data
+        let labels: Vec<&str> = replies[3]["result"].as_array().unwrap().iter().map(|c| c["label"].as_str().unwrap()).collect();
+        assert_eq!(labels.len(), 256 - 0x4e);
+        assert!(labels.contains(&"OP_CHECKSIG") && !labels.contains(&"OP_PUSHDATA1"));
+
+        assert_eq!(replies[4]["error"]["code"], -32601);
+        assert_eq!(replies[5]["params"], json!({"uri": "a", "diagnostics": []}));
+        assert_eq!(replies[6], json!({"jsonrpc": "2.0", "id": 4, "result": null}));
+    }
+}
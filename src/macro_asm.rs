@@ -0,0 +1,432 @@
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::asm::{parse_token, AsmError, SourceMap};
+use crate::opcodes::Opcode;
+use crate::script::{encode_push, ScriptItem};
+
+// Macro assembler on top of ASM text, one statement per line:
+//
+//     include "keys.asm"                  # definitions of another file, relative to this one
+//                                         # and read once even if several files include it
+//     define ALICE 0x02ab...              # constant, replaced by the rest of the line wherever it is used
+//     macro checksig_for(key)             # macro with parameters, up to endmacro
+//         <key> OP_CHECKSIGVERIFY
+//     endmacro
+//     checksig_for(ALICE) checksig_for(BOB) OP_1
+//
+// Anything else is ASM, data is written as 0x02ab... or <02ab...> so that it can't be mistaken
+// for a name (CAFE). Names must be defined before they are used
+
+// Nested macro calls and constant uses before giving up on a recursive definition
+const MAX_EXPANSION_DEPTH: usize = 64;
+const MAX_INCLUDE_DEPTH: usize = 16;
+
+const DIRECTIVES: [&str; 4] = ["define", "macro", "endmacro", "include"];
+
+#[derive(Clone)]
+struct Macro {
+    params: Vec<String>,
+    // (file, line, text) of the lines up to endmacro
+    body: Vec<(usize, usize, String)>
+}
+
+// Where a token is written, lines and columns start at 1
+#[derive(Debug, Clone, Copy)]
+struct Location {
+    file: usize,
+    line: usize,
+    column: usize
+}
+
+struct Assembler {
+    constants: HashMap<String, String>,
+    macros: HashMap<String, Macro>,
+    // Files being assembled, the outermost first, to detect include cycles
+    including: Vec<PathBuf>,
+    // Every file assembled so far, included files are skipped the second time
+    included: HashSet<PathBuf>,
+    // Macros being expanded with their call site, the innermost last
+    calls: Vec<(String, Location)>,
+    // Line of the statement being assembled in the top level file
+    line: usize,
+    bytes: Vec<u8>,
+    source_map: SourceMap
+}
+
+// Assemble a macro ASM file, the source map links every instruction to the top level line using it
+// and to the file and line where it is written
+pub fn assemble_file(path: &Path) -> Result<(Vec<u8>, SourceMap), AsmError> {
+    let text = fs::read_to_string(path).map_err(|e| AsmError {
+        file: Some(path.display().to_string()),
+        line: 0,
+        column: 0,
+        message: format!("Can't read file: {}", e)
+    })?;
+    assemble_source(path, &text)
+}
+
+// Same as assemble_file for the text of a file that may not be saved, such as an editor buffer
+// Includes are still read from the disk, relative to `path`
+pub fn assemble_source(path: &Path, text: &str) -> Result<(Vec<u8>, SourceMap), AsmError> {
+    let mut assembler = Assembler {
+        constants: HashMap::new(),
+        macros: HashMap::new(),
+        including: Vec::new(),
+        included: HashSet::new(),
+        calls: Vec::new(),
+        line: 0,
+        bytes: Vec::new(),
+        source_map: SourceMap::default()
+    };
+    assembler.assemble(path, text)?;
+    Ok((assembler.bytes, assembler.source_map))
+}
+
+impl Assembler {
+    fn error(&self, location: Location, message: &str) -> AsmError {
+        let mut message = message.to_string();
+        for (name, call) in self.calls.iter().rev() {
+            message += &format!(", in {} called at {}:{}", name, self.file_name(call.file), call.line);
+        }
+        AsmError { file: Some(self.file_name(location.file).to_string()), line: location.line, column: location.column, message }
+    }
+
+    fn file_name(&self, file: usize) -> &str {
+        &self.source_map.files()[file].0
+    }
+
+    fn assemble(&mut self, path: &Path, text: &str) -> Result<(), AsmError> {
+        let file = self.source_map.add_file(&path.display().to_string(), text);
+        let canonical = path.canonicalize().unwrap_or_else(|_| path.to_path_buf());
+        self.included.insert(canonical.clone());
+        self.including.push(canonical);
+        let top_level = self.including.len() == 1;
+
+        let mut lines = text.lines().enumerate().map(|(i, line)| (i + 1, line));
+        while let Some((line_nb, line)) = lines.next() {
+            if top_level {
+                self.line = line_nb;
+            }
+            let tokens = split_tokens(line);
+            let location = |column| Location { file, line: line_nb, column };
+            let (column, first) = match tokens.first() {
+                Some(token) => *token,
+                None => continue
+            };
+
+            match first {
+                "define" => {
+                    let (column, name) = *tokens.get(1).ok_or_else(|| self.error(location(column), "Missing constant name"))?;
+                    self.check_name(name, location(column))?;
+                    let value = tokens[2..].iter().map(|(_, t)| *t).collect::<Vec<_>>().join(" ");
+                    if value.is_empty() {
+                        return Err(self.error(location(column), &format!("Missing value for {}", name)))
+                    }
+                    self.constants.insert(name.to_string(), value);
+                }
+                "macro" => {
+                    let header = tokens[1..].iter().map(|(_, t)| *t).collect::<Vec<_>>().join(" ");
+                    let (name, params) = split_call(&header).map_err(|e| self.error(location(column), &e))?;
+                    self.check_name(name, location(column))?;
+                    let params = params.unwrap_or_default();
+                    for param in &params {
+                        if !is_name(param) {
+                            return Err(self.error(location(column), &format!("Invalid parameter name: {}", param)))
+                        }
+                    }
+
+                    let mut body = Vec::new();
+                    loop {
+                        let (body_nb, body_line) = lines.next()
+                            .ok_or_else(|| self.error(location(column), &format!("Missing endmacro for {}", name)))?;
+                        match split_tokens(body_line).first() {
+                            Some((_, "endmacro")) => break,
+                            Some((column, directive)) if DIRECTIVES.contains(directive) => {
+                                let location = Location { file, line: body_nb, column: *column };
+                                return Err(self.error(location, &format!("{} can't be used inside a macro", directive)))
+                            }
+                            _ => body.push((file, body_nb, body_line.to_string()))
+                        }
+                    }
+                    self.macros.insert(name.to_string(), Macro { params, body });
+                }
+                "endmacro" => return Err(self.error(location(column), "endmacro without macro")),
+                "include" => {
+                    let code = line.split('#').next().unwrap().trim_start();
+                    let target = code["include".len()..].trim().trim_matches('"');
+                    if target.is_empty() {
+                        return Err(self.error(location(column), "Missing file to include"))
+                    }
+                    let target = path.parent().unwrap_or(Path::new("")).join(target);
+                    let canonical = target.canonicalize().unwrap_or_else(|_| target.clone());
+                    if self.including.contains(&canonical) {
+                        return Err(self.error(location(column), &format!("{} includes itself", target.display())))
+                    }
+                    if self.including.len() > MAX_INCLUDE_DEPTH {
+                        return Err(self.error(location(column), "Too many nested includes"))
+                    }
+                    if !self.included.contains(&canonical) {
+                        let included = fs::read_to_string(&target)
+                            .map_err(|e| self.error(location(column), &format!("Can't read {}: {}", target.display(), e)))?;
+                        self.assemble(&target, &included)?;
+                    }
+                }
+                _ => {
+                    for (column, token) in tokens {
+                        self.expand(token, location(column), 0)?;
+                    }
+                }
+            }
+        }
+
+        self.including.pop();
+        Ok(())
+    }
+
+    fn check_name(&self, name: &str, location: Location) -> Result<(), AsmError> {
+        if !is_name(name) || DIRECTIVES.contains(&name) {
+            return Err(self.error(location, &format!("Invalid name: {}", name)))
+        }
+        if name.parse::<Opcode>().is_ok() {
+            return Err(self.error(location, &format!("{} is an opcode", name)))
+        }
+        if self.constants.contains_key(name) || self.macros.contains_key(name) {
+            return Err(self.error(location, &format!("{} is already defined", name)))
+        }
+        Ok(())
+    }
+
+    // Emit a token, replacing constants and macro calls by what they stand for
+    fn expand(&mut self, token: &str, location: Location, depth: usize) -> Result<(), AsmError> {
+        if depth > MAX_EXPANSION_DEPTH {
+            return Err(self.error(location, "Expansion too deep, a macro or a constant is probably defined with itself"))
+        }
+        let (name, args) = split_call(token).map_err(|e| self.error(location, &e))?;
+
+        if let Some(definition) = self.macros.get(name).cloned() {
+            let args = args.unwrap_or_default();
+            if args.len() != definition.params.len() {
+                let message = format!("{} expects {} arguments, got {}", name, definition.params.len(), args.len());
+                return Err(self.error(location, &message))
+            }
+            self.calls.push((name.to_string(), location));
+            for (file, line, text) in &definition.body {
+                let text = substitute(text, &definition.params, &args);
+                for (column, token) in split_tokens(&text) {
+                    self.expand(token, Location { file: *file, line: *line, column }, depth + 1)?;
+                }
+            }
+            self.calls.pop();
+            return Ok(())
+        }
+        if args.is_some() {
+            return Err(self.error(location, &format!("Unknown macro: {}", name)))
+        }
+
+        // <NAME> pushes a constant the same way as NAME
+        let token = match token.strip_prefix('<').and_then(|t| t.strip_suffix('>')) {
+            Some(inner) if self.constants.contains_key(inner) => inner,
+            _ => token
+        };
+        if let Some(value) = self.constants.get(token).cloned() {
+            for (_, token) in split_tokens(&value) {
+                self.expand(token, location, depth + 1)?;
+            }
+            return Ok(())
+        }
+
+        let item = parse_token(token).map_err(|e| match is_name(token) {
+            true => self.error(location, &format!("Undefined name: {}", token)),
+            false if token.bytes().all(|b| b.is_ascii_hexdigit()) => {
+                self.error(location, &format!("Data must be written as 0x{} or <{}>", token, token))
+            }
+            false => self.error(location, &e)
+        })?;
+        self.source_map.push_origin(self.bytes.len(), self.line, location.file, location.line);
+        match item {
+            ScriptItem::Opcode(op) => self.bytes.push(op.code),
+            ScriptItem::ByteArray(data) => self.bytes.extend(encode_push(&data))
+        }
+        Ok(())
+    }
+}
+
+fn is_name(s: &str) -> bool {
+    let mut chars = s.chars();
+    matches!(chars.next(), Some(c) if c.is_ascii_alphabetic() || c == '_')
+        && chars.all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+// Split a line into (column, token) up to its comment, keeping macro calls with spaces
+// between their parentheses as one token
+fn split_tokens(line: &str) -> Vec<(usize, &str)> {
+    let code = line.split('#').next().unwrap();
+    let mut tokens = Vec::new();
+    let mut start = None;
+    let mut depth = 0usize;
+    for (i, c) in code.char_indices() {
+        match c {
+            '(' => depth += 1,
+            ')' => depth = depth.saturating_sub(1),
+            _ => {}
+        }
+        match (c.is_whitespace() && depth == 0, start) {
+            (true, Some(s)) => {
+                tokens.push((s + 1, &code[s..i]));
+                start = None;
+            }
+            (false, None) => start = Some(i),
+            _ => {}
+        }
+    }
+    if let Some(s) = start {
+        tokens.push((s + 1, &code[s..]));
+    }
+    tokens
+}
+
+// name(a, b) gives the name and its arguments, a token without parentheses has no argument list
+fn split_call(token: &str) -> Result<(&str, Option<Vec<String>>), String> {
+    let open = match token.find('(') {
+        Some(open) => open,
+        None => return Ok((token, None))
+    };
+    let inner = token[open + 1..].strip_suffix(')').ok_or_else(|| format!("Unclosed macro call: {}", token))?;
+
+    let mut args = Vec::new();
+    let mut depth = 0usize;
+    let mut start = 0;
+    for (i, c) in inner.char_indices() {
+        match c {
+            '(' => depth += 1,
+            ')' => depth = depth.checked_sub(1).ok_or_else(|| format!("Unbalanced parentheses: {}", token))?,
+            ',' if depth == 0 => {
+                args.push(inner[start..i].trim().to_string());
+                start = i + 1;
+            }
+            _ => {}
+        }
+    }
+    args.push(inner[start..].trim().to_string());
+    if args.len() == 1 && args[0].is_empty() {
+        args.clear();
+    }
+    if args.iter().any(String::is_empty) {
+        return Err(format!("Empty argument in {}", token))
+    }
+    Ok((token[..open].trim(), Some(args)))
+}
+
+// Replace every parameter used as a whole word of `text` by its argument
+fn substitute(text: &str, params: &[String], args: &[String]) -> String {
+    let mut result = String::new();
+    let mut word = String::new();
+    let flush = |word: &mut String, result: &mut String| {
+        match params.iter().position(|p| p == word) {
+            Some(i) => result.push_str(&args[i]),
+            None => result.push_str(word)
+        }
+        word.clear();
+    };
+    for c in text.chars() {
+        if c.is_ascii_alphanumeric() || c == '_' {
+            word.push(c);
+        } else {
+            flush(&mut word, &mut result);
+            result.push(c);
+        }
+    }
+    flush(&mut word, &mut result);
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Assemble `files`, the first one being the top level file
+    fn assemble(name: &str, files: &[(&str, &str)]) -> Result<(Vec<u8>, SourceMap), AsmError> {
+        let dir = std::env::temp_dir().join(format!("macro-asm-{}-{}", name, std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        for (file, text) in files {
+            fs::write(dir.join(file), text).unwrap();
+        }
+        let result = assemble_file(&dir.join(files[0].0));
+        fs::remove_dir_all(&dir).unwrap();
+        result
+    }
+
+    fn error(name: &str, text: &str) -> String {
+        let e = assemble(name, &[("main.asm", text)]).unwrap_err();
+        format!("{}:{}: {}", e.line, e.column, e.message)
+    }
+
+    #[test]
+    fn constants_and_macros() {
+        let text = "define KEY <02aa>\nmacro check(k)\n    k OP_CHECKSIGVERIFY\nendmacro\ncheck(KEY) check(0x03bb) 7\n";
+        let (bytes, _) = assemble("expand", &[("main.asm", text)]).unwrap();
+        assert_eq!(hex::encode(bytes), "0202aaad0203bbad57");
+    }
+
+    #[test]
+    fn data_needs_a_prefix() {
+        assert_eq!(error("bare", "OP_1 02ab"), "1:6: Data must be written as 0x02ab or <02ab>");
+        assert_eq!(error("name", "OP_1 CAFE"), "1:6: Undefined name: CAFE");
+        assert_eq!(error("deadbeef", "DEADBEEF OP_DROP"), "1:1: Undefined name: DEADBEEF");
+        let (bytes, _) = assemble("cafe", &[("main.asm", "define CAFE 0xcafe\nCAFE <beef>")]).unwrap();
+        assert_eq!(hex::encode(bytes), "02cafe02beef");
+    }
+
+    #[test]
+    fn errors() {
+        assert_eq!(error("args", "macro m(a)\nOP_DUP\nendmacro\nm(1, 2)"), "4:1: m expects 1 arguments, got 2");
+        assert_eq!(error("opcode", "define OP_DUP 1"), "1:8: OP_DUP is an opcode");
+        assert_eq!(error("twice", "define A 1\ndefine A 2"), "2:8: A is already defined");
+        assert_eq!(error("unclosed", "macro m()\nOP_DUP"), "1:1: Missing endmacro for m");
+        assert_eq!(error("recursive", "define A B\ndefine B A\nA"), "3:1: Expansion too deep, a macro or a constant is probably defined with itself");
+        let call = error("call", "macro m(a)\n    a OP_ADD\nendmacro\nm(X)");
+        assert!(call.starts_with("2:5: Undefined name: X, in m called at "), "{}", call);
+        assert!(call.ends_with("main.asm:4"), "{}", call);
+    }
+
+    #[test]
+    fn source_map_links_expansions_to_their_definition() {
+        let main = "include \"lib.asm\"\nOP_1\ntwice()\n";
+        let lib = "macro twice()\n    OP_DUP\n    OP_ADD\nendmacro\n";
+        let (bytes, source_map) = assemble("map", &[("main.asm", main), ("lib.asm", lib)]).unwrap();
+        assert_eq!(hex::encode(&bytes), "517693");
+        assert_eq!(source_map.entries(), &[(0, 2, 0, 2), (1, 3, 1, 2), (2, 3, 1, 3)]);
+        assert!(source_map.files()[1].0.ends_with("lib.asm"));
+
+        let location = source_map.location_of(2).unwrap();
+        assert!(location.file.unwrap().ends_with("lib.asm"));
+        assert_eq!((location.line, location.text), (3, Some("    OP_ADD")));
+        assert_eq!(source_map.line_of(1), Some(3));
+        assert_eq!(source_map.offsets_in_file(0, 3), Some((vec![1], 3)));
+        assert_eq!(source_map.offsets_in_file(1, 1), Some((vec![1], 2)));
+        assert_eq!(source_map.offsets_in_file(1, 4), None);
+    }
+
+    #[test]
+    fn include_cycles() {
+        let e = assemble("cycle", &[("a.asm", "include \"b.asm\""), ("b.asm", "include \"a.asm\"")]).unwrap_err();
+        assert!(e.file.unwrap().ends_with("b.asm"));
+        assert!(e.message.ends_with("includes itself"));
+    }
+
+    #[test]
+    fn diamond_includes() {
+        // main includes a and b, which both include keys
+        let files = [
+            ("main.asm", "include \"a.asm\"\ninclude \"b.asm\"\ncheck_a() check_b() OP_1"),
+            ("a.asm", "include \"keys.asm\"\nmacro check_a()\n    A OP_CHECKSIGVERIFY\nendmacro"),
+            ("b.asm", "include \"keys.asm\"\nmacro check_b()\n    B OP_CHECKSIGVERIFY\nendmacro"),
+            ("keys.asm", "define A <02aa>\ndefine B <02bb>")
+        ];
+        let (bytes, source_map) = assemble("diamond", &files).unwrap();
+        assert_eq!(hex::encode(bytes), "0202aaad0202bbad51");
+        assert_eq!(source_map.files().iter().filter(|(name, _)| name.ends_with("keys.asm")).count(), 1);
+    }
+}
@@ -1,26 +1,516 @@
-extern crate core;
+use std::env;
+use std::path::Path;
+use std::process::exit;
 
-use crate::script::{as_bool, as_script_nb};
+use btc_script_interpreter::address::{Address, Network};
+use btc_script_interpreter::asm::SourceMap;
+use btc_script_interpreter::analyze::{analyze, PathOutcome};
+use btc_script_interpreter::dap;
+use btc_script_interpreter::contract;
+use btc_script_interpreter::descriptor::Descriptor;
+use btc_script_interpreter::debugger::Debugger;
+use btc_script_interpreter::lint::{lint, LintConfig, LintLevel};
+use btc_script_interpreter::lsp;
+use btc_script_interpreter::macro_asm::assemble_file;
+use btc_script_interpreter::miniscript::{decompile, Miniscript};
+use btc_script_interpreter::interpret::{interpret, ExecutionResult, Interpreter};
+use btc_script_interpreter::observer::{SourceObserver, TerminalObserver, TraceFormat, TraceRecorder};
+use btc_script_interpreter::optimize::{check_equivalence, optimize_bytes, test_corpus};
+use btc_script_interpreter::opcodes::OP_RETURN;
+use btc_script_interpreter::parse;
+use btc_script_interpreter::policy::Policy;
+use btc_script_interpreter::pretty::{pretty_print, FormatOptions};
+use btc_script_interpreter::satisfy::{satisfy, Assets};
+use btc_script_interpreter::script::serialize;
+use btc_script_interpreter::solver::solve;
+use btc_script_interpreter::symbolic::{execute_symbolic, SymbolicOutcome};
+use btc_script_interpreter::transaction::{OutPoint, SigVersion, Transaction, TransactionContext, TxIn, TxOut, SEQUENCE_FINAL};
+use btc_script_interpreter::tui::Tui;
+use btc_script_interpreter::script;
 
-mod opcodes;
-mod parse;
-mod script;
-mod interpret;
+const USAGE: &str = "Usage:
+    btc_script_interpreter                                  Run the example P2PKH script
+    btc_script_interpreter run <hex> [--quiet] [--clean-stack] [--null-fail] [--null-dummy]
+    btc_script_interpreter trace <hex> [--format json|ndjson] [--clean-stack] [--null-fail] [--null-dummy]
+    btc_script_interpreter decode <hex> [--offsets]         Print a script with indented branches and annotated pushes
+    btc_script_interpreter analyze <hex> [--initial <n>]    Compute the stack requirements of every branch
+    btc_script_interpreter spend <hex>                      List the witness requirements of every branch
+    btc_script_interpreter classify <hex> [--network <n>]   Recognise a standard output script and its address
+    btc_script_interpreter descriptor <descriptor> [--index <n>] [--network <n>]
+                                                            Expand an output descriptor into its scripts
+    btc_script_interpreter satisfy <hex> [--key <secret>]... [--preimage <hex>]... [--height <n>] [--time <n>]
+        [--age <blocks>] [--segwit] [--amount <sats>]       Build the smallest P2SH (P2WSH with --segwit) spend
+    btc_script_interpreter miniscript <expression|hex>      Compile Miniscript, or decompile a script, and type it
+    btc_script_interpreter compile <policy>                 Compile a spending policy to Miniscript
+    btc_script_interpreter assemble <file> [--map]          Assemble a macro ASM file, with the source of every offset
+    btc_script_interpreter contract <file> [--arg <name>=<hex>]...
+                                                            Compile a contract, and build its witness from the arguments
+    btc_script_interpreter lint <hex> [--deny|--warn|--allow <lint>]...
+    btc_script_interpreter optimize <hex> [--cases <n>]     Shorten a script and test the result against it
+    btc_script_interpreter debug <hex>                      Start an interactive debugger
+    btc_script_interpreter tui <hex>                        Start the full-screen debugger
+    btc_script_interpreter dap                              Serve the Debug Adapter Protocol on stdio
+    btc_script_interpreter lsp                              Serve the Language Server Protocol on stdio
 
-
-// Constants to configure step-by-step script execution display
-pub const MAX_SCRIPT_DISPLAY_WIDTH:usize = 80;
-pub const MIN_SCRIPT_DISPLAY_WIDTH:usize = 30;
+Scripts can also be given as an address, as an output descriptor, with --index <n> for ranged descriptors,
+as a Miniscript expression, or as the path of a macro ASM file (define, macro, include)";
 
 
 fn main() {
-    let sig_script = "483045022100fcb600ea44edb6b3c9408479c9e29d468bf623e5b465be4d594141b0d7611d2a022069d470033446f0b6c8c85ced09b3e3c2b675e21a0f3017a5fac2ad064a4d1d1e012103dd2162aaf74d3f2e0634ad778380eeecea6ac5f2e53411a128a323ad260d0dc7";
-    let pk_script = "76a9148b6305816c87626a9ac4b972c5c376663b2f5dc888ac";
-    let hex_script = sig_script.to_owned() + pk_script;
-    //let hex_script = "515560606b6c05abcdefabcd";
-    let bin_script = hex::decode(hex_script).unwrap();
-    let script = parse::parse_script(&bin_script).unwrap();
+    let args: Vec<String> = env::args().skip(1).collect();
+    match args.first().map(String::as_str) {
+        None => example(),
+        Some("run") => run(&args[1..]),
+        Some("trace") => trace(&args[1..]),
+        Some("decode") => decode(&args[1..]),
+        Some("analyze") => analyze_paths(&args[1..]),
+        Some("spend") => spend(&args[1..]),
+        Some("classify") => classify(&args[1..]),
+        Some("descriptor") => expand_descriptor(&args[1..]),
+        Some("satisfy") => satisfy_script(&args[1..]),
+        Some("miniscript") => miniscript(&args[1..]),
+        Some("compile") => compile_policy(&args[1..]),
+        Some("assemble") => assemble(&args[1..]),
+        Some("contract") => compile_contract(&args[1..]),
+        Some("lint") => lint_script(&args[1..]),
+        Some("optimize") => optimize_script(&args[1..]),
+        Some("debug") => debug(&args[1..]),
+        Some("tui") => tui(&args[1..]),
+        Some("dap") => {
+            if let Err(e) = dap::run_stdio() {
+                fail(&e.to_string())
+            }
+        }
+        Some("lsp") => {
+            if let Err(e) = lsp::run_stdio() {
+                fail(&e.to_string())
+            }
+        }
+        Some("-h") | Some("--help") => println!("{}", USAGE),
+        Some(_) => fail("Unknown command")
+    }
+}
+
+fn example() {
+    let script = script! {
+        <0x3045022100fcb600ea44edb6b3c9408479c9e29d468bf623e5b465be4d594141b0d7611d2a022069d470033446f0b6c8c85ced09b3e3c2b675e21a0f3017a5fac2ad064a4d1d1e01>
+        <0x03dd2162aaf74d3f2e0634ad778380eeecea6ac5f2e53411a128a323ad260d0dc7>
+        OP_DUP OP_HASH160 <0x8b6305816c87626a9ac4b972c5c376663b2f5dc8> OP_EQUALVERIFY OP_CHECKSIG
+    };
 
     println!("{:?}", &script);
-    let _ = interpret::interpret(&bin_script, true).unwrap();
+    print_result(&interpret(&serialize(&script), true, false));
+}
+
+fn run(args: &[String]) {
+    let (script, source_map) = match assembled_arg(args) {
+        Some((script, source_map)) => (script, Some(source_map)),
+        None => (script_arg(args), None)
+    };
+    let verbose = !has_flag(args, "--quiet");
+    let mut interpreter = flagged_interpreter(&script, args);
+    let result = match &source_map {
+        Some(source_map) if verbose => interpreter.with_observer(SourceObserver::new(source_map.clone())).run(),
+        _ if verbose => interpreter.with_observer(TerminalObserver).run(),
+        _ => interpreter.run()
+    };
+    print_result(&result);
+    if let (Some(e), Some(source_map)) = (&result.error, &source_map) {
+        if let Some(location) = source_map.location_of(e.offset) {
+            println!("Source: {}", location);
+        }
+    }
+}
+
+// Interpreter with the standardness rules enabled on the command line
+fn flagged_interpreter(script: &[u8], args: &[String]) -> Interpreter {
+    Interpreter::new(script)
+        .clean_stack(has_flag(args, "--clean-stack"))
+        .null_fail(has_flag(args, "--null-fail"))
+        .null_dummy(has_flag(args, "--null-dummy"))
+}
+
+fn trace(args: &[String]) {
+    let script = script_arg(args);
+    let format = match option(args, "--format") {
+        Some(f) => f.parse().unwrap_or_else(|e: String| fail(&e)),
+        None => TraceFormat::Json
+    };
+
+    let mut interpreter = flagged_interpreter(&script, args).with_observer(TraceRecorder::new());
+    interpreter.run();
+    print!("{}", interpreter.observer().export(format));
+    if format == TraceFormat::Json {
+        println!();
+    }
+}
+
+fn decode(args: &[String]) {
+    let script = script_arg(args);
+    let options = FormatOptions { offsets: has_flag(args, "--offsets"), ..FormatOptions::default() };
+    print!("{}", pretty_print(&script, &options));
+}
+
+fn analyze_paths(args: &[String]) {
+    let bytes = script_arg(args);
+    let initial_items = option(args, "--initial")
+        .map(|n| n.parse().unwrap_or_else(|_| fail("--initial must be a number")));
+    let items = parse::parse_script_with_offsets(&bytes).unwrap_or_else(|e| fail(&format!("Can't parse script: {}", e)));
+    let (offsets, script): (Vec<usize>, Vec<_>) = items.into_iter().unzip();
+    let offset = |index: usize| offsets.get(index).copied().unwrap_or(bytes.len());
+
+    let analysis = analyze(&script, initial_items);
+    for (i, path) in analysis.paths.iter().enumerate() {
+        let branches: Vec<String> = path.branches.iter()
+            .map(|(index, taken)| format!("{:?} at {}: {}", script[*index], offset(*index), taken))
+            .collect();
+        let outcome = match path.outcome {
+            PathOutcome::Completes => String::from("completes"),
+            PathOutcome::Fails { index, error } => format!("fails at offset {}: {} ({})", offset(index), error, error.name()),
+            PathOutcome::Unknown { index } => format!("stack effect of {:?} at offset {} depends on the witness", script[index], offset(index))
+        };
+        println!("Path {} [{}]: needs {} initial items, max depth {}, {}",
+            i + 1, branches.join(", "), path.min_initial_items, path.max_depth, outcome);
+    }
+    if analysis.truncated {
+        println!("Too many paths, some were not analysed");
+    }
+    match analysis.min_initial_items() {
+        Some(n) => println!("\nAt least {} initial items needed, max depth {}", n, analysis.max_depth()),
+        None => println!("\nNo path runs to the end of the script")
+    }
+}
+
+fn spend(args: &[String]) {
+    let bytes = script_arg(args);
+    let items = parse::parse_script_with_offsets(&bytes).unwrap_or_else(|e| fail(&format!("Can't parse script: {}", e)));
+    let (offsets, script): (Vec<usize>, Vec<_>) = items.into_iter().unzip();
+    let offset = |index: usize| offsets.get(index).copied().unwrap_or(bytes.len());
+
+    let execution = execute_symbolic(&script);
+    for (i, path) in execution.paths.iter().enumerate() {
+        let branches: Vec<String> = path.branches.iter()
+            .map(|(index, taken)| format!("{:?} at {}: {}", script[*index], offset(*index), taken))
+            .collect();
+        let outcome = match &path.outcome {
+            SymbolicOutcome::Spendable => format!("spendable with {} witness items", path.witness_items),
+            SymbolicOutcome::Fails { index, error } => format!("fails at offset {}: {} ({})", offset(*index), error, error.name()),
+            SymbolicOutcome::Contradiction { index } => format!("impossible, contradicting constraints at offset {}", offset(*index)),
+            SymbolicOutcome::Unsupported { index, reason } => format!("not analysed past offset {}: {}", offset(*index), reason)
+        };
+        println!("Path {} [{}]: {}", i + 1, branches.join(", "), outcome);
+        if path.outcome == SymbolicOutcome::Spendable {
+            for requirement in path.requirements() {
+                println!("    {}", requirement);
+            }
+        }
+    }
+    if execution.truncated {
+        println!("Too many paths, some were not analysed");
+    }
+}
+
+fn classify(args: &[String]) {
+    let script = output_script_arg(args);
+    // Addresses given on the command line are shown for their own network by default
+    let network = option(args, "--network")
+        .map(|n| n.parse().unwrap_or_else(|e: String| fail(&e)))
+        .or_else(|| args.iter().find_map(|a| a.parse::<Address>().ok()).map(|address| address.network))
+        .unwrap_or(Network::Mainnet);
+    match Address::from_script(&script, network) {
+        Some(address) => println!("{} ({} address {})", solve(&script), network, address),
+        None => println!("{}", solve(&script))
+    }
+}
+
+fn expand_descriptor(args: &[String]) {
+    let descriptor: Descriptor = positional_arg(args).parse().unwrap_or_else(|e: String| fail(&e));
+    let network = option(args, "--network").map_or(Network::Mainnet, |n| n.parse().unwrap_or_else(|e: String| fail(&e)));
+    let expansion = descriptor.expand(index_arg(args)).unwrap_or_else(|e| fail(&e));
+
+    println!("{}", descriptor.to_string_with_checksum());
+    println!("scriptPubKey: {}", hex::encode(&expansion.script_pubkey));
+    match Address::from_script(&expansion.script_pubkey, network) {
+        Some(address) => println!("    {} ({} address {})", solve(&expansion.script_pubkey), network, address),
+        None => println!("    {}", solve(&expansion.script_pubkey))
+    }
+    if let Some(script) = &expansion.redeem_script {
+        println!("Redeem script: {}", hex::encode(script));
+    }
+    if let Some(script) = &expansion.witness_script {
+        println!("Witness script: {}", hex::encode(script));
+    }
+    for leaf in &expansion.leaves {
+        println!("Leaf script: {}\n    control block: {}", hex::encode(&leaf.script), hex::encode(&leaf.control_block));
+    }
+}
+
+fn satisfy_script(args: &[String]) {
+    let bytes = script_arg(args);
+    let script = parse::parse_script(&bytes).unwrap_or_else(|e| fail(&format!("Can't parse script: {}", e)));
+    let number = |name: &str| option(args, name)
+        .map(|n| n.parse().unwrap_or_else(|_| fail(&format!("{} must be a number", name))));
+
+    let mut assets = Assets { height: number("--height"), time: number("--time"), age_blocks: number("--age"), ..Assets::default() };
+    for pair in args.windows(2) {
+        let value = || hex::decode(&pair[1]).unwrap_or_else(|_| fail(&format!("{} must be hex encoded", pair[0])));
+        match pair[0].as_str() {
+            "--key" => assets.keys.push(secp256k1::SecretKey::from_slice(&value()).unwrap_or_else(|_| fail("Invalid secret key"))),
+            "--preimage" => assets.preimages.push(value()),
+            _ => ()
+        }
+    }
+
+    // Transaction spending the script to an OP_RETURN output
+    let tx = Transaction {
+        version: 1,
+        inputs: vec![TxIn { previous_output: OutPoint::default(), script_sig: Vec::new(), sequence: SEQUENCE_FINAL, witness: Vec::new() }],
+        outputs: vec![TxOut { value: 0, script_pubkey: vec![OP_RETURN.code] }],
+        lock_time: 0
+    };
+    let sig_version = if has_flag(args, "--segwit") { SigVersion::WitnessV0 } else { SigVersion::Base };
+    let amount = option(args, "--amount").map_or(0, |n| n.parse().unwrap_or_else(|_| fail("--amount must be a number")));
+    let context = TransactionContext::new(tx, 0, amount, sig_version);
+
+    let satisfaction = match satisfy(&script, &assets, &context) {
+        Some(satisfaction) => satisfaction,
+        None => {
+            println!("No spending path can be satisfied with these assets");
+            exit(1)
+        }
+    };
+    let offsets: Vec<usize> = parse::parse_script_with_offsets(&bytes).unwrap().into_iter().map(|(offset, _)| offset).collect();
+    let branches: Vec<String> = satisfaction.branches.iter()
+        .map(|(index, taken)| format!("{:?} at {}: {}", script[*index], offsets[*index], taken))
+        .collect();
+    println!("Path [{}]", branches.join(", "));
+    match sig_version {
+        SigVersion::Base => println!("P2SH scriptSig: {}", hex::encode(satisfaction.script_sig())),
+        SigVersion::WitnessV0 => {
+            println!("P2WSH witness, bottom first:");
+            for item in &satisfaction.witness() {
+                println!("    {}", if item.is_empty() { String::from("<empty>") } else { hex::encode(item) });
+            }
+        }
+    }
+    let tx = &satisfaction.context.tx;
+    println!("{} bytes, nVersion {}, nLockTime {}, nSequence 0x{:08x}", satisfaction.size(), tx.version, tx.lock_time, tx.inputs[0].sequence);
+}
+
+fn miniscript(args: &[String]) {
+    let text = positional_arg(args);
+    let miniscript = match hex::decode(text) {
+        Ok(bytes) => decompile(&bytes),
+        Err(_) => text.parse::<Miniscript>()
+    };
+    print_miniscript(&miniscript.unwrap_or_else(|e| fail(&e)));
+}
+
+fn compile_policy(args: &[String]) {
+    let policy: Policy = positional_arg(args).parse().unwrap_or_else(|e: String| fail(&e));
+    print_miniscript(&policy.compile().unwrap_or_else(|e| fail(&e)));
+}
+
+fn print_miniscript(miniscript: &Miniscript) {
+    let script = miniscript.encode();
+    println!("{}\n\n{:?}\n{}\n", miniscript, script, hex::encode(serialize(&script)));
+    println!("Type: {}", miniscript.ty());
+    match miniscript.ops_count() {
+        Some(ops) => println!("{} bytes, {} opcodes when satisfied", miniscript.script_size(), ops),
+        None => println!("{} bytes, can't be satisfied", miniscript.script_size())
+    }
+    match miniscript.check_sanity() {
+        Ok(()) => println!("Sane: non-malleable, needs a signature, within resource limits"),
+        Err(e) => println!("Not sane: {}", e)
+    }
+}
+
+fn assemble(args: &[String]) {
+    let (script, source_map) = assembled_arg(args).unwrap_or_else(|| fail("assemble expects the path of an ASM file"));
+    println!("{}", hex::encode(&script));
+    if has_flag(args, "--map") {
+        for (offset, ..) in source_map.entries() {
+            if let Some(location) = source_map.location_of(*offset) {
+                println!("{:>6}  {}", offset, location);
+            }
+        }
+    }
+}
+
+fn compile_contract(args: &[String]) {
+    let path = positional_arg(args);
+    let source = std::fs::read_to_string(path).unwrap_or_else(|e| fail(&format!("Can't read {}: {}", path, e)));
+    let contract = contract::compile(&source).unwrap_or_else(|e| fail(&format!("{}:{}", path, e)));
+    let bytes = serialize(&contract.script);
+    println!("{:?}\n{}\n", contract.script, hex::encode(&bytes));
+    println!("{} bytes", bytes.len());
+    println!("{}", serde_json::to_string_pretty(&contract.abi()).unwrap());
+
+    let values: Vec<(&str, Vec<u8>)> = args.iter().enumerate()
+        .filter(|(_, a)| *a == "--arg")
+        .map(|(i, _)| {
+            let arg = args.get(i + 1).unwrap_or_else(|| fail("Missing value for --arg"));
+            let (name, value) = arg.split_once('=').unwrap_or_else(|| fail("--arg expects <name>=<hex>"));
+            (name, hex::decode(value).unwrap_or_else(|_| fail(&format!("Invalid hex for {}", name))))
+        })
+        .collect();
+    if !values.is_empty() {
+        let witness = contract.witness(&values).unwrap_or_else(|e| fail(&e));
+        println!("\nWitness:");
+        for item in witness {
+            println!("    {}", hex::encode(item));
+        }
+    }
+}
+
+fn lint_script(args: &[String]) {
+    let bytes = script_arg(args);
+    let mut config = LintConfig::new();
+    for pair in args.windows(2) {
+        let level = match pair[0].as_str() {
+            "--deny" => LintLevel::Deny,
+            "--warn" => LintLevel::Warn,
+            "--allow" => LintLevel::Allow,
+            _ => continue
+        };
+        config.set(pair[1].parse().unwrap_or_else(|e: String| fail(&e)), level);
+    }
+
+    let warnings = lint(&bytes, &config).unwrap_or_else(|e| fail(&format!("Can't parse script: {}", e)));
+    for warning in &warnings {
+        println!("{}", warning);
+    }
+    let errors = warnings.iter().filter(|w| w.level == LintLevel::Deny).count();
+    println!("\n{} warnings, {} errors", warnings.len() - errors, errors);
+    if errors > 0 {
+        exit(1)
+    }
+}
+
+fn optimize_script(args: &[String]) {
+    let bytes = script_arg(args);
+    let script = parse::parse_script(&bytes).unwrap_or_else(|e| fail(&format!("Can't parse script: {}", e)));
+    let cases = option(args, "--cases")
+        .map_or(1000, |n| n.parse().unwrap_or_else(|_| fail("--cases must be a number")));
+
+    let optimization = optimize_bytes(&bytes).unwrap_or_else(|e| fail(&format!("Can't parse script: {}", e)));
+    for rewrite in &optimization.rewrites {
+        println!("{}", rewrite);
+    }
+    println!("\n{:?}\n{}", optimization.script, hex::encode(serialize(&optimization.script)));
+    println!("\n{} bytes → {} bytes ({} saved)",
+        optimization.original_size, optimization.optimized_size, optimization.bytes_saved());
+
+    let corpus = test_corpus(&[&script, &optimization.script], cases, 0);
+    let equivalence = check_equivalence(&script, &optimization.script, &corpus);
+    if equivalence.holds() {
+        println!("Same result on {} test stacks ({} successful)", equivalence.cases, equivalence.successes);
+    } else {
+        println!("Different result on {} of {} test stacks, first one: {:?}",
+            equivalence.mismatches.len(), equivalence.cases, equivalence.mismatches[0]);
+        exit(1)
+    }
+}
+
+fn debug(args: &[String]) {
+    let mut debugger = match assembled_arg(args) {
+        Some((script, source_map)) => Debugger::new(&script).with_source_map(source_map),
+        None => Debugger::new(&script_arg(args))
+    };
+    if let Err(e) = debugger.repl() {
+        fail(&e.to_string())
+    }
+}
+
+fn tui(args: &[String]) {
+    let script = script_arg(args);
+    if let Err(e) = Tui::new(&script).run() {
+        fail(&e.to_string())
+    }
+}
+
+fn print_result(result: &ExecutionResult) {
+    match &result.error {
+        None => println!("\nScript succeeded ({} steps, {} ops)", result.steps, result.op_count),
+        Some(e) => println!("\nScript failed at offset {}: {} ({})", e.offset, e.error, e.error.name())
+    }
+}
+
+// Options followed by a value
+const OPTIONS: [&str; 15] = ["--arg", "--format", "--initial", "--deny", "--warn", "--allow", "--cases", "--key", "--preimage",
+    "--height", "--time", "--age", "--amount", "--network", "--index"];
+
+fn positional_arg(args: &[String]) -> &str {
+    let mut i = 0;
+    while i < args.len() {
+        if OPTIONS.contains(&args[i].as_str()) {
+            i += 2;
+            continue
+        }
+        if !args[i].starts_with("--") {
+            return &args[i]
+        }
+        i += 1;
+    }
+    fail("Missing script")
+}
+
+// Script given as hex, as an address or as a descriptor at --index
+// Descriptors give their output script when `output` is set, the script run against the witness otherwise
+fn decode_script(args: &[String], output: bool) -> Vec<u8> {
+    let text = positional_arg(args);
+    if let Ok(script) = hex::decode(text) {
+        return script
+    }
+    if let Some((script, _)) = assembled_arg(args) {
+        return script
+    }
+    if let Ok(address) = text.parse::<Address>() {
+        return address.script_pubkey
+    }
+    if let Ok(miniscript) = text.parse::<Miniscript>() {
+        return serialize(&miniscript.encode())
+    }
+    let descriptor: Descriptor = text.parse()
+        .unwrap_or_else(|e| fail(&format!("Script must be hex encoded, an address, a descriptor or Miniscript: {}", e)));
+    let expansion = descriptor.expand(index_arg(args)).unwrap_or_else(|e| fail(&e));
+    match output {
+        true => expansion.script_pubkey,
+        false => expansion.executed_script().unwrap_or_else(|| fail("Taproot descriptors have no script to run, give a leaf instead"))
+    }
+}
+
+// Script given as the path of a macro ASM file, with the source of every instruction
+fn assembled_arg(args: &[String]) -> Option<(Vec<u8>, SourceMap)> {
+    let path = Path::new(positional_arg(args));
+    match path.is_file() {
+        true => Some(assemble_file(path).unwrap_or_else(|e| fail(&e.to_string()))),
+        false => None
+    }
+}
+
+fn script_arg(args: &[String]) -> Vec<u8> {
+    decode_script(args, false)
+}
+
+fn output_script_arg(args: &[String]) -> Vec<u8> {
+    decode_script(args, true)
+}
+
+fn index_arg(args: &[String]) -> u32 {
+    option(args, "--index").map_or(0, |n| n.parse().unwrap_or_else(|_| fail("--index must be a number")))
+}
+
+fn has_flag(args: &[String], flag: &str) -> bool {
+    args.iter().any(|a| a == flag)
+}
+
+fn option<'a>(args: &'a [String], name: &str) -> Option<&'a str> {
+    let i = args.iter().position(|a| a == name)?;
+    Some(args.get(i + 1).unwrap_or_else(|| fail(&format!("Missing value for {}", name))))
+}
+
+fn fail(msg: &str) -> ! {
+    eprintln!("{}\n\n{}", msg, USAGE);
+    exit(1)
 }
@@ -0,0 +1,1131 @@
+use std::fmt;
+use std::ops::{BitAnd, BitOr};
+use std::str::FromStr;
+
+use bitcoin_hashes::{hash160, Hash};
+use secp256k1::PublicKey;
+
+use crate::descriptor::split_args;
+use crate::opcodes::*;
+use crate::parse::parse_script;
+use crate::script::{as_script_nb_sized, serialize, to_script_nb, Script, ScriptItem, MAX_OPS_PER_SCRIPT, MAX_SCRIPT_SIZE};
+use crate::transaction::{LOCKTIME_THRESHOLD, SEQUENCE_TYPE_FLAG};
+
+// Letters of the type properties, following Core's notation:
+// - B, V, K, W: base type (pushes a boolean, verifies, pushes a key, wraps a B one element down the stack)
+// - z, o, n, d, u: consumes zero or one stack element, top input is nonzero, dissatisfiable, pushes exactly 1
+// - e, f, s, m: dissatisfactions are non-malleable, no dissatisfaction, needs a signature, non-malleable
+// - x: last opcode is not EQUAL, CHECKSIG or CHECKMULTISIG, so v: adds an OP_VERIFY
+// - g, h, i, j: contains a relative time, relative height, absolute time, absolute height timelock
+// - k: no satisfaction needs both kinds of a relative or absolute timelock
+const TYPE_LETTERS: &str = "BVKWzondufesmxghijk";
+
+// Miniscript type as a set of properties, empty when the expression is invalid
+#[derive(Copy, Clone, Default, Eq, PartialEq, Hash)]
+pub struct Type(u32);
+
+impl Type {
+    fn of(letters: &str) -> Type {
+        Type(letters.chars().fold(0, |bits, c| bits | 1 << TYPE_LETTERS.find(c).expect("unknown type letter")))
+    }
+
+    // All of the properties are present
+    pub fn has(self, letters: &str) -> bool {
+        let other = Type::of(letters);
+        self.0 & other.0 == other.0
+    }
+
+    fn when(self, condition: bool) -> Type {
+        if condition { self } else { Type(0) }
+    }
+
+    // Exactly one base type
+    fn sanitize(self) -> Type {
+        Type(self.0).when("BVKW".chars().filter(|c| self.0 & Type::of(&c.to_string()).0 != 0).count() == 1)
+    }
+
+    pub fn is_valid(self) -> bool {
+        self.0 != 0
+    }
+}
+
+impl BitAnd for Type {
+    type Output = Type;
+
+    fn bitand(self, other: Type) -> Type {
+        Type(self.0 & other.0)
+    }
+}
+
+impl BitOr for Type {
+    type Output = Type;
+
+    fn bitor(self, other: Type) -> Type {
+        Type(self.0 | other.0)
+    }
+}
+
+impl fmt::Display for Type {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let letters: String = TYPE_LETTERS.chars().filter(|c| self.has(&c.to_string())).collect();
+        write!(f, "{}", letters)
+    }
+}
+
+impl fmt::Debug for Type {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Type({})", self)
+    }
+}
+
+// Both timelocks of a kind, heights and times, can't be satisfied together
+fn timelocks_mixed(x: Type, y: Type) -> bool {
+    (x.has("g") && y.has("h")) || (x.has("h") && y.has("g")) || (x.has("i") && y.has("j")) || (x.has("j") && y.has("i"))
+}
+
+// Opcodes counted toward MAX_OPS_PER_SCRIPT: in the script, plus the public keys of executed
+// CHECKMULTISIGs when satisfying and dissatisfying (None when not possible)
+#[derive(Debug, Copy, Clone)]
+struct Ops {
+    count: u32,
+    sat: Option<u32>,
+    dsat: Option<u32>
+}
+
+fn add(a: Option<u32>, b: Option<u32>) -> Option<u32> {
+    Some(a? + b?)
+}
+
+fn max(a: Option<u32>, b: Option<u32>) -> Option<u32> {
+    match (a, b) {
+        (Some(a), Some(b)) => Some(a.max(b)),
+        _ => a.or(b)
+    }
+}
+
+// Miniscript expression for P2WSH scripts, keys are compressed public keys
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub enum Miniscript {
+    False,
+    True,
+    PkK(Vec<u8>),
+    // Public key, or its HASH160 when decompiled from a script
+    PkH(Vec<u8>),
+    Older(u32),
+    After(u32),
+    Sha256(Vec<u8>),
+    Hash256(Vec<u8>),
+    Ripemd160(Vec<u8>),
+    Hash160(Vec<u8>),
+    // Wrappers a: s: c: d: v: j: n:
+    Alt(Box<Miniscript>),
+    Swap(Box<Miniscript>),
+    Check(Box<Miniscript>),
+    DupIf(Box<Miniscript>),
+    Verify(Box<Miniscript>),
+    NonZero(Box<Miniscript>),
+    ZeroNotEqual(Box<Miniscript>),
+    AndV(Box<Miniscript>, Box<Miniscript>),
+    AndB(Box<Miniscript>, Box<Miniscript>),
+    AndOr(Box<Miniscript>, Box<Miniscript>, Box<Miniscript>),
+    OrB(Box<Miniscript>, Box<Miniscript>),
+    OrC(Box<Miniscript>, Box<Miniscript>),
+    OrD(Box<Miniscript>, Box<Miniscript>),
+    OrI(Box<Miniscript>, Box<Miniscript>),
+    Thresh(usize, Vec<Miniscript>),
+    Multi(usize, Vec<Vec<u8>>)
+}
+
+use Miniscript::*;
+
+impl Miniscript {
+    // Whether fragments are nested more than `levels` deep, looking no deeper than that
+    fn deeper_than(&self, levels: usize) -> bool {
+        if levels == 0 {
+            return true
+        }
+        match self {
+            Alt(x) | Swap(x) | Check(x) | DupIf(x) | Verify(x) | NonZero(x) | ZeroNotEqual(x) => x.deeper_than(levels - 1),
+            AndV(x, y) | AndB(x, y) | OrB(x, y) | OrC(x, y) | OrD(x, y) | OrI(x, y) => {
+                x.deeper_than(levels - 1) || y.deeper_than(levels - 1)
+            }
+            AndOr(x, y, z) => x.deeper_than(levels - 1) || y.deeper_than(levels - 1) || z.deeper_than(levels - 1),
+            Thresh(_, subs) => subs.iter().any(|sub| sub.deeper_than(levels - 1)),
+            _ => false
+        }
+    }
+
+    // Type rules of Core's ComputeType, for P2WSH
+    pub fn ty(&self) -> Type {
+        let t = Type::of;
+        let ty = match self {
+            False => t("Bzudemsxk"),
+            True => t("Bzufmxk"),
+            PkK(_) => t("Konudemsxk"),
+            PkH(_) => t("Knudemsxk"),
+            Older(n) => {
+                let time = *n as i64 & SEQUENCE_TYPE_FLAG != 0;
+                t("g").when(time) | t("h").when(!time) | t("Bzfmxk")
+            }
+            After(n) => {
+                let time = *n as i64 >= LOCKTIME_THRESHOLD;
+                t("i").when(time) | t("j").when(!time) | t("Bzfmxk")
+            }
+            Sha256(_) | Hash256(_) | Ripemd160(_) | Hash160(_) => t("Bonudmk"),
+            Alt(x) => {
+                let x = x.ty();
+                t("W").when(x.has("B")) | (x & t("ghijkudfems")) | t("x")
+            }
+            Swap(x) => {
+                let x = x.ty();
+                t("W").when(x.has("Bo")) | (x & t("ghijkudfemsx"))
+            }
+            Check(x) => {
+                let x = x.ty();
+                t("B").when(x.has("K")) | (x & t("ghijkondfem")) | t("us")
+            }
+            // Not u as MINIMALIF is only a policy rule in P2WSH
+            DupIf(x) => {
+                let x = x.ty();
+                t("B").when(x.has("Vz")) | t("o").when(x.has("z")) | t("e").when(x.has("f")) | (x & t("ghijkms")) | t("ndx")
+            }
+            Verify(x) => {
+                let x = x.ty();
+                t("V").when(x.has("B")) | (x & t("ghijkzonms")) | t("fx")
+            }
+            NonZero(x) => {
+                let x = x.ty();
+                t("B").when(x.has("Bn")) | t("e").when(x.has("f")) | (x & t("ghijkoums")) | t("ndx")
+            }
+            ZeroNotEqual(x) => {
+                let x = x.ty();
+                (x & t("ghijkBzondfems")) | t("ux")
+            }
+            AndV(x, y) => {
+                let (x, y) = (x.ty(), y.ty());
+                (y & t("KVB")).when(x.has("V"))
+                    | (x & t("n")) | (y & t("n")).when(x.has("z"))
+                    | ((x | y) & t("o")).when((x | y).has("z"))
+                    | (x & y & t("dmz"))
+                    | ((x | y) & t("s"))
+                    | t("f").when(y.has("f") || x.has("s"))
+                    | (y & t("ux"))
+                    | ((x | y) & t("ghij"))
+                    | t("k").when((x & y).has("k") && !timelocks_mixed(x, y))
+            }
+            AndB(x, y) => {
+                let (x, y) = (x.ty(), y.ty());
+                (x & t("B")).when(y.has("W"))
+                    | ((x | y) & t("o")).when((x | y).has("z"))
+                    | (x & t("n")) | (y & t("n")).when(x.has("z"))
+                    | (x & y & t("e")).when((x & y).has("s"))
+                    | (x & y & t("dzm"))
+                    | t("f").when((x & y).has("f") || x.has("sf") || y.has("sf"))
+                    | ((x | y) & t("s"))
+                    | t("ux")
+                    | ((x | y) & t("ghij"))
+                    | t("k").when((x & y).has("k") && !timelocks_mixed(x, y))
+            }
+            OrB(x, z) => {
+                let (x, z) = (x.ty(), z.ty());
+                t("B").when(x.has("Bd") && z.has("Wd"))
+                    | ((x | z) & t("o")).when((x | z).has("z"))
+                    | (x & z & t("m")).when((x | z).has("s") && (x & z).has("e"))
+                    | (x & z & t("zse"))
+                    | t("dux")
+                    | ((x | z) & t("ghij"))
+                    | (x & z & t("k"))
+            }
+            OrD(x, z) => {
+                let (x, z) = (x.ty(), z.ty());
+                (z & t("B")).when(x.has("Bdu"))
+                    | (x & t("o")).when(z.has("z"))
+                    | (x & z & t("m")).when(x.has("e") && (x | z).has("s"))
+                    | (x & z & t("zes"))
+                    | (z & t("ufd"))
+                    | t("x")
+                    | ((x | z) & t("ghij"))
+                    | (x & z & t("k"))
+            }
+            OrC(x, z) => {
+                let (x, z) = (x.ty(), z.ty());
+                (z & t("V")).when(x.has("Bdu"))
+                    | (x & t("o")).when(z.has("z"))
+                    | (x & z & t("m")).when(x.has("e") && (x | z).has("s"))
+                    | (x & z & t("zs"))
+                    | t("fx")
+                    | ((x | z) & t("ghij"))
+                    | (x & z & t("k"))
+            }
+            OrI(x, z) => {
+                let (x, z) = (x.ty(), z.ty());
+                (x & z & t("VBKufs"))
+                    | t("o").when((x & z).has("z"))
+                    | ((x | z) & t("e")).when((x | z).has("f"))
+                    | (x & z & t("m")).when((x | z).has("s"))
+                    | ((x | z) & t("d"))
+                    | t("x")
+                    | ((x | z) & t("ghij"))
+                    | (x & z & t("k"))
+            }
+            AndOr(x, y, z) => {
+                let (x, y, z) = (x.ty(), y.ty(), z.ty());
+                (y & z & t("BKV")).when(x.has("Bdu"))
+                    | (x & y & z & t("z"))
+                    | ((x | (y & z)) & t("o")).when((x | (y & z)).has("z"))
+                    | (y & z & t("u"))
+                    | (z & t("f")).when(x.has("s") || y.has("f"))
+                    | (z & t("d"))
+                    | (z & t("e")).when(x.has("s") || y.has("f"))
+                    | (x & y & z & t("m")).when(x.has("e") && (x | y | z).has("s"))
+                    | (z & (x | y) & t("s"))
+                    | t("x")
+                    | ((x | y | z) & t("ghij"))
+                    | t("k").when((x & y & z).has("k") && !timelocks_mixed(x, y))
+            }
+            Multi(..) => t("Bnudemsk"),
+            Thresh(k, subs) => {
+                let (mut all_e, mut all_m, mut args, mut num_s) = (true, true, 0, 0);
+                let mut timelocks = t("k");
+                for (i, sub) in subs.iter().enumerate() {
+                    let sub = sub.ty();
+                    if !sub.has(if i == 0 { "Bdu" } else { "Wdu" }) {
+                        return Type::default()
+                    }
+                    all_e &= sub.has("e");
+                    all_m &= sub.has("m");
+                    num_s += sub.has("s") as usize;
+                    args += if sub.has("z") { 0 } else if sub.has("o") { 1 } else { 2 };
+                    timelocks = ((timelocks | sub) & t("ghij"))
+                        | t("k").when((timelocks & sub).has("k") && (*k <= 1 || !timelocks_mixed(timelocks, sub)));
+                }
+                t("Bdu")
+                    | t("z").when(args == 0)
+                    | t("o").when(args == 1)
+                    | t("e").when(all_e && num_s == subs.len())
+                    | t("m").when(all_e && all_m && num_s + k >= subs.len())
+                    | t("s").when(num_s + k > subs.len())
+                    | timelocks
+            }
+        };
+        ty.sanitize()
+    }
+
+    // Valid as a whole script: B, with every subexpression well typed
+    pub fn is_valid_top_level(&self) -> bool {
+        self.ty().has("B")
+    }
+
+    // There is a non-malleable satisfaction for every spending condition
+    pub fn is_non_malleable(&self) -> bool {
+        self.ty().has("m")
+    }
+
+    // Every satisfaction needs a signature, so third parties can't spend it
+    pub fn needs_signature(&self) -> bool {
+        self.ty().has("s")
+    }
+
+    pub fn has_timelock_mix(&self) -> bool {
+        !self.ty().has("k")
+    }
+
+    fn ops(&self) -> Ops {
+        let ops = |count, sat, dsat| Ops { count, sat, dsat };
+        match self {
+            False => ops(0, None, Some(0)),
+            True => ops(0, Some(0), None),
+            PkK(_) => ops(0, Some(0), Some(0)),
+            PkH(_) => ops(3, Some(0), Some(0)),
+            Older(_) | After(_) => ops(1, Some(0), None),
+            Sha256(_) | Hash256(_) | Ripemd160(_) | Hash160(_) => ops(4, Some(0), None),
+            Alt(x) => { let x = x.ops(); ops(2 + x.count, x.sat, x.dsat) }
+            Swap(x) | Check(x) | ZeroNotEqual(x) => { let x = x.ops(); ops(1 + x.count, x.sat, x.dsat) }
+            DupIf(x) => { let x = x.ops(); ops(3 + x.count, x.sat, Some(0)) }
+            Verify(x) => {
+                let fused = !x.ty().has("x");
+                let x = x.ops();
+                ops(x.count + !fused as u32, x.sat, None)
+            }
+            NonZero(x) => { let x = x.ops(); ops(4 + x.count, x.sat, Some(0)) }
+            AndV(x, y) => {
+                let (x, y) = (x.ops(), y.ops());
+                ops(x.count + y.count, add(x.sat, y.sat), None)
+            }
+            AndB(x, y) => {
+                let (x, y) = (x.ops(), y.ops());
+                ops(1 + x.count + y.count, add(x.sat, y.sat), add(x.dsat, y.dsat))
+            }
+            OrB(x, z) => {
+                let (x, z) = (x.ops(), z.ops());
+                ops(1 + x.count + z.count, max(add(x.sat, z.dsat), add(x.dsat, z.sat)), add(x.dsat, z.dsat))
+            }
+            OrD(x, z) => {
+                let (x, z) = (x.ops(), z.ops());
+                ops(3 + x.count + z.count, max(x.sat, add(x.dsat, z.sat)), add(x.dsat, z.dsat))
+            }
+            OrC(x, z) => {
+                let (x, z) = (x.ops(), z.ops());
+                ops(2 + x.count + z.count, max(x.sat, add(x.dsat, z.sat)), None)
+            }
+            OrI(x, z) => {
+                let (x, z) = (x.ops(), z.ops());
+                ops(3 + x.count + z.count, max(x.sat, z.sat), max(x.dsat, z.dsat))
+            }
+            AndOr(x, y, z) => {
+                let (x, y, z) = (x.ops(), y.ops(), z.ops());
+                ops(3 + x.count + y.count + z.count, max(add(y.sat, x.sat), add(x.dsat, z.sat)), add(x.dsat, z.dsat))
+            }
+            Multi(_, keys) => ops(1, Some(keys.len() as u32), Some(keys.len() as u32)),
+            Thresh(k, subs) => {
+                // sats[i]: most opcodes executed with i subexpressions satisfied
+                let mut count = 0;
+                let mut sats = vec![Some(0)];
+                for sub in subs {
+                    let sub = sub.ops();
+                    count += sub.count + 1;
+                    let mut next = vec![add(sats[0], sub.dsat)];
+                    for i in 1..sats.len() {
+                        next.push(max(add(sats[i], sub.dsat), add(sats[i - 1], sub.sat)));
+                    }
+                    next.push(add(sats[sats.len() - 1], sub.sat));
+                    sats = next;
+                }
+                ops(count, sats[*k], sats[0])
+            }
+        }
+    }
+
+    // Opcodes counted toward MAX_OPS_PER_SCRIPT by the most expensive satisfaction
+    pub fn ops_count(&self) -> Option<usize> {
+        let ops = self.ops();
+        ops.sat.map(|sat| (ops.count + sat) as usize)
+    }
+
+    pub fn script_size(&self) -> usize {
+        serialize(&self.encode()).len()
+    }
+
+    fn keys(&self) -> Vec<&[u8]> {
+        match self {
+            PkK(key) | PkH(key) => vec![key],
+            Multi(_, keys) => keys.iter().map(|k| &k[..]).collect(),
+            Thresh(_, subs) => subs.iter().flat_map(|s| s.keys()).collect(),
+            Alt(x) | Swap(x) | Check(x) | DupIf(x) | Verify(x) | NonZero(x) | ZeroNotEqual(x) => x.keys(),
+            AndV(x, y) | AndB(x, y) | OrB(x, y) | OrC(x, y) | OrD(x, y) | OrI(x, y) => [x.keys(), y.keys()].concat(),
+            AndOr(x, y, z) => [x.keys(), y.keys(), z.keys()].concat(),
+            _ => Vec::new()
+        }
+    }
+
+    // Resource limits of P2WSH scripts
+    pub fn check_resource_limits(&self) -> Result<(), String> {
+        let size = self.script_size();
+        if size > MAX_SCRIPT_SIZE {
+            return Err(format!("Script is {} bytes, above the {} bytes limit", size, MAX_SCRIPT_SIZE))
+        }
+        match self.ops_count() {
+            Some(ops) if ops > MAX_OPS_PER_SCRIPT => {
+                Err(format!("Satisfaction executes {} opcodes, above the {} opcodes limit", ops, MAX_OPS_PER_SCRIPT))
+            }
+            _ => Ok(())
+        }
+    }
+
+    // Core's IsSane: valid top level, non-malleable, needs a signature, no timelock mix,
+    // within resource limits and without repeated keys
+    pub fn check_sanity(&self) -> Result<(), String> {
+        if !self.is_valid_top_level() {
+            return Err(format!("Top level expression has type {}, not B", self.ty()))
+        }
+        if !self.is_non_malleable() {
+            return Err(String::from("Some spending conditions only have malleable satisfactions"))
+        }
+        if !self.needs_signature() {
+            return Err(String::from("Some satisfactions don't need a signature"))
+        }
+        if self.has_timelock_mix() {
+            return Err(String::from("Some satisfactions need both a height and a time timelock"))
+        }
+        self.check_resource_limits()?;
+        let mut keys = self.keys();
+        let count = keys.len();
+        keys.sort();
+        keys.dedup();
+        if keys.len() != count {
+            return Err(String::from("Some keys are repeated"))
+        }
+        Ok(())
+    }
+
+    pub fn encode(&self) -> Script {
+        let mut script = Script::new();
+        self.encode_into(&mut script);
+        script
+    }
+
+    fn encode_into(&self, script: &mut Script) {
+        let ops = |script: &mut Script, ops: &[Opcode]| script.extend(ops.iter().map(|op| ScriptItem::Opcode(*op)));
+        let hash = |script: &mut Script, size: usize, op: Opcode, hash: &[u8]| {
+            ops(script, &[OP_SIZE]);
+            script.push(number(size as u32));
+            ops(script, &[OP_EQUALVERIFY, op]);
+            script.push(ScriptItem::ByteArray(hash.to_vec()));
+            ops(script, &[OP_EQUAL]);
+        };
+        match self {
+            False => ops(script, &[OP_0]),
+            True => ops(script, &[OP_1]),
+            PkK(key) => script.push(ScriptItem::ByteArray(key.clone())),
+            PkH(key) => {
+                ops(script, &[OP_DUP, OP_HASH160]);
+                script.push(ScriptItem::ByteArray(key_hash(key)));
+                ops(script, &[OP_EQUALVERIFY]);
+            }
+            Older(n) => { script.push(number(*n)); ops(script, &[OP_CHECKSEQUENCEVERIFY]) }
+            After(n) => { script.push(number(*n)); ops(script, &[OP_CHECKLOCKTIMEVERIFY]) }
+            Sha256(h) => hash(script, 32, OP_SHA256, h),
+            Hash256(h) => hash(script, 32, OP_HASH256, h),
+            Ripemd160(h) => hash(script, 32, OP_RIPEMD160, h),
+            Hash160(h) => hash(script, 32, OP_HASH160, h),
+            Alt(x) => { ops(script, &[OP_TOALTSTACK]); x.encode_into(script); ops(script, &[OP_FROMALTSTACK]) }
+            Swap(x) => { ops(script, &[OP_SWAP]); x.encode_into(script) }
+            Check(x) => { x.encode_into(script); ops(script, &[OP_CHECKSIG]) }
+            DupIf(x) => { ops(script, &[OP_DUP, OP_IF]); x.encode_into(script); ops(script, &[OP_ENDIF]) }
+            Verify(x) => {
+                x.encode_into(script);
+                let fused = match script.last() {
+                    Some(ScriptItem::Opcode(OP_CHECKSIG)) => Some(OP_CHECKSIGVERIFY),
+                    Some(ScriptItem::Opcode(OP_CHECKMULTISIG)) => Some(OP_CHECKMULTISIGVERIFY),
+                    Some(ScriptItem::Opcode(OP_EQUAL)) => Some(OP_EQUALVERIFY),
+                    Some(ScriptItem::Opcode(OP_NUMEQUAL)) => Some(OP_NUMEQUALVERIFY),
+                    _ => None
+                };
+                match fused {
+                    Some(op) => *script.last_mut().unwrap() = ScriptItem::Opcode(op),
+                    None => ops(script, &[OP_VERIFY])
+                }
+            }
+            NonZero(x) => { ops(script, &[OP_SIZE, OP_0NOTEQUAL, OP_IF]); x.encode_into(script); ops(script, &[OP_ENDIF]) }
+            ZeroNotEqual(x) => { x.encode_into(script); ops(script, &[OP_0NOTEQUAL]) }
+            AndV(x, y) => { x.encode_into(script); y.encode_into(script) }
+            AndB(x, y) => { x.encode_into(script); y.encode_into(script); ops(script, &[OP_BOOLAND]) }
+            AndOr(x, y, z) => {
+                x.encode_into(script);
+                ops(script, &[OP_NOTIF]);
+                z.encode_into(script);
+                ops(script, &[OP_ELSE]);
+                y.encode_into(script);
+                ops(script, &[OP_ENDIF]);
+            }
+            OrB(x, z) => { x.encode_into(script); z.encode_into(script); ops(script, &[OP_BOOLOR]) }
+            OrC(x, z) => { x.encode_into(script); ops(script, &[OP_NOTIF]); z.encode_into(script); ops(script, &[OP_ENDIF]) }
+            OrD(x, z) => {
+                x.encode_into(script);
+                ops(script, &[OP_IFDUP, OP_NOTIF]);
+                z.encode_into(script);
+                ops(script, &[OP_ENDIF]);
+            }
+            OrI(x, z) => {
+                ops(script, &[OP_IF]);
+                x.encode_into(script);
+                ops(script, &[OP_ELSE]);
+                z.encode_into(script);
+                ops(script, &[OP_ENDIF]);
+            }
+            Thresh(k, subs) => {
+                for (i, sub) in subs.iter().enumerate() {
+                    sub.encode_into(script);
+                    if i > 0 {
+                        ops(script, &[OP_ADD]);
+                    }
+                }
+                script.push(number(*k as u32));
+                ops(script, &[OP_EQUAL]);
+            }
+            Multi(k, keys) => {
+                script.push(number(*k as u32));
+                script.extend(keys.iter().map(|key| ScriptItem::ByteArray(key.clone())));
+                script.push(number(keys.len() as u32));
+                ops(script, &[OP_CHECKMULTISIG]);
+            }
+        }
+    }
+}
+
+// Minimal push of a number
+fn number(n: u32) -> ScriptItem {
+    match n {
+        0 => ScriptItem::Opcode(OP_0),
+        1..=16 => ScriptItem::Opcode(Opcode { code: OP_1.code + n as u8 - 1 }),
+        _ => ScriptItem::ByteArray(to_script_nb(n as i64))
+    }
+}
+
+fn key_hash(key: &[u8]) -> Vec<u8> {
+    match key.len() {
+        20 => key.to_vec(),
+        _ => hash160::Hash::hash(key).to_vec()
+    }
+}
+
+impl fmt::Display for Miniscript {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        // Wrappers are written as letters before a colon, t: u: l: are shorthands for and_v and or_i
+        let mut wrappers = String::new();
+        let mut node = self;
+        loop {
+            let (letter, inner) = match node {
+                Alt(x) => ('a', x),
+                Swap(x) => ('s', x),
+                Check(x) if !matches!(**x, PkK(_) | PkH(_)) => ('c', x),
+                DupIf(x) => ('d', x),
+                Verify(x) => ('v', x),
+                NonZero(x) => ('j', x),
+                ZeroNotEqual(x) => ('n', x),
+                AndV(x, y) if **y == True => ('t', x),
+                OrI(x, z) if **z == False => ('u', x),
+                OrI(x, z) if **x == False => ('l', z),
+                _ => break
+            };
+            wrappers.push(letter);
+            node = inner;
+        }
+        if !wrappers.is_empty() {
+            write!(f, "{}:", wrappers)?;
+        }
+
+        let hex = |data: &[u8]| hex::encode(data);
+        match node {
+            False => write!(f, "0"),
+            True => write!(f, "1"),
+            PkK(key) => write!(f, "pk_k({})", hex(key)),
+            PkH(key) => write!(f, "pk_h({})", hex(key)),
+            Check(x) => match &**x {
+                PkK(key) => write!(f, "pk({})", hex(key)),
+                PkH(key) => write!(f, "pkh({})", hex(key)),
+                x => write!(f, "c:{}", x)
+            },
+            Older(n) => write!(f, "older({})", n),
+            After(n) => write!(f, "after({})", n),
+            Sha256(h) => write!(f, "sha256({})", hex(h)),
+            Hash256(h) => write!(f, "hash256({})", hex(h)),
+            Ripemd160(h) => write!(f, "ripemd160({})", hex(h)),
+            Hash160(h) => write!(f, "hash160({})", hex(h)),
+            AndV(x, y) => write!(f, "and_v({},{})", x, y),
+            AndB(x, y) => write!(f, "and_b({},{})", x, y),
+            AndOr(x, y, z) if **z == False => write!(f, "and_n({},{})", x, y),
+            AndOr(x, y, z) => write!(f, "andor({},{},{})", x, y, z),
+            OrB(x, z) => write!(f, "or_b({},{})", x, z),
+            OrC(x, z) => write!(f, "or_c({},{})", x, z),
+            OrD(x, z) => write!(f, "or_d({},{})", x, z),
+            OrI(x, z) => write!(f, "or_i({},{})", x, z),
+            Thresh(k, subs) => {
+                let subs: Vec<String> = subs.iter().map(|s| s.to_string()).collect();
+                write!(f, "thresh({},{})", k, subs.join(","))
+            }
+            Multi(k, keys) => {
+                let keys: Vec<String> = keys.iter().map(|k| hex(k)).collect();
+                write!(f, "multi({},{})", k, keys.join(","))
+            }
+            Alt(_) | Swap(_) | DupIf(_) | Verify(_) | NonZero(_) | ZeroNotEqual(_) => unreachable!()
+        }
+    }
+}
+
+pub(crate) fn parse_key(text: &str) -> Result<Vec<u8>, String> {
+    let key = hex::decode(text).map_err(|_| format!("Invalid key {}", text))?;
+    match key.len() {
+        33 if PublicKey::from_slice(&key).is_ok() => Ok(key),
+        _ => Err(format!("Invalid key {}, expected a compressed public key", text))
+    }
+}
+
+pub(crate) fn parse_hash(text: &str, size: usize) -> Result<Vec<u8>, String> {
+    match hex::decode(text) {
+        Ok(hash) if hash.len() == size => Ok(hash),
+        _ => Err(format!("Invalid hash {}, expected {} hex encoded bytes", text, size))
+    }
+}
+
+// Timelocks are between 1 and 2^31 - 1, 0 and the disable flag can't be satisfied
+pub(crate) fn parse_timelock(text: &str) -> Result<u32, String> {
+    match text.parse::<u32>() {
+        Ok(n) if (1..1 << 31).contains(&n) => Ok(n),
+        _ => Err(format!("Invalid timelock {}", text))
+    }
+}
+
+fn wrap(letter: char, x: Miniscript) -> Result<Miniscript, String> {
+    let x = Box::new(x);
+    Ok(match letter {
+        'a' => Alt(x),
+        's' => Swap(x),
+        'c' => Check(x),
+        'd' => DupIf(x),
+        'v' => Verify(x),
+        'j' => NonZero(x),
+        'n' => ZeroNotEqual(x),
+        't' => AndV(x, Box::new(True)),
+        'u' => OrI(x, Box::new(False)),
+        'l' => OrI(Box::new(False), x),
+        _ => return Err(format!("Unknown wrapper {}:", letter))
+    })
+}
+
+// Deepest nesting of fragments accepted when parsing text or decoding a script, as rust-miniscript
+pub(crate) const MAX_DEPTH: usize = 402;
+
+// `depth` is the number of fragments around this one
+fn parse(text: &str, depth: usize) -> Result<Miniscript, String> {
+    if depth > MAX_DEPTH {
+        return Err(format!("Miniscript nested more than {} levels deep", MAX_DEPTH))
+    }
+    let open = text.find('(').unwrap_or(text.len());
+    if let Some(colon) = text[..open].find(':') {
+        let inner = parse(&text[colon + 1..], depth + colon)?;
+        let node = text[..colon].chars().rev().try_fold(inner, |x, letter| wrap(letter, x))?;
+        return typed(node)
+    }
+
+    let (name, args) = match text.strip_suffix(')') {
+        Some(call) if open < text.len() => (&call[..open], split_args(&call[open + 1..])),
+        _ => (text, Vec::new())
+    };
+    let arity = |n: usize| match args.len() == n {
+        true => Ok(()),
+        false => Err(format!("{}() expects {} arguments, got {}", name, n, args.len()))
+    };
+    let sub = |i: usize| parse(args[i], depth + 1).map(Box::new);
+
+    let node = match name {
+        "0" if args.is_empty() => False,
+        "1" if args.is_empty() => True,
+        "pk_k" | "pk" => {
+            arity(1)?;
+            let key = PkK(parse_key(args[0])?);
+            if name == "pk" { Check(Box::new(key)) } else { key }
+        }
+        // pk_h also takes the HASH160 of the key, as found in scripts
+        "pk_h" | "pkh" => {
+            arity(1)?;
+            let key = match args[0].len() {
+                40 => PkH(parse_hash(args[0], 20)?),
+                _ => PkH(parse_key(args[0])?)
+            };
+            if name == "pkh" { Check(Box::new(key)) } else { key }
+        }
+        "older" => { arity(1)?; Older(parse_timelock(args[0])?) }
+        "after" => { arity(1)?; After(parse_timelock(args[0])?) }
+        "sha256" => { arity(1)?; Sha256(parse_hash(args[0], 32)?) }
+        "hash256" => { arity(1)?; Hash256(parse_hash(args[0], 32)?) }
+        "ripemd160" => { arity(1)?; Ripemd160(parse_hash(args[0], 20)?) }
+        "hash160" => { arity(1)?; Hash160(parse_hash(args[0], 20)?) }
+        "and_v" => { arity(2)?; AndV(sub(0)?, sub(1)?) }
+        "and_b" => { arity(2)?; AndB(sub(0)?, sub(1)?) }
+        "and_n" => { arity(2)?; AndOr(sub(0)?, sub(1)?, Box::new(False)) }
+        "andor" => { arity(3)?; AndOr(sub(0)?, sub(1)?, sub(2)?) }
+        "or_b" => { arity(2)?; OrB(sub(0)?, sub(1)?) }
+        "or_c" => { arity(2)?; OrC(sub(0)?, sub(1)?) }
+        "or_d" => { arity(2)?; OrD(sub(0)?, sub(1)?) }
+        "or_i" => { arity(2)?; OrI(sub(0)?, sub(1)?) }
+        "thresh" | "multi" => {
+            if args.len() < 2 {
+                return Err(format!("{}() expects a threshold and at least one argument", name))
+            }
+            let k = args[0].parse::<usize>().map_err(|_| format!("Invalid threshold {}", args[0]))?;
+            if k < 1 || k >= args.len() {
+                return Err(format!("Threshold {} out of range for {} arguments", k, args.len() - 1))
+            }
+            match name {
+                "thresh" => Thresh(k, args[1..].iter().map(|arg| parse(arg, depth + 1)).collect::<Result<_, _>>()?),
+                _ if args.len() - 1 > 20 => return Err(String::from("multi() takes at most 20 keys")),
+                _ => Multi(k, args[1..].iter().map(|arg| parse_key(arg)).collect::<Result<_, _>>()?)
+            }
+        }
+        _ => return Err(format!("Unknown miniscript fragment {}", text))
+    };
+    typed(node)
+}
+
+// Reject expressions without a valid type, pointing at the smallest one
+fn typed(node: Miniscript) -> Result<Miniscript, String> {
+    match node.ty().is_valid() {
+        true => Ok(node),
+        false => Err(format!("{} has no valid type", node))
+    }
+}
+
+impl FromStr for Miniscript {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s: String = s.chars().filter(|c| !c.is_whitespace()).collect();
+        parse(&s, 0)
+    }
+}
+
+// Turns a script back into Miniscript, reading fragments from left to right: postfix opcodes
+// wrap what was just parsed, and V expressions followed by another expression become and_v
+struct Decoder {
+    items: Script,
+    pos: usize,
+    // Expressions being decoded around the current one
+    depth: usize
+}
+
+impl Decoder {
+    fn peek(&self, offset: usize) -> Option<&ScriptItem> {
+        self.items.get(self.pos + offset)
+    }
+
+    fn is(&self, offset: usize, op: Opcode) -> bool {
+        self.peek(offset) == Some(&ScriptItem::Opcode(op))
+    }
+
+    fn number(&self, offset: usize) -> Option<u32> {
+        match self.peek(offset)? {
+            ScriptItem::Opcode(OP_0) => Some(0),
+            ScriptItem::Opcode(op) if (OP_1.code..=OP_16.code).contains(&op.code) => Some((op.code - OP_1.code + 1) as u32),
+            ScriptItem::ByteArray(data) => {
+                let n = as_script_nb_sized(data, 5).ok()?;
+                (n > 16 && n <= u32::MAX as i64 && to_script_nb(n) == *data).then_some(n as u32)
+            }
+            _ => None
+        }
+    }
+
+    fn data(&self, offset: usize, size: usize) -> Option<Vec<u8>> {
+        match self.peek(offset)? {
+            ScriptItem::ByteArray(data) if data.len() == size => Some(data.clone()),
+            _ => None
+        }
+    }
+
+    fn expect(&mut self, op: Opcode) -> Result<(), String> {
+        if !self.is(0, op) {
+            return Err(self.unexpected(&op.to_string()))
+        }
+        self.pos += 1;
+        Ok(())
+    }
+
+    fn unexpected(&self, expected: &str) -> String {
+        match self.peek(0) {
+            Some(item) => format!("Unexpected {:?} at item {}, expected {}", item, self.pos, expected),
+            None => format!("Unexpected end of script, expected {}", expected)
+        }
+    }
+
+    fn starts_expression(&self) -> bool {
+        match self.peek(0) {
+            Some(ScriptItem::ByteArray(_)) => true,
+            Some(ScriptItem::Opcode(op)) => op.code <= OP_16.code || matches!(*op, OP_DUP | OP_SIZE | OP_IF),
+            None => false
+        }
+    }
+
+    fn too_deep(&self) -> String {
+        format!("Miniscript nested more than {} levels deep at item {}", MAX_DEPTH, self.pos)
+    }
+
+    // and_v() chains are read in a loop, they nest to the right
+    fn expression(&mut self) -> Result<Miniscript, String> {
+        if self.depth == MAX_DEPTH {
+            return Err(self.too_deep())
+        }
+        self.depth += 1;
+        let mut xs = vec![self.term()?];
+        while xs.last().unwrap().ty().has("V") && self.starts_expression() {
+            if xs.len() == MAX_DEPTH {
+                return Err(self.too_deep())
+            }
+            xs.push(self.term()?);
+        }
+        self.depth -= 1;
+        let last = xs.pop().unwrap();
+        Ok(xs.into_iter().rev().fold(last, |z, x| AndV(Box::new(x), Box::new(z))))
+    }
+
+    fn term(&mut self) -> Result<Miniscript, String> {
+        let mut x = self.primary()?;
+        loop {
+            // Typing walks the whole fragment
+            if x.deeper_than(MAX_DEPTH) {
+                return Err(self.too_deep())
+            }
+            // Single argument thresh: [X] <k> OP_EQUAL
+            if x.ty().has("B") && self.number(0).is_some() && (self.is(1, OP_EQUAL) || self.is(1, OP_EQUALVERIFY)) {
+                return self.thresh(vec![x])
+            }
+            let op = match self.peek(0) {
+                Some(ScriptItem::Opcode(op)) => *op,
+                _ => return Ok(x)
+            };
+            x = match op {
+                OP_CHECKSIG => { self.pos += 1; Check(Box::new(x)) }
+                OP_CHECKSIGVERIFY => { self.pos += 1; Verify(Box::new(Check(Box::new(x)))) }
+                OP_VERIFY => { self.pos += 1; Verify(Box::new(x)) }
+                OP_0NOTEQUAL => { self.pos += 1; ZeroNotEqual(Box::new(x)) }
+                OP_TOALTSTACK | OP_SWAP => {
+                    let w = self.primary()?;
+                    match self.peek(0) {
+                        Some(ScriptItem::Opcode(OP_BOOLAND)) => { self.pos += 1; AndB(Box::new(x), Box::new(w)) }
+                        Some(ScriptItem::Opcode(OP_BOOLOR)) => { self.pos += 1; OrB(Box::new(x), Box::new(w)) }
+                        Some(ScriptItem::Opcode(OP_ADD)) => { self.pos += 1; return self.thresh(vec![x, w]) }
+                        _ => return Err(self.unexpected("OP_BOOLAND, OP_BOOLOR or OP_ADD"))
+                    }
+                }
+                OP_IFDUP if self.is(1, OP_NOTIF) => {
+                    self.pos += 2;
+                    let z = self.expression()?;
+                    self.expect(OP_ENDIF)?;
+                    OrD(Box::new(x), Box::new(z))
+                }
+                OP_NOTIF => {
+                    self.pos += 1;
+                    let z = self.expression()?;
+                    if self.is(0, OP_ELSE) {
+                        self.pos += 1;
+                        let y = self.expression()?;
+                        self.expect(OP_ENDIF)?;
+                        AndOr(Box::new(x), Box::new(y), Box::new(z))
+                    } else {
+                        self.expect(OP_ENDIF)?;
+                        OrC(Box::new(x), Box::new(z))
+                    }
+                }
+                _ => return Ok(x)
+            };
+        }
+    }
+
+    // [X1] [X2] OP_ADD ... [Xn] OP_ADD <k> OP_EQUAL, after the first OP_ADD
+    fn thresh(&mut self, mut subs: Vec<Miniscript>) -> Result<Miniscript, String> {
+        while self.is(0, OP_TOALTSTACK) || self.is(0, OP_SWAP) {
+            subs.push(self.primary()?);
+            self.expect(OP_ADD)?;
+        }
+        let k = self.number(0).ok_or_else(|| self.unexpected("a threshold"))? as usize;
+        self.pos += 1;
+        let verify = self.is(0, OP_EQUALVERIFY);
+        if !verify {
+            self.expect(OP_EQUAL)?;
+        } else {
+            self.pos += 1;
+        }
+        let thresh = Thresh(k, subs);
+        Ok(if verify { Verify(Box::new(thresh)) } else { thresh })
+    }
+
+    fn primary(&mut self) -> Result<Miniscript, String> {
+        let item = self.peek(0).ok_or_else(|| self.unexpected("an expression"))?.clone();
+        let node = match item {
+            ScriptItem::ByteArray(key) if key.len() == 33 => { self.pos += 1; PkK(key) }
+            ScriptItem::Opcode(OP_DUP) if self.is(1, OP_HASH160) && self.is(3, OP_EQUALVERIFY) && self.data(2, 20).is_some() => {
+                let hash = self.data(2, 20).unwrap();
+                self.pos += 4;
+                PkH(hash)
+            }
+            ScriptItem::Opcode(OP_DUP) if self.is(1, OP_IF) => {
+                self.pos += 2;
+                let x = self.expression()?;
+                self.expect(OP_ENDIF)?;
+                DupIf(Box::new(x))
+            }
+            ScriptItem::Opcode(OP_SIZE) if self.is(1, OP_0NOTEQUAL) && self.is(2, OP_IF) => {
+                self.pos += 3;
+                let x = self.expression()?;
+                self.expect(OP_ENDIF)?;
+                NonZero(Box::new(x))
+            }
+            ScriptItem::Opcode(OP_SIZE) if self.number(1) == Some(32) && self.is(2, OP_EQUALVERIFY) => {
+                let (size, make): (usize, fn(Vec<u8>) -> Miniscript) = match self.peek(3) {
+                    Some(ScriptItem::Opcode(OP_SHA256)) => (32, Sha256),
+                    Some(ScriptItem::Opcode(OP_HASH256)) => (32, Hash256),
+                    Some(ScriptItem::Opcode(OP_RIPEMD160)) => (20, Ripemd160),
+                    Some(ScriptItem::Opcode(OP_HASH160)) => (20, Hash160),
+                    _ => return Err(format!("Unexpected {:?} at item {}, expected a hash opcode", self.peek(3), self.pos + 3))
+                };
+                let hash = self.data(4, size).ok_or_else(|| format!("Expected a {} bytes hash at item {}", size, self.pos + 4))?;
+                self.pos += 5;
+                let verify = self.is(0, OP_EQUALVERIFY);
+                if !verify {
+                    self.expect(OP_EQUAL)?;
+                } else {
+                    self.pos += 1;
+                }
+                if verify { Verify(Box::new(make(hash))) } else { make(hash) }
+            }
+            ScriptItem::Opcode(OP_IF) => {
+                self.pos += 1;
+                let x = self.expression()?;
+                self.expect(OP_ELSE)?;
+                let z = self.expression()?;
+                self.expect(OP_ENDIF)?;
+                OrI(Box::new(x), Box::new(z))
+            }
+            ScriptItem::Opcode(OP_TOALTSTACK) => {
+                self.pos += 1;
+                let x = self.expression()?;
+                self.expect(OP_FROMALTSTACK)?;
+                Alt(Box::new(x))
+            }
+            ScriptItem::Opcode(OP_SWAP) => {
+                self.pos += 1;
+                Swap(Box::new(self.expression()?))
+            }
+            _ => match self.number(0) {
+                Some(n) if n > 0 && n < 1 << 31 && self.is(1, OP_CHECKSEQUENCEVERIFY) => { self.pos += 2; Older(n) }
+                Some(n) if n > 0 && n < 1 << 31 && self.is(1, OP_CHECKLOCKTIMEVERIFY) => { self.pos += 2; After(n) }
+                Some(k) if self.data(1, 33).is_some() => self.multi(k as usize)?,
+                Some(0) => { self.pos += 1; False }
+                Some(1) if item == ScriptItem::Opcode(OP_1) => { self.pos += 1; True }
+                _ => return Err(self.unexpected("an expression"))
+            }
+        };
+        Ok(node)
+    }
+
+    // <k> <key>... <n> OP_CHECKMULTISIG
+    fn multi(&mut self, k: usize) -> Result<Miniscript, String> {
+        self.pos += 1;
+        let mut keys = Vec::new();
+        while let Some(key) = self.data(0, 33) {
+            keys.push(key);
+            self.pos += 1;
+        }
+        if self.number(0) != Some(keys.len() as u32) {
+            return Err(self.unexpected(&format!("{} keys", keys.len())))
+        }
+        self.pos += 1;
+        let verify = self.is(0, OP_CHECKMULTISIGVERIFY);
+        if !verify {
+            self.expect(OP_CHECKMULTISIG)?;
+        } else {
+            self.pos += 1;
+        }
+        let multi = Multi(k, keys);
+        Ok(if verify { Verify(Box::new(multi)) } else { multi })
+    }
+}
+
+// Script back to Miniscript, when it's the encoding of a valid expression
+pub fn decompile(bytes: &[u8]) -> Result<Miniscript, String> {
+    let items = parse_script(bytes).map_err(|e| e.to_string())?;
+    let mut decoder = Decoder { items, pos: 0, depth: 0 };
+    let miniscript = decoder.expression()?;
+    if decoder.peek(0).is_some() {
+        return Err(decoder.unexpected("the end of the script"))
+    }
+    let miniscript = typed(miniscript)?;
+    if serialize(&miniscript.encode()) != bytes {
+        return Err(String::from("Script is not the canonical encoding of a Miniscript expression"))
+    }
+    Ok(miniscript)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const A: &str = "031b84c5567b126440995d3ed5aaba0565d71e1834604819ff9c17f5e9d5dd078f";
+    const B: &str = "024d4b6cd1361032ca9bd2aeb9d900aa4d45d9ead80ac9423374c451a7254d0766";
+    const H: &str = "2bb80d537b1da3e38bd30361aa855686bde0eacd7162fef6a25fe97bf527a25b";
+
+    fn ms(text: &str) -> Miniscript {
+        text.replace('A', A).replace('B', B).replace('H', H).parse().unwrap()
+    }
+
+    fn encoded(text: &str) -> String {
+        hex::encode(serialize(&ms(text).encode()))
+    }
+
+    #[test]
+    fn encodings() {
+        assert_eq!(encoded("pk(A)"), format!("21{}ac", A));
+        assert_eq!(encoded("and_v(v:pk(A),pk(B))"), format!("21{}ad21{}ac", A, B));
+        assert_eq!(encoded("or_d(pk(A),and_v(v:pk(B),older(144)))"), format!("21{}ac736421{}ad029000b268", A, B));
+        assert_eq!(encoded("sha256(H)"), format!("82012088a820{}87", H));
+        assert_eq!(encoded("multi(1,A,B)"), format!("5121{}21{}52ae", A, B));
+        // From Core's miniscript tests
+        assert_eq!(encoded("lltvln:after(1231488000)"), "6300676300676300670400046749b1926869516868");
+    }
+
+    #[test]
+    fn types() {
+        assert_eq!(ms("pk_k(A)").ty().to_string(), "Konduesmxk");
+        assert!(ms("pk(A)").ty().has("Bondusmk") && !ms("pk(A)").ty().has("x"));
+        assert!(ms("older(144)").ty().has("Bzfmxhk") && !ms("older(144)").ty().has("s"));
+        assert!(ms("older(4194305)").ty().has("g"));
+        assert!(ms("after(1231488000)").ty().has("i") && ms("after(100)").ty().has("j"));
+        assert!(ms("v:pk(A)").ty().has("V") && ms("s:pk(A)").ty().has("W") && ms("a:pk(A)").ty().has("W"));
+        assert!(ms("sha256(H)").ty().has("Bonu") && !ms("sha256(H)").ty().has("s"));
+
+        assert_eq!(format!("and_v(pk({}),pk({}))", A, B).parse::<Miniscript>(), Err(format!("and_v(pk({}),pk({})) has no valid type", A, B)));
+        assert!(format!("or_b(pk({}),pk({}))", A, B).parse::<Miniscript>().is_err());
+        assert!(format!("v:v:pk({})", A).parse::<Miniscript>().is_err());
+        assert_eq!("older(0)".parse::<Miniscript>(), Err(String::from("Invalid timelock 0")));
+        assert_eq!("x:older(1)".parse::<Miniscript>(), Err(String::from("Unknown wrapper x:")));
+    }
+
+    #[test]
+    fn sanity() {
+        assert_eq!(ms("or_d(pk(A),and_v(v:pk(B),older(144)))").check_sanity(), Ok(()));
+        assert_eq!(ms("older(144)").check_sanity(), Err(String::from("Some satisfactions don't need a signature")));
+        assert_eq!(ms("v:pk(A)").check_sanity(), Err(String::from("Top level expression has type Vonfsmxk, not B")));
+        assert_eq!(ms("and_v(v:pk(A),pk(A))").check_sanity(), Err(String::from("Some keys are repeated")));
+        assert_eq!(ms("and_v(v:pk(A),and_v(v:older(144),older(4194305)))").check_sanity(),
+            Err(String::from("Some satisfactions need both a height and a time timelock")));
+        // Either branch can be taken, third parties can swap the witness of one for the other
+        assert!(ms("or_i(pk(A),sha256(H))").check_sanity().is_err());
+
+        assert_eq!(ms("multi(2,A,B)").ops_count(), Some(3));
+        assert_eq!(ms("or_d(pk(A),and_v(v:pk(B),older(144)))").ops_count(), Some(6));
+        let keys = vec![A; 20].join(",");
+        let multis = vec![format!("a:multi(1,{})", keys); 15].join(",");
+        let big = format!("thresh(1,pk({}),{})", B, multis);
+        assert!(big.parse::<Miniscript>().unwrap().check_resource_limits().unwrap_err().starts_with("Script is"));
+        // Every executed CHECKMULTISIG also counts its keys
+        let multis = vec![format!("a:multi(1,{})", keys); 10].join(",");
+        let expensive = format!("thresh(10,pk({}),{})", B, multis).parse::<Miniscript>().unwrap();
+        assert!(expensive.check_resource_limits().unwrap_err().starts_with("Satisfaction executes"));
+    }
+
+    #[test]
+    fn display_and_decompile_round_trip() {
+        for text in ["pk(A)", "tv:pk(A)", "and_n(pk(A),older(1))", "andor(pk(A),older(1),pk(B))", "or_i(pk(A),pk(B))",
+            "thresh(2,pk(A),s:pk(B),sln:older(12))", "multi(1,A,B)", "u:pk(A)", "l:pk(A)", "c:pk_k(A)"] {
+            let miniscript = ms(text);
+            let expected = text.replace('A', A).replace('B', B).replace("c:pk_k", "pk");
+            assert_eq!(miniscript.to_string(), expected);
+            assert_eq!(decompile(&serialize(&miniscript.encode())), Ok(miniscript));
+        }
+        // Scripts only hold the hash of pk_h keys
+        let pkh = decompile(&serialize(&ms("pkh(A)").encode())).unwrap();
+        assert_eq!(pkh.to_string(), format!("pkh({})", hex::encode(hash160::Hash::hash(&hex::decode(A).unwrap()))));
+
+        assert!(decompile(&[0x51, 0x87]).is_err());
+        // OP_CHECKSIG OP_VERIFY is written OP_CHECKSIGVERIFY
+        let separate = hex::decode(format!("21{}ac6951", A)).unwrap();
+        assert!(decompile(&separate).is_err());
+        assert_eq!(decompile(&hex::decode(format!("21{}ad51", A)).unwrap()), Ok(ms("and_v(v:pk(A),1)")));
+    }
+
+    #[test]
+    fn deep_nesting_is_an_error() {
+        // Debug builds need more than the 2 MiB stack of test threads for 402 levels
+        std::thread::Builder::new().stack_size(8 << 20).spawn(|| {
+            // Nested OP_IFs and OP_TOALTSTACKs used to recurse until the stack overflowed
+            for op in [0x63, 0x6b] {
+                let mut bytes = vec![op; 9999];
+                bytes.push(0x51);
+                assert!(decompile(&bytes).unwrap_err().starts_with("Miniscript nested more than 402 levels deep"));
+            }
+            let text = format!("{}pk(A)", "v:".repeat(1000));
+            assert!(text.parse::<Miniscript>().unwrap_err().starts_with("Miniscript nested more than 402 levels deep"));
+            let text = format!("{}1{}", "or_i(0,".repeat(1000), ")".repeat(1000));
+            assert!(text.parse::<Miniscript>().unwrap_err().starts_with("Miniscript nested more than 402 levels deep"));
+        }).unwrap().join().unwrap();
+    }
+}
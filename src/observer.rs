@@ -0,0 +1,464 @@
+use std::str::FromStr;
+
+use colored::Colorize;
+use serde_json::{json, Value};
+use tabled::{Alignment, MaxWidth, MinWidth, Modify, Style};
+use tabled::builder::Builder;
+use tabled::object::Rows;
+
+use crate::{MAX_SCRIPT_DISPLAY_WIDTH, MIN_SCRIPT_DISPLAY_WIDTH};
+use crate::asm::SourceMap;
+use crate::interpret::{ExecutionError, ExecutionState, Stack};
+use crate::opcodes::Opcode;
+use crate::script::{Script, ScriptItem};
+
+// Hooks called by the interpreter while it runs a script
+// Every callback receives the state of the execution at the time it is called
+pub trait ExecutionObserver {
+    // Called before every instruction, including the ones skipped by a false branch
+    // `state.pc()` is still the offset of `item`
+    fn before_step(&mut self, _state: &ExecutionState, _item: &ScriptItem) {}
+
+    // Called once `item` has been executed without error
+    fn after_step(&mut self, _state: &ExecutionState, _item: &ScriptItem) {}
+
+    // Called for every element pushed on the main stack
+    fn on_push(&mut self, _state: &ExecutionState, _bytes: &[u8]) {}
+
+    // Called after OP_IF, OP_NOTIF, OP_ELSE or OP_ENDIF updated the condition stack
+    fn on_branch(&mut self, _state: &ExecutionState, _op: Opcode) {}
+
+    // Called when the execution stops on an error, or when it reaches the end of the script
+    // with a failing verdict (EVAL_FALSE, CLEANSTACK)
+    fn on_error(&mut self, _state: &ExecutionState, _error: &ExecutionError) {}
+}
+
+// Observer that ignores everything, used by default
+pub struct NoopObserver;
+
+impl ExecutionObserver for NoopObserver {}
+
+// Prints the remaining script and the stacks after every step
+pub struct TerminalObserver;
+
+impl ExecutionObserver for TerminalObserver {
+    fn before_step(&mut self, state: &ExecutionState, _item: &ScriptItem) {
+        if state.steps() == 0 {
+            print_state(state.stack(), &state.remaining_script(), 0);
+        }
+    }
+
+    fn after_step(&mut self, state: &ExecutionState, _item: &ScriptItem) {
+        print_state(state.stack(), &state.remaining_script(), state.steps());
+    }
+}
+
+// TerminalObserver followed by the source line of every executed instruction for assembled scripts
+pub struct SourceObserver {
+    terminal: TerminalObserver,
+    source_map: SourceMap,
+    // Offset of the instruction being executed
+    pc: usize
+}
+
+impl SourceObserver {
+    pub fn new(source_map: SourceMap) -> SourceObserver {
+        SourceObserver { terminal: TerminalObserver, source_map, pc: 0 }
+    }
+}
+
+impl ExecutionObserver for SourceObserver {
+    fn before_step(&mut self, state: &ExecutionState, item: &ScriptItem) {
+        self.pc = state.pc();
+        self.terminal.before_step(state, item);
+    }
+
+    fn after_step(&mut self, state: &ExecutionState, item: &ScriptItem) {
+        self.terminal.after_step(state, item);
+        if let Some(location) = self.source_map.location_of(self.pc) {
+            println!("{} {}", "Executed".bold(), location);
+        }
+    }
+
+    fn on_error(&mut self, state: &ExecutionState, error: &ExecutionError) {
+        self.terminal.on_error(state, error);
+        if let Some(location) = self.source_map.location_of(error.offset) {
+            println!("{} {}", "Failed at".bold().red(), location);
+        }
+    }
+}
+
+// State of the execution after one step
+pub struct TraceStep {
+    pub step: usize,
+    // Byte offset of the instruction
+    pub pc: usize,
+    pub opcode: Opcode,
+    // false if the instruction was skipped by a false branch
+    pub executed: bool,
+    pub main_stack: Vec<Vec<u8>>,
+    pub alt_stack: Vec<Vec<u8>>,
+    pub condition_stack: Vec<bool>,
+    pub op_count: usize
+}
+
+// Records a snapshot of the state after every step
+// The instruction the execution fails on is recorded with the stacks it was given
+#[derive(Default)]
+pub struct TraceRecorder {
+    pub steps: Vec<TraceStep>,
+    pub error: Option<ExecutionError>,
+    // Step being executed, with the state before it
+    current: Option<TraceStep>
+}
+
+impl TraceRecorder {
+    pub fn new() -> TraceRecorder {
+        TraceRecorder::default()
+    }
+
+    // Whole trace as a single JSON document: {"steps": [...], "error": null | {...}}
+    pub fn to_json(&self) -> String {
+        let steps: Vec<Value> = self.steps.iter().map(TraceStep::to_json).collect();
+        json!({
+            "steps": steps,
+            "error": self.error.as_ref().map(error_to_json)
+        }).to_string()
+    }
+
+    // One JSON object per line for each step, followed by an {"error": {...}} line if the
+    // execution failed
+    pub fn to_ndjson(&self) -> String {
+        let mut out = String::new();
+        for step in &self.steps {
+            out.push_str(&step.to_json().to_string());
+            out.push('\n');
+        }
+        if let Some(error) = &self.error {
+            out.push_str(&json!({"error": error_to_json(error)}).to_string());
+            out.push('\n');
+        }
+        out
+    }
+
+    pub fn export(&self, format: TraceFormat) -> String {
+        match format {
+            TraceFormat::Json => self.to_json(),
+            TraceFormat::Ndjson => self.to_ndjson()
+        }
+    }
+}
+
+impl TraceStep {
+    // Stack elements are hex encoded, bottom of the stack first
+    pub fn to_json(&self) -> Value {
+        let hex_stack = |stack: &Vec<Vec<u8>>| stack.iter().map(hex::encode).collect::<Vec<_>>();
+        json!({
+            "step": self.step,
+            "pc": self.pc,
+            "opcode": self.opcode.to_string(),
+            "executed": self.executed,
+            "main_stack": hex_stack(&self.main_stack),
+            "alt_stack": hex_stack(&self.alt_stack),
+            "condition_stack": self.condition_stack,
+            "op_count": self.op_count
+        })
+    }
+}
+
+fn error_to_json(error: &ExecutionError) -> Value {
+    json!({
+        "name": error.error.name(),
+        "message": error.error.to_string(),
+        "opcode": error.opcode.map(|op| op.to_string()),
+        "offset": error.offset
+    })
+}
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum TraceFormat {
+    Json,
+    Ndjson
+}
+
+impl FromStr for TraceFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "json" => Ok(TraceFormat::Json),
+            "ndjson" | "jsonl" => Ok(TraceFormat::Ndjson),
+            _ => Err(format!("Unknown trace format: {}", s))
+        }
+    }
+}
+
+impl ExecutionObserver for TraceRecorder {
+    fn before_step(&mut self, state: &ExecutionState, item: &ScriptItem) {
+        self.current = Some(TraceStep {
+            step: state.steps() + 1,
+            pc: state.pc(),
+            opcode: Opcode::from(state.script()[state.pc()]),
+            executed: state.will_execute(item),
+            main_stack: state.main_stack().clone(),
+            alt_stack: state.alt_stack().clone(),
+            condition_stack: state.condition_stack().clone(),
+            op_count: state.op_count()
+        });
+    }
+
+    fn after_step(&mut self, state: &ExecutionState, _item: &ScriptItem) {
+        let step = self.current.take().unwrap();
+        self.steps.push(TraceStep {
+            step: state.steps(),
+            main_stack: state.main_stack().clone(),
+            alt_stack: state.alt_stack().clone(),
+            condition_stack: state.condition_stack().clone(),
+            op_count: state.op_count(),
+            ..step
+        });
+    }
+
+    fn on_error(&mut self, _state: &ExecutionState, error: &ExecutionError) {
+        // Errors on the final verdict or on unparsable bytes are not tied to a step
+        if let Some(step) = self.current.take() {
+            self.steps.push(TraceStep { executed: true, ..step });
+        }
+        self.error = Some(*error);
+    }
+}
+
+fn print_stack(stack: &Vec<Vec<u8>>, title: &str, min_width: usize, max_width: usize) {
+    let mut hex_stack = {
+        let mut vec = Vec::new();
+        for elem in stack {
+            vec.push(format!("0x{}", hex::encode(elem)))
+        }
+        if stack.is_empty() {
+            vec.push(String::from(""));
+        }
+        vec
+    };
+    hex_stack.reverse();
+
+    let mut table_builder = Builder::default().set_columns([title]);
+    for item in &hex_stack {
+        table_builder = table_builder.add_record([item]);
+    }
+
+    let table = table_builder.build().with(Style::modern())
+        .with(MaxWidth::wrapping(max_width))
+        .with(MinWidth::new(min_width))
+        .with(Modify::new(Rows::new(1..))
+            .with(Alignment::left()));
+
+    print!("{}", &table.to_string());
+}
+
+pub(crate) fn print_state(stack: &Stack, script: &Script, step_nb: usize) {
+    let mut display_max_width = MAX_SCRIPT_DISPLAY_WIDTH;
+    let display_min_width = MIN_SCRIPT_DISPLAY_WIDTH;
+
+    if let Some((w, _)) = term_size::dimensions() {
+        if w > MIN_SCRIPT_DISPLAY_WIDTH && w < MAX_SCRIPT_DISPLAY_WIDTH {
+            display_max_width = w;
+        }
+    }
+
+    println!("\n\n");
+
+    // Print remaining script instructions
+    if !script.is_empty() {
+        println!("{} (Step {})\n", "Script".bold(), step_nb);
+
+        let colors = ["green", "yellow", "magenta", "cyan", "white"];
+        let mut line_len = 0;
+        for i in 0..script.len() {
+            let mut item_str = format!("{:?}", script[i]);
+            if line_len + item_str.len() > display_max_width && line_len > 0 {
+                println!("\n");
+                line_len = 0;
+            }
+
+            let color = String::from("bright ") + match script[i] {
+                ScriptItem::ByteArray(..) => "blue",
+                ScriptItem::Opcode(op) => {
+                    colors[(op.code % colors.len() as u8) as usize]
+                }
+            };
+
+            if item_str.len() > display_max_width {
+                while item_str.len() > display_max_width {
+                    let sub_str = &item_str[..display_max_width];
+                    println!("{}", sub_str.bold().black().on_color(color.as_str()));
+                    item_str = String::from(&item_str[display_max_width..])
+                }
+                println!("{}\n", item_str.bold().black().on_color(color));
+                line_len = 0;
+            } else {
+                print!("{}", item_str.bold().black().on_color(color));
+                line_len += item_str.len();
+
+                if i != script.len() - 1 {
+                    print!(" ");
+                    line_len += 1;
+                }
+            }
+        }
+        println!();
+    } else {
+        println!("{}", "Final state".bold());
+    }
+
+    print_stack(&stack.main, "Main Stack", display_min_width, display_max_width);
+
+    if !&stack.alt.is_empty() {
+        print_stack(&stack.alt, "Alt Stack", display_min_width, display_max_width);
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::interpret::Interpreter;
+    use crate::script::ScriptError;
+
+    fn trace(script: &str) -> TraceRecorder {
+        let mut interpreter = Interpreter::new(&hex::decode(script).unwrap()).with_observer(TraceRecorder::new());
+        interpreter.run();
+        std::mem::take(interpreter.observer_mut())
+    }
+
+    // Every callback as a line
+    #[derive(Default)]
+    struct EventLog(Vec<String>);
+
+    impl ExecutionObserver for EventLog {
+        fn before_step(&mut self, state: &ExecutionState, item: &ScriptItem) {
+            self.0.push(format!("before {:?} at {}", item, state.pc()));
+        }
+
+        fn after_step(&mut self, state: &ExecutionState, item: &ScriptItem) {
+            self.0.push(format!("after {:?} step {}", item, state.steps()));
+        }
+
+        fn on_push(&mut self, _state: &ExecutionState, bytes: &[u8]) {
+            self.0.push(format!("push {}", hex::encode(bytes)));
+        }
+
+        fn on_branch(&mut self, state: &ExecutionState, op: Opcode) {
+            self.0.push(format!("branch {} {:?}", op, state.condition_stack()));
+        }
+
+        fn on_error(&mut self, _state: &ExecutionState, error: &ExecutionError) {
+            self.0.push(format!("error {} at {}", error.error.name(), error.offset));
+        }
+    }
+
+    #[test]
+    fn callbacks_follow_the_execution() {
+        // OP_1 OP_IF 0x0102 OP_ENDIF OP_0
+        let mut interpreter = Interpreter::new(&hex::decode("51630201026800").unwrap()).with_observer(EventLog::default());
+        interpreter.run();
+        assert_eq!(interpreter.into_observer().0, vec![
+            "before OP_1 at 0", "push 01", "after OP_1 step 1",
+            "before OP_IF at 1", "branch OP_IF [true]", "after OP_IF step 2",
+            "before 0x0102 at 2", "push 0102", "after 0x0102 step 3",
+            "before OP_ENDIF at 5", "branch OP_ENDIF []", "after OP_ENDIF step 4",
+            "before OP_0 at 6", "push ", "after OP_0 step 5",
+            "error EVAL_FALSE at 7"
+        ]);
+    }
+
+    #[test]
+    fn failing_step_has_no_after_step() {
+        let mut interpreter = Interpreter::new(&hex::decode("6a").unwrap()).with_observer(EventLog::default());
+        interpreter.run();
+        assert_eq!(interpreter.into_observer().0, vec!["before OP_RETURN at 0", "error OP_RETURN at 0"]);
+    }
+
+    #[test]
+    fn steps_and_skipped_branches() {
+        // OP_0 OP_IF OP_2 OP_ENDIF OP_1
+        let recorder = trace("0063526851");
+        let steps: Vec<(usize, usize, bool)> = recorder.steps.iter().map(|s| (s.step, s.pc, s.executed)).collect();
+        assert_eq!(steps, vec![(1, 0, true), (2, 1, true), (3, 2, false), (4, 3, true), (5, 4, true)]);
+        assert_eq!(recorder.steps[2].condition_stack, vec![false]);
+        assert_eq!(recorder.steps[4].main_stack, vec![vec![1]]);
+        assert!(recorder.error.is_none());
+    }
+
+    #[test]
+    fn failing_instruction_is_recorded_with_its_input_stacks() {
+        // OP_1 OP_2 OP_NUMEQUALVERIFY
+        let recorder = trace("51529d");
+        assert_eq!(recorder.steps.len(), 3);
+        let last = &recorder.steps[2];
+        assert_eq!((last.step, last.pc, last.opcode.to_string(), last.executed), (3, 2, String::from("OP_NUMEQUALVERIFY"), true));
+        assert_eq!(last.main_stack, vec![vec![1], vec![2]]);
+        let error = recorder.error.unwrap();
+        assert_eq!((error.error, error.offset), (ScriptError::NumEqualVerify, 2));
+    }
+
+    #[test]
+    fn verdict_errors_add_no_step() {
+        let recorder = trace("5100");
+        assert_eq!(recorder.steps.len(), 2);
+        assert_eq!(recorder.error.unwrap().error, ScriptError::EvalFalse);
+    }
+
+    #[test]
+    fn exports() {
+        let recorder = trace("51529d");
+        let json: Value = serde_json::from_str(&recorder.export(TraceFormat::Json)).unwrap();
+        assert_eq!(json["steps"].as_array().unwrap().len(), 3);
+        assert_eq!(json["steps"][2]["main_stack"], json!(["01", "02"]));
+        assert_eq!(json["error"]["name"], "NUMEQUALVERIFY");
+
+        let ndjson = recorder.export(TraceFormat::Ndjson);
+        let lines: Vec<Value> = ndjson.lines().map(|l| serde_json::from_str(l).unwrap()).collect();
+        assert_eq!(lines.len(), 4);
+        assert_eq!(lines[3]["error"]["offset"], 2);
+
+        assert_eq!("JSONL".parse::<TraceFormat>(), Ok(TraceFormat::Ndjson));
+        assert!("xml".parse::<TraceFormat>().is_err());
+    }
+
+    #[test]
+    fn failing_instruction_in_the_exports() {
+        // OP_1 OP_1 OP_IF OP_2 OP_ENDIF OP_NUMEQUALVERIFY
+        let recorder = trace("51516352689d");
+        let json: Value = serde_json::from_str(&recorder.export(TraceFormat::Json)).unwrap();
+        // The failing step holds the state OP_NUMEQUALVERIFY was given, before it is counted
+        assert_eq!(json["steps"][5], json!({
+            "step": 6, "pc": 5, "opcode": "OP_NUMEQUALVERIFY", "executed": true,
+            "main_stack": ["01", "02"], "alt_stack": [], "condition_stack": [], "op_count": 2
+        }));
+        assert_eq!(json["error"], json!({
+            "name": "NUMEQUALVERIFY", "message": "Script failed an OP_NUMEQUALVERIFY operation",
+            "opcode": "OP_NUMEQUALVERIFY", "offset": 5
+        }));
+
+        // The same steps one per line, then the error
+        let lines: Vec<Value> = recorder.export(TraceFormat::Ndjson).lines().map(|l| serde_json::from_str(l).unwrap()).collect();
+        assert_eq!(lines.len(), 7);
+        assert_eq!(lines[..6], json["steps"].as_array().unwrap()[..]);
+        assert_eq!(lines[6], json!({"error": json["error"]}));
+        assert_eq!(lines[3]["condition_stack"], json!([true]));
+    }
+
+    #[test]
+    fn successful_exports() {
+        // OP_0 OP_IF OP_2 OP_ENDIF OP_1
+        let recorder = trace("0063526851");
+        let json: Value = serde_json::from_str(&recorder.export(TraceFormat::Json)).unwrap();
+        assert_eq!(json["error"], Value::Null);
+        assert_eq!(json["steps"][2]["executed"], false);
+        assert_eq!(json["steps"][2]["opcode"], "OP_2");
+
+        let ndjson = recorder.export(TraceFormat::Ndjson);
+        assert_eq!(ndjson.lines().count(), 5);
+        assert!(ndjson.ends_with('\n') && !ndjson.contains("error"));
+    }
+}
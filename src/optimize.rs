@@ -0,0 +1,436 @@
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+
+use crate::analyze::analyze;
+use crate::interpret::Interpreter;
+use crate::opcodes::*;
+use crate::parse::parse_script;
+use crate::script::*;
+
+// Opcodes followed by OP_VERIFY that have a VERIFY variant
+const VERIFY_FUSIONS: [(Opcode, Opcode); 4] = [
+    (OP_EQUAL, OP_EQUALVERIFY),
+    (OP_NUMEQUAL, OP_NUMEQUALVERIFY),
+    (OP_CHECKSIG, OP_CHECKSIGVERIFY),
+    (OP_CHECKMULTISIG, OP_CHECKMULTISIGVERIFY)
+];
+
+// Binary opcodes giving the same result whatever the order of their operands
+const COMMUTATIVE: [Opcode; 10] = [OP_EQUAL, OP_EQUALVERIFY, OP_NUMEQUAL, OP_NUMEQUALVERIFY, OP_NUMNOTEQUAL,
+    OP_ADD, OP_BOOLAND, OP_BOOLOR, OP_MIN, OP_MAX];
+
+#[derive(Debug, Clone)]
+pub struct Optimization {
+    pub script: Script,
+    // Description of every rewrite, in the order they were applied
+    pub rewrites: Vec<String>,
+    pub original_size: usize,
+    pub optimized_size: usize
+}
+
+impl Optimization {
+    pub fn bytes_saved(&self) -> usize {
+        self.original_size - self.optimized_size
+    }
+}
+
+// Apply peephole rewrites until none matches
+pub fn optimize(script: &Script) -> Optimization {
+    rewrite(script, serialize(script).len())
+}
+
+// Same on a serialized script, whose size is counted as given, non-minimal pushes included
+pub fn optimize_bytes(bytes: &[u8]) -> Result<Optimization, ScriptError> {
+    Ok(rewrite(&parse_script(bytes)?, bytes.len()))
+}
+
+fn rewrite(script: &Script, original_size: usize) -> Optimization {
+    let mut items = script.clone();
+    let mut rewrites = Vec::new();
+    // Rewrites remove bytes and opcodes, which could make a script failing on the script size or
+    // op count limits pass: the first ones are left as they are, the second ones only get small_pushes
+    let too_big = original_size > MAX_SCRIPT_SIZE;
+    let too_many_ops = max_op_count(script) > MAX_OPS_PER_SCRIPT;
+    loop {
+        if too_big {
+            break
+        }
+        let count = rewrites.len();
+        small_pushes(&mut items, &mut rewrites);
+        if too_many_ops {
+            break
+        }
+        remove_nops(&mut items, &mut rewrites);
+        fuse_verify(&mut items, &mut rewrites);
+        remove_dropped_pushes(&mut items, &mut rewrites);
+        remove_dup_drop(&mut items, &mut rewrites);
+        commutative_swaps(&mut items, &mut rewrites);
+        fuse_overs(&mut items, &mut rewrites);
+        constant_conditions(&mut items, &mut rewrites);
+        if rewrites.len() == count {
+            break
+        }
+    }
+
+    Optimization {
+        original_size,
+        optimized_size: serialize(&items).len(),
+        script: items,
+        rewrites
+    }
+}
+
+// Opcodes counted toward MAX_OPS_PER_SCRIPT if every branch was executed, with 20 keys
+// for each OP_CHECKMULTISIG
+fn max_op_count(items: &Script) -> usize {
+    items.iter().map(|item| match item {
+        ScriptItem::Opcode(OP_CHECKMULTISIG | OP_CHECKMULTISIGVERIFY) => 21,
+        ScriptItem::Opcode(op) if op.counts_toward_op_limit() => 1,
+        _ => 0
+    }).sum()
+}
+
+// Pushes above MAX_SCRIPT_ELEMENT_SIZE fail the script even when they are not executed
+fn oversized_push(item: &ScriptItem) -> bool {
+    matches!(item, ScriptItem::ByteArray(data) if data.len() > MAX_SCRIPT_ELEMENT_SIZE)
+}
+
+// Value pushed by a push item
+fn constant(item: &ScriptItem) -> Option<Vec<u8>> {
+    match item {
+        ScriptItem::ByteArray(data) => Some(data.clone()),
+        ScriptItem::Opcode(OP_0) => Some(Vec::new()),
+        ScriptItem::Opcode(OP_1NEGATE) => Some(to_script_nb(-1)),
+        ScriptItem::Opcode(op) if (OP_1.code..=OP_16.code).contains(&op.code) => {
+            Some(to_script_nb((op.code - OP_1.code + 1) as i64))
+        }
+        _ => None
+    }
+}
+
+// Whether the item always leaves at least one item on top of the stack when it succeeds
+fn leaves_item(item: &ScriptItem) -> bool {
+    match item {
+        ScriptItem::ByteArray(_) => true,
+        ScriptItem::Opcode(op) => op.category() != Category::FlowControl
+            && op.stack_effect().is_some_and(|(_, outputs)| outputs > 0)
+    }
+}
+
+// <0x05> → OP_5, <> → OP_0, <0x81> → OP_1NEGATE
+fn small_pushes(items: &mut Script, rewrites: &mut Vec<String>) {
+    for item in items.iter_mut() {
+        if let ScriptItem::ByteArray(data) = item {
+            if let [code] = minimal_push(data)[..] {
+                let op = Opcode::from(code);
+                rewrites.push(format!("{} → {}", item, op));
+                *item = ScriptItem::Opcode(op);
+            }
+        }
+    }
+}
+
+fn remove_nops(items: &mut Script, rewrites: &mut Vec<String>) {
+    let before = items.len();
+    items.retain(|item| *item != ScriptItem::Opcode(OP_NOP));
+    for _ in items.len()..before {
+        rewrites.push(String::from("removed OP_NOP"));
+    }
+}
+
+// OP_EQUAL OP_VERIFY → OP_EQUALVERIFY
+fn fuse_verify(items: &mut Script, rewrites: &mut Vec<String>) {
+    let mut i = 0;
+    while i + 1 < items.len() {
+        let fused = VERIFY_FUSIONS.iter().find(|(op, _)| items[i] == ScriptItem::Opcode(*op));
+        if let (Some((op, verify)), ScriptItem::Opcode(OP_VERIFY)) = (fused, &items[i + 1]) {
+            rewrites.push(format!("{} OP_VERIFY → {}", op, verify));
+            items.splice(i..i + 2, [ScriptItem::Opcode(*verify)]);
+        }
+        i += 1;
+    }
+}
+
+// <x> OP_DROP → nothing
+fn remove_dropped_pushes(items: &mut Script, rewrites: &mut Vec<String>) {
+    let mut i = 0;
+    while i + 1 < items.len() {
+        if constant(&items[i]).is_some() && !oversized_push(&items[i]) && items[i + 1] == ScriptItem::Opcode(OP_DROP) {
+            rewrites.push(format!("removed {} OP_DROP", items[i]));
+            items.drain(i..i + 2);
+        } else {
+            i += 1;
+        }
+    }
+}
+
+// OP_DUP OP_DROP → nothing, only when the previous item leaves something to duplicate
+// so that removing them can't turn a failing execution into a successful one
+fn remove_dup_drop(items: &mut Script, rewrites: &mut Vec<String>) {
+    let mut i = 1;
+    while i + 1 < items.len() {
+        if items[i] == ScriptItem::Opcode(OP_DUP) && items[i + 1] == ScriptItem::Opcode(OP_DROP) && leaves_item(&items[i - 1]) {
+            rewrites.push(String::from("removed OP_DUP OP_DROP"));
+            items.drain(i..i + 2);
+        } else {
+            i += 1;
+        }
+    }
+}
+
+// OP_SWAP OP_ADD → OP_ADD, both need two items so they fail on the same stacks
+fn commutative_swaps(items: &mut Script, rewrites: &mut Vec<String>) {
+    let mut i = 0;
+    while i + 1 < items.len() {
+        match (&items[i], &items[i + 1]) {
+            (ScriptItem::Opcode(OP_SWAP), ScriptItem::Opcode(op)) if COMMUTATIVE.contains(op) => {
+                rewrites.push(format!("OP_SWAP {} → {}", op, op));
+                items.remove(i);
+            }
+            _ => i += 1
+        }
+    }
+}
+
+// OP_OVER OP_OVER → OP_2DUP
+fn fuse_overs(items: &mut Script, rewrites: &mut Vec<String>) {
+    let mut i = 0;
+    while i + 1 < items.len() {
+        if items[i] == ScriptItem::Opcode(OP_OVER) && items[i + 1] == ScriptItem::Opcode(OP_OVER) {
+            rewrites.push(String::from("OP_OVER OP_OVER → OP_2DUP"));
+            items.splice(i..i + 2, [ScriptItem::Opcode(OP_2DUP)]);
+        }
+        i += 1;
+    }
+}
+
+// <c> OP_IF A OP_ELSE B OP_ENDIF → A or B depending on c
+fn constant_conditions(items: &mut Script, rewrites: &mut Vec<String>) {
+    let mut i = 1;
+    while i < items.len() {
+        let condition = match (&items[i], constant(&items[i - 1])) {
+            (ScriptItem::Opcode(OP_IF), Some(c)) => as_bool(&c),
+            (ScriptItem::Opcode(OP_NOTIF), Some(c)) => !as_bool(&c),
+            _ => {
+                i += 1;
+                continue
+            }
+        };
+
+        // Positions of the top level OP_ELSEs and of the matching OP_ENDIF
+        let mut depth = 0;
+        let mut separators = vec![i];
+        for (j, item) in items.iter().enumerate().skip(i + 1) {
+            match item {
+                ScriptItem::Opcode(OP_IF) | ScriptItem::Opcode(OP_NOTIF) => depth += 1,
+                ScriptItem::Opcode(OP_ELSE) if depth == 0 => separators.push(j),
+                ScriptItem::Opcode(OP_ENDIF) if depth == 0 => {
+                    separators.push(j);
+                    break
+                }
+                ScriptItem::Opcode(OP_ENDIF) => depth -= 1,
+                _ => ()
+            }
+        }
+        let end = *separators.last().unwrap();
+        if items.get(end) != Some(&ScriptItem::Opcode(OP_ENDIF)) {
+            return
+        }
+
+        // Every OP_ELSE flips the branch being executed
+        let mut kept = Vec::new();
+        let mut dropped = Vec::new();
+        for (n, bounds) in separators.windows(2).enumerate() {
+            let segment = &items[bounds[0] + 1..bounds[1]];
+            if condition == (n % 2 == 0) {
+                kept.extend_from_slice(segment);
+            } else {
+                dropped.extend_from_slice(segment);
+            }
+        }
+        // Disabled opcodes and oversized pushes fail the script even in a branch that is not executed
+        let always_fails = oversized_push(&items[i - 1]) || dropped.iter().any(|item| match item {
+            ScriptItem::Opcode(op) => op.is_disabled() || op.behaviour(ScriptContext::Legacy) == Behaviour::AlwaysInvalid,
+            ScriptItem::ByteArray(_) => oversized_push(item)
+        });
+        if always_fails {
+            i += 1;
+            continue
+        }
+
+        rewrites.push(format!("{} {} with a constant condition → {} branch", items[i - 1], items[i],
+            if condition { "first" } else { "second" }));
+        items.splice(i - 1..=end, kept);
+    }
+}
+
+// Outcome of running two scripts on the same initial stacks
+#[derive(Debug, Clone)]
+pub struct Equivalence {
+    pub cases: usize,
+    // Cases where both scripts succeeded
+    pub successes: usize,
+    // Initial stacks giving a different verdict or final stack
+    pub mismatches: Vec<Vec<Vec<u8>>>
+}
+
+impl Equivalence {
+    pub fn holds(&self) -> bool {
+        self.mismatches.is_empty()
+    }
+}
+
+// Random initial stacks for the given scripts, built from their constants and usual values
+// so that comparisons in the scripts can succeed. The same seed gives the same corpus.
+pub fn test_corpus(scripts: &[&Script], cases: usize, seed: u64) -> Vec<Vec<Vec<u8>>> {
+    let mut rng = StdRng::seed_from_u64(seed);
+    let mut pool: Vec<Vec<u8>> = vec![vec![], vec![0x00], vec![0x01], vec![0x02], vec![0x10], vec![0x81], vec![0x80]];
+    for script in scripts {
+        pool.extend(script.iter().filter_map(constant));
+    }
+    for len in [1, 2, 4, 20, 32, 33] {
+        pool.push((0..len).map(|_| rng.gen()).collect());
+    }
+
+    let depth = scripts.iter()
+        .filter_map(|script| analyze(script, None).paths.iter().map(|p| p.min_initial_items).max())
+        .max()
+        .unwrap_or(0)
+        .min(MAX_STACK_SIZE - 2) + 2;
+
+    (0..cases).map(|_| {
+        let size = rng.gen_range(0..=depth);
+        (0..size).map(|_| pool[rng.gen_range(0..pool.len())].clone()).collect()
+    }).collect()
+}
+
+fn run_with_stack(script: &[u8], initial: &[Vec<u8>]) -> (bool, Vec<Vec<u8>>) {
+    let mut interpreter = Interpreter::new(script);
+    interpreter.stack_mut().main = initial.to_vec();
+    let result = interpreter.run();
+    (result.success, result.main_stack)
+}
+
+// Run both scripts on every initial stack of the corpus and compare the verdicts and final stacks
+pub fn check_equivalence(original: &Script, optimized: &Script, corpus: &[Vec<Vec<u8>>]) -> Equivalence {
+    let (original, optimized) = (serialize(original), serialize(optimized));
+    let mut equivalence = Equivalence { cases: corpus.len(), successes: 0, mismatches: Vec::new() };
+    for initial in corpus {
+        let expected = run_with_stack(&original, initial);
+        let actual = run_with_stack(&optimized, initial);
+        if expected.0 != actual.0 || (expected.0 && expected.1 != actual.1) {
+            equivalence.mismatches.push(initial.clone());
+        } else if expected.0 {
+            equivalence.successes += 1;
+        }
+    }
+    equivalence
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::asm::parse_asm_script;
+
+    // Optimize an ASM script, check the result against the expected ASM and against the original
+    // on a corpus of stacks
+    fn check(original: &str, expected: &str) -> Optimization {
+        let original = parse_asm_script(original).unwrap();
+        let optimization = optimize(&original);
+        assert_eq!(optimization.script, parse_asm_script(expected).unwrap());
+        let corpus = test_corpus(&[&original, &optimization.script], 500, 7);
+        let equivalence = check_equivalence(&original, &optimization.script, &corpus);
+        assert!(equivalence.holds(), "{:?} differs on {:?}", optimization.script, equivalence.mismatches);
+        optimization
+    }
+
+    #[test]
+    fn rewrites() {
+        assert_eq!(check("0x05 0x 0x81", "OP_5 OP_0 OP_1NEGATE").bytes_saved(), 2);
+        check("OP_NOP OP_1 OP_NOP", "OP_1");
+        check("OP_EQUAL OP_VERIFY OP_CHECKSIG OP_VERIFY", "OP_EQUALVERIFY OP_CHECKSIGVERIFY");
+        check("OP_DUP 0x0102 OP_DROP", "OP_DUP");
+        check("OP_SIZE OP_DUP OP_DROP", "OP_SIZE");
+        check("OP_SWAP OP_ADD OP_SWAP OP_SUB", "OP_ADD OP_SWAP OP_SUB");
+        check("OP_OVER OP_OVER OP_EQUAL", "OP_2DUP OP_EQUAL");
+        check("OP_1 OP_IF OP_DUP OP_ELSE OP_DROP OP_ENDIF", "OP_DUP");
+        check("OP_0 OP_NOTIF OP_SHA256 OP_ENDIF", "OP_SHA256");
+        check("OP_0 OP_IF OP_DUP OP_ELSE OP_1 OP_IF OP_SIZE OP_ENDIF OP_ENDIF", "OP_SIZE");
+    }
+
+    #[test]
+    fn rewrites_that_would_change_the_verdict_are_left_out() {
+        // Nothing to duplicate at the start of the script
+        check("OP_DUP OP_DROP OP_1", "OP_DUP OP_DROP OP_1");
+        // A disabled opcode fails the script even in a branch that isn't executed
+        check("OP_1 OP_IF OP_2 OP_ELSE OP_CAT OP_ENDIF", "OP_1 OP_IF OP_2 OP_ELSE OP_CAT OP_ENDIF");
+        // Unclosed conditional
+        check("OP_1 OP_IF OP_2", "OP_1 OP_IF OP_2");
+        check("OP_SWAP OP_SUB", "OP_SWAP OP_SUB");
+    }
+
+    #[test]
+    fn limits_are_kept() {
+        // Pushes above 520 bytes fail the script, dropped or in a branch that isn't executed
+        let big = format!("0x{}", "ab".repeat(521));
+        let failing = [
+            (format!("{} OP_DROP OP_1", big), "OP_1".to_string()),
+            (format!("OP_1 OP_IF OP_2 OP_ELSE {} OP_ENDIF", big), "OP_2".to_string()),
+            (format!("{} OP_IF OP_2 OP_ENDIF", big), "OP_2".to_string()),
+            // 202 opcodes counted, even those that aren't executed
+            (format!("OP_0 OP_IF OP_NOP OP_ENDIF {} OP_1", "OP_NOP ".repeat(199)), "OP_1".to_string())
+        ];
+        for (original, rewritten) in failing {
+            check(&original, &original);
+            // The rewrite would have been caught by the corpus
+            let (original, rewritten) = (parse_asm_script(&original).unwrap(), parse_asm_script(&rewritten).unwrap());
+            let corpus = test_corpus(&[&original], 100, 5);
+            assert_eq!(check_equivalence(&original, &rewritten, &corpus).mismatches.len(), 100);
+        }
+        // Small pushes don't change the op count
+        check(&format!("0x05 {}", "OP_NOP ".repeat(202)), &format!("OP_5 {}", "OP_NOP ".repeat(202)));
+    }
+
+    #[test]
+    fn sizes_are_counted_on_the_input() {
+        // OP_PUSHDATA1 0x05 is re-encoded as OP_5
+        let optimization = optimize_bytes(&hex::decode("4c0105").unwrap()).unwrap();
+        assert_eq!((optimization.original_size, optimization.optimized_size), (3, 1));
+        assert_eq!(optimization.rewrites, vec!["0x05 → OP_5"]);
+        assert_eq!(optimize(&parse_asm_script("OP_1 OP_IF OP_DUP OP_ENDIF").unwrap()).rewrites,
+            vec!["OP_1 OP_IF with a constant condition → first branch"]);
+    }
+
+    #[test]
+    fn pick_and_roll_scripts_on_the_whole_corpus() {
+        let scripts = [
+            "OP_PICK", "OP_ROLL", "OP_1NEGATE OP_PICK", "OP_1NEGATE OP_ROLL", "OP_0 OP_PICK 0x05 OP_DROP",
+            "OP_1 OP_ROLL OP_DUP OP_DROP", "OP_DUP OP_PICK OP_VERIFY", "OP_2 OP_ROLL OP_OVER OP_OVER OP_EQUAL",
+            "0x81 OP_IF OP_PICK OP_ELSE OP_ROLL OP_ENDIF"
+        ];
+        for text in scripts {
+            let original = parse_asm_script(text).unwrap();
+            let optimized = optimize(&original).script;
+            let corpus = test_corpus(&[&original], 1000, 1);
+            let equivalence = check_equivalence(&original, &optimized, &corpus);
+            assert!(equivalence.holds(), "{} differs on {:?}", text, equivalence.mismatches);
+            assert_eq!(equivalence.cases, 1000);
+        }
+    }
+
+    #[test]
+    fn mismatches_are_reported() {
+        let add = parse_asm_script("OP_ADD").unwrap();
+        let sub = parse_asm_script("OP_SUB").unwrap();
+        let corpus = test_corpus(&[&add, &sub], 200, 3);
+        let equivalence = check_equivalence(&add, &sub, &corpus);
+        assert!(!equivalence.holds());
+        assert!(equivalence.mismatches.iter().all(|stack| stack.len() >= 2));
+    }
+
+    #[test]
+    fn corpus_is_deterministic() {
+        let script = parse_asm_script("OP_3 OP_PICK 0x0102 OP_EQUAL").unwrap();
+        assert_eq!(test_corpus(&[&script], 50, 9), test_corpus(&[&script], 50, 9));
+    }
+}
@@ -1,4 +1,4 @@
-use crate::opcodes::{OPCODES, OP_PUSH_DATA_1, OP_PUSH_DATA_2, OP_PUSH_DATA_4};
+use crate::opcodes::{Opcode as op, OP_PUSH_DATA_1, OP_PUSH_DATA_2, OP_PUSH_DATA_4};
 use crate::script::{Script, ScriptError, ScriptItem};
 use crate::script::ScriptItem::{ByteArray, Opcode};
 
@@ -15,31 +15,44 @@ pub fn parse_script(bytes: &[u8]) -> Result<Script, ScriptError> {
     Ok(script)
 }
 
+// Same as parse_script, with the byte offset of every item
+pub fn parse_script_with_offsets(bytes: &[u8]) -> Result<Vec<(usize, ScriptItem)>, ScriptError> {
+    let mut items = Vec::new();
+    let mut cursor: usize = 0;
+    loop {
+        let offset = cursor;
+        match parse_one_op(bytes, &mut cursor)? {
+            Some(item) => items.push((offset, item)),
+            None => return Ok(items)
+        }
+    }
+}
+
 pub fn parse_one_op(bytes: &[u8], pc: &mut usize) -> Result<Option<ScriptItem>, ScriptError> {
     if *pc >= bytes.len() {
         return Ok(None)
     }
 
-    let opcode = OPCODES.get(&bytes[*pc]).unwrap().clone();
+    let opcode = op::from(bytes[*pc]);
     if opcode == OP_PUSH_DATA_1 || opcode == OP_PUSH_DATA_2 || opcode == OP_PUSH_DATA_4 {
         *pc += 1;
         let byte_nb = match opcode {
             OP_PUSH_DATA_1 => {
                 *pc += 1;
-                *bytes.get(*pc - 1).ok_or(ScriptError::BadOpcodeErr)? as usize
+                *bytes.get(*pc - 1).ok_or(ScriptError::BadOpcode)? as usize
             },
             OP_PUSH_DATA_2 => {
                 *pc += 2;
-                usize::from_le_bytes(bytes.get(*pc - 2..*pc).ok_or(ScriptError::BadOpcodeErr)?.try_into().unwrap())
+                u16::from_le_bytes(bytes.get(*pc - 2..*pc).ok_or(ScriptError::BadOpcode)?.try_into().unwrap()) as usize
             },
             OP_PUSH_DATA_4 => {
                 *pc += 4;
-                usize::from_le_bytes(bytes.get(*pc - 4..*pc).ok_or(ScriptError::BadOpcodeErr)?.try_into().unwrap())
+                u32::from_le_bytes(bytes.get(*pc - 4..*pc).ok_or(ScriptError::BadOpcode)?.try_into().unwrap()) as usize
             },
             _ => 0
         };
 
-        let data = bytes.get(*pc..*pc + byte_nb).ok_or(ScriptError::BadOpcodeErr)?;
+        let data = bytes.get(*pc..*pc + byte_nb).ok_or(ScriptError::BadOpcode)?;
         *pc += byte_nb;
         Ok(Some(ByteArray(Vec::from(data))))
     }
@@ -47,7 +60,7 @@ pub fn parse_one_op(bytes: &[u8], pc: &mut usize) -> Result<Option<ScriptItem>,
     else if opcode.code >= 1 && opcode.code <= 75 {
         let byte_nb = opcode.code as usize;
         *pc += 1;
-        let data = bytes.get(*pc..*pc + byte_nb).ok_or(ScriptError::BadOpcodeErr)?;
+        let data = bytes.get(*pc..*pc + byte_nb).ok_or(ScriptError::BadOpcode)?;
         *pc += byte_nb;
         Ok(Some(ByteArray(Vec::from(data))))
     } else {
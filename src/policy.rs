@@ -0,0 +1,360 @@
+use std::fmt;
+use std::str::FromStr;
+
+use crate::descriptor::split_args;
+use crate::miniscript::{parse_hash, parse_key, parse_timelock, Miniscript, Type, MAX_DEPTH};
+
+// Witness bytes of a signature and of a public key, with their length prefix
+const SIGNATURE_SIZE: f64 = 73.0;
+const KEY_SIZE: f64 = 34.0;
+const PREIMAGE_SIZE: f64 = 33.0;
+const MAX_PUBKEYS_PER_MULTISIG: usize = 20;
+
+// Spending conditions, without choosing how they are written in Script
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub enum Policy {
+    Key(Vec<u8>),
+    After(u32),
+    Older(u32),
+    Sha256(Vec<u8>),
+    Hash256(Vec<u8>),
+    Ripemd160(Vec<u8>),
+    Hash160(Vec<u8>),
+    And(Vec<Policy>),
+    // Branches with their relative probability of being used
+    Or(Vec<(u32, Policy)>),
+    Thresh(usize, Vec<Policy>)
+}
+
+impl fmt::Display for Policy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let join = |policies: Vec<String>| policies.join(",");
+        match self {
+            Policy::Key(key) => write!(f, "pk({})", hex::encode(key)),
+            Policy::After(n) => write!(f, "after({})", n),
+            Policy::Older(n) => write!(f, "older({})", n),
+            Policy::Sha256(h) => write!(f, "sha256({})", hex::encode(h)),
+            Policy::Hash256(h) => write!(f, "hash256({})", hex::encode(h)),
+            Policy::Ripemd160(h) => write!(f, "ripemd160({})", hex::encode(h)),
+            Policy::Hash160(h) => write!(f, "hash160({})", hex::encode(h)),
+            Policy::And(subs) => write!(f, "and({})", join(subs.iter().map(|s| s.to_string()).collect())),
+            Policy::Or(subs) => {
+                let subs = subs.iter().map(|(weight, sub)| match weight {
+                    1 => sub.to_string(),
+                    _ => format!("{}@{}", weight, sub)
+                });
+                write!(f, "or({})", join(subs.collect()))
+            }
+            Policy::Thresh(k, subs) => write!(f, "thresh({},{})", k, join(subs.iter().map(|s| s.to_string()).collect()))
+        }
+    }
+}
+
+// `depth` is the number of policies around this one
+fn parse(text: &str, depth: usize) -> Result<Policy, String> {
+    if depth > MAX_DEPTH {
+        return Err(format!("Policy nested more than {} levels deep", MAX_DEPTH))
+    }
+    let open = text.find('(').ok_or_else(|| format!("Unknown policy {}", text))?;
+    let call = text.strip_suffix(')').ok_or_else(|| format!("Missing ) in {}", text))?;
+    let (name, args) = (&call[..open], split_args(&call[open + 1..]));
+    let arity = |n: usize| match args.len() == n {
+        true => Ok(()),
+        false => Err(format!("{}() expects {} arguments, got {}", name, n, args.len()))
+    };
+    let subs = |args: &[&str]| -> Result<Vec<Policy>, String> {
+        if args.len() < 2 {
+            return Err(format!("{}() expects at least 2 policies", name))
+        }
+        args.iter().map(|arg| parse(arg, depth + 1)).collect()
+    };
+
+    Ok(match name {
+        "pk" => { arity(1)?; Policy::Key(parse_key(args[0])?) }
+        "after" => { arity(1)?; Policy::After(parse_timelock(args[0])?) }
+        "older" => { arity(1)?; Policy::Older(parse_timelock(args[0])?) }
+        "sha256" => { arity(1)?; Policy::Sha256(parse_hash(args[0], 32)?) }
+        "hash256" => { arity(1)?; Policy::Hash256(parse_hash(args[0], 32)?) }
+        "ripemd160" => { arity(1)?; Policy::Ripemd160(parse_hash(args[0], 20)?) }
+        "hash160" => { arity(1)?; Policy::Hash160(parse_hash(args[0], 20)?) }
+        "and" => Policy::And(subs(&args)?),
+        "or" if args.len() < 2 => return Err(String::from("or() expects at least 2 policies")),
+        "or" => {
+            Policy::Or(args.iter().map(|arg| match arg.split_once('@') {
+                Some((weight, sub)) => match weight.parse::<u32>() {
+                    Ok(weight) if weight > 0 => Ok((weight, parse(sub, depth + 1)?)),
+                    _ => Err(format!("Invalid probability {}", weight))
+                },
+                None => Ok((1, parse(arg, depth + 1)?))
+            }).collect::<Result<_, String>>()?)
+        }
+        "thresh" => {
+            let k = args.first().and_then(|k| k.parse::<usize>().ok()).ok_or_else(|| format!("Invalid threshold in {}", text))?;
+            if k < 1 || k >= args.len() {
+                return Err(format!("Threshold {} out of range for {} policies", k, args.len() - 1))
+            }
+            Policy::Thresh(k, args[1..].iter().map(|arg| parse(arg, depth + 1)).collect::<Result<_, _>>()?)
+        }
+        _ => return Err(format!("Unknown policy {}", name))
+    })
+}
+
+impl FromStr for Policy {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s: String = s.chars().filter(|c| !c.is_whitespace()).collect();
+        parse(&s, 0)
+    }
+}
+
+// Miniscript for part of a policy, with the expected witness bytes to satisfy and dissatisfy it
+#[derive(Debug, Clone)]
+struct Candidate {
+    ms: Miniscript,
+    ty: Type,
+    size: usize,
+    sat: f64,
+    dsat: Option<f64>
+}
+
+impl Candidate {
+    fn new(ms: Miniscript, sat: f64, dsat: Option<f64>) -> Option<Candidate> {
+        let ty = ms.ty();
+        let size = ms.script_size();
+        ty.is_valid().then_some(Candidate { ms, ty, size, sat, dsat })
+    }
+
+    // Script bytes plus expected witness bytes, both are paid for when spending
+    fn cost(&self) -> f64 {
+        self.size as f64 + self.sat
+    }
+}
+
+fn boxed(c: &Candidate) -> Box<Miniscript> {
+    Box::new(c.ms.clone())
+}
+
+// Every wrapper chain of up to 4 wrappers, keeping the cheapest candidate of each type
+fn wrap_and_prune(candidates: Vec<Candidate>) -> Vec<Candidate> {
+    let mut all = prune(candidates);
+    for _ in 0..4 {
+        let mut wrapped = Vec::new();
+        for c in &all {
+            let x = boxed(c);
+            wrapped.extend([
+                Candidate::new(Miniscript::Alt(x.clone()), c.sat, c.dsat),
+                Candidate::new(Miniscript::Swap(x.clone()), c.sat, c.dsat),
+                Candidate::new(Miniscript::Check(x.clone()), c.sat, c.dsat),
+                Candidate::new(Miniscript::DupIf(x.clone()), c.sat + 2.0, Some(1.0)),
+                Candidate::new(Miniscript::Verify(x.clone()), c.sat, None),
+                Candidate::new(Miniscript::NonZero(x.clone()), c.sat, Some(1.0)),
+                Candidate::new(Miniscript::ZeroNotEqual(x), c.sat, c.dsat)
+            ].into_iter().flatten());
+        }
+        all.extend(wrapped);
+        all = prune(all);
+    }
+    all
+}
+
+// Cheapest candidate of each type, the first one found on ties so compiling gives the same result every time
+fn prune(candidates: Vec<Candidate>) -> Vec<Candidate> {
+    let mut best: Vec<Candidate> = Vec::new();
+    for c in candidates {
+        match best.iter_mut().find(|other| other.ty == c.ty) {
+            Some(other) if other.cost() <= c.cost() => {}
+            Some(other) => *other = c,
+            None => best.push(c)
+        }
+    }
+    best
+}
+
+fn and_candidates(xs: &[Candidate], ys: &[Candidate]) -> Vec<Candidate> {
+    let mut found = Vec::new();
+    for (x, y) in xs.iter().flat_map(|x| ys.iter().flat_map(move |y| [(x, y), (y, x)])) {
+        let sat = x.sat + y.sat;
+        found.extend([
+            Candidate::new(Miniscript::AndV(boxed(x), boxed(y)), sat, None),
+            Candidate::new(Miniscript::AndB(boxed(x), boxed(y)), sat, x.dsat.zip(y.dsat).map(|(a, b)| a + b)),
+            Candidate::new(Miniscript::AndOr(boxed(x), boxed(y), Box::new(Miniscript::False)), sat, x.dsat)
+        ].into_iter().flatten());
+    }
+    wrap_and_prune(found)
+}
+
+// Branch x is used with probability p, z with 1 - p
+fn or_candidates(xs: &[Candidate], zs: &[Candidate], p: f64) -> Vec<Candidate> {
+    let mut found = Vec::new();
+    let pairs = xs.iter().flat_map(|x| zs.iter().flat_map(move |z| [(x, z, p), (z, x, 1.0 - p)]));
+    for (x, z, p) in pairs {
+        let q = 1.0 - p;
+        found.extend(Candidate::new(Miniscript::OrI(boxed(x), boxed(z)), p * (x.sat + 2.0) + q * (z.sat + 1.0),
+            x.dsat.zip(z.dsat).map(|(a, b)| (a + 2.0).min(b + 1.0))));
+        let Some(x_dsat) = x.dsat else { continue };
+        let dsat = z.dsat.map(|z_dsat| x_dsat + z_dsat);
+        let sat = p * x.sat + q * (x_dsat + z.sat);
+        found.extend([
+            Candidate::new(Miniscript::OrD(boxed(x), boxed(z)), sat, dsat),
+            Candidate::new(Miniscript::OrC(boxed(x), boxed(z)), sat, None)
+        ].into_iter().flatten());
+        if let Some(z_dsat) = z.dsat {
+            let sat = p * (x.sat + z_dsat) + q * (x_dsat + z.sat);
+            found.extend(Candidate::new(Miniscript::OrB(boxed(x), boxed(z)), sat, dsat));
+        }
+    }
+    wrap_and_prune(found)
+}
+
+// thresh() with the cheapest Bdu first argument and Wdu other ones, or multi() for keys only
+fn thresh_candidates(k: usize, subs: &[Policy]) -> Vec<Candidate> {
+    let mut found = Vec::new();
+    let n = subs.len();
+    let keys: Vec<Vec<u8>> = subs.iter().filter_map(|sub| match sub {
+        Policy::Key(key) => Some(key.clone()),
+        _ => None
+    }).collect();
+    if keys.len() == n && n <= MAX_PUBKEYS_PER_MULTISIG {
+        found.extend(Candidate::new(Miniscript::Multi(k, keys), 1.0 + SIGNATURE_SIZE * k as f64, Some(1.0 + k as f64)));
+    }
+
+    // Each argument is satisfied with probability k / n
+    let p = k as f64 / n as f64;
+    let expected = |c: &Candidate| c.dsat.map(|dsat| p * c.sat + (1.0 - p) * dsat);
+    let cheapest = |candidates: &[Candidate], ty: &str| candidates.iter()
+        .filter(|c| c.ty.has(ty) && c.dsat.is_some())
+        .min_by(|a, b| (expected(a).unwrap() + a.size as f64).total_cmp(&(expected(b).unwrap() + b.size as f64)))
+        .cloned();
+    let compiled: Vec<Vec<Candidate>> = subs.iter().map(compile_candidates).collect();
+    let args: Option<Vec<Candidate>> = compiled.iter().enumerate()
+        .map(|(i, candidates)| cheapest(candidates, if i == 0 { "Bdu" } else { "Wdu" }))
+        .collect();
+    if let Some(args) = args {
+        let sat = args.iter().map(|c| expected(c).unwrap()).sum();
+        let dsat = args.iter().map(|c| c.dsat.unwrap()).sum();
+        found.extend(Candidate::new(Miniscript::Thresh(k, args.into_iter().map(|c| c.ms).collect()), sat, Some(dsat)));
+    }
+
+    // All or one of the arguments are and() and or() chains
+    if k == n {
+        found.extend(compile_candidates(&Policy::And(subs.to_vec())));
+    } else if k == 1 {
+        found.extend(compile_candidates(&Policy::Or(subs.iter().map(|sub| (1, sub.clone())).collect())));
+    }
+    wrap_and_prune(found)
+}
+
+fn compile_candidates(policy: &Policy) -> Vec<Candidate> {
+    let terminal = |ms: Miniscript, sat: f64, dsat: Option<f64>| wrap_and_prune(Candidate::new(ms, sat, dsat).into_iter().collect());
+    match policy {
+        Policy::Key(key) => wrap_and_prune([
+            Candidate::new(Miniscript::PkK(key.clone()), SIGNATURE_SIZE, Some(1.0)),
+            Candidate::new(Miniscript::PkH(key.clone()), SIGNATURE_SIZE + KEY_SIZE, Some(1.0 + KEY_SIZE))
+        ].into_iter().flatten().collect()),
+        Policy::After(n) => terminal(Miniscript::After(*n), 0.0, None),
+        Policy::Older(n) => terminal(Miniscript::Older(*n), 0.0, None),
+        Policy::Sha256(h) => terminal(Miniscript::Sha256(h.clone()), PREIMAGE_SIZE, Some(PREIMAGE_SIZE)),
+        Policy::Hash256(h) => terminal(Miniscript::Hash256(h.clone()), PREIMAGE_SIZE, Some(PREIMAGE_SIZE)),
+        Policy::Ripemd160(h) => terminal(Miniscript::Ripemd160(h.clone()), PREIMAGE_SIZE, Some(PREIMAGE_SIZE)),
+        Policy::Hash160(h) => terminal(Miniscript::Hash160(h.clone()), PREIMAGE_SIZE, Some(PREIMAGE_SIZE)),
+        Policy::And(subs) => {
+            let first = compile_candidates(&subs[0]);
+            subs[1..].iter().fold(first, |xs, sub| and_candidates(&xs, &compile_candidates(sub)))
+        }
+        Policy::Or(subs) => {
+            let (weight, first) = &subs[0];
+            let first = (*weight, compile_candidates(first));
+            subs[1..].iter().fold(first, |(wx, xs), (wz, sub)| {
+                let p = wx as f64 / (wx + wz) as f64;
+                (wx + wz, or_candidates(&xs, &compile_candidates(sub), p))
+            }).1
+        }
+        Policy::Thresh(k, subs) => thresh_candidates(*k, subs)
+    }
+}
+
+impl Policy {
+    // Cheapest valid, non-malleable Miniscript for this policy, by script size plus expected witness size
+    pub fn compile(&self) -> Result<Miniscript, String> {
+        compile_candidates(self).into_iter()
+            .filter(|c| c.ty.has("Bmk") && c.ms.check_resource_limits().is_ok())
+            .min_by(|a, b| {
+                // Prefer scripts that can't be spent without a signature
+                (!a.ty.has("s")).cmp(&!b.ty.has("s")).then(a.cost().total_cmp(&b.cost()))
+            })
+            .map(|c| c.ms)
+            .ok_or_else(|| String::from("No non-malleable Miniscript within resource limits for this policy"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const A: &str = "031b84c5567b126440995d3ed5aaba0565d71e1834604819ff9c17f5e9d5dd078f";
+    const B: &str = "024d4b6cd1361032ca9bd2aeb9d900aa4d45d9ead80ac9423374c451a7254d0766";
+    const C: &str = "02531fe6068134503d2723133227c867ac8fa6c83c537e9a44c3c5bdbdcb1fe337";
+    const H: &str = "2bb80d537b1da3e38bd30361aa855686bde0eacd7162fef6a25fe97bf527a25b";
+
+    fn keys(text: &str) -> String {
+        text.replace('A', A).replace('B', B).replace('C', C).replace('H', H)
+    }
+
+    fn compiled(policy: &str) -> String {
+        let miniscript = keys(policy).parse::<Policy>().unwrap().compile().unwrap();
+        miniscript.to_string().replace(A, "A").replace(B, "B").replace(C, "C").replace(H, "H")
+    }
+
+    #[test]
+    fn compilations() {
+        for (policy, miniscript) in [
+            ("pk(A)", "pk(A)"),
+            ("and(pk(A),pk(B))", "and_v(v:pk(B),pk(A))"),
+            ("thresh(2,pk(A),pk(B),pk(C))", "multi(2,A,B,C)"),
+            ("and(pk(A),sha256(H))", "and_v(v:sha256(H),pk(A))"),
+            ("or(pk(A),pk(B))", "or_b(pk(A),s:pk(B))"),
+            // The likely branch is made cheaper to satisfy
+            ("or(99@pk(A),pk(B))", "or_d(pk(A),pkh(B))"),
+            ("or(pk(A),and(pk(B),after(100)))", "or_d(pk(A),and_v(v:pk(B),after(100)))"),
+            ("and(pk(A),or(99@pk(B),older(1000)))", "and_v(or_c(pk(B),v:older(1000)),pk(A))"),
+            ("or(pk(A),and(pk(B),pk(C)))", "or_d(pk(A),and_v(v:pk(C),pk(B)))")
+        ] {
+            assert_eq!(compiled(policy), miniscript, "{}", policy);
+            let miniscript = keys(policy).parse::<Policy>().unwrap().compile().unwrap();
+            assert_eq!(miniscript.check_sanity(), Ok(()), "{}", policy);
+        }
+        // Policies without a signature still compile, they are just not sane
+        assert_eq!(compiled("older(100)"), "older(100)");
+    }
+
+    #[test]
+    fn syntax() {
+        let policy = keys("or(9@pk(A),and(pk(B),thresh(1,older(10),after(20))))");
+        assert_eq!(policy.parse::<Policy>().unwrap().to_string(), policy);
+        assert_eq!(keys(" or( pk(A) , pk(B) ) ").parse::<Policy>().unwrap().to_string(), keys("or(pk(A),pk(B))"));
+
+        for (policy, error) in [
+            ("or(pk(A))", "or() expects at least 2 policies"),
+            ("and(pk(A))", "and() expects at least 2 policies"),
+            ("thresh(3,pk(A),pk(B))", "Threshold 3 out of range for 2 policies"),
+            ("or(0@pk(A),pk(B))", "Invalid probability 0"),
+            ("pk(A,B)", "pk() expects 1 arguments, got 2"),
+            ("older(0)", "Invalid timelock 0"),
+            ("sha256(00)", "Invalid hash 00, expected 32 hex encoded bytes"),
+            ("multi(1,A)", "Unknown policy multi"),
+            ("pk", "Unknown policy pk")
+        ] {
+            assert_eq!(keys(policy).parse::<Policy>(), Err(keys(error)), "{}", policy);
+        }
+    }
+
+    #[test]
+    fn deep_nesting_is_an_error() {
+        // Debug builds need more than the 2 MiB stack of test threads for 402 levels
+        std::thread::Builder::new().stack_size(8 << 20).spawn(|| {
+            let policy = format!("{}pk(A){}", "and(pk(A),".repeat(1000), ")".repeat(1000));
+            assert_eq!(keys(&policy).parse::<Policy>(), Err("Policy nested more than 402 levels deep".to_string()));
+        }).unwrap().join().unwrap();
+    }
+}
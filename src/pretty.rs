@@ -0,0 +1,201 @@
+use secp256k1::ecdsa::Signature;
+use secp256k1::PublicKey;
+
+use crate::opcodes::{OP_ELSE, OP_ENDIF, OP_IF, OP_NOTIF};
+use crate::parse::parse_one_op;
+use crate::script::{as_script_nb, to_script_nb, ScriptItem};
+use crate::transaction::{SIGHASH_ALL, SIGHASH_ANYONECANPAY, SIGHASH_NONE, SIGHASH_SINGLE};
+
+// Code longer than this doesn't push the annotations further right
+const MAX_CODE_WIDTH: usize = 48;
+
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub struct FormatOptions {
+    // Spaces added for every OP_IF/OP_NOTIF block
+    pub indent: usize,
+    // Start every line with the byte offset of its instruction
+    pub offsets: bool
+}
+
+impl Default for FormatOptions {
+    fn default() -> Self {
+        FormatOptions { indent: 4, offsets: false }
+    }
+}
+
+// One instruction per line, with conditional blocks indented and pushes annotated with what they
+// look like. A script that can't be parsed is printed up to the error. Conditionals that are never
+// closed and OP_ELSE/OP_ENDIF without OP_IF are flagged.
+//
+//     OP_IF
+//         0x02...                  # compressed public key
+//     OP_ELSE
+//         0x9000                   # number 144
+//         OP_CHECKSEQUENCEVERIFY
+//     OP_ENDIF
+pub fn pretty_print(script: &[u8], options: &FormatOptions) -> String {
+    let offset_width = script.len().to_string().len();
+    let mut lines = Vec::new();
+    // Lines of the open OP_IF/OP_NOTIF, the innermost last
+    let mut open = Vec::new();
+    let mut pc = 0;
+    loop {
+        let offset = pc;
+        let item = match parse_one_op(script, &mut pc) {
+            Ok(Some(item)) => item,
+            Ok(None) => break,
+            Err(e) => {
+                lines.push((offset, open.len(), format!("<{} bytes that can't be parsed: {}>", script.len() - offset, e), None));
+                break
+            }
+        };
+
+        let unbalanced = Some(String::from("unbalanced, no matching OP_IF"));
+        let (line_depth, annotation) = match &item {
+            ScriptItem::Opcode(OP_IF) | ScriptItem::Opcode(OP_NOTIF) => {
+                open.push(lines.len());
+                (open.len() - 1, None)
+            }
+            ScriptItem::Opcode(OP_ELSE) if open.is_empty() => (0, unbalanced),
+            ScriptItem::Opcode(OP_ELSE) => (open.len() - 1, None),
+            ScriptItem::Opcode(OP_ENDIF) => match open.pop() {
+                Some(_) => (open.len(), None),
+                None => (0, unbalanced)
+            },
+            ScriptItem::ByteArray(data) => (open.len(), describe_push(data)),
+            ScriptItem::Opcode(_) => (open.len(), None)
+        };
+        lines.push((offset, line_depth, format!("{:?}", item), annotation));
+    }
+    for line in open {
+        lines[line].3 = Some(String::from("unbalanced, never closed by OP_ENDIF"));
+    }
+
+    let code_width = lines.iter()
+        .filter(|(_, _, _, annotation)| annotation.is_some())
+        .map(|(_, depth, code, _)| depth * options.indent + code.len())
+        .filter(|width| *width <= MAX_CODE_WIDTH)
+        .max()
+        .unwrap_or(0);
+    let mut output = String::new();
+    for (offset, depth, code, annotation) in lines {
+        if options.offsets {
+            output += &format!("{:>width$}  ", offset, width = offset_width);
+        }
+        let code = format!("{}{}", " ".repeat(depth * options.indent), code);
+        match annotation {
+            Some(annotation) => output += &format!("{:<width$}  # {}\n", code, annotation, width = code_width),
+            None => output += &format!("{}\n", code)
+        }
+    }
+    output
+}
+
+// What pushed data looks like: a number, a signature with its sighash type, a public key,
+// a hash or some text. Several guesses are given when they all fit.
+pub fn describe_push(data: &[u8]) -> Option<String> {
+    let mut guesses = Vec::new();
+    if let Some(sighash) = signature_hash_type(data) {
+        guesses.push(format!("signature, {}", sighash));
+    }
+    match data.len() {
+        33 if PublicKey::from_slice(data).is_ok() => guesses.push(String::from("compressed public key")),
+        65 if PublicKey::from_slice(data).is_ok() => guesses.push(String::from("uncompressed public key")),
+        20 | 32 => guesses.push(format!("{}-byte hash", data.len())),
+        _ => {}
+    }
+    if let Ok(n) = as_script_nb(data) {
+        if to_script_nb(n) == data {
+            guesses.push(format!("number {}", n));
+        }
+    }
+    if !data.is_empty() && data.iter().all(|b| b.is_ascii_graphic() || *b == b' ') {
+        guesses.push(format!("\"{}\"", String::from_utf8_lossy(data)));
+    }
+    match guesses.is_empty() {
+        true => None,
+        false => Some(guesses.join(", "))
+    }
+}
+
+// Name of the sighash type of a DER signature
+fn signature_hash_type(data: &[u8]) -> Option<String> {
+    let (sighash, der) = data.split_last()?;
+    Signature::from_der(der).ok()?;
+    let base = match sighash & !SIGHASH_ANYONECANPAY {
+        SIGHASH_ALL => "SIGHASH_ALL",
+        SIGHASH_NONE => "SIGHASH_NONE",
+        SIGHASH_SINGLE => "SIGHASH_SINGLE",
+        _ => return Some(format!("unknown sighash type 0x{:02x}", sighash))
+    };
+    Some(match sighash & SIGHASH_ANYONECANPAY {
+        0 => base.to_string(),
+        _ => format!("{}|ANYONECANPAY", base)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use secp256k1::{Message, Secp256k1, SecretKey};
+
+    use super::*;
+
+    fn decode(script: &str, options: &FormatOptions) -> String {
+        pretty_print(&hex::decode(script).unwrap(), options)
+    }
+
+    #[test]
+    fn branches_are_indented() {
+        // OP_IF <144> OP_CSV OP_ELSE OP_0 OP_NOTIF OP_1 OP_ENDIF OP_ENDIF
+        assert_eq!(decode("63029000b2670064516868", &FormatOptions::default()), "\
+OP_IF
+    0x9000  # number 144
+    OP_CHECKSEQUENCEVERIFY
+OP_ELSE
+    OP_0
+    OP_NOTIF
+        OP_1
+    OP_ENDIF
+OP_ENDIF
+");
+    }
+
+    #[test]
+    fn offsets() {
+        let options = FormatOptions { indent: 2, offsets: true };
+        assert_eq!(decode("5163029000b268", &options), "0  OP_1\n1  OP_IF\n2    0x9000  # number 144\n5    OP_CHECKSEQUENCEVERIFY\n6  OP_ENDIF\n");
+    }
+
+    #[test]
+    fn unbalanced_conditionals_are_flagged() {
+        assert_eq!(decode("6351676868", &FormatOptions::default()),
+            "OP_IF\n    OP_1\nOP_ELSE\nOP_ENDIF\nOP_ENDIF  # unbalanced, no matching OP_IF\n");
+        assert_eq!(decode("63", &FormatOptions::default()), "OP_IF  # unbalanced, never closed by OP_ENDIF\n");
+        assert_eq!(decode("6751", &FormatOptions::default()), "OP_ELSE  # unbalanced, no matching OP_IF\nOP_1\n");
+    }
+
+    #[test]
+    fn unparsable_tail() {
+        let text = decode("51024e", &FormatOptions::default());
+        assert!(text.starts_with("OP_1\n<2 bytes that can't be parsed: "), "{}", text);
+    }
+
+    #[test]
+    fn push_descriptions() {
+        let secp = Secp256k1::new();
+        let secret = SecretKey::from_slice(&[1; 32]).unwrap();
+        let mut sig = secp.sign_ecdsa(&Message::from_slice(&[7; 32]).unwrap(), &secret).serialize_der().to_vec();
+        sig.push(SIGHASH_SINGLE | SIGHASH_ANYONECANPAY);
+        assert_eq!(describe_push(&sig).unwrap(), "signature, SIGHASH_SINGLE|ANYONECANPAY");
+        *sig.last_mut().unwrap() = 0x05;
+        assert_eq!(describe_push(&sig).unwrap(), "signature, unknown sighash type 0x05");
+
+        let key = PublicKey::from_secret_key(&secp, &secret);
+        assert_eq!(describe_push(&key.serialize()).unwrap(), "compressed public key");
+        assert_eq!(describe_push(&key.serialize_uncompressed()).unwrap(), "uncompressed public key");
+        assert_eq!(describe_push(&[0xff; 20]).unwrap(), "20-byte hash");
+        assert_eq!(describe_push(&[0x90, 0x00]).unwrap(), "number 144");
+        assert_eq!(describe_push(b"hi").unwrap(), "number 26984, \"hi\"");
+        assert_eq!(describe_push(&[0x00, 0x00]), None);
+    }
+}
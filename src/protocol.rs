@@ -0,0 +1,72 @@
+use std::io::{self, BufRead, Write};
+
+use serde_json::Value;
+
+// Base protocol shared by the Debug Adapter Protocol and the Language Server Protocol:
+// a Content-Length header, an empty line, then a JSON body
+
+// Read the next message, returns None at the end of the input
+pub fn read_message(reader: &mut impl BufRead) -> io::Result<Option<Value>> {
+    let mut length = None;
+    loop {
+        let mut header = String::new();
+        if reader.read_line(&mut header)? == 0 {
+            return Ok(None)
+        }
+        let header = header.trim();
+        if header.is_empty() {
+            break
+        }
+        if let Some(value) = header.strip_prefix("Content-Length:") {
+            length = value.trim().parse::<usize>().ok();
+        }
+    }
+
+    let length = length.ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, "Missing Content-Length header"))?;
+    let mut body = vec![0; length];
+    reader.read_exact(&mut body)?;
+    serde_json::from_slice(&body).map(Some).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+}
+
+pub fn write_message(writer: &mut impl Write, message: &Value) -> io::Result<()> {
+    let body = message.to_string();
+    write!(writer, "Content-Length: {}\r\n\r\n{}", body.len(), body)?;
+    writer.flush()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn round_trip() {
+        let mut buffer = Vec::new();
+        write_message(&mut buffer, &json!({"seq": 1, "command": "initialize"})).unwrap();
+        write_message(&mut buffer, &json!({"text": "é"})).unwrap();
+        // The length counts bytes, not characters
+        assert!(buffer.starts_with(b"Content-Length: 32\r\n\r\n{"));
+        assert!(buffer.ends_with(b"Content-Length: 13\r\n\r\n{\"text\":\"\xc3\xa9\"}"));
+
+        let mut reader = &buffer[..];
+        assert_eq!(read_message(&mut reader).unwrap(), Some(json!({"seq": 1, "command": "initialize"})));
+        assert_eq!(read_message(&mut reader).unwrap(), Some(json!({"text": "é"})));
+        assert_eq!(read_message(&mut reader).unwrap(), None);
+    }
+
+    #[test]
+    fn other_headers() {
+        let mut reader = &b"Content-Type: application/vscode-jsonrpc\r\nContent-Length: 2\r\n\r\n{}"[..];
+        assert_eq!(read_message(&mut reader).unwrap(), Some(json!({})));
+    }
+
+    #[test]
+    fn invalid_messages() {
+        let error = read_message(&mut &b"Content-Type: json\r\n\r\n{}"[..]).unwrap_err();
+        assert_eq!(error.to_string(), "Missing Content-Length header");
+        let error = read_message(&mut &b"Content-Length: 2\r\n\r\n{"[..]).unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::UnexpectedEof);
+        let error = read_message(&mut &b"Content-Length: 2\r\n\r\n{]"[..]).unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+    }
+}
@@ -0,0 +1,290 @@
+use bitcoin_hashes::{hash160, ripemd160, sha1, sha256, sha256d, Hash};
+use secp256k1::{PublicKey, Secp256k1, SecretKey};
+
+use crate::interpret::interpret_input;
+use crate::opcodes::*;
+use crate::script::*;
+use crate::symbolic::{execute_symbolic, Expr, Requirement, SymbolicPath};
+use crate::transaction::*;
+
+// What the spender has at hand to satisfy a script
+#[derive(Debug, Clone, Default)]
+pub struct Assets {
+    pub keys: Vec<SecretKey>,
+    pub preimages: Vec<Vec<u8>>,
+    // Current block height and median time past, for absolute timelocks
+    pub height: Option<u32>,
+    pub time: Option<u32>,
+    // Blocks and seconds since the spent output confirmed, for relative timelocks
+    pub age_blocks: Option<u32>,
+    pub age_seconds: Option<u32>
+}
+
+impl Assets {
+    // Public keys of the secret keys, compressed and uncompressed
+    fn public_keys(&self) -> Vec<(Vec<u8>, &SecretKey)> {
+        let secp = Secp256k1::signing_only();
+        self.keys.iter().flat_map(|secret| {
+            let public = PublicKey::from_secret_key(&secp, secret);
+            [(public.serialize().to_vec(), secret), (public.serialize_uncompressed().to_vec(), secret)]
+        }).collect()
+    }
+
+    fn secret_key(&self, pubkey: &[u8]) -> Option<&SecretKey> {
+        self.public_keys().into_iter().find(|(public, _)| public == pubkey).map(|(_, secret)| secret)
+    }
+
+    // Preimage of `digest`, public keys are the usual HASH160 preimages
+    fn preimage(&self, hash: Opcode, digest: &[u8]) -> Option<Vec<u8>> {
+        let keys = self.public_keys().into_iter().map(|(public, _)| public);
+        self.preimages.iter().cloned().chain(keys).find(|preimage| {
+            let hashed = match hash {
+                OP_RIPEMD160 => ripemd160::Hash::hash(preimage).to_vec(),
+                OP_SHA1 => sha1::Hash::hash(preimage).to_vec(),
+                OP_SHA256 => sha256::Hash::hash(preimage).to_vec(),
+                OP_HASH160 => hash160::Hash::hash(preimage).to_vec(),
+                _ => sha256d::Hash::hash(preimage).to_vec()
+            };
+            hashed == digest
+        })
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Satisfaction {
+    // Index of every OP_IF/OP_NOTIF/OP_IFDUP decided by the witness and the branch taken
+    pub branches: Vec<(usize, bool)>,
+    // Initial stack, bottom first
+    pub stack: Vec<Vec<u8>>,
+    // Spending input, with the nLockTime, nSequence and version required by the path
+    pub context: TransactionContext,
+    // Redeem or witness script the stack satisfies
+    pub script: Vec<u8>
+}
+
+impl Satisfaction {
+    // scriptSig spending the P2SH output: the stack as minimal pushes followed by the redeem script
+    pub fn script_sig(&self) -> Vec<u8> {
+        let mut script_sig: Vec<u8> = self.stack.iter().flat_map(|item| minimal_push(item)).collect();
+        script_sig.extend(encode_push(&self.script));
+        script_sig
+    }
+
+    // Witness spending the P2WSH output, bottom first: the stack followed by the witness script
+    pub fn witness(&self) -> Vec<Vec<u8>> {
+        let mut witness = self.stack.clone();
+        witness.push(self.script.clone());
+        witness
+    }
+
+    // Serialized size of the scriptSig or of the witness, depending on the signature version
+    pub fn size(&self) -> usize {
+        match self.context.sig_version {
+            SigVersion::Base => self.script_sig().len(),
+            SigVersion::WitnessV0 => witness_size(&self.witness())
+        }
+    }
+}
+
+// Smallest stack satisfying the script with the assets, over every path found by the symbolic engine
+// For P2SH and P2WSH, `script` is the redeem or witness script, which then goes on top of the stack.
+// Every candidate is confirmed by running interpret_input on the spending input.
+pub fn satisfy(script: &Script, assets: &Assets, context: &TransactionContext) -> Option<Satisfaction> {
+    let bytes = serialize(script);
+    execute_symbolic(script).spendable_paths()
+        .filter_map(|path| satisfy_path(&bytes, path, assets, context))
+        .min_by_key(|satisfaction| satisfaction.size())
+}
+
+fn assign(items: &mut [Option<Vec<u8>>], index: usize, value: Vec<u8>) -> Option<()> {
+    match &items[index] {
+        Some(current) if *current != value => None,
+        _ => {
+            items[index] = Some(value);
+            Some(())
+        }
+    }
+}
+
+// Set the nLockTime or nSequence of the spending input to meet a timelock, when the assets allow it
+fn lock(context: &mut TransactionContext, relative: bool, value: i64, assets: &Assets) -> Option<()> {
+    let input = &mut context.tx.inputs[context.input];
+    if !relative {
+        let now = if value < LOCKTIME_THRESHOLD { assets.height? } else { assets.time? } as i64;
+        if value > now {
+            return None
+        }
+        let current = context.tx.lock_time as i64;
+        let same_kind = (current < LOCKTIME_THRESHOLD) == (value < LOCKTIME_THRESHOLD);
+        context.tx.lock_time = if same_kind { current.max(value) } else { value } as u32;
+        // nLockTime is ignored when every input is final
+        if input.sequence == SEQUENCE_FINAL {
+            input.sequence = SEQUENCE_FINAL - 1;
+        }
+        return Some(())
+    }
+
+    let masked = value & (SEQUENCE_TYPE_FLAG | SEQUENCE_MASK);
+    let age = if masked & SEQUENCE_TYPE_FLAG != 0 { assets.age_seconds? / 512 } else { assets.age_blocks? } as i64;
+    if masked & SEQUENCE_MASK > age {
+        return None
+    }
+    let current = input.sequence as i64;
+    let same_kind = current & SEQUENCE_DISABLE_FLAG == 0 && current & SEQUENCE_TYPE_FLAG == masked & SEQUENCE_TYPE_FLAG;
+    input.sequence = if same_kind { current.max(masked) } else { masked } as u32;
+    context.tx.version = context.tx.version.max(2);
+    Some(())
+}
+
+fn satisfy_path(script: &[u8], path: &SymbolicPath, assets: &Assets, context: &TransactionContext) -> Option<Satisfaction> {
+    let mut items = vec![None; path.witness_items];
+    let mut context = context.clone();
+    // Keys, witness items of the signatures and number of signatures required
+    let mut signatures = Vec::new();
+
+    for requirement in path.requirements() {
+        match requirement {
+            Requirement::Preimage { hash, digest, item: Expr::Witness(i) } => {
+                assign(&mut items, i, assets.preimage(hash, &digest)?)?
+            }
+            Requirement::Signature { key, sig: Expr::Witness(i) } => signatures.push((vec![key], vec![i], 1)),
+            Requirement::NoSignature { sig: Expr::Witness(i), .. } => assign(&mut items, i, Vec::new())?,
+            Requirement::Multisig { required, keys, sigs } => {
+                let sigs = sigs.iter().map(|sig| match sig {
+                    Expr::Witness(i) => Some(*i),
+                    _ => None
+                }).collect::<Option<Vec<usize>>>()?;
+                signatures.push((keys, sigs, required));
+            }
+            Requirement::Range { value: Expr::Witness(i), min, max } => {
+                let (min, max) = (min.unwrap_or(i64::MIN), max.unwrap_or(i64::MAX));
+                if min > max {
+                    return None
+                }
+                assign(&mut items, i, to_script_nb(0.clamp(min, max)))?
+            }
+            Requirement::Timelock { relative, value } => lock(&mut context, relative, value, assets)?,
+            Requirement::Condition { expr: Expr::Witness(i), holds } => {
+                assign(&mut items, i, if holds { vec![1] } else { Vec::new() })?
+            }
+            Requirement::Condition { expr: Expr::Op(OP_EQUAL, args), holds: true } => match &args[..] {
+                [Expr::Witness(i), Expr::Const(c)] | [Expr::Const(c), Expr::Witness(i)] => assign(&mut items, *i, c.clone())?,
+                _ => ()
+            },
+            // Anything else is left to the interpreter run below
+            _ => ()
+        }
+    }
+
+    // Signatures come last: the keys may be witness items and the transaction must be final
+    let mut signed = Vec::new();
+    for (keys, sigs, required) in signatures {
+        let owned: Vec<&SecretKey> = keys.iter().filter_map(|key| {
+            let pubkey = match key {
+                Expr::Const(bytes) => bytes.clone(),
+                Expr::Witness(i) => items[*i].clone()?,
+                _ => return None
+            };
+            assets.secret_key(&pubkey)
+        }).take(required).collect();
+        if owned.len() < required {
+            return None
+        }
+        // CHECKMULTISIG matches signatures and keys in the same order
+        signed.extend(sigs.into_iter().zip(owned).map(|(i, secret)| (i, context.sign(secret, script, SIGHASH_ALL))));
+    }
+    for (i, sig) in signed {
+        assign(&mut items, i, sig)?;
+    }
+
+    // Witness items no requirement constrains are left empty, the top item is witness item 0
+    let stack: Vec<Vec<u8>> = items.into_iter().rev().map(Option::unwrap_or_default).collect();
+    if !interpret_input(script, stack.clone(), &context).success {
+        return None
+    }
+
+    Some(Satisfaction { branches: path.branches.clone(), stack, context, script: script.to_vec() })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::asm::parse_asm_script;
+    use crate::parse::parse_script;
+
+    const KEY_A: &str = "0x031b84c5567b126440995d3ed5aaba0565d71e1834604819ff9c17f5e9d5dd078f";
+    const KEY_B: &str = "0x024d4b6cd1361032ca9bd2aeb9d900aa4d45d9ead80ac9423374c451a7254d0766";
+
+    fn context(sig_version: SigVersion) -> TransactionContext {
+        let tx = Transaction {
+            version: 1,
+            inputs: vec![TxIn { previous_output: OutPoint::default(), script_sig: Vec::new(), sequence: SEQUENCE_FINAL, witness: Vec::new() }],
+            outputs: vec![TxOut { value: 0, script_pubkey: vec![OP_RETURN.code] }],
+            lock_time: 0
+        };
+        TransactionContext::new(tx, 0, 50_000, sig_version)
+    }
+
+    fn assets(secrets: &[u8]) -> Assets {
+        let keys = secrets.iter().map(|s| SecretKey::from_slice(&[*s; 32]).unwrap()).collect();
+        Assets { keys, ..Assets::default() }
+    }
+
+    #[test]
+    fn p2sh_script_sig_ends_with_the_redeem_script() {
+        let script = parse_asm_script(&format!("{} OP_CHECKSIG", KEY_A)).unwrap();
+        let satisfaction = satisfy(&script, &assets(&[1]), &context(SigVersion::Base)).unwrap();
+        assert_eq!(satisfaction.stack.len(), 1);
+
+        let pushes = parse_script(&satisfaction.script_sig()).unwrap();
+        assert_eq!(pushes, vec![ScriptItem::ByteArray(satisfaction.stack[0].clone()), ScriptItem::ByteArray(serialize(&script))]);
+        assert_eq!(satisfaction.size(), satisfaction.script_sig().len());
+
+        // The signature is checked against the redeem script
+        assert!(interpret_input(&serialize(&script), satisfaction.stack.clone(), &satisfaction.context).success);
+    }
+
+    #[test]
+    fn p2wsh_witness_ends_with_the_witness_script() {
+        let script = parse_asm_script(&format!("OP_1 {} {} OP_2 OP_CHECKMULTISIG", KEY_A, KEY_B)).unwrap();
+        let satisfaction = satisfy(&script, &assets(&[2]), &context(SigVersion::WitnessV0)).unwrap();
+        let witness = satisfaction.witness();
+        assert_eq!(witness.len(), 3);
+        assert!(witness[0].is_empty());
+        assert_eq!(witness[2], serialize(&script));
+        assert_eq!(satisfaction.size(), witness_size(&witness));
+    }
+
+    #[test]
+    fn preimages() {
+        let script = parse_asm_script("OP_SHA256 0x2bb80d537b1da3e38bd30361aa855686bde0eacd7162fef6a25fe97bf527a25b OP_EQUAL").unwrap();
+        assert!(satisfy(&script, &Assets::default(), &context(SigVersion::Base)).is_none());
+        let assets = Assets { preimages: vec![b"secret".to_vec()], ..Assets::default() };
+        let satisfaction = satisfy(&script, &assets, &context(SigVersion::Base)).unwrap();
+        assert_eq!(satisfaction.stack, vec![b"secret".to_vec()]);
+    }
+
+    #[test]
+    fn timelocked_branch() {
+        let script = parse_asm_script(&format!(
+            "OP_IF {} OP_CHECKSIG OP_ELSE 0x9000 OP_CHECKSEQUENCEVERIFY OP_DROP {} OP_CHECKSIG OP_ENDIF", KEY_A, KEY_B
+        )).unwrap();
+        assert!(satisfy(&script, &assets(&[2]), &context(SigVersion::Base)).is_none());
+        let assets = Assets { age_blocks: Some(144), ..assets(&[2]) };
+        let satisfaction = satisfy(&script, &assets, &context(SigVersion::Base)).unwrap();
+        assert_eq!(satisfaction.branches, vec![(0, false)]);
+        assert!(satisfaction.stack[1].is_empty());
+        assert_eq!(satisfaction.context.tx.inputs[0].sequence, 144);
+        assert_eq!(satisfaction.context.tx.version, 2);
+    }
+
+    #[test]
+    fn smallest_path_wins() {
+        // Both keys can sign, the first branch needs a single signature
+        let script = parse_asm_script(&format!(
+            "OP_IF {} OP_CHECKSIG OP_ELSE OP_2 {} {} OP_2 OP_CHECKMULTISIG OP_ENDIF", KEY_A, KEY_A, KEY_B
+        )).unwrap();
+        let satisfaction = satisfy(&script, &assets(&[1, 2]), &context(SigVersion::Base)).unwrap();
+        assert_eq!(satisfaction.branches, vec![(0, true)]);
+    }
+}
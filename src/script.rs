@@ -1,5 +1,6 @@
+use std::fmt;
 use std::fmt::{Debug, Formatter};
-use crate::opcodes::Opcode as op;
+use crate::opcodes::{Opcode as op, OP_0, OP_1, OP_1NEGATE, OP_PUSH_DATA_1, OP_PUSH_DATA_2, OP_PUSH_DATA_4};
 
 pub const MAX_NUM_SIZE: usize = 4;
 pub const MAX_SCRIPT_SIZE: usize = 10000;
@@ -7,34 +8,290 @@ pub const MAX_STACK_SIZE: usize = 1000;
 pub const MAX_OPS_PER_SCRIPT: usize = 201;
 pub const MAX_SCRIPT_ELEMENT_SIZE: usize = 520;
 
-#[derive(Debug)]
+// Mirrors Core's ScriptError_t (script/script_error.h), in the same order
+#[derive(Debug, Eq, PartialEq, Copy, Clone, Hash)]
 pub enum ScriptError {
-    InvalidStackOperationErr,
-    InvalidAltStackOperationErr,
-    ScriptNumberOverflowErr,
-    StackOverflowErr,
-    ScriptSizeErr,
-    PushSizeErr,
-    EqualVerifyErr,
-    NumEqualVerifyErr,
-    UnbalancedConditionalErr,
-    DisabledOpcodeErr,
-    BadOpcodeErr,
-    VerifyErr,
-    OpReturnErr,
-    OpCountErr
+    Ok,
+    UnknownError,
+    EvalFalse,
+    OpReturn,
+
+    // Max sizes
+    ScriptSize,
+    PushSize,
+    OpCount,
+    StackSize,
+    SigCount,
+    PubkeyCount,
+
+    // Failed verify operations
+    Verify,
+    EqualVerify,
+    CheckMultisigVerify,
+    CheckSigVerify,
+    NumEqualVerify,
+
+    // Logical/Format/Canonical errors
+    BadOpcode,
+    DisabledOpcode,
+    InvalidStackOperation,
+    InvalidAltstackOperation,
+    UnbalancedConditional,
+
+    // CHECKLOCKTIMEVERIFY and CHECKSEQUENCEVERIFY
+    NegativeLocktime,
+    UnsatisfiedLocktime,
+
+    // Malleability
+    SigHashtype,
+    SigDer,
+    MinimalData,
+    SigPushOnly,
+    SigHighS,
+    SigNullDummy,
+    PubkeyType,
+    CleanStack,
+    MinimalIf,
+    SigNullFail,
+
+    // Softfork safeness
+    DiscourageUpgradableNops,
+    DiscourageUpgradableWitnessProgram,
+    DiscourageUpgradableTaprootVersion,
+    DiscourageOpSuccess,
+    DiscourageUpgradablePubkeyType,
+
+    // Segregated witness
+    WitnessProgramWrongLength,
+    WitnessProgramWitnessEmpty,
+    WitnessProgramMismatch,
+    WitnessMalleated,
+    WitnessMalleatedP2sh,
+    WitnessUnexpected,
+    WitnessPubkeyType,
+
+    // Taproot
+    SchnorrSigSize,
+    SchnorrSigHashtype,
+    SchnorrSig,
+    TaprootWrongControlSize,
+    TapscriptValidationWeight,
+    TapscriptCheckMultisig,
+    TapscriptMinimalIf,
+    TapscriptEmptyPubkey,
+
+    // Constant scriptCode
+    OpCodeSeparator,
+    SigFindAndDelete,
+
+    // Not in Core: a signature was checked without a transaction to sign
+    SigUnverifiable
+}
+
+impl ScriptError {
+    pub const ALL: [ScriptError; 55] = [
+        ScriptError::Ok,
+        ScriptError::UnknownError,
+        ScriptError::EvalFalse,
+        ScriptError::OpReturn,
+        ScriptError::ScriptSize,
+        ScriptError::PushSize,
+        ScriptError::OpCount,
+        ScriptError::StackSize,
+        ScriptError::SigCount,
+        ScriptError::PubkeyCount,
+        ScriptError::Verify,
+        ScriptError::EqualVerify,
+        ScriptError::CheckMultisigVerify,
+        ScriptError::CheckSigVerify,
+        ScriptError::NumEqualVerify,
+        ScriptError::BadOpcode,
+        ScriptError::DisabledOpcode,
+        ScriptError::InvalidStackOperation,
+        ScriptError::InvalidAltstackOperation,
+        ScriptError::UnbalancedConditional,
+        ScriptError::NegativeLocktime,
+        ScriptError::UnsatisfiedLocktime,
+        ScriptError::SigHashtype,
+        ScriptError::SigDer,
+        ScriptError::MinimalData,
+        ScriptError::SigPushOnly,
+        ScriptError::SigHighS,
+        ScriptError::SigNullDummy,
+        ScriptError::PubkeyType,
+        ScriptError::CleanStack,
+        ScriptError::MinimalIf,
+        ScriptError::SigNullFail,
+        ScriptError::DiscourageUpgradableNops,
+        ScriptError::DiscourageUpgradableWitnessProgram,
+        ScriptError::DiscourageUpgradableTaprootVersion,
+        ScriptError::DiscourageOpSuccess,
+        ScriptError::DiscourageUpgradablePubkeyType,
+        ScriptError::WitnessProgramWrongLength,
+        ScriptError::WitnessProgramWitnessEmpty,
+        ScriptError::WitnessProgramMismatch,
+        ScriptError::WitnessMalleated,
+        ScriptError::WitnessMalleatedP2sh,
+        ScriptError::WitnessUnexpected,
+        ScriptError::WitnessPubkeyType,
+        ScriptError::SchnorrSigSize,
+        ScriptError::SchnorrSigHashtype,
+        ScriptError::SchnorrSig,
+        ScriptError::TaprootWrongControlSize,
+        ScriptError::TapscriptValidationWeight,
+        ScriptError::TapscriptCheckMultisig,
+        ScriptError::TapscriptMinimalIf,
+        ScriptError::TapscriptEmptyPubkey,
+        ScriptError::OpCodeSeparator,
+        ScriptError::SigFindAndDelete,
+        ScriptError::SigUnverifiable,
+    ];
+
+    // Name used by Core in its test vectors and logs (e.g. "EVAL_FALSE")
+    pub fn name(&self) -> &'static str {
+        match self {
+            ScriptError::Ok => "OK",
+            ScriptError::UnknownError => "UNKNOWN_ERROR",
+            ScriptError::EvalFalse => "EVAL_FALSE",
+            ScriptError::OpReturn => "OP_RETURN",
+            ScriptError::ScriptSize => "SCRIPT_SIZE",
+            ScriptError::PushSize => "PUSH_SIZE",
+            ScriptError::OpCount => "OP_COUNT",
+            ScriptError::StackSize => "STACK_SIZE",
+            ScriptError::SigCount => "SIG_COUNT",
+            ScriptError::PubkeyCount => "PUBKEY_COUNT",
+            ScriptError::Verify => "VERIFY",
+            ScriptError::EqualVerify => "EQUALVERIFY",
+            ScriptError::CheckMultisigVerify => "CHECKMULTISIGVERIFY",
+            ScriptError::CheckSigVerify => "CHECKSIGVERIFY",
+            ScriptError::NumEqualVerify => "NUMEQUALVERIFY",
+            ScriptError::BadOpcode => "BAD_OPCODE",
+            ScriptError::DisabledOpcode => "DISABLED_OPCODE",
+            ScriptError::InvalidStackOperation => "INVALID_STACK_OPERATION",
+            ScriptError::InvalidAltstackOperation => "INVALID_ALTSTACK_OPERATION",
+            ScriptError::UnbalancedConditional => "UNBALANCED_CONDITIONAL",
+            ScriptError::NegativeLocktime => "NEGATIVE_LOCKTIME",
+            ScriptError::UnsatisfiedLocktime => "UNSATISFIED_LOCKTIME",
+            ScriptError::SigHashtype => "SIG_HASHTYPE",
+            ScriptError::SigDer => "SIG_DER",
+            ScriptError::MinimalData => "MINIMALDATA",
+            ScriptError::SigPushOnly => "SIG_PUSHONLY",
+            ScriptError::SigHighS => "SIG_HIGH_S",
+            ScriptError::SigNullDummy => "SIG_NULLDUMMY",
+            ScriptError::PubkeyType => "PUBKEYTYPE",
+            ScriptError::CleanStack => "CLEANSTACK",
+            ScriptError::MinimalIf => "MINIMALIF",
+            ScriptError::SigNullFail => "NULLFAIL",
+            ScriptError::DiscourageUpgradableNops => "DISCOURAGE_UPGRADABLE_NOPS",
+            ScriptError::DiscourageUpgradableWitnessProgram => "DISCOURAGE_UPGRADABLE_WITNESS_PROGRAM",
+            ScriptError::DiscourageUpgradableTaprootVersion => "DISCOURAGE_UPGRADABLE_TAPROOT_VERSION",
+            ScriptError::DiscourageOpSuccess => "DISCOURAGE_OP_SUCCESS",
+            ScriptError::DiscourageUpgradablePubkeyType => "DISCOURAGE_UPGRADABLE_PUBKEYTYPE",
+            ScriptError::WitnessProgramWrongLength => "WITNESS_PROGRAM_WRONG_LENGTH",
+            ScriptError::WitnessProgramWitnessEmpty => "WITNESS_PROGRAM_WITNESS_EMPTY",
+            ScriptError::WitnessProgramMismatch => "WITNESS_PROGRAM_MISMATCH",
+            ScriptError::WitnessMalleated => "WITNESS_MALLEATED",
+            ScriptError::WitnessMalleatedP2sh => "WITNESS_MALLEATED_P2SH",
+            ScriptError::WitnessUnexpected => "WITNESS_UNEXPECTED",
+            ScriptError::WitnessPubkeyType => "WITNESS_PUBKEYTYPE",
+            ScriptError::SchnorrSigSize => "SCHNORR_SIG_SIZE",
+            ScriptError::SchnorrSigHashtype => "SCHNORR_SIG_HASHTYPE",
+            ScriptError::SchnorrSig => "SCHNORR_SIG",
+            ScriptError::TaprootWrongControlSize => "TAPROOT_WRONG_CONTROL_SIZE",
+            ScriptError::TapscriptValidationWeight => "TAPSCRIPT_VALIDATION_WEIGHT",
+            ScriptError::TapscriptCheckMultisig => "TAPSCRIPT_CHECKMULTISIG",
+            ScriptError::TapscriptMinimalIf => "TAPSCRIPT_MINIMALIF",
+            ScriptError::TapscriptEmptyPubkey => "TAPSCRIPT_EMPTY_PUBKEY",
+            ScriptError::OpCodeSeparator => "OP_CODESEPARATOR",
+            ScriptError::SigFindAndDelete => "SIG_FINDANDDELETE",
+            ScriptError::SigUnverifiable => "SIG_UNVERIFIABLE",
+        }
+    }
+
+    // Parse one of Core's error names, the inverse of `name`
+    pub fn from_name(name: &str) -> Option<ScriptError> {
+        ScriptError::ALL.into_iter().find(|e| e.name() == name)
+    }
+}
+
+impl fmt::Display for ScriptError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        // Same strings as Core's ScriptErrorString
+        let msg = match self {
+            ScriptError::Ok => "No error",
+            ScriptError::UnknownError => "unknown error",
+            ScriptError::EvalFalse => "Script evaluated without error but finished with a false/empty top stack element",
+            ScriptError::OpReturn => "OP_RETURN was encountered",
+            ScriptError::ScriptSize => "Script is too big",
+            ScriptError::PushSize => "Push value size limit exceeded",
+            ScriptError::OpCount => "Operation limit exceeded",
+            ScriptError::StackSize => "Stack size limit exceeded",
+            ScriptError::SigCount => "Signature count negative or greater than pubkey count",
+            ScriptError::PubkeyCount => "Pubkey count negative or limit exceeded",
+            ScriptError::Verify => "Script failed an OP_VERIFY operation",
+            ScriptError::EqualVerify => "Script failed an OP_EQUALVERIFY operation",
+            ScriptError::CheckMultisigVerify => "Script failed an OP_CHECKMULTISIGVERIFY operation",
+            ScriptError::CheckSigVerify => "Script failed an OP_CHECKSIGVERIFY operation",
+            ScriptError::NumEqualVerify => "Script failed an OP_NUMEQUALVERIFY operation",
+            ScriptError::BadOpcode => "Opcode missing or not understood",
+            ScriptError::DisabledOpcode => "Attempted to use a disabled opcode",
+            ScriptError::InvalidStackOperation => "Operation not valid with the current stack size",
+            ScriptError::InvalidAltstackOperation => "Operation not valid with the current altstack size",
+            ScriptError::UnbalancedConditional => "Invalid OP_IF construction",
+            ScriptError::NegativeLocktime => "Negative locktime",
+            ScriptError::UnsatisfiedLocktime => "Locktime requirement not satisfied",
+            ScriptError::SigHashtype => "Signature hash type missing or not understood",
+            ScriptError::SigDer => "Non-canonical DER signature",
+            ScriptError::MinimalData => "Data push larger than necessary",
+            ScriptError::SigPushOnly => "Only push operators allowed in signatures",
+            ScriptError::SigHighS => "Non-canonical signature: S value is unnecessarily high",
+            ScriptError::SigNullDummy => "Dummy CHECKMULTISIG argument must be zero",
+            ScriptError::PubkeyType => "Public key is neither compressed or uncompressed",
+            ScriptError::CleanStack => "Stack size must be exactly one after execution",
+            ScriptError::MinimalIf => "OP_IF/NOTIF argument must be minimal",
+            ScriptError::SigNullFail => "Signature must be zero for failed CHECK(MULTI)SIG operation",
+            ScriptError::DiscourageUpgradableNops => "NOPx reserved for soft-fork upgrades",
+            ScriptError::DiscourageUpgradableWitnessProgram => "Witness version reserved for soft-fork upgrades",
+            ScriptError::DiscourageUpgradableTaprootVersion => "Taproot version reserved for soft-fork upgrades",
+            ScriptError::DiscourageOpSuccess => "OP_SUCCESSx reserved for soft-fork upgrades",
+            ScriptError::DiscourageUpgradablePubkeyType => "Public key version reserved for soft-fork upgrades",
+            ScriptError::WitnessProgramWrongLength => "Witness program has incorrect length",
+            ScriptError::WitnessProgramWitnessEmpty => "Witness program was passed an empty witness",
+            ScriptError::WitnessProgramMismatch => "Witness program hash mismatch",
+            ScriptError::WitnessMalleated => "Witness requires empty scriptSig",
+            ScriptError::WitnessMalleatedP2sh => "Witness requires only-redeemscript scriptSig",
+            ScriptError::WitnessUnexpected => "Witness provided for non-witness script",
+            ScriptError::WitnessPubkeyType => "Using non-compressed keys in segwit",
+            ScriptError::SchnorrSigSize => "Invalid Schnorr signature size",
+            ScriptError::SchnorrSigHashtype => "Invalid Schnorr signature hash type",
+            ScriptError::SchnorrSig => "Invalid Schnorr signature",
+            ScriptError::TaprootWrongControlSize => "Invalid Taproot control block size",
+            ScriptError::TapscriptValidationWeight => "Too much signature validation relative to witness weight",
+            ScriptError::TapscriptCheckMultisig => "OP_CHECKMULTISIG(VERIFY) is not available in tapscript",
+            ScriptError::TapscriptMinimalIf => "OP_IF/NOTIF argument must be minimal in tapscript",
+            ScriptError::TapscriptEmptyPubkey => "Empty public key in tapscript",
+            ScriptError::OpCodeSeparator => "Using OP_CODESEPARATOR in non-witness script",
+            ScriptError::SigFindAndDelete => "Signature is found in scriptCode",
+            ScriptError::SigUnverifiable => "Signature can't be checked without a transaction",
+        };
+        write!(f, "{}", msg)
+    }
 }
 
+impl std::error::Error for ScriptError {}
+
 pub type Script = Vec<ScriptItem>;
 
 // Not sure if I should use references that would probably be more optimized but create code bloat
+#[derive(Clone, Eq, PartialEq)]
 pub enum ScriptItem {
     Opcode(op),
     ByteArray(Vec<u8>)
 }
 
-impl Debug for ScriptItem {
-    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+// ASM, as read back by parse_asm
+impl fmt::Display for ScriptItem {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
         match self {
             ScriptItem::Opcode(op) => write!(f, "{}", op),
             ScriptItem::ByteArray(b) => write!(f, "0x{}", hex::encode(b))
@@ -42,6 +299,55 @@ impl Debug for ScriptItem {
     }
 }
 
+impl Debug for ScriptItem {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        fmt::Display::fmt(self, f)
+    }
+}
+
+// Smallest push opcode for this data, followed by the data
+// Does not turn small numbers into OP_1..OP_16 so that parsing the result gives back the same item
+pub fn encode_push(data: &[u8]) -> Vec<u8> {
+    let mut bytes = Vec::with_capacity(data.len() + 5);
+    match data.len() {
+        0 => bytes.push(OP_0.code),
+        len @ 1..=75 => bytes.push(len as u8),
+        len @ 76..=0xff => bytes.extend([OP_PUSH_DATA_1.code, len as u8]),
+        len @ 0x100..=0xffff => {
+            bytes.push(OP_PUSH_DATA_2.code);
+            bytes.extend((len as u16).to_le_bytes());
+        }
+        len => {
+            bytes.push(OP_PUSH_DATA_4.code);
+            bytes.extend((len as u32).to_le_bytes());
+        }
+    }
+    bytes.extend_from_slice(data);
+    bytes
+}
+
+// Push following Core's minimal push rule (MINIMALDATA): OP_0, OP_1..OP_16 and OP_1NEGATE
+// for the values they push, otherwise the smallest push opcode
+pub fn minimal_push(data: &[u8]) -> Vec<u8> {
+    match data {
+        [n @ 1..=16] => vec![OP_1.code + n - 1],
+        [0x81] => vec![OP_1NEGATE.code],
+        _ => encode_push(data)
+    }
+}
+
+// Convert a parsed script back to bytes
+pub fn serialize(script: &Script) -> Vec<u8> {
+    let mut bytes = Vec::new();
+    for item in script {
+        match item {
+            ScriptItem::Opcode(op) => bytes.push(op.code),
+            ScriptItem::ByteArray(data) => bytes.extend(encode_push(data))
+        }
+    }
+    bytes
+}
+
 // Convert an int to the Script Number format used on the stack
 pub fn to_script_nb(value: i64) -> Vec<u8> {
     let mut result = Vec::with_capacity(4);
@@ -62,14 +368,20 @@ pub fn to_script_nb(value: i64) -> Vec<u8> {
         *result.last_mut().unwrap() |= 0x80;
     }
 
-    return result
+    result
 }
 
 // Convert a Script Number to an int
 // Only numbers of at most 4 bytes are accepted
+// Core throws a scriptnum_error on overflow, which surfaces as UNKNOWN_ERROR
 pub fn as_script_nb(bytes: &[u8]) -> Result<i64, ScriptError> {
-    if bytes.len() > MAX_NUM_SIZE {
-        return Err(ScriptError::ScriptNumberOverflowErr)
+    as_script_nb_sized(bytes, MAX_NUM_SIZE)
+}
+
+// Same as as_script_nb with another size limit, locktimes are 5 bytes numbers
+pub fn as_script_nb_sized(bytes: &[u8], max_size: usize) -> Result<i64, ScriptError> {
+    if bytes.len() > max_size {
+        return Err(ScriptError::UnknownError)
     }
 
     if bytes.is_empty() {
@@ -77,8 +389,8 @@ pub fn as_script_nb(bytes: &[u8]) -> Result<i64, ScriptError> {
     }
 
     let mut result= 0;
-    for i in 0..bytes.len() {
-        result |= (bytes[i] as i64) << 8*i;
+    for (i, byte) in bytes.iter().enumerate() {
+        result |= (*byte as i64) << (8 * i);
     }
 
     if bytes.last().unwrap() & 0x80 != 0 {
@@ -89,13 +401,71 @@ pub fn as_script_nb(bytes: &[u8]) -> Result<i64, ScriptError> {
 }
 
 pub fn as_bool(bytes: &[u8]) -> bool {
-    for i in 0..bytes.len() {
-        if bytes[i] != 0 {
-            if i == bytes.len() - 1 && bytes[i] == 0x80 {
-                return false
-            }
-            return true
+    for (i, byte) in bytes.iter().enumerate() {
+        if *byte != 0 {
+            return !(i == bytes.len() - 1 && *byte == 0x80)
         }
     }
-    return false
-}
\ No newline at end of file
+    false
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashSet;
+
+    use super::*;
+    use crate::opcodes::{OP_5, OP_DUP};
+
+    #[test]
+    fn error_names_round_trip() {
+        for error in ScriptError::ALL {
+            assert_eq!(ScriptError::from_name(error.name()), Some(error));
+        }
+        let names: HashSet<&str> = ScriptError::ALL.iter().map(ScriptError::name).collect();
+        assert_eq!(names.len(), ScriptError::ALL.len());
+        assert_eq!(ScriptError::from_name("nullfail"), None);
+        assert_eq!(ScriptError::from_name("UNKNOWN"), None);
+    }
+
+    #[test]
+    fn core_names_and_messages() {
+        assert_eq!(ScriptError::from_name("NULLFAIL"), Some(ScriptError::SigNullFail));
+        assert_eq!(ScriptError::from_name("INVALID_STACK_OPERATION"), Some(ScriptError::InvalidStackOperation));
+        assert_eq!(ScriptError::from_name("UNBALANCED_CONDITIONAL"), Some(ScriptError::UnbalancedConditional));
+        assert_eq!(ScriptError::EvalFalse.name(), "EVAL_FALSE");
+        assert_eq!(ScriptError::SigNullDummy.to_string(), "Dummy CHECKMULTISIG argument must be zero");
+        assert_eq!(ScriptError::MinimalData.to_string(), "Data push larger than necessary");
+    }
+
+    #[test]
+    fn script_numbers() {
+        let vectors: [(i64, &str); 9] = [(0, ""), (1, "01"), (-1, "81"), (127, "7f"), (128, "8000"), (-128, "8080"),
+            (255, "ff00"), (256, "0001"), (-2147483647, "ffffffff")];
+        for (n, encoded) in vectors {
+            assert_eq!(hex::encode(to_script_nb(n)), encoded);
+            assert_eq!(as_script_nb(&hex::decode(encoded).unwrap()), Ok(n));
+        }
+        assert_eq!(as_script_nb(&[0, 0, 0, 0, 1]), Err(ScriptError::UnknownError));
+        assert_eq!(as_script_nb_sized(&[0, 0, 0, 0, 1], 5), Ok(1 << 32));
+    }
+
+    #[test]
+    fn booleans() {
+        assert!(!as_bool(&[]));
+        assert!(!as_bool(&[0, 0]));
+        assert!(!as_bool(&[0, 0x80]));
+        assert!(as_bool(&[0x80, 0]));
+        assert!(as_bool(&[1]));
+    }
+
+    #[test]
+    fn pushes() {
+        assert_eq!(encode_push(&[5]), [1, 5]);
+        assert_eq!(minimal_push(&[5]), [OP_5.code]);
+        assert_eq!(minimal_push(&[0x81]), [OP_1NEGATE.code]);
+        assert_eq!(minimal_push(&[]), [OP_0.code]);
+        assert_eq!(encode_push(&[0; 300])[..3], [OP_PUSH_DATA_2.code, 0x2c, 0x01]);
+        let script = vec![ScriptItem::Opcode(OP_DUP), ScriptItem::ByteArray(vec![0xab; 2])];
+        assert_eq!(serialize(&script), [OP_DUP.code, 2, 0xab, 0xab]);
+    }
+}
@@ -0,0 +1,275 @@
+use std::fmt;
+
+use crate::builder::Builder;
+use crate::opcodes::*;
+use crate::parse::parse_one_op;
+use crate::script::{to_script_nb, ScriptItem};
+
+// Standard output script templates, as recognised by Core's Solver
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub enum Template {
+    NonStandard,
+    // Pay-to-anchor, the spendable-by-anyone v1 witness program 0x4e73
+    Anchor,
+    PubKey { key: Vec<u8> },
+    PubKeyHash { hash: Vec<u8> },
+    ScriptHash { hash: Vec<u8> },
+    Multisig { required: usize, keys: Vec<Vec<u8>> },
+    // OP_RETURN followed by pushes only, unspendable
+    NullData { data: Vec<Vec<u8>> },
+    WitnessV0KeyHash { hash: Vec<u8> },
+    WitnessV0ScriptHash { hash: Vec<u8> },
+    WitnessV1Taproot { key: Vec<u8> },
+    // Witness program of a version without spending rules yet
+    WitnessUnknown { version: u8, program: Vec<u8> }
+}
+
+impl Template {
+    // Core's name for the output type
+    pub fn name(&self) -> &'static str {
+        match self {
+            Template::NonStandard => "nonstandard",
+            Template::Anchor => "anchor",
+            Template::PubKey { .. } => "pubkey",
+            Template::PubKeyHash { .. } => "pubkeyhash",
+            Template::ScriptHash { .. } => "scripthash",
+            Template::Multisig { .. } => "multisig",
+            Template::NullData { .. } => "nulldata",
+            Template::WitnessV0KeyHash { .. } => "witness_v0_keyhash",
+            Template::WitnessV0ScriptHash { .. } => "witness_v0_scripthash",
+            Template::WitnessV1Taproot { .. } => "witness_v1_taproot",
+            Template::WitnessUnknown { .. } => "witness_unknown"
+        }
+    }
+
+    pub fn is_standard(&self) -> bool {
+        *self != Template::NonStandard
+    }
+
+    // Output script of the template, the inverse of solve
+    pub fn script(&self) -> Option<Vec<u8>> {
+        let builder = match self {
+            Template::NonStandard => return None,
+            Template::Anchor => Builder::anchor(),
+            Template::PubKey { key } => Builder::p2pk(key),
+            Template::PubKeyHash { hash } => Builder::p2pkh(hash),
+            Template::ScriptHash { hash } => Builder::p2sh(hash),
+            Template::Multisig { required, keys } => Builder::multisig(*required, keys),
+            Template::NullData { data } => Builder::null_data(data),
+            Template::WitnessV0KeyHash { hash } => Builder::p2wpkh(hash),
+            Template::WitnessV0ScriptHash { hash } => Builder::p2wsh(hash),
+            Template::WitnessV1Taproot { key } => Builder::p2tr(key),
+            Template::WitnessUnknown { version, program } => Builder::witness_program(*version, program)
+        };
+        Some(builder.into_bytes())
+    }
+}
+
+impl fmt::Display for Template {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Template::NonStandard | Template::Anchor => write!(f, "{}", self.name()),
+            Template::PubKey { key } => write!(f, "{} 0x{}", self.name(), hex::encode(key)),
+            Template::PubKeyHash { hash } | Template::ScriptHash { hash } |
+            Template::WitnessV0KeyHash { hash } | Template::WitnessV0ScriptHash { hash } => {
+                write!(f, "{} 0x{}", self.name(), hex::encode(hash))
+            }
+            Template::WitnessV1Taproot { key } => write!(f, "{} 0x{}", self.name(), hex::encode(key)),
+            Template::Multisig { required, keys } => {
+                let keys: Vec<String> = keys.iter().map(|k| format!("0x{}", hex::encode(k))).collect();
+                write!(f, "{} {}-of-{} [{}]", self.name(), required, keys.len(), keys.join(", "))
+            }
+            Template::NullData { data } => {
+                let data: Vec<String> = data.iter().map(|d| format!("0x{}", hex::encode(d))).collect();
+                write!(f, "{} [{}]", self.name(), data.join(", "))
+            }
+            Template::WitnessUnknown { version, program } => {
+                write!(f, "{} version {} 0x{}", self.name(), version, hex::encode(program))
+            }
+        }
+    }
+}
+
+// Length of a public key given its first byte, 0 when the prefix is unknown
+fn key_size(prefix: u8) -> usize {
+    match prefix {
+        0x02 | 0x03 => 33,
+        0x04 | 0x06 | 0x07 => 65,
+        _ => 0
+    }
+}
+
+fn valid_key(data: &[u8]) -> bool {
+    !data.is_empty() && key_size(data[0]) == data.len()
+}
+
+// OP_1..OP_16
+fn small_integer(code: u8) -> Option<usize> {
+    (OP_1.code..=OP_16.code).contains(&code).then(|| (code - OP_1.code + 1) as usize)
+}
+
+// <version> <program>, with a 2 to 40 bytes program pushed directly
+fn witness_program(bytes: &[u8]) -> Option<(u8, &[u8])> {
+    if !(4..=42).contains(&bytes.len()) || bytes[1] as usize + 2 != bytes.len() {
+        return None
+    }
+    let version = match bytes[0] {
+        0 => 0,
+        code => small_integer(code)? as u8
+    };
+    Some((version, &bytes[2..]))
+}
+
+// <m> <key>... <n> OP_CHECKMULTISIG
+fn multisig(bytes: &[u8]) -> Option<Template> {
+    if bytes.last() != Some(&OP_CHECKMULTISIG.code) {
+        return None
+    }
+    let required = small_integer(bytes[0])?;
+    let mut pc = 1;
+    let mut keys = Vec::new();
+    let n = loop {
+        match parse_one_op(bytes, &mut pc).ok()?? {
+            ScriptItem::ByteArray(data) if valid_key(&data) => keys.push(data),
+            ScriptItem::Opcode(op) => break small_integer(op.code)?,
+            ScriptItem::ByteArray(_) => return None
+        }
+    };
+    if keys.len() != n || n < required || pc + 1 != bytes.len() {
+        return None
+    }
+    Some(Template::Multisig { required, keys })
+}
+
+// OP_RETURN followed by push opcodes only
+fn null_data(bytes: &[u8]) -> Option<Template> {
+    if bytes.first() != Some(&OP_RETURN.code) {
+        return None
+    }
+    let mut pc = 1;
+    let mut data = Vec::new();
+    while let Some(item) = parse_one_op(bytes, &mut pc).ok()? {
+        match item {
+            ScriptItem::ByteArray(d) => data.push(d),
+            ScriptItem::Opcode(OP_0) => data.push(Vec::new()),
+            ScriptItem::Opcode(OP_1NEGATE) => data.push(to_script_nb(-1)),
+            ScriptItem::Opcode(op) if op.code <= OP_16.code => {
+                data.extend(small_integer(op.code).map(|n| to_script_nb(n as i64)))
+            }
+            ScriptItem::Opcode(_) => return None
+        }
+    }
+    Some(Template::NullData { data })
+}
+
+// Classify an output script, following the order of Core's Solver
+pub fn solve(bytes: &[u8]) -> Template {
+    // OP_HASH160 <20 bytes> OP_EQUAL
+    if bytes.len() == 23 && bytes[0] == OP_HASH160.code && bytes[1] == 20 && bytes[22] == OP_EQUAL.code {
+        return Template::ScriptHash { hash: bytes[2..22].to_vec() }
+    }
+
+    if let Some((version, program)) = witness_program(bytes) {
+        return match (version, program.len()) {
+            (0, 20) => Template::WitnessV0KeyHash { hash: program.to_vec() },
+            (0, 32) => Template::WitnessV0ScriptHash { hash: program.to_vec() },
+            (1, 32) => Template::WitnessV1Taproot { key: program.to_vec() },
+            (1, _) if program == [0x4e, 0x73] => Template::Anchor,
+            (0, _) => Template::NonStandard,
+            _ => Template::WitnessUnknown { version, program: program.to_vec() }
+        }
+    }
+
+    if let Some(template) = null_data(bytes) {
+        return template
+    }
+
+    // <key> OP_CHECKSIG
+    if let [size, key @ .., last] = bytes {
+        if *last == OP_CHECKSIG.code && *size as usize == key.len() && matches!(key.len(), 33 | 65) && valid_key(key) {
+            return Template::PubKey { key: key.to_vec() }
+        }
+    }
+
+    // OP_DUP OP_HASH160 <20 bytes> OP_EQUALVERIFY OP_CHECKSIG
+    if bytes.len() == 25 && bytes[0] == OP_DUP.code && bytes[1] == OP_HASH160.code && bytes[2] == 20
+        && bytes[23] == OP_EQUALVERIFY.code && bytes[24] == OP_CHECKSIG.code {
+        return Template::PubKeyHash { hash: bytes[3..23].to_vec() }
+    }
+
+    multisig(bytes).unwrap_or(Template::NonStandard)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const KEY_A: &str = "031b84c5567b126440995d3ed5aaba0565d71e1834604819ff9c17f5e9d5dd078f";
+    const KEY_B: &str = "024d4b6cd1361032ca9bd2aeb9d900aa4d45d9ead80ac9423374c451a7254d0766";
+    const HASH: &str = "751e76e8199196d454941c45d1b3a323f1433bd6";
+
+    fn solved(script: &str) -> Template {
+        solve(&hex::decode(script).unwrap())
+    }
+
+    fn bytes(s: &str) -> Vec<u8> {
+        hex::decode(s).unwrap()
+    }
+
+    #[test]
+    fn standard_templates() {
+        let templates = [
+            (format!("76a914{}88ac", HASH), Template::PubKeyHash { hash: bytes(HASH) }),
+            (format!("a914{}87", HASH), Template::ScriptHash { hash: bytes(HASH) }),
+            (format!("0014{}", HASH), Template::WitnessV0KeyHash { hash: bytes(HASH) }),
+            (String::from("00201863143c14c5166804bd19203356da136c985678cd4d27a1b8c6329604903262"),
+                Template::WitnessV0ScriptHash { hash: bytes("1863143c14c5166804bd19203356da136c985678cd4d27a1b8c6329604903262") }),
+            (String::from("5120a60869f0dbcf1dc659c9cecbaf8050135ea9e8cdc487053f1dc6880949dc684c"),
+                Template::WitnessV1Taproot { key: bytes("a60869f0dbcf1dc659c9cecbaf8050135ea9e8cdc487053f1dc6880949dc684c") }),
+            (String::from("51024e73"), Template::Anchor),
+            (String::from("5210751e76e8199196d454941c45d1b3a323"), Template::WitnessUnknown { version: 2, program: bytes("751e76e8199196d454941c45d1b3a323") }),
+            (format!("21{}ac", KEY_A), Template::PubKey { key: bytes(KEY_A) }),
+            (format!("5121{}21{}52ae", KEY_A, KEY_B), Template::Multisig { required: 1, keys: vec![bytes(KEY_A), bytes(KEY_B)] }),
+            (String::from("6a04deadbeef"), Template::NullData { data: vec![bytes("deadbeef")] }),
+            (String::from("6a"), Template::NullData { data: Vec::new() })
+        ];
+        for (script, template) in templates {
+            assert_eq!(solved(&script), template, "{}", script);
+            assert!(template.is_standard());
+            // Building the template gives back the script
+            assert_eq!(template.script().map(hex::encode), Some(script));
+        }
+        // Small numbers are data too
+        assert_eq!(solved("6a00514f"), Template::NullData { data: vec![Vec::new(), vec![1], vec![0x81]] });
+    }
+
+    #[test]
+    fn non_standard_scripts() {
+        for script in [
+            // v0 program of the wrong size
+            format!("0015{}00", HASH),
+            // Uncompressed prefix on a compressed key size
+            format!("2104{}ac", &KEY_A[2..]),
+            // More signatures required than keys
+            format!("5221{}51ae", KEY_A),
+            format!("5121{}21{}53ae", KEY_A, KEY_B),
+            format!("5121{}52ae", HASH),
+            // OP_RETURN followed by an opcode that isn't a push
+            String::from("6a76"),
+            format!("76a914{}88ad", HASH),
+            String::new()
+        ] {
+            assert_eq!(solved(&script), Template::NonStandard, "{}", script);
+        }
+        assert_eq!(Template::NonStandard.script(), None);
+    }
+
+    #[test]
+    fn display() {
+        assert_eq!(solved(&format!("0014{}", HASH)).to_string(), format!("witness_v0_keyhash 0x{}", HASH));
+        assert_eq!(solved(&format!("5121{}21{}52ae", KEY_A, KEY_B)).to_string(), format!("multisig 1-of-2 [0x{}, 0x{}]", KEY_A, KEY_B));
+        assert_eq!(solved("6a02abcd").to_string(), "nulldata [0xabcd]");
+        assert_eq!(solved("6002abcd").to_string(), "witness_unknown version 16 0xabcd");
+        assert_eq!(Template::Anchor.to_string(), "anchor");
+    }
+}
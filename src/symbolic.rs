@@ -0,0 +1,670 @@
+use std::collections::HashMap;
+use std::fmt;
+
+use crate::analyze::{ConditionStack, MAX_PATHS, MAX_STEPS};
+use crate::interpret::Interpreter;
+use crate::opcodes::*;
+use crate::script::*;
+use crate::transaction::{LOCKTIME_THRESHOLD, SEQUENCE_DISABLE_FLAG, SEQUENCE_MASK, SEQUENCE_TYPE_FLAG};
+
+// Size of the tags standing for stack elements when an opcode is run by the interpreter, no opcode run
+// that way creates a value of this size: numbers have at most 5 bytes and hashes 20 or 32
+const TAG_SIZE: usize = 9;
+
+// Value of a stack element in terms of the witness
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum Expr {
+    Const(Vec<u8>),
+    // Element of the initial stack, 0 being the top
+    Witness(usize),
+    // Result of an opcode, arguments in stack order (deepest first)
+    Op(Opcode, Vec<Expr>)
+}
+
+impl fmt::Display for Expr {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Expr::Const(bytes) if bytes.len() <= MAX_NUM_SIZE => write!(f, "{}", as_script_nb(bytes).unwrap_or(0)),
+            Expr::Const(bytes) => write!(f, "0x{}", hex::encode(bytes)),
+            Expr::Witness(i) => write!(f, "w{}", i),
+            Expr::Op(op, args) => {
+                let infix = match *op {
+                    OP_EQUAL | OP_NUMEQUAL => Some("=="),
+                    OP_NUMNOTEQUAL => Some("!="),
+                    OP_LESSTHAN => Some("<"),
+                    OP_GREATERTHAN => Some(">"),
+                    OP_LESSTHANOREQUAL => Some("<="),
+                    OP_GREATERTHANOREQUAL => Some(">="),
+                    OP_ADD => Some("+"),
+                    OP_SUB => Some("-"),
+                    OP_BOOLAND => Some("&&"),
+                    OP_BOOLOR => Some("||"),
+                    _ => None
+                };
+                match (infix, &args[..]) {
+                    (Some(infix), [a, b]) => write!(f, "({} {} {})", a, infix, b),
+                    _ => {
+                        let args: Vec<String> = args.iter().map(|a| a.to_string()).collect();
+                        write!(f, "{}({})", &op.to_string()[3..], args.join(", "))
+                    }
+                }
+            }
+        }
+    }
+}
+
+// What the witness must provide for a path to succeed
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum Requirement {
+    Preimage { hash: Opcode, digest: Vec<u8>, item: Expr },
+    Signature { key: Expr, sig: Expr },
+    // A failing CHECKSIG, the signature must be empty under NULLFAIL
+    NoSignature { key: Expr, sig: Expr },
+    Multisig { required: usize, keys: Vec<Expr>, sigs: Vec<Expr> },
+    // Inclusive bounds on a number
+    Range { value: Expr, min: Option<i64>, max: Option<i64> },
+    // nLockTime (absolute) or nSequence (relative) of the spending transaction
+    Timelock { relative: bool, value: i64 },
+    Condition { expr: Expr, holds: bool }
+}
+
+impl fmt::Display for Requirement {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Requirement::Preimage { hash, digest, item } => {
+                write!(f, "{} is the {} preimage of 0x{}", item, &hash.to_string()[3..], hex::encode(digest))
+            }
+            Requirement::Signature { key, sig } => write!(f, "{} is a signature for key {}", sig, key),
+            Requirement::NoSignature { key, sig } => write!(f, "{} is empty, no signature for key {}", sig, key),
+            Requirement::Multisig { required, keys, sigs } => {
+                let keys: Vec<String> = keys.iter().map(|k| k.to_string()).collect();
+                let sigs: Vec<String> = sigs.iter().map(|s| s.to_string()).collect();
+                write!(f, "{} are signatures for {} of the keys [{}]", sigs.join(", "), required, keys.join(", "))
+            }
+            Requirement::Range { value, min, max } => match (min, max) {
+                (Some(min), Some(max)) if min == max => write!(f, "{} == {}", value, min),
+                (Some(min), Some(max)) => write!(f, "{} <= {} <= {}", min, value, max),
+                (Some(min), None) => write!(f, "{} >= {}", value, min),
+                (None, Some(max)) => write!(f, "{} <= {}", value, max),
+                (None, None) => write!(f, "{} is a number", value)
+            },
+            Requirement::Timelock { relative: false, value } if *value < LOCKTIME_THRESHOLD => {
+                write!(f, "spent at block height {} or later", value)
+            }
+            Requirement::Timelock { relative: false, value } => write!(f, "spent at time {} or later", value),
+            Requirement::Timelock { relative: true, value } if value & SEQUENCE_TYPE_FLAG != 0 => {
+                write!(f, "spent {} seconds or more after the output confirms", (value & SEQUENCE_MASK) * 512)
+            }
+            Requirement::Timelock { relative: true, value } => {
+                write!(f, "spent {} blocks or more after the output confirms", value & SEQUENCE_MASK)
+            }
+            Requirement::Condition { expr, holds: true } => write!(f, "{} is true", expr),
+            Requirement::Condition { expr, holds: false } => write!(f, "{} is false", expr)
+        }
+    }
+}
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum SymbolicOutcome {
+    // The path succeeds when its constraints hold
+    Spendable,
+    // The path fails whatever the witness
+    Fails { index: usize, error: ScriptError },
+    // The path needs a constraint and its opposite
+    Contradiction { index: usize },
+    // The engine can't follow the item at this index
+    Unsupported { index: usize, reason: String }
+}
+
+#[derive(Clone, Debug)]
+pub struct SymbolicPath {
+    // Index of every OP_IF/OP_NOTIF/OP_IFDUP decided by the witness and the branch taken
+    pub branches: Vec<(usize, bool)>,
+    // Number of initial stack elements the path reads
+    pub witness_items: usize,
+    // Expressions that must be true (or false) for the path to succeed
+    pub constraints: Vec<(Expr, bool)>,
+    pub outcome: SymbolicOutcome
+}
+
+impl SymbolicPath {
+    // Constraints grouped into preimages, signatures, ranges and timelocks
+    pub fn requirements(&self) -> Vec<Requirement> {
+        self.constraints.iter().map(|(expr, holds)| requirement(expr, *holds)).collect()
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct SymbolicExecution {
+    pub paths: Vec<SymbolicPath>,
+    // Whether some paths were left out because of MAX_PATHS or MAX_STEPS
+    pub truncated: bool
+}
+
+impl SymbolicExecution {
+    pub fn spendable_paths(&self) -> impl Iterator<Item = &SymbolicPath> {
+        self.paths.iter().filter(|p| p.outcome == SymbolicOutcome::Spendable)
+    }
+}
+
+fn number(expr: &Expr) -> Option<i64> {
+    match expr {
+        Expr::Const(bytes) => as_script_nb(bytes).ok(),
+        _ => None
+    }
+}
+
+fn requirement(expr: &Expr, holds: bool) -> Requirement {
+    let condition = Requirement::Condition { expr: expr.clone(), holds };
+    let (op, args) = match expr {
+        Expr::Op(op, args) => (*op, &args[..]),
+        _ => return condition
+    };
+
+    match (op, args, holds) {
+        (OP_EQUAL, [a, b], true) => {
+            let preimage = |hashed: &Expr, digest: &Expr| match (hashed, digest) {
+                (Expr::Op(hash, item), Expr::Const(digest)) if (OP_RIPEMD160.code..=OP_HASH256.code).contains(&hash.code) => {
+                    Some(Requirement::Preimage { hash: *hash, digest: digest.clone(), item: item[0].clone() })
+                }
+                _ => None
+            };
+            preimage(a, b).or_else(|| preimage(b, a)).unwrap_or(condition)
+        }
+        (OP_CHECKSIG, [sig, key], true) => Requirement::Signature { key: key.clone(), sig: sig.clone() },
+        (OP_CHECKSIG, [sig, key], false) => Requirement::NoSignature { key: key.clone(), sig: sig.clone() },
+        (OP_CHECKMULTISIG, _, true) => {
+            // dummy <sig>... <m> <key>... <n>
+            let n = number(&args[args.len() - 1]).unwrap_or(0) as usize;
+            let m = number(&args[args.len() - 2 - n]).unwrap_or(0) as usize;
+            Requirement::Multisig {
+                required: m,
+                keys: args[args.len() - 1 - n..args.len() - 1].to_vec(),
+                sigs: args[1..1 + m].to_vec()
+            }
+        }
+        (OP_CHECKLOCKTIMEVERIFY, [Expr::Const(n)], _) => Requirement::Timelock {
+            relative: false,
+            value: as_script_nb(n).unwrap_or(0)
+        },
+        (OP_CHECKSEQUENCEVERIFY, [Expr::Const(n)], _) => Requirement::Timelock {
+            relative: true,
+            value: as_script_nb(n).unwrap_or(0)
+        },
+        (OP_WITHIN, [value, min, max], true) => match (number(min), number(max)) {
+            (Some(min), Some(max)) => Requirement::Range { value: value.clone(), min: Some(min), max: Some(max - 1) },
+            _ => condition
+        },
+        (OP_LESSTHAN | OP_GREATERTHAN | OP_LESSTHANOREQUAL | OP_GREATERTHANOREQUAL | OP_NUMEQUAL, [a, b], _) => {
+            // Relation "value op c", turned around when the constant is on the left
+            let (value, c, op) = match (number(a), number(b)) {
+                (None, Some(c)) => (a, c, op),
+                (Some(c), None) => (b, c, match op {
+                    OP_LESSTHAN => OP_GREATERTHAN,
+                    OP_GREATERTHAN => OP_LESSTHAN,
+                    OP_LESSTHANOREQUAL => OP_GREATERTHANOREQUAL,
+                    OP_GREATERTHANOREQUAL => OP_LESSTHANOREQUAL,
+                    _ => op
+                }),
+                _ => return condition
+            };
+            let (min, max) = match (op, holds) {
+                (OP_LESSTHAN, true) | (OP_GREATERTHANOREQUAL, false) => (None, Some(c - 1)),
+                (OP_LESSTHANOREQUAL, true) | (OP_GREATERTHAN, false) => (None, Some(c)),
+                (OP_GREATERTHAN, true) | (OP_LESSTHANOREQUAL, false) => (Some(c + 1), None),
+                (OP_GREATERTHANOREQUAL, true) | (OP_LESSTHAN, false) => (Some(c), None),
+                (OP_NUMEQUAL, true) => (Some(c), Some(c)),
+                _ => return condition
+            };
+            Requirement::Range { value: value.clone(), min, max }
+        }
+        _ => condition
+    }
+}
+
+// State of one path through the script
+#[derive(Clone)]
+struct Path {
+    index: usize,
+    main: Vec<Expr>,
+    alt: Vec<Expr>,
+    condition_stack: ConditionStack,
+    witness_items: usize,
+    constraints: Vec<(Expr, bool)>,
+    branches: Vec<(usize, bool)>
+}
+
+// Reasons to stop following a path
+enum Stop {
+    Fails(ScriptError),
+    Contradiction,
+    Unsupported(String)
+}
+
+// OP_IF/OP_NOTIF/OP_IFDUP on a value coming from the witness
+struct Fork {
+    op: Opcode,
+    condition: Expr
+}
+
+impl From<ScriptError> for Stop {
+    fn from(error: ScriptError) -> Self {
+        Stop::Fails(error)
+    }
+}
+
+impl Path {
+    // Make sure the main stack holds `n` items, taking the missing ones from the witness
+    fn ensure(&mut self, n: usize) {
+        if self.main.len() < n {
+            let missing = n - self.main.len();
+            let items = (self.witness_items..self.witness_items + missing).rev().map(Expr::Witness);
+            self.main.splice(0..0, items);
+            self.witness_items += missing;
+        }
+    }
+
+    fn pop(&mut self) -> Expr {
+        self.ensure(1);
+        self.main.pop().unwrap()
+    }
+
+    // Record that `expr` must be true (or false), `error` is the failure when it is a constant with the wrong value
+    fn assume(&mut self, expr: Expr, holds: bool, error: ScriptError) -> Result<(), Stop> {
+        match expr {
+            Expr::Const(bytes) if as_bool(&bytes) == holds => Ok(()),
+            Expr::Const(_) => Err(Stop::Fails(error)),
+            Expr::Op(OP_NOT, mut args) => self.assume(args.pop().unwrap(), !holds, error),
+            Expr::Op(OP_0NOTEQUAL, mut args) => self.assume(args.pop().unwrap(), holds, error),
+            expr => {
+                if self.constraints.contains(&(expr.clone(), !holds)) {
+                    return Err(Stop::Contradiction)
+                }
+                if !self.constraints.contains(&(expr.clone(), holds)) {
+                    self.constraints.push((expr, holds));
+                }
+                Ok(())
+            }
+        }
+    }
+
+    // Run an opcode with the interpreter. Every element is replaced by a tag the interpreter can only
+    // move around, except the `reads` constants on top of the main stack that the opcode reads, and the
+    // elements left are mapped back to the expression whose tag they hold
+    fn run_concrete(&mut self, op: Opcode, reads: usize) -> Result<(), Stop> {
+        let mut symbols = HashMap::new();
+        let reads_from = self.main.len() - reads;
+        let mut lower = |(i, expr): (usize, &Expr)| match expr {
+            Expr::Const(bytes) if i >= reads_from => bytes.clone(),
+            expr => {
+                let tag = [&[0xff; TAG_SIZE - 4][..], &(symbols.len() as u32).to_le_bytes()].concat();
+                symbols.insert(tag.clone(), expr.clone());
+                tag
+            }
+        };
+        let main = self.main.iter().enumerate().map(&mut lower).collect();
+        let alt = self.alt.iter().map(|expr| (0, expr)).map(&mut lower).collect();
+
+        let mut interpreter = Interpreter::new(&[op.code]);
+        interpreter.stack_mut().main = main;
+        interpreter.stack_mut().alt = alt;
+        interpreter.step()?;
+
+        let lift = |bytes: &Vec<u8>| symbols.get(bytes).cloned().unwrap_or_else(|| Expr::Const(bytes.clone()));
+        let state = interpreter.state();
+        self.main = state.main_stack().iter().map(lift).collect();
+        self.alt = state.alt_stack().iter().map(lift).collect();
+        Ok(())
+    }
+
+    // Follow one branch of a fork: the OP_IF branch or the OP_IFDUP duplication when `taken`
+    fn take(&mut self, fork: &Fork, taken: bool) -> Result<(), Stop> {
+        self.branches.push((self.index, taken));
+        let holds = taken != (fork.op == OP_NOTIF);
+        self.assume(fork.condition.clone(), holds, ScriptError::UnknownError)?;
+        match fork.op {
+            OP_IFDUP if taken => self.main.push(fork.condition.clone()),
+            OP_IFDUP => (),
+            _ => self.condition_stack.push(taken)
+        }
+        Ok(())
+    }
+
+    // Analyse one item, returns a fork when the witness decides which way the path goes
+    fn execute(&mut self, item: &ScriptItem) -> Result<Option<Fork>, Stop> {
+        let executing = self.condition_stack.all_true();
+        let op = match item {
+            ScriptItem::ByteArray(b) if b.len() > MAX_SCRIPT_ELEMENT_SIZE => return Err(Stop::Fails(ScriptError::PushSize)),
+            ScriptItem::ByteArray(b) => {
+                if executing {
+                    self.main.push(Expr::Const(b.clone()));
+                }
+                return Ok(None)
+            }
+            ScriptItem::Opcode(op) => *op
+        };
+
+        if op.is_disabled() {
+            return Err(Stop::Fails(ScriptError::DisabledOpcode))
+        }
+        if op.behaviour(ScriptContext::Legacy) == Behaviour::AlwaysInvalid {
+            return Err(Stop::Fails(ScriptError::BadOpcode))
+        }
+
+        match op {
+            OP_IF | OP_NOTIF if executing => {
+                let condition = self.pop();
+                if let Expr::Const(b) = &condition {
+                    let taken = as_bool(b) != (op == OP_NOTIF);
+                    self.condition_stack.push(taken);
+                    return Ok(None)
+                }
+                return Ok(Some(Fork { op, condition }))
+            }
+            OP_IF | OP_NOTIF => self.condition_stack.push(false),
+            OP_ELSE => self.condition_stack.toggle_top()?,
+            OP_ENDIF => self.condition_stack.pop()?,
+            _ if !executing => (),
+
+            OP_VERIFY => {
+                let v = self.pop();
+                self.assume(v, true, ScriptError::Verify)?;
+            }
+            OP_RETURN => return Err(Stop::Fails(ScriptError::OpReturn)),
+            OP_IFDUP => {
+                self.ensure(1);
+                let top = self.main.last().unwrap().clone();
+                if let Expr::Const(b) = &top {
+                    if as_bool(b) {
+                        self.main.push(top);
+                    }
+                    return Ok(None)
+                }
+                return Ok(Some(Fork { op, condition: top }))
+            }
+            OP_PICK | OP_ROLL => {
+                self.ensure(1);
+                let n = match number(self.main.last().unwrap()) {
+                    Some(n) => n,
+                    None => return Err(Stop::Unsupported(format!("{} index comes from the witness", op)))
+                };
+                // The stacks can't hold that many elements
+                if n >= MAX_STACK_SIZE as i64 {
+                    return Err(Stop::Fails(ScriptError::InvalidStackOperation))
+                }
+                self.ensure(n.max(0) as usize + 2);
+                self.run_concrete(op, 1)?;
+            }
+            OP_CHECKLOCKTIMEVERIFY | OP_CHECKSEQUENCEVERIFY => {
+                self.ensure(1);
+                let top = self.main.last().unwrap().clone();
+                match number(&top) {
+                    Some(n) if n < 0 => return Err(Stop::Fails(ScriptError::NegativeLocktime)),
+                    // The relative locktime check is disabled by this flag
+                    Some(n) if op == OP_CHECKSEQUENCEVERIFY && n & SEQUENCE_DISABLE_FLAG != 0 => (),
+                    _ => self.assume(Expr::Op(op, vec![top]), true, ScriptError::UnsatisfiedLocktime)?
+                }
+            }
+            OP_CHECKSIG | OP_CHECKSIGVERIFY => {
+                let key = self.pop();
+                let sig = self.pop();
+                let result = Expr::Op(OP_CHECKSIG, vec![sig, key]);
+                if op == OP_CHECKSIGVERIFY {
+                    self.assume(result, true, ScriptError::CheckSigVerify)?;
+                } else {
+                    self.main.push(result);
+                }
+            }
+            OP_CHECKMULTISIG | OP_CHECKMULTISIGVERIFY => {
+                self.ensure(1);
+                let keys = number(self.main.last().unwrap())
+                    .ok_or_else(|| Stop::Unsupported(String::from("number of keys comes from the witness")))?;
+                if !(0..=20).contains(&keys) {
+                    return Err(Stop::Fails(ScriptError::PubkeyCount))
+                }
+                self.ensure(keys as usize + 2);
+                let sigs = number(&self.main[self.main.len() - keys as usize - 2])
+                    .ok_or_else(|| Stop::Unsupported(String::from("number of signatures comes from the witness")))?;
+                if sigs < 0 || sigs > keys {
+                    return Err(Stop::Fails(ScriptError::SigCount))
+                }
+                let n = (keys + sigs) as usize + 3;
+                self.ensure(n);
+                let args = self.main.split_off(self.main.len() - n);
+                let result = Expr::Op(OP_CHECKMULTISIG, args);
+                if op == OP_CHECKMULTISIGVERIFY {
+                    self.assume(result, true, ScriptError::CheckMultisigVerify)?;
+                } else {
+                    self.main.push(result);
+                }
+            }
+            OP_SIZE => {
+                self.ensure(1);
+                let top = self.main.last().unwrap().clone();
+                match top {
+                    Expr::Const(b) => self.main.push(Expr::Const(to_script_nb(b.len() as i64))),
+                    top => self.main.push(Expr::Op(OP_SIZE, vec![top]))
+                }
+            }
+            OP_DEPTH => return Err(Stop::Unsupported(String::from("OP_DEPTH depends on the witness size"))),
+            // Opcodes that only move elements around, or reading constants only
+            _ => {
+                let inputs = op.stack_effect().map_or(0, |(inputs, _)| inputs);
+                self.ensure(inputs);
+                let args = &self.main[self.main.len() - inputs..];
+                let shuffle = op.category() == Category::Stack || op.category() == Category::Constant;
+                if shuffle {
+                    self.run_concrete(op, 0)?;
+                    return Ok(None)
+                }
+                if args.iter().all(|a| matches!(a, Expr::Const(_))) {
+                    self.run_concrete(op, inputs)?;
+                    return Ok(None)
+                }
+
+                let args = self.main.split_off(self.main.len() - inputs);
+                let (base, error) = match op {
+                    OP_EQUALVERIFY => (OP_EQUAL, ScriptError::EqualVerify),
+                    OP_NUMEQUALVERIFY => (OP_NUMEQUAL, ScriptError::NumEqualVerify),
+                    _ => (op, ScriptError::UnknownError)
+                };
+                let result = Expr::Op(base, args);
+                if base != op {
+                    self.assume(result, true, error)?;
+                } else {
+                    self.main.push(result);
+                }
+            }
+        }
+        Ok(None)
+    }
+
+    fn stop(self, stop: Stop) -> SymbolicPath {
+        let index = self.index;
+        self.finish(match stop {
+            Stop::Fails(error) => SymbolicOutcome::Fails { index, error },
+            Stop::Contradiction => SymbolicOutcome::Contradiction { index },
+            Stop::Unsupported(reason) => SymbolicOutcome::Unsupported { index, reason }
+        })
+    }
+
+    fn finish(self, outcome: SymbolicOutcome) -> SymbolicPath {
+        SymbolicPath {
+            branches: self.branches,
+            witness_items: self.witness_items,
+            constraints: self.constraints,
+            outcome
+        }
+    }
+}
+
+// Run a script on a symbolic witness and collect, for every path, the constraints
+// the witness and the spending transaction must meet
+pub fn execute_symbolic(script: &Script) -> SymbolicExecution {
+    let start = Path {
+        index: 0,
+        main: Vec::new(),
+        alt: Vec::new(),
+        condition_stack: ConditionStack::default(),
+        witness_items: 0,
+        constraints: Vec::new(),
+        branches: Vec::new()
+    };
+
+    let mut paths = Vec::new();
+    let mut pending = vec![start];
+    let mut truncated = false;
+    let mut steps = 0;
+
+    while let Some(mut path) = pending.pop() {
+        if steps >= MAX_STEPS {
+            truncated = true;
+            break
+        }
+        let result = loop {
+            steps += 1;
+            let item = match script.get(path.index) {
+                Some(item) => item,
+                None if !path.condition_stack.is_empty() => break Err(Stop::Fails(ScriptError::UnbalancedConditional)),
+                None => {
+                    // Core's verdict: the top element must be true
+                    let top = path.pop();
+                    break path.assume(top, true, ScriptError::EvalFalse)
+                }
+            };
+            match path.execute(item) {
+                Ok(Some(fork)) => {
+                    if paths.len() + pending.len() + 1 < MAX_PATHS {
+                        let mut other = path.clone();
+                        match other.take(&fork, false) {
+                            Err(stop) => paths.push(other.stop(stop)),
+                            Ok(()) => {
+                                other.index += 1;
+                                pending.push(other);
+                            }
+                        }
+                    } else {
+                        truncated = true;
+                    }
+                    if let Err(stop) = path.take(&fork, true) {
+                        break Err(stop)
+                    }
+                }
+                Ok(None) => (),
+                Err(stop) => break Err(stop)
+            }
+            path.index += 1;
+        };
+
+        paths.push(match result {
+            Ok(()) => path.finish(SymbolicOutcome::Spendable),
+            Err(stop) => path.stop(stop)
+        });
+    }
+
+    paths.sort_by(|a, b| a.branches.iter().map(|(i, c)| (*i, !*c)).cmp(b.branches.iter().map(|(i, c)| (*i, !*c))));
+    SymbolicExecution { paths, truncated }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::asm::parse_asm_script;
+
+    fn execute(text: &str) -> SymbolicExecution {
+        execute_symbolic(&parse_asm_script(text).unwrap())
+    }
+
+    #[test]
+    fn negative_pick_and_roll_indices_fail_the_path() {
+        for text in ["OP_1NEGATE OP_PICK", "OP_1NEGATE OP_ROLL", "OP_1 OP_2 0x81 OP_PICK"] {
+            let execution = execute(text);
+            assert_eq!(execution.paths.len(), 1);
+            let index = text.split(' ').count() - 1;
+            assert_eq!(execution.paths[0].outcome, SymbolicOutcome::Fails { index, error: ScriptError::InvalidStackOperation });
+        }
+    }
+
+    #[test]
+    fn huge_pick_and_roll_indices_fail_the_path() {
+        for op in ["OP_PICK", "OP_ROLL"] {
+            let execution = execute(&format!("0xffffff7f {}", op));
+            assert_eq!(execution.paths[0].outcome, SymbolicOutcome::Fails { index: 1, error: ScriptError::InvalidStackOperation });
+            assert_eq!(execution.paths[0].witness_items, 0);
+        }
+    }
+
+    #[test]
+    fn constants_are_not_mistaken_for_witness_items() {
+        // Bytes that looked like the placeholders of symbolic values, with and without a witness item around
+        let execution = execute("0xff73796d00000000 OP_DUP OP_EQUAL");
+        assert_eq!(execution.paths[0].outcome, SymbolicOutcome::Spendable);
+        let execution = execute("0xffffffffff00000000 OP_SWAP OP_2DUP OP_DROP OP_DROP OP_VERIFY OP_SIZE OP_9 OP_EQUAL");
+        let path = &execution.paths[0];
+        assert_eq!(path.outcome, SymbolicOutcome::Spendable);
+        assert_eq!(path.constraints, vec![(Expr::Witness(0), true)]);
+        let execution = execute("OP_2 0xffffffffff00000000 OP_DUP OP_SIZE OP_NIP OP_ROT OP_PICK");
+        assert_eq!(execution.paths[0].outcome, SymbolicOutcome::Spendable);
+        assert_eq!(execution.paths[0].witness_items, 1);
+        assert_eq!(execution.paths[0].constraints, vec![(Expr::Witness(0), true)]);
+    }
+
+    #[test]
+    fn pick_and_roll_read_the_witness() {
+        let execution = execute("OP_2 OP_PICK OP_VERIFY OP_1 OP_ROLL");
+        let path = &execution.paths[0];
+        assert_eq!(path.outcome, SymbolicOutcome::Spendable);
+        assert_eq!(path.witness_items, 3);
+        assert_eq!(path.constraints, vec![(Expr::Witness(2), true), (Expr::Witness(1), true)]);
+
+        let execution = execute("OP_PICK");
+        assert!(matches!(execution.paths[0].outcome, SymbolicOutcome::Unsupported { index: 0, .. }));
+    }
+
+    #[test]
+    fn pay_to_pubkey_hash() {
+        let hash = "0x751e76e8199196d454941c45d1b3a323f1433bd6";
+        let execution = execute(&format!("OP_DUP OP_HASH160 {} OP_EQUALVERIFY OP_CHECKSIG", hash));
+        assert_eq!(execution.paths.len(), 1);
+        let requirements = execution.paths[0].requirements();
+        assert_eq!(requirements[0], Requirement::Preimage {
+            hash: OP_HASH160,
+            digest: hex::decode(&hash[2..]).unwrap(),
+            item: Expr::Witness(0)
+        });
+        assert_eq!(requirements[1], Requirement::Signature { key: Expr::Witness(0), sig: Expr::Witness(1) });
+        assert_eq!(requirements[1].to_string(), "w1 is a signature for key w0");
+    }
+
+    #[test]
+    fn branches_and_timelocks() {
+        let execution = execute("OP_IF 0x9000 OP_CHECKSEQUENCEVERIFY OP_DROP OP_ELSE 0x40e20100 OP_CHECKLOCKTIMEVERIFY OP_DROP OP_ENDIF OP_1");
+        assert_eq!(execution.paths.len(), 2);
+        assert_eq!(execution.paths[0].branches, vec![(0, true)]);
+        assert_eq!(execution.paths[0].requirements()[1].to_string(), "spent 144 blocks or more after the output confirms");
+        assert_eq!(execution.paths[1].branches, vec![(0, false)]);
+        assert_eq!(execution.paths[1].requirements()[1].to_string(), "spent at block height 123456 or later");
+        assert_eq!(execution.spendable_paths().count(), 2);
+    }
+
+    #[test]
+    fn deep_nesting_is_truncated() {
+        // Every path is as long as the script, the step budget leaves most of them out
+        let nested = [vec![ScriptItem::Opcode(OP_IF); 3000], vec![ScriptItem::Opcode(OP_1)], vec![ScriptItem::Opcode(OP_ENDIF); 3000]].concat();
+        let execution = execute_symbolic(&nested);
+        assert!(execution.truncated);
+        assert!(execution.paths.len() < 1000);
+        assert_eq!(execution.paths[0].constraints.len(), 3000);
+        assert_eq!(execution.paths[0].outcome, SymbolicOutcome::Spendable);
+    }
+
+    #[test]
+    fn contradictions_and_failures() {
+        let execution = execute("OP_DUP OP_VERIFY OP_NOT OP_VERIFY");
+        assert_eq!(execution.paths[0].outcome, SymbolicOutcome::Contradiction { index: 3 });
+        let execution = execute("OP_1 OP_IF OP_RETURN OP_ENDIF");
+        assert_eq!(execution.paths[0].outcome, SymbolicOutcome::Fails { index: 2, error: ScriptError::OpReturn });
+        let execution = execute("OP_IF OP_1");
+        assert!(execution.paths.iter().all(|p| p.outcome == SymbolicOutcome::Fails { index: 2, error: ScriptError::UnbalancedConditional }));
+    }
+}
@@ -0,0 +1,515 @@
+use bitcoin_hashes::{sha256d, Hash};
+use secp256k1::{ecdsa, Message, PublicKey, Secp256k1, SecretKey};
+
+use crate::opcodes::*;
+use crate::parse::parse_one_op;
+use crate::script::{encode_push, ScriptError};
+
+pub const SIGHASH_ALL: u8 = 0x01;
+pub const SIGHASH_NONE: u8 = 0x02;
+pub const SIGHASH_SINGLE: u8 = 0x03;
+pub const SIGHASH_ANYONECANPAY: u8 = 0x80;
+
+// Locktimes below this are block heights, above are timestamps
+pub const LOCKTIME_THRESHOLD: i64 = 500_000_000;
+// nSequence of an input that doesn't enable nLockTime
+pub const SEQUENCE_FINAL: u32 = 0xffffffff;
+// nSequence flags of BIP 68
+pub const SEQUENCE_DISABLE_FLAG: i64 = 1 << 31;
+pub const SEQUENCE_TYPE_FLAG: i64 = 1 << 22;
+pub const SEQUENCE_MASK: i64 = 0xffff;
+
+#[derive(Debug, Clone, Default, Eq, PartialEq)]
+pub struct OutPoint {
+    // Hash of the previous transaction, in serialization order
+    pub txid: [u8; 32],
+    pub vout: u32
+}
+
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct TxIn {
+    pub previous_output: OutPoint,
+    pub script_sig: Vec<u8>,
+    pub sequence: u32,
+    pub witness: Vec<Vec<u8>>
+}
+
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct TxOut {
+    pub value: u64,
+    pub script_pubkey: Vec<u8>
+}
+
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct Transaction {
+    pub version: i32,
+    pub inputs: Vec<TxIn>,
+    pub outputs: Vec<TxOut>,
+    pub lock_time: u32
+}
+
+// Signature hashing scheme: legacy scripts and P2SH, or segwit v0 (BIP 143)
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum SigVersion {
+    Base,
+    WitnessV0
+}
+
+pub(crate) fn write_compact_size(bytes: &mut Vec<u8>, n: usize) {
+    match n {
+        0..=0xfc => bytes.push(n as u8),
+        0xfd..=0xffff => {
+            bytes.push(0xfd);
+            bytes.extend((n as u16).to_le_bytes());
+        }
+        0x10000..=0xffffffff => {
+            bytes.push(0xfe);
+            bytes.extend((n as u32).to_le_bytes());
+        }
+        _ => {
+            bytes.push(0xff);
+            bytes.extend((n as u64).to_le_bytes());
+        }
+    }
+}
+
+fn write_script(bytes: &mut Vec<u8>, script: &[u8]) {
+    write_compact_size(bytes, script.len());
+    bytes.extend_from_slice(script);
+}
+
+fn write_output(bytes: &mut Vec<u8>, output: &TxOut) {
+    bytes.extend(output.value.to_le_bytes());
+    write_script(bytes, &output.script_pubkey);
+}
+
+fn write_outpoint(bytes: &mut Vec<u8>, outpoint: &OutPoint) {
+    bytes.extend(outpoint.txid);
+    bytes.extend(outpoint.vout.to_le_bytes());
+}
+
+// Size of a witness stack once serialized
+pub fn witness_size(witness: &[Vec<u8>]) -> usize {
+    let mut bytes = Vec::new();
+    write_compact_size(&mut bytes, witness.len());
+    for item in witness {
+        write_script(&mut bytes, item);
+    }
+    bytes.len()
+}
+
+// Remove every push of `data` from the script, only matching at instruction boundaries
+pub fn find_and_delete(script: &[u8], data: &[u8]) -> Vec<u8> {
+    let pattern = encode_push(data);
+    let mut result = Vec::with_capacity(script.len());
+    let mut pc = 0;
+    loop {
+        while script[pc..].starts_with(&pattern) {
+            pc += pattern.len();
+        }
+        let start = pc;
+        match parse_one_op(script, &mut pc) {
+            Ok(Some(_)) => result.extend_from_slice(&script[start..pc]),
+            _ => {
+                result.extend_from_slice(&script[start..]);
+                return result
+            }
+        }
+    }
+}
+
+impl Transaction {
+    // Serialization without witnesses, as hashed into the txid
+    pub fn serialize(&self) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        bytes.extend(self.version.to_le_bytes());
+        write_compact_size(&mut bytes, self.inputs.len());
+        for input in &self.inputs {
+            write_outpoint(&mut bytes, &input.previous_output);
+            write_script(&mut bytes, &input.script_sig);
+            bytes.extend(input.sequence.to_le_bytes());
+        }
+        write_compact_size(&mut bytes, self.outputs.len());
+        for output in &self.outputs {
+            write_output(&mut bytes, output);
+        }
+        bytes.extend(self.lock_time.to_le_bytes());
+        bytes
+    }
+
+    pub fn txid(&self) -> [u8; 32] {
+        sha256d::Hash::hash(&self.serialize()).into_inner()
+    }
+
+    // Hash signed by a signature of `input` with the given sighash type
+    pub fn signature_hash(&self, input: usize, script_code: &[u8], amount: u64, sighash_type: u8, sig_version: SigVersion) -> [u8; 32] {
+        match sig_version {
+            SigVersion::Base => self.legacy_signature_hash(input, script_code, sighash_type),
+            SigVersion::WitnessV0 => self.segwit_signature_hash(input, script_code, amount, sighash_type)
+        }
+    }
+
+    fn legacy_signature_hash(&self, input: usize, script_code: &[u8], sighash_type: u8) -> [u8; 32] {
+        let base_type = sighash_type & 0x1f;
+        let anyone_can_pay = sighash_type & SIGHASH_ANYONECANPAY != 0;
+        // Core signs the number one when there is no output matching the input
+        if base_type == SIGHASH_SINGLE && input >= self.outputs.len() {
+            let mut one = [0; 32];
+            one[0] = 1;
+            return one
+        }
+
+        // OP_CODESEPARATORs are left out of the signed script
+        let mut signed_script = Vec::with_capacity(script_code.len());
+        let mut pc = 0;
+        while pc < script_code.len() {
+            let start = pc;
+            if parse_one_op(script_code, &mut pc).is_err() {
+                signed_script.extend_from_slice(&script_code[start..]);
+                break
+            }
+            if script_code[start] != OP_CODESEPARATOR.code {
+                signed_script.extend_from_slice(&script_code[start..pc]);
+            }
+        }
+
+        let mut bytes = Vec::new();
+        bytes.extend(self.version.to_le_bytes());
+        let inputs: Vec<usize> = if anyone_can_pay { vec![input] } else { (0..self.inputs.len()).collect() };
+        write_compact_size(&mut bytes, inputs.len());
+        for i in inputs {
+            write_outpoint(&mut bytes, &self.inputs[i].previous_output);
+            write_script(&mut bytes, if i == input { &signed_script } else { &[] });
+            let other_input = i != input && (base_type == SIGHASH_NONE || base_type == SIGHASH_SINGLE);
+            bytes.extend(if other_input { 0 } else { self.inputs[i].sequence }.to_le_bytes());
+        }
+        match base_type {
+            SIGHASH_NONE => write_compact_size(&mut bytes, 0),
+            SIGHASH_SINGLE => {
+                write_compact_size(&mut bytes, input + 1);
+                for _ in 0..input {
+                    write_output(&mut bytes, &TxOut { value: u64::MAX, script_pubkey: Vec::new() });
+                }
+                write_output(&mut bytes, &self.outputs[input]);
+            }
+            _ => {
+                write_compact_size(&mut bytes, self.outputs.len());
+                for output in &self.outputs {
+                    write_output(&mut bytes, output);
+                }
+            }
+        }
+        bytes.extend(self.lock_time.to_le_bytes());
+        bytes.extend((sighash_type as u32).to_le_bytes());
+        sha256d::Hash::hash(&bytes).into_inner()
+    }
+
+    fn segwit_signature_hash(&self, input: usize, script_code: &[u8], amount: u64, sighash_type: u8) -> [u8; 32] {
+        let base_type = sighash_type & 0x1f;
+        let anyone_can_pay = sighash_type & SIGHASH_ANYONECANPAY != 0;
+        let hash = |bytes: Vec<u8>| sha256d::Hash::hash(&bytes).into_inner();
+
+        let mut prevouts = Vec::new();
+        let mut sequences = Vec::new();
+        for txin in &self.inputs {
+            write_outpoint(&mut prevouts, &txin.previous_output);
+            sequences.extend(txin.sequence.to_le_bytes());
+        }
+        let mut outputs = Vec::new();
+        match base_type {
+            SIGHASH_SINGLE if input < self.outputs.len() => write_output(&mut outputs, &self.outputs[input]),
+            SIGHASH_SINGLE | SIGHASH_NONE => (),
+            _ => self.outputs.iter().for_each(|output| write_output(&mut outputs, output))
+        }
+
+        let hash_prevouts = if anyone_can_pay { [0; 32] } else { hash(prevouts) };
+        let all_sequences = !anyone_can_pay && base_type != SIGHASH_SINGLE && base_type != SIGHASH_NONE;
+        let hash_sequence = if all_sequences { hash(sequences) } else { [0; 32] };
+        let hash_outputs = if outputs.is_empty() { [0; 32] } else { hash(outputs) };
+
+        let mut bytes = Vec::new();
+        bytes.extend(self.version.to_le_bytes());
+        bytes.extend(hash_prevouts);
+        bytes.extend(hash_sequence);
+        write_outpoint(&mut bytes, &self.inputs[input].previous_output);
+        write_script(&mut bytes, script_code);
+        bytes.extend(amount.to_le_bytes());
+        bytes.extend(self.inputs[input].sequence.to_le_bytes());
+        bytes.extend(hash_outputs);
+        bytes.extend(self.lock_time.to_le_bytes());
+        bytes.extend((sighash_type as u32).to_le_bytes());
+        hash(bytes)
+    }
+}
+
+// Input of a transaction being verified, gives meaning to signatures and timelocks
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct TransactionContext {
+    pub tx: Transaction,
+    pub input: usize,
+    // Value of the spent output, signed by segwit signatures
+    pub amount: u64,
+    pub sig_version: SigVersion
+}
+
+impl TransactionContext {
+    pub fn new(tx: Transaction, input: usize, amount: u64, sig_version: SigVersion) -> TransactionContext {
+        TransactionContext { tx, input, amount, sig_version }
+    }
+
+    // DER signature followed by the sighash type, with a low S and a low R like Core's wallet
+    pub fn sign(&self, secret_key: &SecretKey, script_code: &[u8], sighash_type: u8) -> Vec<u8> {
+        let hash = self.tx.signature_hash(self.input, script_code, self.amount, sighash_type, self.sig_version);
+        let message = Message::from_slice(&hash).unwrap();
+        let signature = Secp256k1::signing_only().sign_ecdsa_low_r(&message, secret_key);
+        let mut bytes = signature.serialize_der().to_vec();
+        bytes.push(sighash_type);
+        bytes
+    }
+
+    // Core's signature check with strict encodings (DERSIG, LOW_S, STRICTENC)
+    // An empty signature is a valid encoding for a failing check
+    pub fn check_signature(&self, sig: &[u8], pubkey: &[u8], script_code: &[u8]) -> Result<bool, ScriptError> {
+        let (sighash_type, der) = match sig.split_last() {
+            Some(split) => split,
+            None => return Ok(false)
+        };
+        let signature = ecdsa::Signature::from_der(der).map_err(|_| ScriptError::SigDer)?;
+        let mut normalized = signature;
+        normalized.normalize_s();
+        if normalized != signature {
+            return Err(ScriptError::SigHighS)
+        }
+        if !(SIGHASH_ALL..=SIGHASH_SINGLE).contains(&(sighash_type & !SIGHASH_ANYONECANPAY)) {
+            return Err(ScriptError::SigHashtype)
+        }
+        let pubkey = PublicKey::from_slice(pubkey).map_err(|_| ScriptError::PubkeyType)?;
+
+        let hash = self.tx.signature_hash(self.input, script_code, self.amount, *sighash_type, self.sig_version);
+        let message = Message::from_slice(&hash).unwrap();
+        Ok(Secp256k1::verification_only().verify_ecdsa(&message, &signature, &pubkey).is_ok())
+    }
+
+    // OP_CHECKLOCKTIMEVERIFY (BIP 65)
+    pub fn check_lock_time(&self, lock_time: i64) -> bool {
+        let tx_lock_time = self.tx.lock_time as i64;
+        // Heights and timestamps can't be compared
+        if (tx_lock_time < LOCKTIME_THRESHOLD) != (lock_time < LOCKTIME_THRESHOLD) {
+            return false
+        }
+        // A final input would make the transaction ignore its nLockTime
+        lock_time <= tx_lock_time && self.tx.inputs[self.input].sequence != SEQUENCE_FINAL
+    }
+
+    // OP_CHECKSEQUENCEVERIFY (BIP 112)
+    pub fn check_sequence(&self, sequence: i64) -> bool {
+        let tx_sequence = self.tx.inputs[self.input].sequence as i64;
+        if self.tx.version < 2 || tx_sequence & SEQUENCE_DISABLE_FLAG != 0 {
+            return false
+        }
+        let mask = SEQUENCE_TYPE_FLAG | SEQUENCE_MASK;
+        let (tx_sequence, sequence) = (tx_sequence & mask, sequence & mask);
+        if (tx_sequence < SEQUENCE_TYPE_FLAG) != (sequence < SEQUENCE_TYPE_FLAG) {
+            return false
+        }
+        sequence <= tx_sequence
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn bytes(s: &str) -> Vec<u8> {
+        hex::decode(s).unwrap()
+    }
+
+    fn hash(s: &str) -> [u8; 32] {
+        bytes(s).try_into().unwrap()
+    }
+
+    // Txids are shown reversed by block explorers
+    fn reversed(s: &str) -> [u8; 32] {
+        let mut hash = hash(s);
+        hash.reverse();
+        hash
+    }
+
+    fn input(txid: [u8; 32], vout: u32, sequence: u32) -> TxIn {
+        TxIn { previous_output: OutPoint { txid, vout }, script_sig: Vec::new(), sequence, witness: Vec::new() }
+    }
+
+    fn output(value: u64, script_pubkey: &str) -> TxOut {
+        TxOut { value, script_pubkey: bytes(script_pubkey) }
+    }
+
+    const SATOSHI_KEY: &str = "0411db93e1dcdb8a016b49840f8c53bc1eb68a382e97b1482ecad7b148a6909a5cb2e0eaddfb84ccf9744464f82e160bfa9b8b64f9d4c03f999b8643f656b412a3";
+    const R: &str = "4e45e16932b8af514961a1d3a1a25fdf3f4f7732e9d624c6c61548ab5fb8cd41";
+
+    fn block_170_sig() -> Vec<u8> {
+        bytes(&format!("30440220{}0220181522ec8eca07de4860a4acdd12909d831cc56cbbac4622082221a8768d1d0901", R))
+    }
+
+    // First bitcoin transaction, block 170
+    fn block_170() -> (TransactionContext, Vec<u8>) {
+        let mut spent = input(reversed("0437cd7f8525ceed2324359c2d0ba26006d92d856a9c20fa0241106ee5a597c9"), 0, SEQUENCE_FINAL);
+        spent.script_sig = encode_push(&block_170_sig());
+        let tx = Transaction {
+            version: 1,
+            inputs: vec![spent],
+            outputs: vec![
+                output(1_000_000_000, "4104ae1a62fe09c5f51b13905f07f06b99a2f7159b2225f374cd378d71302fa28414e7aab37397f554a7df5f142c21c1b7303b8a0626f1baded5c72a704f7e6cd84cac"),
+                output(4_000_000_000, &format!("41{}ac", SATOSHI_KEY))
+            ],
+            lock_time: 0
+        };
+        (TransactionContext::new(tx, 0, 5_000_000_000, SigVersion::Base), bytes(&format!("41{}ac", SATOSHI_KEY)))
+    }
+
+    #[test]
+    fn legacy_signature_of_block_170() {
+        let (context, script_code) = block_170();
+        assert_eq!(context.tx.txid(), reversed("f4184fc596403b9d638783cf57adfe4c75c605f6356fbc91338530e9831e9e16"));
+
+        let sig = block_170_sig();
+        let key = bytes(SATOSHI_KEY);
+        assert_eq!(context.check_signature(&sig, &key, &script_code), Ok(true));
+        // Signs a different script code or output
+        assert_eq!(context.check_signature(&sig, &key, &script_code[1..]), Ok(false));
+        let mut other = context.clone();
+        other.tx.outputs[0].value += 1;
+        assert_eq!(other.check_signature(&sig, &key, &script_code), Ok(false));
+    }
+
+    #[test]
+    fn signature_encoding_checks() {
+        let (context, script_code) = block_170();
+        let key = bytes(SATOSHI_KEY);
+        let high_s = bytes(&format!("30450220{}022100e7eadd137135f821b79f5b5322ed6f6137921779f39c5a19b7b03ce459a9243801", R));
+        assert_eq!(context.check_signature(&high_s, &key, &script_code), Err(ScriptError::SigHighS));
+        let bad_hashtype = bytes(&format!("30440220{}0220181522ec8eca07de4860a4acdd12909d831cc56cbbac4622082221a8768d1d0904", R));
+        assert_eq!(context.check_signature(&bad_hashtype, &key, &script_code), Err(ScriptError::SigHashtype));
+        // Wrong total length in the DER header
+        let bad_der = bytes(&format!("30450220{}0220181522ec8eca07de4860a4acdd12909d831cc56cbbac4622082221a8768d1d0901", R));
+        assert_eq!(context.check_signature(&bad_der, &key, &script_code), Err(ScriptError::SigDer));
+        assert_eq!(context.check_signature(&[], &key, &script_code), Ok(false));
+        assert_eq!(context.check_signature(&bytes(&format!("3044{}", "00".repeat(0x44))), &key, &script_code), Err(ScriptError::SigDer));
+        assert_eq!(context.check_signature(&block_170_sig(), &key[1..], &script_code), Err(ScriptError::PubkeyType));
+    }
+
+    #[test]
+    fn legacy_sighash_types() {
+        let (context, script_code) = block_170();
+        let tx = &context.tx;
+        // SIGHASH_SINGLE without a matching output signs the number one
+        let mut single = tx.clone();
+        single.inputs.push(input([1; 32], 0, 0));
+        single.inputs.push(input([2; 32], 0, 0));
+        let mut one = [0; 32];
+        one[0] = 1;
+        assert_eq!(single.signature_hash(2, &script_code, 0, SIGHASH_SINGLE, SigVersion::Base), one);
+        assert_ne!(single.signature_hash(1, &script_code, 0, SIGHASH_SINGLE, SigVersion::Base), one);
+
+        // NONE ignores the outputs, ANYONECANPAY the other inputs
+        let mut changed = tx.clone();
+        changed.outputs.pop();
+        assert_eq!(tx.signature_hash(0, &script_code, 0, SIGHASH_NONE, SigVersion::Base),
+            changed.signature_hash(0, &script_code, 0, SIGHASH_NONE, SigVersion::Base));
+        let mut extra_input = tx.clone();
+        extra_input.inputs.push(input([1; 32], 0, 0));
+        let acp = SIGHASH_ALL | SIGHASH_ANYONECANPAY;
+        assert_eq!(tx.signature_hash(0, &script_code, 0, acp, SigVersion::Base),
+            extra_input.signature_hash(0, &script_code, 0, acp, SigVersion::Base));
+        assert_ne!(tx.signature_hash(0, &script_code, 0, SIGHASH_ALL, SigVersion::Base),
+            extra_input.signature_hash(0, &script_code, 0, SIGHASH_ALL, SigVersion::Base));
+
+        // OP_CODESEPARATORs are not signed
+        let mut separated = vec![OP_CODESEPARATOR.code];
+        separated.extend(&script_code);
+        assert_eq!(tx.signature_hash(0, &separated, 0, SIGHASH_ALL, SigVersion::Base),
+            tx.signature_hash(0, &script_code, 0, SIGHASH_ALL, SigVersion::Base));
+    }
+
+    #[test]
+    fn bip143_native_p2wpkh() {
+        let tx = Transaction {
+            version: 1,
+            inputs: vec![
+                input(hash("fff7f7881a8099afa6940d42d1e7f6362bec38171ea3edf433541db4e4ad969f"), 0, 0xffffffee),
+                input(hash("ef51e1b804cc89d182d279655c3aa89e815b1b309fe287d9b2b55d57b90ec68a"), 1, SEQUENCE_FINAL)
+            ],
+            outputs: vec![
+                output(112_340_000, "76a9148280b37df378db99f66f85c95a783a76ac7a6d5988ac"),
+                output(223_450_000, "76a9143bde42dbee7e4dbe6a21b2d50ce2f0167faa815988ac")
+            ],
+            lock_time: 17
+        };
+        assert_eq!(hex::encode(tx.serialize()), "0100000002fff7f7881a8099afa6940d42d1e7f6362bec38171ea3edf433541db4e4ad969f0000000000eeffffffef51e1b804cc89d182d279655c3aa89e815b1b309fe287d9b2b55d57b90ec68a0100000000ffffffff02202cb206000000001976a9148280b37df378db99f66f85c95a783a76ac7a6d5988ac9093510d000000001976a9143bde42dbee7e4dbe6a21b2d50ce2f0167faa815988ac11000000");
+        let script_code = bytes("76a9141d0f172a0ecb48aee1be1f2687d2963ae33f71a188ac");
+        assert_eq!(tx.signature_hash(1, &script_code, 600_000_000, SIGHASH_ALL, SigVersion::WitnessV0),
+            hash("c37af31116d1b27caf68aae9e3ac82f1477929014d5b917657d0eb49478cb670"));
+    }
+
+    #[test]
+    fn bip143_p2sh_p2wpkh() {
+        let tx = Transaction {
+            version: 1,
+            inputs: vec![input(hash("db6b1b20aa0fd7b23880be2ecbd4a98130974cf4748fb66092ac4d3ceb1a5477"), 1, 0xfffffffe)],
+            outputs: vec![
+                output(199_996_600, "76a914a457b684d7f0d539a46a45bbc043f35b59d0d96388ac"),
+                output(800_000_000, "76a914fd270b1ee6abcaea97fea7ad0402e8bd8ad6d77c88ac")
+            ],
+            lock_time: 1170
+        };
+        let script_code = bytes("76a91479091972186c449eb1ded22b78e40d009bdf008988ac");
+        assert_eq!(tx.signature_hash(0, &script_code, 1_000_000_000, SIGHASH_ALL, SigVersion::WitnessV0),
+            hash("64f3b0f4dd2bb3aa1ce8566d220cc74dda9df97d8490cc81d89d735c92e59fb6"));
+    }
+
+    #[test]
+    fn signatures_round_trip() {
+        let (mut context, script_code) = block_170();
+        context.sig_version = SigVersion::WitnessV0;
+        let secret = SecretKey::from_slice(&[1; 32]).unwrap();
+        let key = bytes("031b84c5567b126440995d3ed5aaba0565d71e1834604819ff9c17f5e9d5dd078f");
+        for sighash_type in [SIGHASH_ALL, SIGHASH_NONE, SIGHASH_SINGLE | SIGHASH_ANYONECANPAY] {
+            let sig = context.sign(&secret, &script_code, sighash_type);
+            assert!(sig.len() <= 71);
+            assert_eq!(sig.last(), Some(&sighash_type));
+            assert_eq!(context.check_signature(&sig, &key, &script_code), Ok(true));
+            // The amount is signed by segwit signatures
+            let mut other = context.clone();
+            other.amount += 1;
+            assert_eq!(other.check_signature(&sig, &key, &script_code), Ok(false));
+        }
+    }
+
+    #[test]
+    fn timelocks() {
+        let (mut context, _) = block_170();
+        context.tx.lock_time = 500;
+        assert!(!context.check_lock_time(400));
+        context.tx.inputs[0].sequence = 0xfffffffe;
+        assert!(context.check_lock_time(400) && context.check_lock_time(500));
+        assert!(!context.check_lock_time(501) && !context.check_lock_time(LOCKTIME_THRESHOLD));
+
+        context.tx.inputs[0].sequence = 10;
+        assert!(!context.check_sequence(5));
+        context.tx.version = 2;
+        assert!(context.check_sequence(5) && context.check_sequence(10) && !context.check_sequence(11));
+        assert!(!context.check_sequence(SEQUENCE_TYPE_FLAG | 5));
+        context.tx.inputs[0].sequence = (SEQUENCE_TYPE_FLAG | 10) as u32;
+        assert!(context.check_sequence(SEQUENCE_TYPE_FLAG | 5) && !context.check_sequence(5));
+        context.tx.inputs[0].sequence = SEQUENCE_FINAL;
+        assert!(!context.check_sequence(0));
+    }
+
+    #[test]
+    fn serialization_helpers() {
+        assert_eq!(witness_size(&[vec![0; 72], vec![0; 33]]), 1 + 1 + 72 + 1 + 33);
+        assert_eq!(witness_size(&[vec![0; 253]]), 1 + 3 + 253);
+        // OP_1 <0xaa> OP_2 <0x01aa> <0xaa>, the push embedded in <0x01aa> is kept
+        assert_eq!(find_and_delete(&bytes("5101aa520201aa01aa"), &bytes("aa")), bytes("51520201aa"));
+        assert_eq!(find_and_delete(&bytes("01aa01aa51"), &bytes("aa")), bytes("51"));
+    }
+}
@@ -0,0 +1,315 @@
+use std::io;
+
+use ratatui::crossterm::event::{self, Event, KeyCode, KeyEventKind};
+use ratatui::layout::{Constraint, Layout, Rect};
+use ratatui::style::{Color, Modifier, Style};
+use ratatui::text::{Line, Span};
+use ratatui::widgets::{Block, List, ListItem, ListState, Paragraph};
+use ratatui::{DefaultTerminal, Frame};
+
+use crate::debugger::{format_element, Debugger, ElementFormat, StackName};
+use crate::parse::parse_one_op;
+use crate::script::{as_bool, ScriptItem};
+
+const HELP: &str = "s/→ step  b/← back  c continue  r rewind to branch  0 restart  ↑/↓ select  tab switch stack  q quit";
+
+// Full-screen debugger with panes for the script, the stacks and the selected element
+pub struct Tui {
+    debugger: Debugger,
+    items: Vec<(usize, ScriptItem)>,
+    focus: StackName,
+    // Position of the selected element from the top of the focused stack
+    selected: usize
+}
+
+impl Tui {
+    pub fn new(script: &[u8]) -> Tui {
+        // Keep what can be parsed, the interpreter reports the error when it gets there
+        let mut items = Vec::new();
+        let mut cursor = 0;
+        let mut offset = cursor;
+        while let Ok(Some(item)) = parse_one_op(script, &mut cursor) {
+            items.push((offset, item));
+            offset = cursor;
+        }
+        Tui { debugger: Debugger::new(script), items, focus: StackName::Main, selected: 0 }
+    }
+
+    // Take over the terminal until the user quits
+    pub fn run(mut self) -> io::Result<()> {
+        let mut terminal = ratatui::init();
+        let result = self.event_loop(&mut terminal);
+        ratatui::restore();
+        result
+    }
+
+    fn event_loop(&mut self, terminal: &mut DefaultTerminal) -> io::Result<()> {
+        loop {
+            terminal.draw(|frame| self.draw(frame))?;
+
+            // Resize events simply trigger a redraw with the new frame size
+            let key = match event::read()? {
+                Event::Key(key) if key.kind == KeyEventKind::Press => key,
+                _ => continue
+            };
+            if !self.handle_key(key.code) {
+                return Ok(())
+            }
+        }
+    }
+
+    // Apply a key press, returns false when the user quits
+    fn handle_key(&mut self, code: KeyCode) -> bool {
+        match code {
+            KeyCode::Char('q') | KeyCode::Esc => return false,
+            KeyCode::Char('s') | KeyCode::Char(' ') | KeyCode::Right => { self.debugger.step(1); }
+            KeyCode::Char('b') | KeyCode::Left => self.debugger.back(1),
+            KeyCode::Char('c') => { self.debugger.continue_execution(); }
+            KeyCode::Char('r') => { self.debugger.rewind_to_branch(); }
+            KeyCode::Char('0') => self.debugger.goto(0),
+            KeyCode::Up => self.selected = self.selected.saturating_sub(1),
+            KeyCode::Down => self.selected += 1,
+            KeyCode::Tab => {
+                self.focus = match self.focus {
+                    StackName::Main => StackName::Alt,
+                    StackName::Alt => StackName::Main
+                };
+                self.selected = 0;
+            }
+            _ => {}
+        }
+        self.selected = self.selected.min(self.focused_stack().len().saturating_sub(1));
+        true
+    }
+
+    fn focused_stack(&self) -> &Vec<Vec<u8>> {
+        let state = self.debugger.interpreter().state();
+        match self.focus {
+            StackName::Main => state.main_stack(),
+            StackName::Alt => state.alt_stack()
+        }
+    }
+
+    fn draw(&self, frame: &mut Frame) {
+        let [body, status] = Layout::vertical([Constraint::Min(8), Constraint::Length(3)]).areas(frame.area());
+        let [left, right] = Layout::horizontal([Constraint::Percentage(55), Constraint::Percentage(45)]).areas(body);
+        let [script_area, decoded_area] = Layout::vertical([Constraint::Min(4), Constraint::Length(7)]).areas(left);
+        let [main_area, alt_area, condition_area] = Layout::vertical([
+            Constraint::Percentage(50),
+            Constraint::Percentage(30),
+            Constraint::Percentage(20)
+        ]).areas(right);
+
+        self.draw_script(frame, script_area);
+        self.draw_stack(frame, main_area, StackName::Main);
+        self.draw_stack(frame, alt_area, StackName::Alt);
+        self.draw_condition_stack(frame, condition_area);
+        self.draw_decoded(frame, decoded_area);
+        self.draw_status(frame, status);
+    }
+
+    // Script wrapped by hand so that the current instruction can be kept in view
+    fn draw_script(&self, frame: &mut Frame, area: Rect) {
+        let state = self.debugger.interpreter().state();
+        let width = area.width.saturating_sub(2).max(1) as usize;
+
+        let mut lines: Vec<Line> = Vec::new();
+        let mut line: Vec<Span> = Vec::new();
+        let mut line_len = 0;
+        let mut current_line = 0;
+        for (offset, item) in &self.items {
+            let style = if *offset == state.pc() {
+                Style::default().fg(Color::Black).bg(Color::Yellow).add_modifier(Modifier::BOLD)
+            } else if self.debugger.skipped().contains(offset) {
+                Style::default().fg(Color::DarkGray).add_modifier(Modifier::DIM)
+            } else if *offset < state.pc() {
+                Style::default().fg(Color::Gray)
+            } else {
+                match item {
+                    ScriptItem::ByteArray(..) => Style::default().fg(Color::LightBlue),
+                    ScriptItem::Opcode(..) => Style::default().fg(Color::White).add_modifier(Modifier::BOLD)
+                }
+            };
+
+            let text = format!("{:?}", item);
+            if line_len > 0 && line_len + text.len() > width {
+                lines.push(Line::from(std::mem::take(&mut line)));
+                line_len = 0;
+            }
+            if *offset == state.pc() {
+                current_line = lines.len();
+            }
+            // Long pushes are split over several lines
+            let mut rest = text.as_str();
+            while rest.len() > width {
+                let (chunk, tail) = rest.split_at(width);
+                lines.push(Line::from(Span::styled(chunk.to_string(), style)));
+                rest = tail;
+            }
+            line.push(Span::styled(rest.to_string(), style));
+            line.push(Span::raw(" "));
+            line_len += rest.len() + 1;
+        }
+        if !line.is_empty() {
+            lines.push(Line::from(line));
+        }
+
+        let height = area.height.saturating_sub(2) as usize;
+        let scroll = current_line.saturating_sub(height / 2) as u16;
+        let title = format!(" Script (step {}, offset {}) ", state.steps(), state.pc());
+        frame.render_widget(Paragraph::new(lines).block(Block::bordered().title(title)).scroll((scroll, 0)), area);
+    }
+
+    fn draw_stack(&self, frame: &mut Frame, area: Rect, name: StackName) {
+        let state = self.debugger.interpreter().state();
+        let (stack, title) = match name {
+            StackName::Main => (state.main_stack(), " Main Stack "),
+            StackName::Alt => (state.alt_stack(), " Alt Stack ")
+        };
+        let items: Vec<ListItem> = stack.iter().rev().enumerate()
+            .map(|(i, elem)| ListItem::new(format!("{:>3} 0x{}", i, hex::encode(elem))))
+            .collect();
+
+        let focused = self.focus == name;
+        let block = Block::bordered().title(title).border_style(match focused {
+            true => Style::default().fg(Color::Cyan),
+            false => Style::default()
+        });
+        let list = List::new(items).block(block).highlight_style(Style::default().add_modifier(Modifier::REVERSED));
+        let mut list_state = ListState::default();
+        if focused && !stack.is_empty() {
+            list_state.select(Some(self.selected));
+        }
+        frame.render_stateful_widget(list, area, &mut list_state);
+    }
+
+    fn draw_condition_stack(&self, frame: &mut Frame, area: Rect) {
+        let conditions = self.debugger.interpreter().state().condition_stack();
+        let items: Vec<ListItem> = conditions.iter().rev().map(|c| {
+            let color = if *c { Color::Green } else { Color::Red };
+            ListItem::new(Span::styled(c.to_string(), Style::default().fg(color)))
+        }).collect();
+        frame.render_widget(List::new(items).block(Block::bordered().title(" Condition Stack ")), area);
+    }
+
+    fn draw_decoded(&self, frame: &mut Frame, area: Rect) {
+        let stack = self.focused_stack();
+        let lines = match stack.iter().rev().nth(self.selected) {
+            Some(elem) => vec![
+                Line::from(format!("Size:   {} bytes", elem.len())),
+                Line::from(format!("Hex:    {}", format_element(elem, ElementFormat::Hex))),
+                Line::from(format!("Number: {}", format_element(elem, ElementFormat::Number))),
+                Line::from(format!("Bool:   {}", as_bool(elem))),
+                Line::from(format!("ASCII:  {}", format_element(elem, ElementFormat::Ascii)))
+            ],
+            None => vec![Line::from("Empty stack")]
+        };
+        let title = format!(" Element {} ", self.selected);
+        frame.render_widget(Paragraph::new(lines).block(Block::bordered().title(title)), area);
+    }
+
+    fn draw_status(&self, frame: &mut Frame, area: Rect) {
+        let state = self.debugger.interpreter().state();
+        let status = if state.is_finished() {
+            match self.debugger.result().error {
+                Some(e) => Span::styled(format!("Failed: {} ({})", e.error, e.error.name()), Style::default().fg(Color::Red)),
+                None => Span::styled("Script succeeded", Style::default().fg(Color::Green))
+            }
+        } else {
+            Span::raw(format!("Running, {} ops", state.op_count()))
+        };
+        let line = Line::from(vec![status, Span::raw("  |  "), Span::styled(HELP, Style::default().fg(Color::DarkGray))]);
+        frame.render_widget(Paragraph::new(line).block(Block::bordered()), area);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ratatui::backend::TestBackend;
+    use ratatui::Terminal;
+
+    fn tui(script: &str) -> Tui {
+        Tui::new(&hex::decode(script).unwrap())
+    }
+
+    // Text of the screen, one string per row
+    fn screen(tui: &Tui) -> Vec<String> {
+        let mut terminal = Terminal::new(TestBackend::new(100, 30)).unwrap();
+        terminal.draw(|frame| tui.draw(frame)).unwrap();
+        let buffer = terminal.backend().buffer();
+        buffer.content.chunks(buffer.area.width as usize)
+            .map(|row| row.iter().map(|cell| cell.symbol()).collect())
+            .collect()
+    }
+
+    fn shows(tui: &Tui, text: &str) -> bool {
+        screen(tui).iter().any(|row| row.contains(text))
+    }
+
+    #[test]
+    fn keys_drive_the_debugger() {
+        // OP_1 OP_IF 0x0201ff OP_ENDIF OP_TOALTSTACK
+        let mut tui = tui("51630201ff686b");
+        assert!(tui.handle_key(KeyCode::Char('c')));
+        assert!(tui.debugger.interpreter().state().is_finished());
+        assert!(tui.handle_key(KeyCode::Char('r')));
+        assert_eq!(tui.debugger.interpreter().state().pc(), 1);
+        assert!(tui.handle_key(KeyCode::Right));
+        assert!(tui.handle_key(KeyCode::Char('s')));
+        assert_eq!(tui.debugger.interpreter().state().main_stack(), &vec![vec![0x01, 0xff]]);
+        assert!(tui.handle_key(KeyCode::Left));
+        assert_eq!(tui.debugger.interpreter().state().steps(), 2);
+        assert!(tui.handle_key(KeyCode::Char('0')));
+        assert_eq!(tui.debugger.interpreter().state().steps(), 0);
+        assert!(!tui.handle_key(KeyCode::Char('q')));
+        assert!(!tui.handle_key(KeyCode::Esc));
+    }
+
+    #[test]
+    fn selection_stays_on_the_focused_stack() {
+        // OP_1 OP_2 OP_3 OP_4 OP_TOALTSTACK
+        let mut tui = tui("515253546b");
+        tui.handle_key(KeyCode::Char('c'));
+        for _ in 0..5 {
+            tui.handle_key(KeyCode::Down);
+        }
+        assert_eq!(tui.selected, 2);
+        tui.handle_key(KeyCode::Up);
+        assert_eq!(tui.selected, 1);
+        tui.handle_key(KeyCode::Tab);
+        assert!(tui.focus == StackName::Alt);
+        tui.handle_key(KeyCode::Down);
+        assert_eq!(tui.selected, 0);
+        tui.handle_key(KeyCode::Tab);
+        assert!(tui.focus == StackName::Main);
+    }
+
+    #[test]
+    fn draws_state_and_decoded_element() {
+        // OP_1 OP_IF 0x0201ff OP_ENDIF OP_DUP OP_TOALTSTACK
+        let mut tui = tui("51630201ff68766b");
+        assert!(shows(&tui, "Script (step 0, offset 0)"));
+        assert!(shows(&tui, "Empty stack"));
+        assert!(shows(&tui, "Running, 0 ops"));
+
+        tui.debugger.step(3);
+        assert!(shows(&tui, "0 0x01ff"));
+        assert!(shows(&tui, "Size:   2 bytes"));
+        assert!(shows(&tui, "Number: -32513"));
+        assert!(shows(&tui, "true"));
+
+        tui.handle_key(KeyCode::Char('c'));
+        assert!(shows(&tui, "Script succeeded"));
+        tui.handle_key(KeyCode::Tab);
+        assert!(shows(&tui, "Hex:    0x01ff"));
+    }
+
+    #[test]
+    fn draws_the_failure() {
+        // OP_1 OP_VERIFY
+        let mut tui = tui("5169");
+        tui.handle_key(KeyCode::Char('c'));
+        assert!(shows(&tui, "Failed: Script evaluated without error"));
+    }
+}